@@ -0,0 +1,63 @@
+//! Command line options of ossim-info, kept apart from the binary so they can be tested
+
+/// What to print for each file
+#[derive(Debug, Default)]
+pub struct Options {
+    pub image: bool,
+    pub projection: bool,
+    pub dump: bool,
+    pub center: bool,
+    pub levels: bool,
+    pub metadata: bool,
+    pub help: bool,
+    pub entry: usize,
+    pub filenames: Vec<String>
+}
+
+
+impl Options {
+
+    /// Returns the options given by the command line arguments, after the program name.
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+        let mut options = Options::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => options.help = true,
+                "--entry" => options.entry = Options::entry(args.next())?,
+                _ if arg.starts_with("--entry=") => options.entry = Options::entry(Some(arg["--entry=".len()..].to_string()))?,
+                _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+                // Single letter options may be combined, e.g. -ipc
+                _ if arg.starts_with('-') && arg.len() > 1 => {
+                    for letter in arg[1..].chars() {
+                        match letter {
+                            'i' => options.image = true,
+                            'p' => options.projection = true,
+                            'd' => options.dump = true,
+                            'c' => options.center = true,
+                            'r' => options.levels = true,
+                            'm' => options.metadata = true,
+                            'h' => options.help = true,
+                            _ => return Err(format!("unknown option -{}", letter))
+                        }
+                    }
+                },
+                _ => options.filenames.push(arg)
+            }
+        }
+        if !(options.image || options.projection || options.dump || options.center || options.levels) {
+            options.metadata = true;
+        }
+        Ok(options)
+    }
+
+    /// Returns the image entry given to --entry.
+    fn entry(value: Option<String>) -> Result<usize, String> {
+        let value = value.ok_or_else(|| "--entry needs an image entry number".to_string())?;
+        value.parse().map_err(|_| format!("--entry needs an image entry number but was given \"{}\"", value))
+    }
+
+    /// Returns whether any option describes the image entry.
+    pub fn uses_image(&self) -> bool {
+        self.image || self.projection || self.center || self.levels
+    }
+}
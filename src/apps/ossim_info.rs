@@ -1,12 +1,240 @@
-use std::env;
-
-use ossim_oxide::base::Model;
-use ossim_oxide::model::nitf::NITF;
-
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    let filename = &args[1];
-
-    let nitf = NITF::new(filename.to_string()).unwrap();
-    println!("{}",nitf);
-}
+mod info_options;
+
+use std::env;
+use std::process;
+
+use ossim_oxide::base::Model;
+use ossim_oxide::model::nitf::{ImageCompression, ImageSubheader, NitfError, SampleType, SecurityMarking, Tre, TreRegistry, NITF};
+
+use info_options::Options;
+
+const USAGE: &str = "usage: ossim-info [options] <file>...
+
+Prints information about each NITF file. With no options every header field is printed,
+as with -m.
+
+options:
+  -i           image information of the image entry
+  -p           projection and geometry of the image entry
+  -d           every header field followed by the decoded fields of each tagged record
+               extension
+  -c           ground and image center point of the image entry
+  -r           reduced resolution levels of the image entry
+  -m           every header field, with extensions undecoded
+  --entry N    zero based image entry used by -i, -p, -c and -r (default 0)
+  -h, --help   print this help";
+
+fn main() {
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("ossim-info: {}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+    if options.help {
+        println!("{}", USAGE);
+        return;
+    }
+    if options.filenames.is_empty() {
+        eprintln!("ossim-info: no input file\n\n{}", USAGE);
+        process::exit(2);
+    }
+
+    let mut failed = false;
+    for filename in &options.filenames {
+        if options.filenames.len() > 1 {
+            println!("{}:", filename);
+        }
+        if let Err(error) = print_info(filename, &options) {
+            eprintln!("ossim-info: {}: {}", filename, error);
+            failed = true;
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+
+/// Prints the information the options ask for about one file.
+fn print_info(filename: &str, options: &Options) -> Result<(), NitfError> {
+    let nitf = NITF::new(filename.to_string())?;
+
+    if options.metadata || options.dump {
+        println!("{}", nitf.to_string().trim_start());
+    }
+    if options.dump {
+        print_decoded_tres(&nitf);
+    }
+    if !options.uses_image() {
+        return Ok(());
+    }
+
+    let segments = nitf.image_segments();
+    let image = &segments.get(options.entry).ok_or_else(|| NitfError::InvalidRequest {
+        message: format!("image entry {} does not exist in a file with {} image entries", options.entry, segments.len())
+    })?.subheader;
+    let prefix = format!("image{}", options.entry);
+    if options.image {
+        print_image(&prefix, image, segments.len());
+    }
+    if options.projection {
+        print_projection(&prefix, image)?;
+    }
+    if options.center {
+        print_center(&prefix, image)?;
+    }
+    if options.levels {
+        print_levels(&prefix, image)?;
+    }
+    Ok(())
+}
+
+
+/// Prints the fields of every extension with a definition, keyed like the header fields.
+/// Extensions that do not match their definition are reported without stopping the dump.
+fn print_decoded_tres(nitf: &NITF) {
+    let registry = TreRegistry::new();
+    let print = |prefix: &str, tres: &[Tre]| {
+        let mut occurrences = Vec::new();
+        for tre in tres {
+            occurrences.push(&tre.tag);
+            let key = match occurrences.iter().filter(|tag| **tag == &tre.tag).count() {
+                1 => tre.tag.clone(),
+                n => format!("{}_{}", tre.tag, n)
+            };
+            match registry.decode(tre) {
+                Ok(fields) => for field in fields.unwrap_or_default() {
+                    let indices: String = field.indices.iter().map(|index| format!("[{}]", index)).collect();
+                    println!("{}::{}::{}{}: {}", prefix, key, field.name, indices, field.value);
+                },
+                Err(error) => eprintln!("ossim-info: {}::{} could not be decoded: {}", prefix, key, error)
+            }
+        }
+    };
+
+    print("NITF", &nitf.file_header().tres);
+    for segment in nitf.image_segments() {
+        print(&format!("NITF::IMAGE{:03}", segment.index), &segment.subheader.tres);
+    }
+    for segment in nitf.graphic_segments() {
+        print(&format!("NITF::GRAPHIC{:03}", segment.index), &segment.subheader.tres);
+    }
+    for segment in nitf.symbol_segments() {
+        print(&format!("NITF::SYMBOL{:03}", segment.index), &segment.subheader.tres);
+    }
+    for segment in nitf.label_segments() {
+        print(&format!("NITF::LABEL{:03}", segment.index), &segment.subheader.tres);
+    }
+    for segment in nitf.text_segments() {
+        print(&format!("NITF::TEXT{:03}", segment.index), &segment.subheader.tres);
+    }
+}
+
+
+fn print_image(prefix: &str, image: &ImageSubheader, entries: usize) {
+    let scalar_type = match SampleType::for_image(image) {
+        Ok(sample_type) => format!("{:?}", sample_type).to_lowercase(),
+        Err(_) => "unsupported".to_string()
+    };
+    println!("{}.number_entries: {}", prefix, entries);
+    println!("{}.image_id: {}", prefix, image.image_id);
+    println!("{}.title: {}", prefix, image.image_title);
+    println!("{}.security: {}", prefix, SecurityMarking::from_fields(&image.security));
+    println!("{}.number_lines: {}", prefix, image.rows);
+    println!("{}.number_samples: {}", prefix, image.columns);
+    println!("{}.number_bands: {}", prefix, image.bands.len());
+    println!("{}.scalar_type: {}", prefix, scalar_type);
+    println!("{}.bits_per_pixel: {}", prefix, image.bits_per_pixel);
+    println!("{}.actual_bits_per_pixel: {}", prefix, image.actual_bits_per_pixel);
+    println!("{}.pixel_value_type: {}", prefix, image.pixel_value_type.code());
+    println!("{}.representation: {}", prefix, image.representation.code());
+    println!("{}.category: {}", prefix, image.category);
+    println!("{}.compression: {}", prefix, image.compression.code());
+    println!("{}.image_mode: {}", prefix, image.mode.code());
+    println!("{}.blocks_per_row: {}", prefix, image.blocks_per_row);
+    println!("{}.blocks_per_column: {}", prefix, image.blocks_per_column);
+    println!("{}.tile_width: {}", prefix, image.pixels_per_block_horizontal);
+    println!("{}.tile_height: {}", prefix, image.pixels_per_block_vertical);
+}
+
+
+fn print_projection(prefix: &str, image: &ImageSubheader) -> Result<(), NitfError> {
+    let rpc = image.rpc_model()?;
+    let corners = image.corners();
+    let projection = match (&rpc, &corners) {
+        (Some(_), _) => "rpc",
+        (None, Some(_)) => "corners",
+        (None, None) => "none"
+    };
+    println!("{}.projection: {}", prefix, projection);
+    if let Some(coordinates) = image.coordinate_representation {
+        println!("{}.icords: {}", prefix, coordinates.code());
+        println!("{}.igeolo: {}", prefix, image.geographic_location);
+    }
+    if let Some(corners) = corners {
+        for (name, (latitude, longitude)) in ["ul", "ur", "lr", "ll"].iter().zip(corners.iter()) {
+            println!("{}.{}_lat: {:.6}", prefix, name, latitude);
+            println!("{}.{}_lon: {:.6}", prefix, name, longitude);
+        }
+    }
+    if let Some(rpc) = rpc {
+        println!("{}.rpc.line_offset: {}", prefix, rpc.line_offset);
+        println!("{}.rpc.sample_offset: {}", prefix, rpc.sample_offset);
+        println!("{}.rpc.latitude_offset: {}", prefix, rpc.latitude_offset);
+        println!("{}.rpc.longitude_offset: {}", prefix, rpc.longitude_offset);
+        println!("{}.rpc.height_offset: {}", prefix, rpc.height_offset);
+        println!("{}.rpc.line_scale: {}", prefix, rpc.line_scale);
+        println!("{}.rpc.sample_scale: {}", prefix, rpc.sample_scale);
+        println!("{}.rpc.latitude_scale: {}", prefix, rpc.latitude_scale);
+        println!("{}.rpc.longitude_scale: {}", prefix, rpc.longitude_scale);
+        println!("{}.rpc.height_scale: {}", prefix, rpc.height_scale);
+        println!("{}.rpc.error_bias: {}", prefix, rpc.error_bias);
+        println!("{}.rpc.error_random: {}", prefix, rpc.error_random);
+    }
+    Ok(())
+}
+
+
+/// Prints the center of the image, projected to the ground through the RPC model at its
+/// height offset, or else interpolated between the IGEOLO corners.
+fn print_center(prefix: &str, image: &ImageSubheader) -> Result<(), NitfError> {
+    let line = (f64::from(image.rows) - 1.0) / 2.0;
+    let sample = (f64::from(image.columns) - 1.0) / 2.0;
+    let ground = match image.rpc_model()? {
+        Some(rpc) => rpc.image_to_ground(line, sample, rpc.height_offset),
+        None => image.corners().map(|corners| {
+            let sum = corners.iter().fold((0.0, 0.0), |sum, corner| (sum.0 + corner.0, sum.1 + corner.1));
+            (sum.0 / 4.0, sum.1 / 4.0)
+        })
+    };
+    println!("{}.center_line: {}", prefix, line);
+    println!("{}.center_sample: {}", prefix, sample);
+    match ground {
+        Some((latitude, longitude)) => {
+            println!("{}.center_lat: {:.6}", prefix, latitude);
+            println!("{}.center_lon: {:.6}", prefix, longitude);
+        },
+        None => println!("{}.center_ground: unknown", prefix)
+    }
+    Ok(())
+}
+
+
+/// Prints the size of each resolution level, which JPEG 2000 codestreams hold one of for
+/// each decomposition level recorded in J2KLRA besides the full resolution.
+fn print_levels(prefix: &str, image: &ImageSubheader) -> Result<(), NitfError> {
+    let decompositions = match image.compression {
+        ImageCompression::Jpeg2000 | ImageCompression::Jpeg2000Masked =>
+            image.jpeg2000_layers()?.map(|layers| layers.levels).unwrap_or(0),
+        _ => 0
+    };
+    println!("{}.number_reduced_resolution_levels: {}", prefix, u32::from(decompositions) + 1);
+    for level in 0..=u32::from(decompositions) {
+        let reduced = |length: u32| (u64::from(length) + (1 << level) - 1) >> level;
+        println!("{}.rlevel{}.number_lines: {}", prefix, level, reduced(image.rows));
+        println!("{}.rlevel{}.number_samples: {}", prefix, level, reduced(image.columns));
+    }
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use std::env;
+use std::fs::File;
+use std::process;
+
+use ossim_oxide::model::nitf::{validate, Finding, Severity, NITF};
+
+const USAGE: &str = "usage: ossim-nitf-validate <file>...
+
+Checks each NITF file against MIL-STD-2500C and prints one line per finding with its
+severity, field and byte offset. Exits with status 1 when any file has an error.";
+
+fn main() {
+    let filenames: Vec<String> = env::args().skip(1).collect();
+    if filenames.iter().any(|filename| filename == "-h" || filename == "--help") {
+        println!("{}", USAGE);
+        return;
+    }
+    if filenames.is_empty() {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    }
+
+    let mut failed = false;
+    for filename in &filenames {
+        let file = match File::open(filename) {
+            Ok(file) => file,
+            Err(error) => {
+                eprintln!("ossim-nitf-validate: {}: {}", filename, error);
+                failed = true;
+                continue;
+            }
+        };
+        // Only the headers are read from the file, not the segment data
+        let findings = match NITF::from_reader_lenient(file) {
+            Ok(nitf) => validate(&nitf),
+            Err(error) => vec![Finding::from_error(&error)]
+        };
+        for finding in &findings {
+            println!("{}: {}", filename, finding);
+        }
+        if findings.is_empty() {
+            println!("{}: no findings", filename);
+        }
+        failed |= findings.iter().any(|finding| finding.severity == Severity::Error);
+    }
+    if failed {
+        process::exit(1);
+    }
+}
@@ -4,11 +4,13 @@
 pub trait Model {
     /// Type of self of implemented model.
     type MyType;
+    /// Type of error returned when the model cannot be read.
+    type Error;
     /// Returns Result<self type> for given file.
     ///
     /// # Arguments
     ///
     /// * `filename` - A string of the path to the model's file.
-    fn new(filename: String) -> std::io::Result<Self::MyType>;
+    fn new(filename: String) -> Result<Self::MyType, Self::Error>;
 
 }
@@ -0,0 +1,550 @@
+//! Decoding of the binary encoded CGM (ISO/IEC 8632-3) held by graphic segments into vector
+//! primitives, covering the elements of the NITF CGM profile (MIL-STD-2301A)
+
+use super::error::NitfError;
+use super::field::Color;
+use super::NITF;
+
+/// Point in virtual device coordinates (VDC) as x and y. The NITF CGM profile places VDC on
+/// the pixel grid, x counting columns and y rows from the graphic's location (SLOC).
+pub type CgmPoint = (i32, i32);
+
+
+/// Line Type (LINE TYPE and EDGE TYPE)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineType {
+    /// 1 - solid
+    Solid,
+    /// 2 - dash
+    Dash,
+    /// 3 - dot
+    Dot,
+    /// 4 - dash dot
+    DashDot,
+    /// 5 - dash dot dot
+    DashDotDot
+}
+
+impl LineType {
+    /// Returns the line type for the given LINE TYPE index.
+    pub fn from_code(code: i32) -> Option<LineType> {
+        match code {
+            1 => Some(LineType::Solid),
+            2 => Some(LineType::Dash),
+            3 => Some(LineType::Dot),
+            4 => Some(LineType::DashDot),
+            5 => Some(LineType::DashDotDot),
+            _ => None
+        }
+    }
+
+    /// Returns the LINE TYPE index for the line type.
+    pub fn code(&self) -> i32 {
+        match self {
+            LineType::Solid => 1,
+            LineType::Dash => 2,
+            LineType::Dot => 3,
+            LineType::DashDot => 4,
+            LineType::DashDotDot => 5
+        }
+    }
+}
+
+
+/// Color, width in VDC and type of a line or of the edge of a filled primitive
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LineStyle {
+    pub color: Color,
+    pub width: i32,
+    pub line_type: LineType
+}
+
+
+/// Interior of a closed primitive (INTERIOR STYLE)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FillStyle {
+    /// 0 - hollow, only the boundary is drawn, in the fill color
+    Hollow(Color),
+    /// 1 - solid, filled with the color
+    Solid(Color),
+    /// 3 - hatched in the color with the HATCH INDEX style
+    Hatch(Color, i32),
+    /// 4 - empty, nothing is drawn
+    Empty
+}
+
+
+/// Closure of a closed arc (CLOSE TYPE)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArcClosure {
+    /// 0 - closed through the center
+    Pie,
+    /// 1 - closed by the chord between the ends
+    Chord
+}
+
+
+/// Vector primitive of a CGM picture, with the attributes in effect where it was drawn.
+/// Rectangles are returned as polygons, and circles and circular arcs as ellipses and
+/// elliptical arcs whose conjugate diameter ends lie along the axes.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GraphicPrimitive {
+    /// POLYLINE
+    Polyline {
+        points: Vec<CgmPoint>,
+        line: LineStyle
+    },
+    /// POLYGON and RECTANGLE, with the edge when it is visible
+    Polygon {
+        points: Vec<CgmPoint>,
+        fill: FillStyle,
+        edge: Option<LineStyle>
+    },
+    /// ELLIPSE and CIRCLE, given by the center and the ends of two conjugate diameters
+    Ellipse {
+        center: CgmPoint,
+        first_diameter: CgmPoint,
+        second_diameter: CgmPoint,
+        fill: FillStyle,
+        edge: Option<LineStyle>
+    },
+    /// ELLIPTICAL ARC and CIRCULAR ARC CENTRE, drawn from the direction of `start` to the
+    /// direction of `end` as vectors from the center
+    Arc {
+        center: CgmPoint,
+        first_diameter: CgmPoint,
+        second_diameter: CgmPoint,
+        start: CgmPoint,
+        end: CgmPoint,
+        line: LineStyle
+    },
+    /// ELLIPTICAL ARC CLOSE and CIRCULAR ARC CENTRE CLOSE
+    ClosedArc {
+        center: CgmPoint,
+        first_diameter: CgmPoint,
+        second_diameter: CgmPoint,
+        start: CgmPoint,
+        end: CgmPoint,
+        closure: ArcClosure,
+        fill: FillStyle,
+        edge: Option<LineStyle>
+    },
+    /// TEXT along with any APPEND TEXT, with the character height in VDC
+    Text {
+        position: CgmPoint,
+        text: String,
+        color: Color,
+        height: i32,
+        font: i32
+    }
+}
+
+
+/// One picture of a CGM
+#[derive(Clone, Debug, PartialEq)]
+pub struct CgmPicture {
+    /// Picture identifier from BEGIN PICTURE
+    pub name: String,
+    /// First and second corners of the VDC EXTENT, when given
+    pub vdc_extent: Option<(CgmPoint, CgmPoint)>,
+    /// BACKGROUND COLOUR, when given
+    pub background: Option<Color>,
+    /// Primitives in drawing order
+    pub primitives: Vec<GraphicPrimitive>
+}
+
+
+/// Precisions of the metafile, which apply to every picture
+struct Precisions {
+    integer: u32,
+    index: u32,
+    vdc: u32
+}
+
+
+/// Attributes of a picture, reset to their defaults by BEGIN PICTURE
+struct Attributes {
+    line: LineStyle,
+    edge: LineStyle,
+    edge_visible: bool,
+    interior_style: i32,
+    fill_color: Color,
+    hatch_index: i32,
+    text_color: Color,
+    character_height: i32,
+    font: i32
+}
+
+impl Default for Attributes {
+    fn default() -> Attributes {
+        let black = Color { red: 0, green: 0, blue: 0 };
+        let style = LineStyle { color: black, width: 1, line_type: LineType::Solid };
+        Attributes {
+            line: style,
+            edge: style,
+            edge_visible: false,
+            interior_style: 0,
+            fill_color: black,
+            hatch_index: 1,
+            text_color: black,
+            character_height: 1,
+            font: 1
+        }
+    }
+}
+
+impl Attributes {
+    fn fill(&self) -> Result<FillStyle, String> {
+        match self.interior_style {
+            0 => Ok(FillStyle::Hollow(self.fill_color)),
+            1 => Ok(FillStyle::Solid(self.fill_color)),
+            3 => Ok(FillStyle::Hatch(self.fill_color, self.hatch_index)),
+            4 => Ok(FillStyle::Empty),
+            style => Err(format!("interior style {} is not in the NITF CGM profile", style))
+        }
+    }
+
+    fn edge(&self) -> Option<LineStyle> {
+        match self.edge_visible {
+            true => Some(self.edge),
+            false => None
+        }
+    }
+}
+
+
+/// Reader of the parameters of one element
+struct Parameters<'a> {
+    data: &'a [u8],
+    position: usize
+}
+
+impl<'a> Parameters<'a> {
+
+    fn remaining(&self) -> usize {
+        self.data.len() - self.position
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let bytes = self.data.get(self.position..self.position + length).ok_or("truncated parameters")?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    /// Reads a signed big endian integer of `bits` bits.
+    fn signed(&mut self, bits: u32) -> Result<i32, String> {
+        let bytes = self.bytes(bits as usize / 8)?;
+        let value = bytes.iter().fold(0u32, |value, byte| value << 8 | u32::from(*byte));
+        Ok((value << (32 - bits)) as i32 >> (32 - bits))
+    }
+
+    fn enumerated(&mut self) -> Result<i32, String> {
+        self.signed(16)
+    }
+
+    fn point(&mut self, precisions: &Precisions) -> Result<CgmPoint, String> {
+        Ok((self.signed(precisions.vdc)?, self.signed(precisions.vdc)?))
+    }
+
+    fn points(&mut self, precisions: &Precisions) -> Result<Vec<CgmPoint>, String> {
+        let mut points = Vec::new();
+        while self.remaining() > 0 {
+            points.push(self.point(precisions)?);
+        }
+        Ok(points)
+    }
+
+    /// Reads a direct color of eight bit components.
+    fn color(&mut self) -> Result<Color, String> {
+        let bytes = self.bytes(3)?;
+        Ok(Color { red: bytes[0], green: bytes[1], blue: bytes[2] })
+    }
+
+    /// Reads a string, whose length of 255 or more is held in continued two byte counts.
+    fn string(&mut self) -> Result<String, String> {
+        let mut length = usize::from(self.bytes(1)?[0]);
+        let mut bytes = Vec::new();
+        if length < 255 {
+            bytes.extend_from_slice(self.bytes(length)?);
+        } else {
+            loop {
+                let count = self.signed(16)? as u16;
+                length = usize::from(count & 0x7FFF);
+                bytes.extend_from_slice(self.bytes(length)?);
+                if count & 0x8000 == 0 {
+                    break;
+                }
+            }
+        }
+        Ok(bytes.iter().map(|byte| char::from(*byte)).collect())
+    }
+}
+
+
+/// Returns the integer precision in bits, which may be 8, 16, 24 or 32.
+fn precision(bits: i32) -> Result<u32, String> {
+    match bits {
+        8 | 16 | 24 | 32 => Ok(bits as u32),
+        _ => Err(format!("{} bit precision is not supported", bits))
+    }
+}
+
+
+/// Element of a binary CGM, with the byte offset of its command header
+struct Element {
+    class: u8,
+    id: u8,
+    offset: usize,
+    parameters: Vec<u8>
+}
+
+
+/// Splits binary CGM data into elements up to END METAFILE. Partitioned parameters are
+/// joined.
+fn elements(data: &[u8]) -> Result<Vec<Element>, String> {
+    let mut elements = Vec::new();
+    let mut position = 0;
+    while position < data.len() {
+        let offset = position;
+        let word = |position: usize| data.get(position..position + 2)
+            .map(|bytes| u16::from(bytes[0]) << 8 | u16::from(bytes[1]))
+            .ok_or_else(|| format!("element at byte {} is truncated", offset));
+        let header = word(position)?;
+        position += 2;
+        let (class, id) = ((header >> 12) as u8, (header >> 5 & 0x7F) as u8);
+        let mut parameters = Vec::new();
+        let mut length = usize::from(header & 0x1F);
+        let mut partitioned = false;
+        if length == 31 {
+            let long = word(position)?;
+            position += 2;
+            length = usize::from(long & 0x7FFF);
+            partitioned = long & 0x8000 != 0;
+        }
+        loop {
+            let bytes = data.get(position..position + length).ok_or_else(|| format!("element at byte {} is truncated", offset))?;
+            parameters.extend_from_slice(bytes);
+            position += length + length % 2;
+            if !partitioned {
+                break;
+            }
+            let long = word(position)?;
+            position += 2;
+            length = usize::from(long & 0x7FFF);
+            partitioned = long & 0x8000 != 0;
+        }
+        elements.push(Element { class, id, offset, parameters });
+        if (class, id) == (0, 2) {
+            break;
+        }
+    }
+    Ok(elements)
+}
+
+
+/// Decodes binary CGM data into its pictures.
+pub(crate) fn decode(data: &[u8]) -> Result<Vec<CgmPicture>, String> {
+    let mut precisions = Precisions { integer: 16, index: 16, vdc: 16 };
+    let mut pictures = Vec::new();
+    let mut picture: Option<(CgmPicture, Attributes)> = None;
+
+    for Element { class, id, offset, parameters } in elements(data)? {
+        let mut parameters = Parameters { data: &parameters, position: 0 };
+        let result = (|| -> Result<(), String> {
+            match (class, id) {
+                // Delimiters
+                (0, 3) => {
+                    let picture_name = parameters.string().unwrap_or_default();
+                    if let Some((picture, _)) = picture.take() {
+                        pictures.push(picture);
+                    }
+                    picture = Some((CgmPicture {
+                        name: picture_name,
+                        vdc_extent: None,
+                        background: None,
+                        primitives: Vec::new()
+                    }, Attributes::default()));
+                },
+                (0, 5) => if let Some((picture, _)) = picture.take() {
+                    pictures.push(picture);
+                },
+
+                // Metafile descriptor and control
+                (1, 3) => require(parameters.enumerated()?, 0, "real VDC are not in the NITF CGM profile")?,
+                (1, 4) => precisions.integer = precision(parameters.signed(precisions.integer)?)?,
+                (1, 6) => precisions.index = precision(parameters.signed(precisions.integer)?)?,
+                (1, 7) => require(parameters.signed(precisions.integer)?, 8, "only eight bit color components are in the NITF CGM profile")?,
+                (3, 1) => precisions.vdc = precision(parameters.signed(precisions.integer)?)?,
+
+                (class, id) => {
+                    let (picture, attributes) = match picture.as_mut() {
+                        Some(picture) => picture,
+                        None => return Ok(())
+                    };
+                    let primitives = &mut picture.primitives;
+                    match (class, id) {
+                        // Picture descriptor
+                        (2, 2) => require(parameters.enumerated()?, 1, "indexed color is not in the NITF CGM profile")?,
+                        (2, 3) | (2, 5) => require(parameters.enumerated()?, 0, "scaled widths are not in the NITF CGM profile")?,
+                        (2, 6) => picture.vdc_extent = Some((parameters.point(&precisions)?, parameters.point(&precisions)?)),
+                        (2, 7) => picture.background = Some(parameters.color()?),
+
+                        // Graphical primitives
+                        (4, 1) => primitives.push(GraphicPrimitive::Polyline {
+                            points: parameters.points(&precisions)?,
+                            line: attributes.line
+                        }),
+                        (4, 4) => {
+                            let position = parameters.point(&precisions)?;
+                            let _final = parameters.enumerated()?;
+                            primitives.push(GraphicPrimitive::Text {
+                                position,
+                                text: parameters.string()?,
+                                color: attributes.text_color,
+                                height: attributes.character_height,
+                                font: attributes.font
+                            });
+                        },
+                        (4, 6) => {
+                            let _final = parameters.enumerated()?;
+                            let appended = parameters.string()?;
+                            match primitives.last_mut() {
+                                Some(GraphicPrimitive::Text { text, .. }) => text.push_str(&appended),
+                                _ => return Err("APPEND TEXT without TEXT".to_string())
+                            }
+                        },
+                        (4, 7) => primitives.push(GraphicPrimitive::Polygon {
+                            points: parameters.points(&precisions)?,
+                            fill: attributes.fill()?,
+                            edge: attributes.edge()
+                        }),
+                        (4, 11) => {
+                            let ((x1, y1), (x2, y2)) = (parameters.point(&precisions)?, parameters.point(&precisions)?);
+                            primitives.push(GraphicPrimitive::Polygon {
+                                points: vec![(x1, y1), (x2, y1), (x2, y2), (x1, y2)],
+                                fill: attributes.fill()?,
+                                edge: attributes.edge()
+                            });
+                        },
+                        (4, 12) => {
+                            let (x, y) = parameters.point(&precisions)?;
+                            let radius = parameters.signed(precisions.vdc)?;
+                            primitives.push(GraphicPrimitive::Ellipse {
+                                center: (x, y),
+                                first_diameter: (x + radius, y),
+                                second_diameter: (x, y + radius),
+                                fill: attributes.fill()?,
+                                edge: attributes.edge()
+                            });
+                        },
+                        (4, 15) | (4, 16) => {
+                            let (x, y) = parameters.point(&precisions)?;
+                            let start = parameters.point(&precisions)?;
+                            let end = parameters.point(&precisions)?;
+                            let radius = parameters.signed(precisions.vdc)?;
+                            let (first_diameter, second_diameter) = ((x + radius, y), (x, y + radius));
+                            primitives.push(match id {
+                                15 => GraphicPrimitive::Arc { center: (x, y), first_diameter, second_diameter, start, end, line: attributes.line },
+                                _ => GraphicPrimitive::ClosedArc {
+                                    center: (x, y), first_diameter, second_diameter, start, end,
+                                    closure: arc_closure(parameters.enumerated()?)?,
+                                    fill: attributes.fill()?,
+                                    edge: attributes.edge()
+                                }
+                            });
+                        },
+                        (4, 17) => primitives.push(GraphicPrimitive::Ellipse {
+                            center: parameters.point(&precisions)?,
+                            first_diameter: parameters.point(&precisions)?,
+                            second_diameter: parameters.point(&precisions)?,
+                            fill: attributes.fill()?,
+                            edge: attributes.edge()
+                        }),
+                        (4, 18) | (4, 19) => {
+                            let center = parameters.point(&precisions)?;
+                            let first_diameter = parameters.point(&precisions)?;
+                            let second_diameter = parameters.point(&precisions)?;
+                            let start = parameters.point(&precisions)?;
+                            let end = parameters.point(&precisions)?;
+                            primitives.push(match id {
+                                18 => GraphicPrimitive::Arc { center, first_diameter, second_diameter, start, end, line: attributes.line },
+                                _ => GraphicPrimitive::ClosedArc {
+                                    center, first_diameter, second_diameter, start, end,
+                                    closure: arc_closure(parameters.enumerated()?)?,
+                                    fill: attributes.fill()?,
+                                    edge: attributes.edge()
+                                }
+                            });
+                        },
+
+                        // Attributes
+                        (5, 2) => attributes.line.line_type = line_type(parameters.signed(precisions.index)?)?,
+                        (5, 3) => attributes.line.width = parameters.signed(precisions.vdc)?,
+                        (5, 4) => attributes.line.color = parameters.color()?,
+                        (5, 10) => attributes.font = parameters.signed(precisions.index)?,
+                        (5, 14) => attributes.text_color = parameters.color()?,
+                        (5, 15) => attributes.character_height = parameters.signed(precisions.vdc)?,
+                        (5, 22) => attributes.interior_style = parameters.enumerated()?,
+                        (5, 23) => attributes.fill_color = parameters.color()?,
+                        (5, 24) => attributes.hatch_index = parameters.signed(precisions.index)?,
+                        (5, 27) => attributes.edge.line_type = line_type(parameters.signed(precisions.index)?)?,
+                        (5, 28) => attributes.edge.width = parameters.signed(precisions.vdc)?,
+                        (5, 29) => attributes.edge.color = parameters.color()?,
+                        (5, 30) => attributes.edge_visible = parameters.enumerated()? == 1,
+
+                        // Other elements do not change the primitives
+                        _ => {}
+                    }
+                }
+            }
+            Ok(())
+        })();
+        result.map_err(|message| format!("element {}/{} at byte {}: {}", class, id, offset, message))?;
+    }
+
+    if let Some((picture, _)) = picture.take() {
+        pictures.push(picture);
+    }
+    Ok(pictures)
+}
+
+
+/// Returns an error with `message` unless `value` is the one the NITF CGM profile allows.
+fn require(value: i32, allowed: i32, message: &str) -> Result<(), String> {
+    match value == allowed {
+        true => Ok(()),
+        false => Err(message.to_string())
+    }
+}
+
+
+fn line_type(code: i32) -> Result<LineType, String> {
+    LineType::from_code(code).ok_or_else(|| format!("line type {} is not in the NITF CGM profile", code))
+}
+
+
+fn arc_closure(code: i32) -> Result<ArcClosure, String> {
+    match code {
+        0 => Ok(ArcClosure::Pie),
+        1 => Ok(ArcClosure::Chord),
+        _ => Err(format!("close type {} is not pie or chord", code))
+    }
+}
+
+
+impl NITF {
+
+    /// Returns the pictures of the CGM held by the graphic segment at `index`, decoded into
+    /// vector primitives.
+    pub fn read_graphic(&self, index: usize) -> Result<Vec<CgmPicture>, NitfError> {
+        let segment = self.graphic_segments().get(index).ok_or_else(|| NitfError::InvalidRequest {
+            message: format!("there is no graphic segment {}", index)
+        })?;
+        let data = self.segment_data(segment)?;
+        decode(&data).map_err(|message| NitfError::Decode {
+            compression: "CGM".to_string(),
+            offset: segment.data_offset(),
+            message
+        })
+    }
+}
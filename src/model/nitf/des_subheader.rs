@@ -0,0 +1,257 @@
+//! NITF data extension segment (DES) subheader
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use super::error::NitfError;
+use super::field::{FieldReader, FieldWriter};
+use super::file_header::NitfVersion;
+use super::insert_optional;
+use super::security::SecurityFields;
+use super::tre::TreLocation;
+
+/// DESID of the segments holding extensions that overflow a header area
+pub(crate) const TRE_OVERFLOW: &str = "TRE_OVERFLOW";
+
+/// DESID of the segments holding an XML document
+pub(crate) const XML_DATA_CONTENT: &str = "XML_DATA_CONTENT";
+
+
+/// Returns the header area named by an Overflowed Header Type (DESOFLW) code.
+fn overflow_location(code: &str) -> Option<TreLocation> {
+    match code {
+        "UDHD" => Some(TreLocation::FileUserDefined),
+        "XHD" => Some(TreLocation::FileExtended),
+        "UDID" => Some(TreLocation::ImageUserDefined),
+        "IXSHD" => Some(TreLocation::ImageExtended),
+        "SXSHD" => Some(TreLocation::GraphicExtended),
+        "LXSHD" => Some(TreLocation::LabelExtended),
+        "TXSHD" => Some(TreLocation::TextExtended),
+        _ => None
+    }
+}
+
+
+/// Returns the Overflowed Header Type (DESOFLW) code of a header area.
+fn overflow_code(location: TreLocation) -> &'static str {
+    match location {
+        TreLocation::FileUserDefined => "UDHD",
+        TreLocation::FileExtended => "XHD",
+        TreLocation::ImageUserDefined => "UDID",
+        TreLocation::ImageExtended => "IXSHD",
+        TreLocation::GraphicExtended => "SXSHD",
+        TreLocation::LabelExtended => "LXSHD",
+        TreLocation::TextExtended => "TXSHD",
+        TreLocation::Overflow => ""
+    }
+}
+
+
+/// User defined subheader fields of an XML_DATA_CONTENT data extension segment. DESSHL
+/// holds 0, 5, 283 or 773 bytes of them, and the fields left out are blank.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct XmlDataContent {
+    /// Cyclic Redundancy Check of the XML (DESCRC), None when not given as 99999
+    pub crc: Option<u16>,
+    /// XML File Type (DESSHFT)
+    pub file_type: String,
+    /// Date and Time as CCYY-MM-DDThh:mm:ssZ (DESSHDT)
+    pub date_time: String,
+    /// Responsible Party (DESSHRP)
+    pub responsible_party: String,
+    /// Specification Identifier (DESSHSI)
+    pub specification_id: String,
+    /// Specification Version (DESSHSV)
+    pub specification_version: String,
+    /// Specification Date (DESSHSD)
+    pub specification_date: String,
+    /// Target Namespace (DESSHTN)
+    pub target_namespace: String,
+    /// Location Polygon (DESSHLPG)
+    pub location_polygon: String,
+    /// Location Point (DESSHLPT)
+    pub location_point: String,
+    /// Location Identifier (DESSHLI)
+    pub location_id: String,
+    /// Location Identifier Namespace URI (DESSHLIN)
+    pub location_id_namespace: String,
+    /// Abstract (DESSHABS)
+    pub description: String
+}
+
+impl XmlDataContent {
+
+    /// Parses the user defined subheader fields, which fill the reader's data.
+    fn parse(reader: &mut FieldReader, length_offset: u64) -> Result<XmlDataContent, NitfError> {
+        let length = reader.remaining();
+        if ![0, 5, 283, 773].contains(&length) {
+            return Err(NitfError::InvalidValue { field: "DESSHL".to_string(), offset: length_offset, value: length.to_string() });
+        }
+        let mut fields = XmlDataContent::default();
+        if length >= 5 {
+            let offset = reader.position();
+            fields.crc = match reader.number::<u32>("DESCRC", 5)? {
+                99999 => None,
+                crc => Some(u16::try_from(crc).map_err(|_| NitfError::InvalidValue {
+                    field: "DESCRC".to_string(), offset, value: crc.to_string()
+                })?)
+            };
+        }
+        if length >= 283 {
+            fields.file_type = reader.trimmed("DESSHFT", 8)?;
+            fields.date_time = reader.trimmed("DESSHDT", 20)?;
+            fields.responsible_party = reader.trimmed("DESSHRP", 40)?;
+            fields.specification_id = reader.trimmed("DESSHSI", 60)?;
+            fields.specification_version = reader.trimmed("DESSHSV", 10)?;
+            fields.specification_date = reader.trimmed("DESSHSD", 20)?;
+            fields.target_namespace = reader.trimmed("DESSHTN", 120)?;
+        }
+        if length >= 773 {
+            fields.location_polygon = reader.trimmed("DESSHLPG", 125)?;
+            fields.location_point = reader.trimmed("DESSHLPT", 25)?;
+            fields.location_id = reader.trimmed("DESSHLI", 20)?;
+            fields.location_id_namespace = reader.trimmed("DESSHLIN", 120)?;
+            fields.description = reader.trimmed("DESSHABS", 200)?;
+        }
+        Ok(fields)
+    }
+
+    /// Inserts the fields present into a string map view.
+    fn insert_into(&self, header: &mut BTreeMap<String,String>) {
+        header.insert("DESCRC".to_string(), self.crc.map(|crc| format!("{:05}", crc)).unwrap_or_else(|| "99999".to_string()));
+        insert_optional(header, "DESSHFT", self.file_type.clone());
+        insert_optional(header, "DESSHDT", self.date_time.clone());
+        insert_optional(header, "DESSHRP", self.responsible_party.clone());
+        insert_optional(header, "DESSHSI", self.specification_id.clone());
+        insert_optional(header, "DESSHSV", self.specification_version.clone());
+        insert_optional(header, "DESSHSD", self.specification_date.clone());
+        insert_optional(header, "DESSHTN", self.target_namespace.clone());
+        insert_optional(header, "DESSHLPG", self.location_polygon.clone());
+        insert_optional(header, "DESSHLPT", self.location_point.clone());
+        insert_optional(header, "DESSHLI", self.location_id.clone());
+        insert_optional(header, "DESSHLIN", self.location_id_namespace.clone());
+        insert_optional(header, "DESSHABS", self.description.clone());
+    }
+}
+
+
+/// NITF data extension segment subheader
+#[derive(Clone, Debug, PartialEq)]
+pub struct DesSubheader {
+    /// Unique DES Type Identifier (DESID), or DESTAG in NITF 2.0
+    pub des_id: String,
+    /// Version of the Data Definition (DESVER)
+    pub version: u8,
+    /// DES Security Fields (DESCLAS through DESCTLN)
+    pub security: SecurityFields,
+    /// Header area the extensions overflow from (DESOFLW) and the one based number of its
+    /// segment, or 0 for the file header (DESITEM). Only TRE_OVERFLOW segments, and the
+    /// Registered and Controlled Extensions segments of NITF 2.0, have them.
+    pub overflow: Option<(TreLocation, u16)>,
+    /// DES User-defined Subheader Fields (DESSHF), DESSHL bytes long
+    pub user_defined: Vec<u8>,
+    /// User defined subheader fields decoded from `user_defined` when the DESID is
+    /// XML_DATA_CONTENT
+    pub xml_data_content: Option<XmlDataContent>
+}
+
+
+impl Default for DesSubheader {
+    /// Returns the subheader of an unclassified version 1 data extension without user defined
+    /// subheader fields.
+    fn default() -> DesSubheader {
+        DesSubheader {
+            des_id: String::new(),
+            version: 1,
+            security: SecurityFields::default(),
+            overflow: None,
+            user_defined: Vec::new(),
+            xml_data_content: None
+        }
+    }
+}
+
+
+impl DesSubheader {
+
+    /// Parses the data extension segment subheader at the reader's position.
+    pub(crate) fn parse(reader: &mut FieldReader, version: NitfVersion) -> Result<DesSubheader, NitfError> {
+
+        reader.literal("DE", "DE")?;
+        let nitf20 = version == NitfVersion::Nitf20;
+        let des_id = reader.trimmed(if nitf20 { "DESTAG" } else { "DESID" }, 25)?;
+        let des_version = reader.number("DESVER", 2)?;
+        let security = SecurityFields::parse(reader, "DE", version)?;
+        let overflows = match nitf20 {
+            true => des_id == "Registered Extensions" || des_id == "Controlled Extensions",
+            false => des_id == TRE_OVERFLOW
+        };
+        let overflow = match overflows {
+            true => Some((reader.code("DESOFLW", 6, overflow_location)?, reader.number("DESITEM", 3)?)),
+            false => None
+        };
+        let length_offset = reader.position();
+        let user_defined_length = reader.number("DESSHL", 4)?;
+        let offset = reader.position();
+        let user_defined = reader.bytes("DESSHF", user_defined_length)?.to_vec();
+        let xml_data_content = match des_id == XML_DATA_CONTENT {
+            true => Some(XmlDataContent::parse(&mut FieldReader::new(&user_defined, offset), length_offset)?),
+            false => None
+        };
+
+        Ok(DesSubheader {
+            des_id,
+            version: des_version,
+            security,
+            overflow,
+            user_defined,
+            xml_data_content
+        })
+    }
+
+    /// Writes the data extension segment subheader, with the user defined fields as they are
+    /// held in `user_defined`.
+    pub(crate) fn write(&self, writer: &mut FieldWriter) -> Result<(), NitfError> {
+
+        writer.string("DE", "DE", 2)?;
+        writer.string("DESID", &self.des_id, 25)?;
+        writer.number("DESVER", self.version, 2)?;
+        self.security.write(writer, "DE")?;
+        match (self.des_id == TRE_OVERFLOW, self.overflow) {
+            (true, Some((location, item))) => {
+                writer.string("DESOFLW", overflow_code(location), 6)?;
+                writer.number("DESITEM", item, 3)?;
+            },
+            (false, None) => {},
+            _ => return Err(NitfError::InvalidRequest {
+                message: format!("DES {} needs DESOFLW and DESITEM only when it is {}", self.des_id, TRE_OVERFLOW)
+            })
+        }
+        writer.number("DESSHL", self.user_defined.len(), 4)?;
+        writer.bytes(&self.user_defined);
+        Ok(())
+    }
+
+    /// Returns the data extension segment subheader as a map of field names to display strings.
+    pub fn to_map(&self) -> BTreeMap<String,String> {
+
+        let mut data_ext_seg_subheader = BTreeMap::new();
+
+        data_ext_seg_subheader.insert("DE".to_string(), "DE".to_string());
+        data_ext_seg_subheader.insert("DESID".to_string(), self.des_id.clone());
+        data_ext_seg_subheader.insert("DESVER".to_string(), format!("{:02}", self.version));
+        self.security.insert_into(&mut data_ext_seg_subheader, "DE");
+        if let Some((location, item)) = self.overflow {
+            data_ext_seg_subheader.insert("DESOFLW".to_string(), overflow_code(location).to_string());
+            data_ext_seg_subheader.insert("DESITEM".to_string(), format!("{:03}", item));
+        }
+        data_ext_seg_subheader.insert("DESSHL".to_string(), format!("{:04}", self.user_defined.len()));
+        match &self.xml_data_content {
+            Some(fields) if !self.user_defined.is_empty() => fields.insert_into(&mut data_ext_seg_subheader),
+            Some(_) => {},
+            None => insert_optional(&mut data_ext_seg_subheader, "DESSHF", String::from_utf8_lossy(&self.user_defined).trim().to_string())
+        }
+
+        data_ext_seg_subheader
+    }
+}
@@ -0,0 +1,466 @@
+//! JPEG 2000 code-block decoding (ITU-T T.800 Annexes C and D): the MQ arithmetic decoder and
+//! the significance propagation, magnitude refinement and cleanup coding passes
+
+/// Selective arithmetic coding bypass code-block style flag
+pub(crate) const BYPASS: u8 = 0x01;
+/// Reset of context probabilities on coding pass boundaries
+pub(crate) const RESET: u8 = 0x02;
+/// Termination on each coding pass
+pub(crate) const TERMINATE_ALL: u8 = 0x04;
+/// Vertically causal context formation
+pub(crate) const VERTICALLY_CAUSAL: u8 = 0x08;
+/// Segmentation symbols after each cleanup pass
+pub(crate) const SEGMENTATION_SYMBOLS: u8 = 0x20;
+
+/// Probability estimate (Qe) of each MQ state
+const QE: [u32; 47] = [
+    0x5601, 0x3401, 0x1801, 0x0AC1, 0x0521, 0x0221, 0x5601, 0x5401, 0x4801, 0x3801, 0x3001, 0x2401,
+    0x1C01, 0x1601, 0x5601, 0x5401, 0x5101, 0x4801, 0x3801, 0x3401, 0x3001, 0x2801, 0x2401, 0x2201,
+    0x1C01, 0x1801, 0x1601, 0x1401, 0x1201, 0x1101, 0x0AC1, 0x09C1, 0x08A1, 0x0521, 0x0441, 0x02A1,
+    0x0221, 0x0141, 0x0111, 0x0085, 0x0049, 0x0025, 0x0015, 0x0009, 0x0005, 0x0001, 0x5601
+];
+/// Next state after a more probable symbol
+const NEXT_MPS: [u8; 47] = [
+    1, 2, 3, 4, 5, 38, 7, 8, 9, 10, 11, 12, 13, 29, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24,
+    25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 45, 46
+];
+/// Next state after a less probable symbol
+const NEXT_LPS: [u8; 47] = [
+    1, 6, 9, 12, 29, 33, 6, 14, 14, 14, 17, 18, 20, 21, 14, 14, 15, 16, 17, 18, 19, 19, 20, 21,
+    22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 46
+];
+/// Whether a less probable symbol exchanges the sense of the more probable symbol
+const SWITCH: [bool; 47] = [
+    true, false, false, false, false, false, true, false, false, false, false, false,
+    false, false, true, false, false, false, false, false, false, false, false, false,
+    false, false, false, false, false, false, false, false, false, false, false, false,
+    false, false, false, false, false, false, false, false, false, false, false
+];
+
+/// Number of contexts: 9 significance, 5 sign, 3 refinement, run-length and uniform
+const CONTEXTS: usize = 19;
+const FIRST_SIGN_CONTEXT: usize = 9;
+const FIRST_REFINEMENT_CONTEXT: usize = 14;
+const RUN_LENGTH_CONTEXT: usize = 17;
+const UNIFORM_CONTEXT: usize = 18;
+
+/// Coefficient state flags
+const SIGNIFICANT: u8 = 0x01;
+const NEGATIVE: u8 = 0x02;
+const VISITED: u8 = 0x04;
+const REFINED: u8 = 0x08;
+
+
+/// Subband orientation, which selects the significance contexts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Orientation {
+    LowLow,
+    HighLow,
+    LowHigh,
+    HighHigh
+}
+
+
+/// MQ arithmetic decoder over one codeword segment
+struct MqDecoder<'a> {
+    data: &'a [u8],
+    position: usize,
+    c: u32,
+    a: u32,
+    count: u32,
+    states: [(u8, u8); CONTEXTS]
+}
+
+impl<'a> MqDecoder<'a> {
+    fn new(data: &'a [u8], states: [(u8, u8); CONTEXTS]) -> MqDecoder<'a> {
+        let mut decoder = MqDecoder {
+            data,
+            position: 0,
+            c: 0,
+            a: 0x8000,
+            count: 0,
+            states
+        };
+        decoder.c = u32::from(decoder.byte(0)) << 16;
+        decoder.byte_in();
+        decoder.c <<= 7;
+        decoder.count -= 7;
+        decoder
+    }
+
+    /// Returns the byte at `position`, reading past the end of the segment as 0xFF.
+    fn byte(&self, position: usize) -> u8 {
+        self.data.get(position).copied().unwrap_or(0xFF)
+    }
+
+    fn byte_in(&mut self) {
+        if self.byte(self.position) == 0xFF {
+            let next = self.byte(self.position + 1);
+            if next > 0x8F {
+                self.c += 0xFF00;
+                self.count = 8;
+            } else {
+                self.position += 1;
+                self.c += u32::from(next) << 9;
+                self.count = 7;
+            }
+        } else {
+            self.position += 1;
+            self.c += u32::from(self.byte(self.position)) << 8;
+            self.count = 8;
+        }
+    }
+
+    fn decode(&mut self, context: usize) -> u8 {
+        let (state, mps) = self.states[context];
+        let qe = QE[usize::from(state)];
+        self.a -= qe;
+        let symbol;
+        if (self.c >> 16) < qe {
+            // Less probable symbol interval, or a conditional exchange
+            if self.a < qe {
+                symbol = mps;
+                self.states[context].0 = NEXT_MPS[usize::from(state)];
+            } else {
+                symbol = 1 - mps;
+                self.states[context] = (NEXT_LPS[usize::from(state)], if SWITCH[usize::from(state)] { 1 - mps } else { mps });
+            }
+            self.a = qe;
+        } else {
+            self.c -= qe << 16;
+            if self.a & 0x8000 != 0 {
+                return mps;
+            }
+            if self.a < qe {
+                symbol = 1 - mps;
+                self.states[context] = (NEXT_LPS[usize::from(state)], if SWITCH[usize::from(state)] { 1 - mps } else { mps });
+            } else {
+                symbol = mps;
+                self.states[context].0 = NEXT_MPS[usize::from(state)];
+            }
+        }
+        while self.a & 0x8000 == 0 {
+            if self.count == 0 {
+                self.byte_in();
+            }
+            self.a <<= 1;
+            self.c <<= 1;
+            self.count -= 1;
+        }
+        symbol
+    }
+}
+
+
+/// Decoder of raw (bypassed) coding passes
+struct RawDecoder<'a> {
+    data: &'a [u8],
+    position: usize,
+    byte: u8,
+    count: u32
+}
+
+impl<'a> RawDecoder<'a> {
+    fn new(data: &'a [u8]) -> RawDecoder<'a> {
+        RawDecoder {
+            data,
+            position: 0,
+            byte: 0,
+            count: 0
+        }
+    }
+
+    fn decode(&mut self) -> u8 {
+        if self.count == 0 {
+            // A byte following 0xFF carries a stuffed zero in its most significant bit
+            self.count = if self.byte == 0xFF { 7 } else { 8 };
+            self.byte = self.data.get(self.position).copied().unwrap_or(0xFF);
+            self.position += 1;
+        }
+        self.count -= 1;
+        (self.byte >> self.count) & 1
+    }
+}
+
+
+/// Source of the decisions of one coding pass
+enum Decisions<'a> {
+    Arithmetic(MqDecoder<'a>),
+    Raw(RawDecoder<'a>)
+}
+
+impl<'a> Decisions<'a> {
+    fn decode(&mut self, context: usize) -> u8 {
+        match self {
+            Decisions::Arithmetic(decoder) => decoder.decode(context),
+            Decisions::Raw(decoder) => decoder.decode()
+        }
+    }
+
+    fn states(&self) -> Option<[(u8, u8); CONTEXTS]> {
+        match self {
+            Decisions::Arithmetic(decoder) => Some(decoder.states),
+            Decisions::Raw(_) => None
+        }
+    }
+}
+
+
+/// Initial state and more probable symbol of every context
+fn initial_states() -> [(u8, u8); CONTEXTS] {
+    let mut states = [(0, 0); CONTEXTS];
+    states[0] = (4, 0);
+    states[RUN_LENGTH_CONTEXT] = (3, 0);
+    states[UNIFORM_CONTEXT] = (46, 0);
+    states
+}
+
+
+/// Codeword segment of a code-block: its bytes and the number of coding passes it holds
+pub(crate) struct CodewordSegment {
+    pub(crate) data: Vec<u8>,
+    pub(crate) passes: u32
+}
+
+
+/// Code-block being decoded, with a one coefficient border of flags around it
+struct Block {
+    width: usize,
+    height: usize,
+    orientation: Orientation,
+    vertically_causal: bool,
+    flags: Vec<u8>,
+    magnitudes: Vec<i32>
+}
+
+impl Block {
+    fn flag(&self, x: usize, y: usize) -> u8 {
+        self.flags[(y + 1) * (self.width + 2) + x + 1]
+    }
+
+    fn flag_mut(&mut self, x: usize, y: usize) -> &mut u8 {
+        &mut self.flags[(y + 1) * (self.width + 2) + x + 1]
+    }
+
+    /// Returns the flags of the neighbour at an offset, ignoring the stripe below the current
+    /// one in vertically causal mode.
+    fn neighbour(&self, x: usize, y: usize, dx: isize, dy: isize) -> u8 {
+        if dy > 0 && self.vertically_causal && y % 4 == 3 {
+            return 0;
+        }
+        self.flags[((y + 1) as isize + dy) as usize * (self.width + 2) + ((x + 1) as isize + dx) as usize]
+    }
+
+    /// Returns the number of significant horizontal, vertical and diagonal neighbours.
+    fn neighbourhood(&self, x: usize, y: usize) -> (u32, u32, u32) {
+        let significant = |dx, dy| u32::from(self.neighbour(x, y, dx, dy) & SIGNIFICANT);
+        (significant(-1, 0) + significant(1, 0),
+         significant(0, -1) + significant(0, 1),
+         significant(-1, -1) + significant(1, -1) + significant(-1, 1) + significant(1, 1))
+    }
+
+    fn significance_context(&self, x: usize, y: usize) -> usize {
+        let (horizontal, vertical, diagonal) = self.neighbourhood(x, y);
+        let (horizontal, vertical) = match self.orientation {
+            Orientation::HighLow => (vertical, horizontal),
+            _ => (horizontal, vertical)
+        };
+        match self.orientation {
+            Orientation::HighHigh => match (diagonal, horizontal + vertical) {
+                (3.., _) => 8,
+                (2, 1..) => 7,
+                (2, _) => 6,
+                (1, 2..) => 5,
+                (1, 1) => 4,
+                (1, _) => 3,
+                (_, 2..) => 2,
+                (_, 1) => 1,
+                _ => 0
+            },
+            _ => match (horizontal, vertical, diagonal) {
+                (2, _, _) => 8,
+                (1, 1.., _) => 7,
+                (1, 0, 1..) => 6,
+                (1, 0, 0) => 5,
+                (0, 2, _) => 4,
+                (0, 1, _) => 3,
+                (0, 0, 2..) => 2,
+                (0, 0, 1) => 1,
+                _ => 0
+            }
+        }
+    }
+
+    fn decode_sign(&mut self, decisions: &mut Decisions, x: usize, y: usize) {
+        let contribution = |first: u8, second: u8| -> i32 {
+            let sign = |flags: u8| match (flags & SIGNIFICANT != 0, flags & NEGATIVE != 0) {
+                (true, false) => 1,
+                (true, true) => -1,
+                _ => 0
+            };
+            (sign(first) + sign(second)).clamp(-1, 1)
+        };
+        let horizontal = contribution(self.neighbour(x, y, -1, 0), self.neighbour(x, y, 1, 0));
+        let vertical = contribution(self.neighbour(x, y, 0, -1), self.neighbour(x, y, 0, 1));
+        let (context, flip) = match (horizontal, vertical) {
+            (1, 1) => (4, 0),
+            (1, 0) => (3, 0),
+            (1, _) => (2, 0),
+            (0, 1) => (1, 0),
+            (0, 0) => (0, 0),
+            (0, _) => (1, 1),
+            (_, 1) => (2, 1),
+            (_, 0) => (3, 1),
+            _ => (4, 1)
+        };
+        // Raw passes code the sign directly, without the context's prediction
+        let negative = match decisions {
+            Decisions::Raw(decoder) => decoder.decode(),
+            Decisions::Arithmetic(decoder) => decoder.decode(FIRST_SIGN_CONTEXT + context) ^ flip
+        };
+        *self.flag_mut(x, y) |= SIGNIFICANT | if negative == 1 { NEGATIVE } else { 0 };
+    }
+
+    fn significance_pass(&mut self, decisions: &mut Decisions, plane: u32) {
+        for stripe in (0..self.height).step_by(4) {
+            for x in 0..self.width {
+                for y in stripe..(stripe + 4).min(self.height) {
+                    let (horizontal, vertical, diagonal) = self.neighbourhood(x, y);
+                    if self.flag(x, y) & SIGNIFICANT != 0 || horizontal + vertical + diagonal == 0 {
+                        continue;
+                    }
+                    if decisions.decode(self.significance_context(x, y)) == 1 {
+                        self.decode_sign(decisions, x, y);
+                        self.magnitudes[y * self.width + x] |= 1 << plane;
+                    }
+                    *self.flag_mut(x, y) |= VISITED;
+                }
+            }
+        }
+    }
+
+    fn refinement_pass(&mut self, decisions: &mut Decisions, plane: u32) {
+        for stripe in (0..self.height).step_by(4) {
+            for x in 0..self.width {
+                for y in stripe..(stripe + 4).min(self.height) {
+                    let flags = self.flag(x, y);
+                    if flags & SIGNIFICANT == 0 || flags & VISITED != 0 {
+                        continue;
+                    }
+                    let context = if flags & REFINED != 0 {
+                        2
+                    } else {
+                        let (horizontal, vertical, diagonal) = self.neighbourhood(x, y);
+                        if horizontal + vertical + diagonal > 0 { 1 } else { 0 }
+                    };
+                    self.magnitudes[y * self.width + x] |= i32::from(decisions.decode(FIRST_REFINEMENT_CONTEXT + context)) << plane;
+                    *self.flag_mut(x, y) |= REFINED;
+                }
+            }
+        }
+    }
+
+    fn cleanup_pass(&mut self, decisions: &mut Decisions, plane: u32, segmentation_symbols: bool) {
+        for stripe in (0..self.height).step_by(4) {
+            for x in 0..self.width {
+                let mut first = stripe;
+                let end = (stripe + 4).min(self.height);
+
+                // A full column with no significance anywhere near it is coded as a run
+                let run = end - stripe == 4 && (stripe..end).all(|y| {
+                    let (horizontal, vertical, diagonal) = self.neighbourhood(x, y);
+                    self.flag(x, y) & (SIGNIFICANT | VISITED) == 0 && horizontal + vertical + diagonal == 0
+                });
+                if run {
+                    if decisions.decode(RUN_LENGTH_CONTEXT) == 0 {
+                        continue;
+                    }
+                    let position = usize::from(decisions.decode(UNIFORM_CONTEXT)) << 1 | usize::from(decisions.decode(UNIFORM_CONTEXT));
+                    first = stripe + position;
+                    self.decode_sign(decisions, x, first);
+                    self.magnitudes[first * self.width + x] |= 1 << plane;
+                    first += 1;
+                }
+
+                for y in first..end {
+                    if self.flag(x, y) & (SIGNIFICANT | VISITED) != 0 {
+                        continue;
+                    }
+                    if decisions.decode(self.significance_context(x, y)) == 1 {
+                        self.decode_sign(decisions, x, y);
+                        self.magnitudes[y * self.width + x] |= 1 << plane;
+                    }
+                }
+            }
+        }
+        if segmentation_symbols {
+            for _ in 0..4 {
+                decisions.decode(UNIFORM_CONTEXT);
+            }
+        }
+        for flags in self.flags.iter_mut() {
+            *flags &= !VISITED;
+        }
+    }
+}
+
+
+/// Decodes a code-block from its codeword segments. `bitplanes` is the number of magnitude
+/// bit-planes coded, after any missing most significant ones. Returns the signed coefficient
+/// magnitudes and the lowest bit-plane reached.
+pub(crate) fn decode(width: usize, height: usize, orientation: Orientation, style: u8, bitplanes: u32, segments: &[CodewordSegment]) -> (Vec<i32>, u32) {
+    let mut block = Block {
+        width,
+        height,
+        orientation,
+        vertically_causal: style & VERTICALLY_CAUSAL != 0,
+        flags: vec![0; (width + 2) * (height + 2)],
+        magnitudes: vec![0; width * height]
+    };
+    let mut lowest_plane = bitplanes;
+    if bitplanes == 0 {
+        return (block.magnitudes, lowest_plane);
+    }
+    let mut states = initial_states();
+    let mut pass = 0u32;
+
+    for segment in segments.iter() {
+        let mut decisions: Option<Decisions> = None;
+        for _ in 0..segment.passes {
+            if pass >= 3 * bitplanes - 2 {
+                break;
+            }
+            // Passes cycle cleanup, significance, refinement, starting with a cleanup pass
+            let kind = (pass + 2) % 3;
+            let plane = bitplanes - 1 - pass.div_ceil(3);
+            let raw = style & BYPASS != 0 && pass >= 10 && kind != 2;
+            let decisions = decisions.get_or_insert_with(|| if raw {
+                Decisions::Raw(RawDecoder::new(&segment.data))
+            } else {
+                Decisions::Arithmetic(MqDecoder::new(&segment.data, states))
+            });
+            match kind {
+                0 => block.significance_pass(decisions, plane),
+                1 => block.refinement_pass(decisions, plane),
+                _ => block.cleanup_pass(decisions, plane, style & SEGMENTATION_SYMBOLS != 0)
+            }
+            if let Some(current) = decisions.states() {
+                states = current;
+            }
+            if style & RESET != 0 {
+                states = initial_states();
+                if let Decisions::Arithmetic(decoder) = decisions {
+                    decoder.states = states;
+                }
+            }
+            lowest_plane = plane;
+            pass += 1;
+        }
+    }
+
+    let coefficients = block.magnitudes.iter().enumerate().map(|(index, magnitude)| {
+        let (x, y) = (index % width, index / width);
+        if block.flag(x, y) & NEGATIVE != 0 { -magnitude } else { *magnitude }
+    }).collect();
+    (coefficients, lowest_plane)
+}
@@ -0,0 +1,127 @@
+//! Errors produced while reading a NITF
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Error describing why a NITF could not be read. Parse errors record the field name and
+/// the byte offset of the field within the file.
+#[derive(Debug)]
+pub enum NitfError {
+    /// Reading from the underlying file failed.
+    Io(io::Error),
+    /// The field runs past the end of the file.
+    Truncated {
+        field: String,
+        offset: u64
+    },
+    /// The field is not a valid character string.
+    InvalidCharacters {
+        field: String,
+        offset: u64
+    },
+    /// The field should hold a number but does not.
+    NonNumeric {
+        field: String,
+        offset: u64,
+        value: String
+    },
+    /// The field holds a value not allowed by the standard.
+    InvalidValue {
+        field: String,
+        offset: u64,
+        value: String
+    },
+    /// A length recorded in the field disagrees with the length actually found.
+    LengthMismatch {
+        field: String,
+        offset: u64,
+        expected: u64,
+        actual: u64
+    },
+    /// The field holds a valid value that this crate cannot read yet.
+    Unsupported {
+        field: String,
+        value: String
+    },
+    /// A read asked for pixels, bands or data outside the segment.
+    InvalidRequest {
+        message: String
+    },
+    /// A tagged record extension definition could not be loaded.
+    InvalidDefinition {
+        message: String
+    },
+    /// Compressed image data or graphic data starting at the offset could not be decoded.
+    Decode {
+        compression: String,
+        offset: u64,
+        message: String
+    }
+}
+
+impl fmt::Display for NitfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NitfError::Io(error) => write!(f, "{}", error),
+            NitfError::Truncated { field, offset } =>
+                write!(f, "{} at offset {} runs past the end of the file", field, offset),
+            NitfError::InvalidCharacters { field, offset } =>
+                write!(f, "{} at offset {} is not a valid character string", field, offset),
+            NitfError::NonNumeric { field, offset, value } =>
+                write!(f, "{} at offset {} is not numeric: \"{}\"", field, offset, value),
+            NitfError::InvalidValue { field, offset, value } =>
+                write!(f, "{} at offset {} has an invalid value: \"{}\"", field, offset, value),
+            NitfError::LengthMismatch { field, offset, expected, actual } =>
+                write!(f, "{} at offset {} is {} but the actual length is {}", field, offset, expected, actual),
+            NitfError::Unsupported { field, value } =>
+                write!(f, "{} \"{}\" is not supported", field, value),
+            NitfError::InvalidRequest { message } =>
+                write!(f, "invalid request: {}", message),
+            NitfError::InvalidDefinition { message } =>
+                write!(f, "invalid TRE definition: {}", message),
+            NitfError::Decode { compression, offset, message } =>
+                write!(f, "{} data at offset {} could not be decoded: {}", compression, offset, message)
+        }
+    }
+}
+
+impl Error for NitfError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            NitfError::Io(error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+/// Length fields that disagree with the lengths found while reading a file. A strict read
+/// fails on the first mismatch, while a lenient read records each one and carries on.
+pub(crate) struct LengthChecks {
+    lenient: bool,
+    pub(crate) mismatches: Vec<NitfError>
+}
+
+impl LengthChecks {
+
+    pub(crate) fn new(lenient: bool) -> LengthChecks {
+        LengthChecks { lenient, mismatches: Vec::new() }
+    }
+
+    /// Records a length mismatch, or returns it as the error of a strict read.
+    pub(crate) fn record(&mut self, mismatch: NitfError) -> Result<(), NitfError> {
+        match self.lenient {
+            true => {
+                self.mismatches.push(mismatch);
+                Ok(())
+            },
+            false => Err(mismatch)
+        }
+    }
+}
+
+impl From<io::Error> for NitfError {
+    fn from(error: io::Error) -> NitfError {
+        NitfError::Io(error)
+    }
+}
@@ -0,0 +1,278 @@
+//! CCITT T.4 decoding of the bi-level image data held by image segments compressed with
+//! IC = C1 and M1
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// End of line code, 000000000001
+const EOL_LENGTH: u32 = 12;
+
+/// White terminating and make-up codes as (code, length, run)
+const WHITE: [(u16, u8, u16); 91] = [
+    (0x35, 8, 0), (0x07, 6, 1), (0x07, 4, 2), (0x08, 4, 3), (0x0B, 4, 4), (0x0C, 4, 5), (0x0E, 4, 6),
+    (0x0F, 4, 7), (0x13, 5, 8), (0x14, 5, 9), (0x07, 5, 10), (0x08, 5, 11), (0x08, 6, 12), (0x03, 6, 13),
+    (0x34, 6, 14), (0x35, 6, 15), (0x2A, 6, 16), (0x2B, 6, 17), (0x27, 7, 18), (0x0C, 7, 19), (0x08, 7, 20),
+    (0x17, 7, 21), (0x03, 7, 22), (0x04, 7, 23), (0x28, 7, 24), (0x2B, 7, 25), (0x13, 7, 26), (0x24, 7, 27),
+    (0x18, 7, 28), (0x02, 8, 29), (0x03, 8, 30), (0x1A, 8, 31), (0x1B, 8, 32), (0x12, 8, 33), (0x13, 8, 34),
+    (0x14, 8, 35), (0x15, 8, 36), (0x16, 8, 37), (0x17, 8, 38), (0x28, 8, 39), (0x29, 8, 40), (0x2A, 8, 41),
+    (0x2B, 8, 42), (0x2C, 8, 43), (0x2D, 8, 44), (0x04, 8, 45), (0x05, 8, 46), (0x0A, 8, 47), (0x0B, 8, 48),
+    (0x52, 8, 49), (0x53, 8, 50), (0x54, 8, 51), (0x55, 8, 52), (0x24, 8, 53), (0x25, 8, 54), (0x58, 8, 55),
+    (0x59, 8, 56), (0x5A, 8, 57), (0x5B, 8, 58), (0x4A, 8, 59), (0x4B, 8, 60), (0x32, 8, 61), (0x33, 8, 62),
+    (0x34, 8, 63), (0x1B, 5, 64), (0x12, 5, 128), (0x17, 6, 192), (0x37, 7, 256), (0x36, 8, 320),
+    (0x37, 8, 384), (0x64, 8, 448), (0x65, 8, 512), (0x68, 8, 576), (0x67, 8, 640), (0xCC, 9, 704),
+    (0xCD, 9, 768), (0xD2, 9, 832), (0xD3, 9, 896), (0xD4, 9, 960), (0xD5, 9, 1024), (0xD6, 9, 1088),
+    (0xD7, 9, 1152), (0xD8, 9, 1216), (0xD9, 9, 1280), (0xDA, 9, 1344), (0xDB, 9, 1408), (0x98, 9, 1472),
+    (0x99, 9, 1536), (0x9A, 9, 1600), (0x18, 6, 1664), (0x9B, 9, 1728)
+];
+
+/// Black terminating and make-up codes as (code, length, run)
+const BLACK: [(u16, u8, u16); 91] = [
+    (0x37, 10, 0), (0x02, 3, 1), (0x03, 2, 2), (0x02, 2, 3), (0x03, 3, 4), (0x03, 4, 5), (0x02, 4, 6),
+    (0x03, 5, 7), (0x05, 6, 8), (0x04, 6, 9), (0x04, 7, 10), (0x05, 7, 11), (0x07, 7, 12), (0x04, 8, 13),
+    (0x07, 8, 14), (0x18, 9, 15), (0x17, 10, 16), (0x18, 10, 17), (0x08, 10, 18), (0x67, 11, 19),
+    (0x68, 11, 20), (0x6C, 11, 21), (0x37, 11, 22), (0x28, 11, 23), (0x17, 11, 24), (0x18, 11, 25),
+    (0xCA, 12, 26), (0xCB, 12, 27), (0xCC, 12, 28), (0xCD, 12, 29), (0x68, 12, 30), (0x69, 12, 31),
+    (0x6A, 12, 32), (0x6B, 12, 33), (0xD2, 12, 34), (0xD3, 12, 35), (0xD4, 12, 36), (0xD5, 12, 37),
+    (0xD6, 12, 38), (0xD7, 12, 39), (0x6C, 12, 40), (0x6D, 12, 41), (0xDA, 12, 42), (0xDB, 12, 43),
+    (0x54, 12, 44), (0x55, 12, 45), (0x56, 12, 46), (0x57, 12, 47), (0x64, 12, 48), (0x65, 12, 49),
+    (0x52, 12, 50), (0x53, 12, 51), (0x24, 12, 52), (0x37, 12, 53), (0x38, 12, 54), (0x27, 12, 55),
+    (0x28, 12, 56), (0x58, 12, 57), (0x59, 12, 58), (0x2B, 12, 59), (0x2C, 12, 60), (0x5A, 12, 61),
+    (0x66, 12, 62), (0x67, 12, 63), (0x0F, 10, 64), (0xC8, 12, 128), (0xC9, 12, 192), (0x5B, 12, 256),
+    (0x33, 12, 320), (0x34, 12, 384), (0x35, 12, 448), (0x6C, 13, 512), (0x6D, 13, 576), (0x4A, 13, 640),
+    (0x4B, 13, 704), (0x4C, 13, 768), (0x4D, 13, 832), (0x72, 13, 896), (0x73, 13, 960), (0x74, 13, 1024),
+    (0x75, 13, 1088), (0x76, 13, 1152), (0x77, 13, 1216), (0x52, 13, 1280), (0x53, 13, 1344),
+    (0x54, 13, 1408), (0x55, 13, 1472), (0x5A, 13, 1536), (0x5B, 13, 1600), (0x64, 13, 1664),
+    (0x65, 13, 1728)
+];
+
+/// Make-up codes shared by both colours as (code, length, run)
+const EXTENDED: [(u16, u8, u16); 13] = [
+    (0x08, 11, 1792), (0x0C, 11, 1856), (0x0D, 11, 1920), (0x12, 12, 1984), (0x13, 12, 2048),
+    (0x14, 12, 2112), (0x15, 12, 2176), (0x16, 12, 2240), (0x17, 12, 2304), (0x1C, 12, 2368),
+    (0x1D, 12, 2432), (0x1E, 12, 2496), (0x1F, 12, 2560)
+];
+
+
+/// Coding scheme recorded in COMRAT
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Scheme {
+    /// Modified Huffman, every line coded on its own
+    OneDimensional,
+    /// Modified READ, with lines coded against the previous one between one dimensional lines
+    TwoDimensional
+}
+
+
+/// Run length codes of one colour, keyed by (length, code)
+struct RunCodes(HashMap<(u8, u16), u16>);
+
+impl RunCodes {
+    fn new(codes: &[(u16, u8, u16)]) -> RunCodes {
+        RunCodes(codes.iter().chain(EXTENDED.iter()).map(|(code, length, run)| ((*length, *code), *run)).collect())
+    }
+}
+
+fn run_codes(black: bool) -> &'static RunCodes {
+    static WHITE_CODES: OnceLock<RunCodes> = OnceLock::new();
+    static BLACK_CODES: OnceLock<RunCodes> = OnceLock::new();
+    match black {
+        false => WHITE_CODES.get_or_init(|| RunCodes::new(&WHITE)),
+        true => BLACK_CODES.get_or_init(|| RunCodes::new(&BLACK))
+    }
+}
+
+
+/// Reader of bits, most significant first, reading zeros past the end of the data
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize
+}
+
+impl<'a> BitReader<'a> {
+    fn peek(&self, count: u32) -> u16 {
+        (0..count as usize).fold(0, |value, index| {
+            let position = self.position + index;
+            let bit = self.data.get(position / 8).map(|byte| byte >> (7 - position % 8) & 1).unwrap_or(0);
+            value << 1 | u16::from(bit)
+        })
+    }
+
+    fn bit(&mut self) -> u8 {
+        let bit = self.peek(1) as u8;
+        self.position += 1;
+        bit
+    }
+
+    fn exhausted(&self) -> bool {
+        self.position >= self.data.len() * 8
+    }
+
+    /// Consumes an end of line code and any fill bits before it, returning whether there
+    /// was one.
+    fn end_of_line(&mut self) -> bool {
+        let zeros = (self.position..self.data.len() * 8).take_while(|position| self.data[position / 8] >> (7 - position % 8) & 1 == 0).count();
+        if zeros < EOL_LENGTH as usize - 1 || self.position + zeros >= self.data.len() * 8 {
+            return false;
+        }
+        self.position += zeros + 1;
+        true
+    }
+
+    /// Reads one run of the given colour, including any make-up codes before its
+    /// terminating code.
+    fn run(&mut self, black: bool) -> Result<usize, String> {
+        let codes = run_codes(black);
+        let mut total = 0;
+        loop {
+            let run = (2..=13).find_map(|length| {
+                codes.0.get(&(length, self.peek(u32::from(length)))).map(|run| (length, *run))
+            });
+            let (length, run) = run.ok_or_else(|| format!("invalid {} run code at bit {}", if black { "black" } else { "white" }, self.position))?;
+            self.position += usize::from(length);
+            total += usize::from(run);
+            if run < 64 {
+                return Ok(total);
+            }
+        }
+    }
+}
+
+
+/// Returns the first changing element of the reference line after `position` that changes
+/// to the colour opposite `black`, and the changing element after it.
+fn reference_changes(reference: &[usize], position: Option<usize>, black: bool, width: usize) -> (usize, usize) {
+    // Even changing elements start black runs and odd ones start white runs
+    let first = reference.iter().enumerate()
+        .position(|(index, change)| position.is_none_or(|position| *change > position) && (index % 2 == 1) == black);
+    match first {
+        Some(index) => (reference[index], reference.get(index + 1).copied().unwrap_or(width)),
+        None => (width, width)
+    }
+}
+
+
+/// Decodes a line coded on its own, returning its changing elements.
+fn one_dimensional(reader: &mut BitReader, width: usize) -> Result<Vec<usize>, String> {
+    let mut changes = Vec::new();
+    let mut position = 0;
+    let mut black = false;
+    while position < width {
+        position += reader.run(black)?;
+        if position > width {
+            return Err(format!("runs of {} pixels in a {} pixel line", position, width));
+        }
+        changes.push(position);
+        black = !black;
+    }
+    Ok(changes)
+}
+
+
+/// Decodes a line coded against the reference line, returning its changing elements.
+fn two_dimensional(reader: &mut BitReader, reference: &[usize], width: usize) -> Result<Vec<usize>, String> {
+    let mut changes = Vec::new();
+    // The imaginary element before the first pixel
+    let mut position: Option<usize> = None;
+    let mut black = false;
+    while position.is_none_or(|position| position < width) {
+        let start = position.unwrap_or(0);
+        let (b1, b2) = reference_changes(reference, position, black, width);
+        let offset = if reader.peek(1) == 1 {
+            reader.position += 1;
+            Some(0)
+        } else {
+            match reader.peek(3) {
+                0b011 => { reader.position += 3; Some(1) },
+                0b010 => { reader.position += 3; Some(-1) },
+                0b001 => {
+                    // Horizontal mode, two runs coded one dimensionally
+                    reader.position += 3;
+                    let first = start + reader.run(black)?;
+                    let second = first + reader.run(!black)?;
+                    if second > width {
+                        return Err(format!("runs of {} pixels in a {} pixel line", second, width));
+                    }
+                    changes.push(first);
+                    changes.push(second);
+                    position = Some(second);
+                    continue;
+                },
+                _ => match (reader.peek(4), reader.peek(6), reader.peek(7)) {
+                    (0b0001, _, _) => {
+                        // Pass mode, the run continues below the next reference run
+                        reader.position += 4;
+                        position = Some(b2);
+                        continue;
+                    },
+                    (_, 0b000011, _) => { reader.position += 6; Some(2) },
+                    (_, 0b000010, _) => { reader.position += 6; Some(-2) },
+                    (_, _, 0b0000011) => { reader.position += 7; Some(3) },
+                    (_, _, 0b0000010) => { reader.position += 7; Some(-3) },
+                    (_, _, 0b0000001) => return Err(format!("uncompressed mode extension at bit {} is not supported", reader.position)),
+                    _ => None
+                }
+            }
+        };
+        // Vertical mode, the change is within three pixels of the reference line's
+        let change = offset.map(|offset| b1 as isize + offset)
+            .filter(|change| *change >= start as isize && *change <= width as isize && position.is_none_or(|position| *change as usize > position || *change as usize == width))
+            .ok_or_else(|| format!("invalid two dimensional code at bit {}", reader.position))? as usize;
+        changes.push(change);
+        position = Some(change);
+        black = !black;
+    }
+    Ok(changes)
+}
+
+
+/// Decodes `height` lines of `width` pixels, returning 1 for black and 0 for white pixels
+/// row by row, and the number of bytes read including the return to control codes that
+/// follow the last line.
+pub(crate) fn decode(data: &[u8], width: usize, height: usize, scheme: Scheme) -> Result<(Vec<u8>, usize), String> {
+    let mut reader = BitReader {
+        data,
+        position: 0
+    };
+    let mut pixels = vec![0u8; width * height];
+    let mut reference: Vec<usize> = Vec::new();
+
+    for (line, row) in pixels.chunks_mut(width.max(1)).take(height).enumerate() {
+        if reader.exhausted() {
+            return Err(format!("data ends after {} of {} lines", line, height));
+        }
+        // A tag bit after each end of line tells whether the next line is two dimensional
+        let coded_alone = match (reader.end_of_line(), scheme) {
+            (true, Scheme::TwoDimensional) => reader.bit() == 1,
+            (false, Scheme::TwoDimensional) => reference.is_empty(),
+            (_, Scheme::OneDimensional) => true
+        };
+        let changes = match coded_alone {
+            true => one_dimensional(&mut reader, width)?,
+            false => two_dimensional(&mut reader, &reference, width)?
+        };
+        let mut start = 0;
+        for (index, change) in changes.iter().enumerate() {
+            let end = (*change).min(width);
+            if index % 2 == 1 {
+                row[start..end].fill(1);
+            }
+            start = end;
+        }
+        reference = changes;
+    }
+
+    // Return to control, six end of line codes each followed by a tag bit in two
+    // dimensional coding. A single end of line code belongs to the stream after this one.
+    let end = reader.position;
+    let mut count = 0;
+    while count < 6 && reader.end_of_line() {
+        if scheme == Scheme::TwoDimensional {
+            reader.bit();
+        }
+        count += 1;
+    }
+    if count < 2 {
+        reader.position = end;
+    }
+    Ok((pixels, reader.position.div_ceil(8).min(data.len())))
+}
@@ -0,0 +1,321 @@
+//! Field value types and the reader used to pull fixed width fields out of NITF headers
+
+use std::fmt;
+use std::str::FromStr;
+
+use super::error::NitfError;
+use super::tre::{Tre, TreLocation};
+
+/// Date and time field stored as CCYYMMDDhhmmss (e.g. FDT, IDATIM)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}/{:02}/{:02} {:02}:{:02}:{:02}", self.year, self.month, self.day, self.hour, self.minute, self.second)
+    }
+}
+
+/// Date field stored as CCYYMMDD (e.g. FSDCDT, ISSRDT)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}/{:02}/{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// Three byte binary RGB color (e.g. FBKGC)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Color {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{:02X}{:02X}{:02X}", self.red, self.green, self.blue)
+    }
+}
+
+
+/// Sequential reader over the fixed width fields of a NITF header.
+pub(crate) struct FieldReader<'a> {
+    data: &'a [u8],
+    offset: u64,
+    cursor: usize
+}
+
+
+impl<'a> FieldReader<'a> {
+
+    /// Returns a reader over `data`, which was read from `offset` in the file.
+    pub(crate) fn new(data: &'a [u8], offset: u64) -> FieldReader<'a> {
+        FieldReader {
+            data,
+            offset,
+            cursor: 0
+        }
+    }
+
+    /// Returns the file offset of the next field.
+    pub(crate) fn position(&self) -> u64 {
+        self.offset + self.cursor as u64
+    }
+
+    /// Returns the number of bytes left to read.
+    pub(crate) fn remaining(&self) -> usize {
+        self.data.len() - self.cursor
+    }
+
+    /// Returns the next `length` bytes and advances the cursor past them.
+    pub(crate) fn bytes(&mut self, field: &str, length: usize) -> Result<&'a [u8], NitfError> {
+        let bytes = self.data.get(self.cursor..self.cursor+length)
+            .ok_or_else(|| NitfError::Truncated { field: field.to_string(), offset: self.position() })?;
+        self.cursor += length;
+        Ok(bytes)
+    }
+
+    /// Returns the next `length` bytes as a big endian unsigned binary number.
+    pub(crate) fn binary(&mut self, field: &str, length: usize) -> Result<u64, NitfError> {
+        Ok(self.bytes(field, length)?.iter().fold(0, |value, byte| value << 8 | u64::from(*byte)))
+    }
+
+    /// Returns the next `length` bytes as an untrimmed string.
+    pub(crate) fn string(&mut self, field: &str, length: usize) -> Result<String, NitfError> {
+        let offset = self.position();
+        String::from_utf8(self.bytes(field, length)?.to_vec())
+            .map_err(|_| NitfError::InvalidCharacters { field: field.to_string(), offset })
+    }
+
+    /// Reads a field that must hold exactly the `expected` value, such as a file part type.
+    pub(crate) fn literal(&mut self, field: &str, expected: &str) -> Result<(), NitfError> {
+        let offset = self.position();
+        let value = self.string(field, expected.len())?;
+        if value != expected {
+            return Err(NitfError::InvalidValue { field: field.to_string(), offset, value });
+        }
+        Ok(())
+    }
+
+    /// Returns the next `length` bytes as a trimmed string.
+    pub(crate) fn trimmed(&mut self, field: &str, length: usize) -> Result<String, NitfError> {
+        Ok(self.string(field, length)?.trim().to_string())
+    }
+
+    /// Returns the next `length` bytes as a number.
+    pub(crate) fn number<T: FromStr>(&mut self, field: &str, length: usize) -> Result<T, NitfError> {
+        let offset = self.position();
+        let value = self.trimmed(field, length)?;
+        value.parse::<T>().map_err(|_| NitfError::NonNumeric { field: field.to_string(), offset, value })
+    }
+
+    /// Returns a coded value, such as a classification or compression type.
+    pub(crate) fn code<T>(&mut self, field: &str, length: usize, from_code: fn(&str) -> Option<T>) -> Result<T, NitfError> {
+        let offset = self.position();
+        let value = self.trimmed(field, length)?;
+        from_code(&value).ok_or(NitfError::InvalidValue { field: field.to_string(), offset, value })
+    }
+
+    /// Returns a coded value, or None when the field is blank.
+    pub(crate) fn optional_code<T>(&mut self, field: &str, length: usize, from_code: fn(&str) -> Option<T>) -> Result<Option<T>, NitfError> {
+        let offset = self.position();
+        let value = self.trimmed(field, length)?;
+        if value.is_empty() {
+            return Ok(None);
+        }
+        match from_code(&value) {
+            Some(code) => Ok(Some(code)),
+            None => Err(NitfError::InvalidValue { field: field.to_string(), offset, value })
+        }
+    }
+
+    /// Returns a CCYYMMDDhhmmss field.
+    pub(crate) fn date_time(&mut self, field: &str) -> Result<DateTime, NitfError> {
+        let offset = self.position();
+        let mut reader = FieldReader::new(self.bytes(field, 14)?, offset);
+        Ok(DateTime {
+            year: reader.number(field, 4)?,
+            month: reader.number(field, 2)?,
+            day: reader.number(field, 2)?,
+            hour: reader.number(field, 2)?,
+            minute: reader.number(field, 2)?,
+            second: reader.number(field, 2)?
+        })
+    }
+
+    /// Returns a NITF 2.0 DDHHMMSSZMONYY field, such as 04123045ZJAN98. Two digit years
+    /// before 70 are taken to be in the 2000s.
+    pub(crate) fn legacy_date_time(&mut self, field: &str) -> Result<DateTime, NitfError> {
+        const MONTHS: [&str; 12] = ["JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC"];
+        let offset = self.position();
+        let mut reader = FieldReader::new(self.bytes(field, 14)?, offset);
+        let day = reader.number(field, 2)?;
+        let hour = reader.number(field, 2)?;
+        let minute = reader.number(field, 2)?;
+        let second = reader.number(field, 2)?;
+        reader.literal(field, "Z")?;
+        let month = reader.string(field, 3)?;
+        let month = MONTHS.iter().position(|name| name.eq_ignore_ascii_case(&month))
+            .ok_or(NitfError::InvalidValue { field: field.to_string(), offset, value: month })?;
+        let year: u16 = reader.number(field, 2)?;
+        Ok(DateTime {
+            year: if year < 70 { 2000 + year } else { 1900 + year },
+            month: month as u8 + 1,
+            day,
+            hour,
+            minute,
+            second
+        })
+    }
+
+    /// Returns a CCYYMMDD field, or None when the field is blank.
+    pub(crate) fn date(&mut self, field: &str) -> Result<Option<Date>, NitfError> {
+        let offset = self.position();
+        let bytes = self.bytes(field, 8)?;
+        if bytes.iter().all(|byte| *byte == b' ') {
+            return Ok(None);
+        }
+        let mut reader = FieldReader::new(bytes, offset);
+        Ok(Some(Date {
+            year: reader.number(field, 4)?,
+            month: reader.number(field, 2)?,
+            day: reader.number(field, 2)?
+        }))
+    }
+
+    /// Returns a three byte binary color field.
+    pub(crate) fn color(&mut self, field: &str) -> Result<Color, NitfError> {
+        let bytes = self.bytes(field, 3)?;
+        Ok(Color {
+            red: bytes[0],
+            green: bytes[1],
+            blue: bytes[2]
+        })
+    }
+
+    /// Returns the overflow and tagged record extensions of a user defined or extended data
+    /// area, which is a five byte length followed by a three byte overflow and the data when
+    /// the length is non-zero.
+    pub(crate) fn tre_area(&mut self, length: &str, overflow: &str, data: &str, location: TreLocation) -> Result<(u16, Vec<Tre>), NitfError> {
+        let offset = self.position();
+        let data_length: usize = self.number(length, 5)?;
+        if data_length == 0 {
+            return Ok((0, Vec::new()));
+        }
+        if data_length < 3 {
+            return Err(NitfError::InvalidValue { field: length.to_string(), offset, value: data_length.to_string() });
+        }
+        let overflow = self.number(overflow, 3)?;
+        let offset = self.position();
+        let mut reader = FieldReader::new(self.bytes(data, data_length - 3)?, offset);
+        Ok((overflow, Tre::parse_all(&mut reader, location)?))
+    }
+}
+
+
+/// Sequential writer of the fixed width fields of a NITF header.
+pub(crate) struct FieldWriter {
+    data: Vec<u8>,
+    offset: u64,
+    /// Name, file offset and length of each character field written
+    fields: Vec<(String, u64, usize)>
+}
+
+
+impl FieldWriter {
+
+    /// Returns a writer whose output will be placed at `offset` in the file.
+    pub(crate) fn new(offset: u64) -> FieldWriter {
+        FieldWriter {
+            data: Vec::new(),
+            offset,
+            fields: Vec::new()
+        }
+    }
+
+    /// Returns the file offset of the next field.
+    pub(crate) fn position(&self) -> u64 {
+        self.offset + self.data.len() as u64
+    }
+
+    /// Returns the name, file offset and length of each character field written, in order.
+    pub(crate) fn fields(&self) -> &[(String, u64, usize)] {
+        &self.fields
+    }
+
+    /// Returns the bytes written.
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Writes raw bytes.
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    /// Writes a string left justified and padded with spaces to `length` bytes.
+    pub(crate) fn string(&mut self, field: &str, value: &str, length: usize) -> Result<(), NitfError> {
+        if value.len() > length {
+            return Err(NitfError::InvalidValue { field: field.to_string(), offset: self.position(), value: value.to_string() });
+        }
+        self.fields.push((field.to_string(), self.position(), length));
+        self.data.extend_from_slice(value.as_bytes());
+        self.data.resize(self.data.len() + length - value.len(), b' ');
+        Ok(())
+    }
+
+    /// Writes a number right justified and padded with zeros to `length` bytes.
+    pub(crate) fn number<T: fmt::Display>(&mut self, field: &str, value: T, length: usize) -> Result<(), NitfError> {
+        self.string(field, &format!("{:0length$}", value, length = length), length)
+    }
+
+    /// Writes a CCYYMMDDhhmmss field.
+    pub(crate) fn date_time(&mut self, field: &str, value: &DateTime) -> Result<(), NitfError> {
+        let text = format!("{:04}{:02}{:02}{:02}{:02}{:02}", value.year, value.month, value.day, value.hour, value.minute, value.second);
+        self.string(field, &text, 14)
+    }
+
+    /// Writes a CCYYMMDD field, blank when the date is None.
+    pub(crate) fn date(&mut self, field: &str, value: &Option<Date>) -> Result<(), NitfError> {
+        let text = value.map(|value| format!("{:04}{:02}{:02}", value.year, value.month, value.day)).unwrap_or_default();
+        self.string(field, &text, 8)
+    }
+
+    /// Writes a three byte binary color field.
+    pub(crate) fn color(&mut self, value: &Color) {
+        self.data.extend_from_slice(&[value.red, value.green, value.blue]);
+    }
+
+    /// Writes a user defined or extended data area holding the extensions of `tres` found at
+    /// `location`, as a five byte length followed, when there is data, by the three byte
+    /// overflow and the extensions.
+    pub(crate) fn tre_area(&mut self, fields: (&str, &str), overflow: u16, tres: &[Tre], location: TreLocation) -> Result<(), NitfError> {
+        let tres: Vec<&Tre> = tres.iter().filter(|tre| tre.location == location).collect();
+        if tres.is_empty() && overflow == 0 {
+            return self.number(fields.0, 0, 5);
+        }
+        let length: usize = tres.iter().map(|tre| tre.length()).sum();
+        self.number(fields.0, length + 3, 5)?;
+        self.number(fields.1, overflow, 3)?;
+        for tre in tres {
+            self.string("CETAG", &tre.tag, 6)?;
+            self.number("CEL", tre.data.len(), 5)?;
+            self.bytes(&tre.data);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,355 @@
+//! NITF file header
+
+use std::collections::BTreeMap;
+
+use super::error::{LengthChecks, NitfError};
+use super::field::{Color, DateTime, FieldReader, FieldWriter};
+use super::security::SecurityFields;
+use super::insert_optional;
+use super::tre::{self, Tre, TreLocation};
+
+/// File Length (FL) value used when the length of the file is not known.
+pub(crate) const UNKNOWN_FILE_LENGTH: u64 = 999_999_999_999;
+
+/// Version of the standard a file follows, from FHDR and FVER
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NitfVersion {
+    /// NITF 02.00 (MIL-STD-2500A)
+    Nitf20,
+    /// NITF 02.10 (MIL-STD-2500C)
+    Nitf21,
+    /// NSIF 01.00 (STANAG 4545), laid out as NITF 2.1
+    Nsif10
+}
+
+impl NitfVersion {
+    /// Returns the version for the given FHDR and FVER values.
+    pub fn from_code(profile_name: &str, version: &str) -> Option<NitfVersion> {
+        match (profile_name, version) {
+            ("NITF", "02.00") => Some(NitfVersion::Nitf20),
+            ("NITF", "02.10") => Some(NitfVersion::Nitf21),
+            ("NSIF", "01.00") => Some(NitfVersion::Nsif10),
+            _ => None
+        }
+    }
+}
+
+
+/// Subheader and data lengths of one segment as listed in the file header.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SegmentLengths {
+    /// Length of the segment subheader (e.g. LISHn)
+    pub subheader_length: u64,
+    /// Length of the segment data (e.g. LIn)
+    pub data_length: u64
+}
+
+/// NITF file header
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileHeader {
+    /// File Profile Name (FHDR)
+    pub profile_name: String,
+    /// File Version (FVER)
+    pub version: String,
+    /// Complexity Level (CLEVEL)
+    pub complexity_level: u8,
+    /// Standard Type (STYPE)
+    pub standard_type: String,
+    /// Originating Station ID (OSTAID)
+    pub originating_station_id: String,
+    /// File Date and Time (FDT)
+    pub date_time: DateTime,
+    /// File Title (FTITLE)
+    pub title: String,
+    /// File Security Fields (FSCLAS through FSCTLN)
+    pub security: SecurityFields,
+    /// File Copy Number (FSCOP)
+    pub copy_number: u32,
+    /// File Number of Copies (FSCPYS)
+    pub number_of_copies: u32,
+    /// Encryption (ENCRYP)
+    pub encryption: u8,
+    /// File Background Color (FBKGC)
+    pub background_color: Color,
+    /// Originator's Name (ONAME)
+    pub originator_name: String,
+    /// Originator's Phone (OPHONE)
+    pub originator_phone: String,
+    /// File Length (FL)
+    pub file_length: u64,
+    /// NITF File Header Length (HL)
+    pub header_length: u64,
+    /// Image segment lengths (LISHn, LIn)
+    pub image_segments: Vec<SegmentLengths>,
+    /// Graphic segment lengths, or symbol segment lengths in NITF 2.0 (LSSHn, LSn)
+    pub graphic_segments: Vec<SegmentLengths>,
+    /// Reserved for Future Use (NUMX), where NITF 2.0 counts its labels
+    pub reserved_for_future_use: u16,
+    /// Label segment lengths (LLSHn, LLn), NITF 2.0 only
+    pub label_segments: Vec<SegmentLengths>,
+    /// Text segment lengths (LTSHn, LTn)
+    pub text_segments: Vec<SegmentLengths>,
+    /// Data extension segment lengths (LDSHn, LDn)
+    pub data_ext_segments: Vec<SegmentLengths>,
+    /// Reserved extension segment lengths (LRESHn, LREn)
+    pub reserved_ext_segments: Vec<SegmentLengths>,
+    /// User Defined Header Overflow (UDHOFL)
+    pub user_defined_overflow: u16,
+    /// Extended Header Data Overflow (XHDLOFL)
+    pub extended_overflow: u16,
+    /// Tagged record extensions from the UDHD and XHD areas, in file order
+    pub tres: Vec<Tre>
+}
+
+
+impl Default for FileHeader {
+    /// Returns the header of an empty unclassified NITF 2.1 file at complexity level 3.
+    fn default() -> FileHeader {
+        FileHeader {
+            profile_name: "NITF".to_string(),
+            version: "02.10".to_string(),
+            complexity_level: 3,
+            standard_type: "BF01".to_string(),
+            originating_station_id: String::new(),
+            date_time: DateTime::default(),
+            title: String::new(),
+            security: SecurityFields::default(),
+            copy_number: 0,
+            number_of_copies: 0,
+            encryption: 0,
+            background_color: Color::default(),
+            originator_name: String::new(),
+            originator_phone: String::new(),
+            file_length: 0,
+            header_length: 0,
+            image_segments: Vec::new(),
+            graphic_segments: Vec::new(),
+            reserved_for_future_use: 0,
+            label_segments: Vec::new(),
+            text_segments: Vec::new(),
+            data_ext_segments: Vec::new(),
+            reserved_ext_segments: Vec::new(),
+            user_defined_overflow: 0,
+            extended_overflow: 0,
+            tres: Vec::new()
+        }
+    }
+}
+
+
+impl FileHeader {
+
+    /// Parses the file header at the reader's position, checking FL against `file_size` and HL
+    /// against the number of bytes parsed.
+    pub(crate) fn parse(reader: &mut FieldReader, file_size: u64, checks: &mut LengthChecks) -> Result<FileHeader, NitfError> {
+
+        let start = reader.position();
+
+        let profile_name = reader.string("FHDR", 4)?;
+        let version = reader.string("FVER", 5)?;
+        let nitf_version = NitfVersion::from_code(&profile_name, &version)
+            .ok_or_else(|| NitfError::Unsupported { field: "FHDR and FVER".to_string(), value: format!("{}{}", profile_name, version) })?;
+        let nitf20 = nitf_version == NitfVersion::Nitf20;
+        let complexity_level = reader.number("CLEVEL", 2)?;
+        let standard_type = reader.string("STYPE", 4)?;
+        let originating_station_id = reader.trimmed("OSTAID", 10)?;
+        let date_time = match nitf20 {
+            true => reader.legacy_date_time("FDT")?,
+            false => reader.date_time("FDT")?
+        };
+        let title = reader.trimmed("FTITLE", 80)?;
+        let security = SecurityFields::parse(reader, "F", nitf_version)?;
+        let copy_number = reader.number("FSCOP", 5)?;
+        let number_of_copies = reader.number("FSCPYS", 5)?;
+        let encryption = reader.number("ENCRYP", 1)?;
+        // NITF 2.0 has no background color and a longer originator's name
+        let background_color = match nitf20 {
+            true => Color::default(),
+            false => reader.color("FBKGC")?
+        };
+        let originator_name = reader.trimmed("ONAME", if nitf20 { 27 } else { 24 })?;
+        let originator_phone = reader.trimmed("OPHONE", 18)?;
+        let file_length_offset = reader.position();
+        let file_length = reader.number("FL", 12)?;
+        if file_length != file_size && file_length != UNKNOWN_FILE_LENGTH {
+            checks.record(NitfError::LengthMismatch { field: "FL".to_string(), offset: file_length_offset, expected: file_length, actual: file_size })?;
+        }
+        let header_length_offset = reader.position();
+        let header_length = reader.number("HL", 6)?;
+
+        let image_segments = FileHeader::parse_lengths(reader, "NUMI", ("LISH", 6), ("LI", 10))?;
+        let graphic_segments = FileHeader::parse_lengths(reader, "NUMS", ("LSSH", 4), ("LS", 6))?;
+        let (reserved_for_future_use, label_segments) = match nitf20 {
+            true => (0, FileHeader::parse_lengths(reader, "NUML", ("LLSH", 4), ("LL", 3))?),
+            false => (reader.number("NUMX", 3)?, Vec::new())
+        };
+        let text_segments = FileHeader::parse_lengths(reader, "NUMT", ("LTSH", 4), ("LT", 5))?;
+        let data_ext_segments = FileHeader::parse_lengths(reader, "NUMDES", ("LDSH", 4), ("LD", 9))?;
+        let reserved_ext_segments = FileHeader::parse_lengths(reader, "NUMRES", ("LRESH", 4), ("LRE", 7))?;
+
+        let (user_defined_overflow, mut tres) = reader.tre_area("UDHDL", "UDHOFL", "UDHD", TreLocation::FileUserDefined)?;
+        let (extended_overflow, extended_tres) = reader.tre_area("XHDL", "XHDLOFL", "XHD", TreLocation::FileExtended)?;
+        tres.extend(extended_tres);
+
+        let parsed_length = reader.position() - start;
+        if parsed_length != header_length {
+            checks.record(NitfError::LengthMismatch { field: "HL".to_string(), offset: header_length_offset, expected: header_length, actual: parsed_length })?;
+        }
+
+        Ok(FileHeader {
+            profile_name,
+            version,
+            complexity_level,
+            standard_type,
+            originating_station_id,
+            date_time,
+            title,
+            security,
+            copy_number,
+            number_of_copies,
+            encryption,
+            background_color,
+            originator_name,
+            originator_phone,
+            file_length,
+            header_length,
+            image_segments,
+            graphic_segments,
+            reserved_for_future_use,
+            label_segments,
+            text_segments,
+            data_ext_segments,
+            reserved_ext_segments,
+            user_defined_overflow,
+            extended_overflow,
+            tres
+        })
+    }
+
+    /// Parses a segment count followed by its table of subheader and data lengths.
+    fn parse_lengths(reader: &mut FieldReader, count: &str, subheader: (&str, usize), data: (&str, usize)) -> Result<Vec<SegmentLengths>, NitfError> {
+        let num_of_segments: usize = reader.number(count, 3)?;
+        (1..=num_of_segments).map(|_| {
+            Ok(SegmentLengths {
+                subheader_length: reader.number(subheader.0, subheader.1)?,
+                data_length: reader.number(data.0, data.1)?
+            })
+        }).collect()
+    }
+
+    /// Writes the NITF 2.1 or NSIF 1.0 file header with its FL, HL and segment length fields as
+    /// they are.
+    pub(crate) fn write(&self, writer: &mut FieldWriter) -> Result<(), NitfError> {
+
+        writer.string("FHDR", &self.profile_name, 4)?;
+        writer.string("FVER", &self.version, 5)?;
+        writer.number("CLEVEL", self.complexity_level, 2)?;
+        writer.string("STYPE", &self.standard_type, 4)?;
+        writer.string("OSTAID", &self.originating_station_id, 10)?;
+        writer.date_time("FDT", &self.date_time)?;
+        writer.string("FTITLE", &self.title, 80)?;
+        self.security.write(writer, "F")?;
+        writer.number("FSCOP", self.copy_number, 5)?;
+        writer.number("FSCPYS", self.number_of_copies, 5)?;
+        writer.number("ENCRYP", self.encryption, 1)?;
+        writer.color(&self.background_color);
+        writer.string("ONAME", &self.originator_name, 24)?;
+        writer.string("OPHONE", &self.originator_phone, 18)?;
+        writer.number("FL", self.file_length, 12)?;
+        writer.number("HL", self.header_length, 6)?;
+
+        FileHeader::write_lengths(writer, &self.image_segments, "NUMI", ("LISH", 6), ("LI", 10))?;
+        FileHeader::write_lengths(writer, &self.graphic_segments, "NUMS", ("LSSH", 4), ("LS", 6))?;
+        writer.number("NUMX", self.reserved_for_future_use, 3)?;
+        FileHeader::write_lengths(writer, &self.text_segments, "NUMT", ("LTSH", 4), ("LT", 5))?;
+        FileHeader::write_lengths(writer, &self.data_ext_segments, "NUMDES", ("LDSH", 4), ("LD", 9))?;
+        FileHeader::write_lengths(writer, &self.reserved_ext_segments, "NUMRES", ("LRESH", 4), ("LRE", 7))?;
+
+        writer.tre_area(("UDHDL", "UDHOFL"), self.user_defined_overflow, &self.tres, TreLocation::FileUserDefined)?;
+        writer.tre_area(("XHDL", "XHDLOFL"), self.extended_overflow, &self.tres, TreLocation::FileExtended)
+    }
+
+    /// Writes a segment count followed by its table of subheader and data lengths.
+    fn write_lengths(writer: &mut FieldWriter, segments: &[SegmentLengths], count: &str, subheader: (&str, usize), data: (&str, usize)) -> Result<(), NitfError> {
+        writer.number(count, segments.len(), 3)?;
+        for (n, lengths) in segments.iter().enumerate() {
+            writer.number(&format!("{}{:03}", subheader.0, n + 1), lengths.subheader_length, subheader.1)?;
+            writer.number(&format!("{}{:03}", data.0, n + 1), lengths.data_length, data.1)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the version of the standard the file follows, or None when FHDR and FVER do
+    /// not name a supported version.
+    pub fn nitf_version(&self) -> Option<NitfVersion> {
+        NitfVersion::from_code(&self.profile_name, &self.version)
+    }
+
+    /// Returns the offset of FL. The fields before it take 342 bytes in every version, plus
+    /// the downgrading event (FSDEVT) of a NITF 2.0 header.
+    pub(crate) fn file_length_offset(&self) -> u64 {
+        match self.security.downgrading.as_str() {
+            "999998" => 382,
+            _ => 342
+        }
+    }
+
+    /// Returns every tagged record extension in the file header with the given tag.
+    pub fn find_tres(&self, tag: &str) -> Vec<&Tre> {
+        tre::find(&self.tres, tag)
+    }
+
+    /// Returns the file header as a map of field names to display strings.
+    pub fn to_map(&self) -> BTreeMap<String,String> {
+
+        let mut file_header = BTreeMap::new();
+
+        file_header.insert("FHDR".to_string(), self.profile_name.clone());
+        file_header.insert("FVER".to_string(), self.version.clone());
+        file_header.insert("CLEVEL".to_string(), format!("{:02}", self.complexity_level));
+        file_header.insert("STYPE".to_string(), self.standard_type.clone());
+        file_header.insert("OSTAID".to_string(), self.originating_station_id.clone());
+        file_header.insert("FDT".to_string(), self.date_time.to_string());
+        insert_optional(&mut file_header, "FTITLE", self.title.clone());
+        self.security.insert_into(&mut file_header, "F");
+        file_header.insert("FSCOP".to_string(), format!("{:05}", self.copy_number));
+        file_header.insert("FSCPYS".to_string(), format!("{:05}", self.number_of_copies));
+        file_header.insert("ENCRYP".to_string(), self.encryption.to_string());
+        let nitf20 = self.nitf_version() == Some(NitfVersion::Nitf20);
+        if !nitf20 {
+            file_header.insert("FBKGC".to_string(), self.background_color.to_string());
+        }
+        insert_optional(&mut file_header, "ONAME", self.originator_name.clone());
+        insert_optional(&mut file_header, "OPHONE", self.originator_phone.clone());
+        file_header.insert("FL".to_string(), format!("{:012}", self.file_length));
+        file_header.insert("HL".to_string(), format!("{:06}", self.header_length));
+
+        let tables = [
+            ("NUMI", &self.image_segments, ("LISH", 6), ("LI", 10)),
+            ("NUMS", &self.graphic_segments, ("LSSH", 4), ("LS", 6)),
+            ("NUML", &self.label_segments, ("LLSH", 4), ("LL", 3)),
+            ("NUMT", &self.text_segments, ("LTSH", 4), ("LT", 5)),
+            ("NUMDES", &self.data_ext_segments, ("LDSH", 4), ("LD", 9)),
+            ("NUMRES", &self.reserved_ext_segments, ("LRESH", 4), ("LRE", 7))
+        ];
+        for (count, segments, subheader, data) in tables.iter() {
+            if *count == "NUML" && !nitf20 {
+                continue;
+            }
+            file_header.insert(count.to_string(), format!("{:03}", segments.len()));
+            for (n, lengths) in segments.iter().enumerate() {
+                file_header.insert(format!("{}{:03}", subheader.0, n + 1), format!("{:0width$}", lengths.subheader_length, width = subheader.1));
+                file_header.insert(format!("{}{:03}", data.0, n + 1), format!("{:0width$}", lengths.data_length, width = data.1));
+            }
+        }
+        if !nitf20 {
+            file_header.insert("NUMX".to_string(), format!("{:03}", self.reserved_for_future_use));
+        }
+
+        tre::insert_area(&mut file_header, ("UDHDL", "UDHOFL"), self.user_defined_overflow, &self.tres, TreLocation::FileUserDefined);
+        tre::insert_area(&mut file_header, ("XHDL", "XHDLOFL"), self.extended_overflow, &self.tres, TreLocation::FileExtended);
+        tre::insert_overflow(&mut file_header, &self.tres);
+
+        file_header
+    }
+}
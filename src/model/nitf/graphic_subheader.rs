@@ -0,0 +1,182 @@
+//! NITF graphic subheader
+
+use std::collections::BTreeMap;
+
+use super::error::NitfError;
+use super::field::{FieldReader, FieldWriter};
+use super::file_header::NitfVersion;
+use super::insert_optional;
+use super::security::SecurityFields;
+use super::tre::{self, Tre, TreLocation};
+
+/// Graphic Color (SCOLOR)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GraphicColor {
+    /// C - the graphic uses color
+    Color,
+    /// M - the graphic is monochrome
+    Monochrome
+}
+
+impl GraphicColor {
+    /// Returns the graphic color for the given SCOLOR code.
+    pub fn from_code(code: &str) -> Option<GraphicColor> {
+        match code {
+            "C" => Some(GraphicColor::Color),
+            "M" => Some(GraphicColor::Monochrome),
+            _ => None
+        }
+    }
+
+    /// Returns the SCOLOR code for the graphic color.
+    pub fn code(&self) -> &'static str {
+        match self {
+            GraphicColor::Color => "C",
+            GraphicColor::Monochrome => "M"
+        }
+    }
+}
+
+
+/// NITF graphic subheader. The graphic data is a CGM (SFMT = C), the only format NITF 2.1
+/// allows, so SFMT and the reserved SSTRUCT and SRES fields are not kept.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GraphicSubheader {
+    /// Graphic Identifier (SID)
+    pub graphic_id: String,
+    /// Graphic Name (SNAME)
+    pub name: String,
+    /// Graphic Security Fields (SSCLAS through SSRDT)
+    pub security: SecurityFields,
+    /// Encryption (ENCRYP)
+    pub encryption: u8,
+    /// Graphic Display Level (SDLVL)
+    pub display_level: u16,
+    /// Graphic Attachment Level (SALVL)
+    pub attachment_level: u16,
+    /// Graphic Location as row and column (SLOC)
+    pub location: (i32, i32),
+    /// First Graphic Bound Location as row and column (SBND1)
+    pub first_bound: (i32, i32),
+    /// Graphic Color (SCOLOR)
+    pub color: GraphicColor,
+    /// Second Graphic Bound Location as row and column (SBND2)
+    pub second_bound: (i32, i32),
+    /// Extended Subheader Overflow (SXSOFL)
+    pub extended_overflow: u16,
+    /// Tagged record extensions from the SXSHD area, in file order
+    pub tres: Vec<Tre>
+}
+
+
+impl Default for GraphicSubheader {
+    /// Returns the subheader of an unclassified color graphic at display level 1, attached
+    /// to the file at its origin.
+    fn default() -> GraphicSubheader {
+        GraphicSubheader {
+            graphic_id: String::new(),
+            name: String::new(),
+            security: SecurityFields::default(),
+            encryption: 0,
+            display_level: 1,
+            attachment_level: 0,
+            location: (0, 0),
+            first_bound: (0, 0),
+            color: GraphicColor::Color,
+            second_bound: (0, 0),
+            extended_overflow: 0,
+            tres: Vec::new()
+        }
+    }
+}
+
+
+impl GraphicSubheader {
+
+    /// Parses the graphic subheader at the reader's position.
+    pub(crate) fn parse(reader: &mut FieldReader) -> Result<GraphicSubheader, NitfError> {
+
+        reader.literal("SY", "SY")?;
+        let graphic_id = reader.trimmed("SID", 10)?;
+        let name = reader.trimmed("SNAME", 20)?;
+        let security = SecurityFields::parse(reader, "S", NitfVersion::Nitf21)?;
+        let encryption = reader.number("ENCRYP", 1)?;
+        reader.literal("SFMT", "C")?;
+        reader.string("SSTRUCT", 13)?;
+        let display_level = reader.number("SDLVL", 3)?;
+        let attachment_level = reader.number("SALVL", 3)?;
+        let location = (reader.number("SLOC", 5)?, reader.number("SLOC", 5)?);
+        let first_bound = (reader.number("SBND1", 5)?, reader.number("SBND1", 5)?);
+        let color = reader.code("SCOLOR", 1, GraphicColor::from_code)?;
+        let second_bound = (reader.number("SBND2", 5)?, reader.number("SBND2", 5)?);
+        reader.string("SRES", 2)?;
+        let (extended_overflow, tres) = reader.tre_area("SXSHDL", "SXSOFL", "SXSHD", TreLocation::GraphicExtended)?;
+
+        Ok(GraphicSubheader {
+            graphic_id,
+            name,
+            security,
+            encryption,
+            display_level,
+            attachment_level,
+            location,
+            first_bound,
+            color,
+            second_bound,
+            extended_overflow,
+            tres
+        })
+    }
+
+    /// Writes the graphic subheader.
+    pub(crate) fn write(&self, writer: &mut FieldWriter) -> Result<(), NitfError> {
+
+        writer.string("SY", "SY", 2)?;
+        writer.string("SID", &self.graphic_id, 10)?;
+        writer.string("SNAME", &self.name, 20)?;
+        self.security.write(writer, "S")?;
+        writer.number("ENCRYP", self.encryption, 1)?;
+        writer.string("SFMT", "C", 1)?;
+        writer.number("SSTRUCT", 0, 13)?;
+        writer.number("SDLVL", self.display_level, 3)?;
+        writer.number("SALVL", self.attachment_level, 3)?;
+        writer.number("SLOC", self.location.0, 5)?;
+        writer.number("SLOC", self.location.1, 5)?;
+        writer.number("SBND1", self.first_bound.0, 5)?;
+        writer.number("SBND1", self.first_bound.1, 5)?;
+        writer.string("SCOLOR", self.color.code(), 1)?;
+        writer.number("SBND2", self.second_bound.0, 5)?;
+        writer.number("SBND2", self.second_bound.1, 5)?;
+        writer.number("SRES", 0, 2)?;
+        writer.tre_area(("SXSHDL", "SXSOFL"), self.extended_overflow, &self.tres, TreLocation::GraphicExtended)
+    }
+
+    /// Returns every tagged record extension in the graphic subheader with the given tag.
+    pub fn find_tres(&self, tag: &str) -> Vec<&Tre> {
+        tre::find(&self.tres, tag)
+    }
+
+    /// Returns the graphic subheader as a map of field names to display strings.
+    pub fn to_map(&self) -> BTreeMap<String,String> {
+
+        let mut graphic_subheader = BTreeMap::new();
+
+        graphic_subheader.insert("SY".to_string(), "SY".to_string());
+        graphic_subheader.insert("SID".to_string(), self.graphic_id.clone());
+        insert_optional(&mut graphic_subheader, "SNAME", self.name.clone());
+        self.security.insert_into(&mut graphic_subheader, "S");
+        graphic_subheader.insert("ENCRYP".to_string(), self.encryption.to_string());
+        graphic_subheader.insert("SFMT".to_string(), "C".to_string());
+        graphic_subheader.insert("SDLVL".to_string(), format!("{:03}", self.display_level));
+        graphic_subheader.insert("SALVL".to_string(), format!("{:03}", self.attachment_level));
+        graphic_subheader.insert("SLOC".to_string(), format!("{:05}{:05}", self.location.0, self.location.1));
+        graphic_subheader.insert("SBND1".to_string(), format!("{:05}{:05}", self.first_bound.0, self.first_bound.1));
+        graphic_subheader.insert("SCOLOR".to_string(), self.color.code().to_string());
+        graphic_subheader.insert("SBND2".to_string(), format!("{:05}{:05}", self.second_bound.0, self.second_bound.1));
+
+        tre::insert_area(&mut graphic_subheader, ("SXSHDL", "SXSOFL"), self.extended_overflow, &self.tres, TreLocation::GraphicExtended);
+        tre::insert_overflow(&mut graphic_subheader, &self.tres);
+
+        graphic_subheader
+    }
+}
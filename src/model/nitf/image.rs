@@ -0,0 +1,1242 @@
+//! Pixel access for NITF image segments
+
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::sync::OnceLock;
+
+use super::error::NitfError;
+use super::field::FieldReader;
+use super::fax::{self, Scheme};
+use super::image_subheader::{BiLevelCoding, ImageCompression, ImageMode, ImageRepresentation, ImageSubheader, PixelJustification, PixelValueType};
+use super::jpeg;
+use super::lossless_jpeg;
+use super::jpeg2000::{CodestreamIndex, Jpeg2000Options, Jpeg2000Request};
+use super::mask::MaskTable;
+use super::segment::Segment;
+use super::vq::Codebook;
+use super::NITF;
+
+/// Rectangle of pixels within an image segment
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rectangle {
+    /// First row
+    pub row: u32,
+    /// First column
+    pub column: u32,
+    /// Number of rows
+    pub rows: u32,
+    /// Number of columns
+    pub columns: u32
+}
+
+impl Rectangle {
+    /// Returns the rectangle with the given first row and column and size.
+    pub fn new(row: u32, column: u32, rows: u32, columns: u32) -> Rectangle {
+        Rectangle {
+            row,
+            column,
+            rows,
+            columns
+        }
+    }
+}
+
+
+/// Type pixel values are returned as, chosen from PVTYPE and NBPP
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleType {
+    /// INT or B with NBPP 1 to 8
+    U8,
+    /// INT with NBPP 9 to 16
+    U16,
+    /// INT with NBPP 17 to 32
+    U32,
+    /// INT with NBPP 33 to 64
+    U64,
+    /// SI with NBPP 1 to 8
+    I8,
+    /// SI with NBPP 9 to 16
+    I16,
+    /// SI with NBPP 17 to 32
+    I32,
+    /// SI with NBPP 33 to 64
+    I64,
+    /// R with NBPP 32
+    F32,
+    /// R with NBPP 64
+    F64,
+    /// C with NBPP 64, a real and imaginary pair of 32 bit floating point values
+    Complex
+}
+
+impl SampleType {
+    /// Returns the sample type pixels of the image are returned as.
+    pub fn for_image(subheader: &ImageSubheader) -> Result<SampleType, NitfError> {
+        let bits = subheader.bits_per_pixel;
+        let sample_type = match (subheader.pixel_value_type, bits) {
+            (PixelValueType::Integer, 1..=8) => SampleType::U8,
+            (PixelValueType::Integer, 9..=16) => SampleType::U16,
+            (PixelValueType::Integer, 17..=32) => SampleType::U32,
+            (PixelValueType::Integer, 33..=64) => SampleType::U64,
+            (PixelValueType::SignedInteger, 1..=8) => SampleType::I8,
+            (PixelValueType::SignedInteger, 9..=16) => SampleType::I16,
+            (PixelValueType::SignedInteger, 17..=32) => SampleType::I32,
+            (PixelValueType::SignedInteger, 33..=64) => SampleType::I64,
+            (PixelValueType::Real, 32) => SampleType::F32,
+            (PixelValueType::Real, 64) => SampleType::F64,
+            (PixelValueType::Complex, 64) => SampleType::Complex,
+            (PixelValueType::BiLevel, 1) => SampleType::U8,
+            _ => return Err(NitfError::Unsupported {
+                field: "NBPP".to_string(),
+                value: format!("{} with PVTYPE {}", bits, subheader.pixel_value_type.code())
+            })
+        };
+        Ok(sample_type)
+    }
+
+    /// Returns the number of bits in each value of the sample type.
+    pub fn bits(&self) -> u8 {
+        match self {
+            SampleType::U8 | SampleType::I8 => 8,
+            SampleType::U16 | SampleType::I16 => 16,
+            SampleType::U32 | SampleType::I32 | SampleType::F32 => 32,
+            SampleType::U64 | SampleType::I64 | SampleType::F64 | SampleType::Complex => 64
+        }
+    }
+}
+
+
+/// Pixel values of one sample type
+#[derive(Clone, Debug, PartialEq)]
+pub enum PixelData {
+    /// U8 sample type
+    U8(Vec<u8>),
+    /// U16 sample type
+    U16(Vec<u16>),
+    /// U32 sample type
+    U32(Vec<u32>),
+    /// U64 sample type
+    U64(Vec<u64>),
+    /// I8 sample type
+    I8(Vec<i8>),
+    /// I16 sample type
+    I16(Vec<i16>),
+    /// I32 sample type
+    I32(Vec<i32>),
+    /// I64 sample type
+    I64(Vec<i64>),
+    /// F32 sample type
+    F32(Vec<f32>),
+    /// F64 sample type
+    F64(Vec<f64>),
+    /// Real and imaginary parts
+    Complex(Vec<(f32, f32)>)
+}
+
+impl PixelData {
+
+    /// Returns `length` zero valued pixels.
+    pub fn new(sample_type: SampleType, length: usize) -> PixelData {
+        match sample_type {
+            SampleType::U8 => PixelData::U8(vec![0; length]),
+            SampleType::U16 => PixelData::U16(vec![0; length]),
+            SampleType::U32 => PixelData::U32(vec![0; length]),
+            SampleType::U64 => PixelData::U64(vec![0; length]),
+            SampleType::I8 => PixelData::I8(vec![0; length]),
+            SampleType::I16 => PixelData::I16(vec![0; length]),
+            SampleType::I32 => PixelData::I32(vec![0; length]),
+            SampleType::I64 => PixelData::I64(vec![0; length]),
+            SampleType::F32 => PixelData::F32(vec![0.0; length]),
+            SampleType::F64 => PixelData::F64(vec![0.0; length]),
+            SampleType::Complex => PixelData::Complex(vec![(0.0, 0.0); length])
+        }
+    }
+
+    /// Returns the type of the pixel values.
+    pub fn sample_type(&self) -> SampleType {
+        match self {
+            PixelData::U8(_) => SampleType::U8,
+            PixelData::U16(_) => SampleType::U16,
+            PixelData::U32(_) => SampleType::U32,
+            PixelData::U64(_) => SampleType::U64,
+            PixelData::I8(_) => SampleType::I8,
+            PixelData::I16(_) => SampleType::I16,
+            PixelData::I32(_) => SampleType::I32,
+            PixelData::I64(_) => SampleType::I64,
+            PixelData::F32(_) => SampleType::F32,
+            PixelData::F64(_) => SampleType::F64,
+            PixelData::Complex(_) => SampleType::Complex
+        }
+    }
+
+    /// Returns the number of pixel values.
+    pub fn len(&self) -> usize {
+        match self {
+            PixelData::U8(data) => data.len(),
+            PixelData::U16(data) => data.len(),
+            PixelData::U32(data) => data.len(),
+            PixelData::U64(data) => data.len(),
+            PixelData::I8(data) => data.len(),
+            PixelData::I16(data) => data.len(),
+            PixelData::I32(data) => data.len(),
+            PixelData::I64(data) => data.len(),
+            PixelData::F32(data) => data.len(),
+            PixelData::F64(data) => data.len(),
+            PixelData::Complex(data) => data.len()
+        }
+    }
+
+    /// Returns true when there are no pixel values.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the value at `index` as its bit pattern, with signed integers sign extended to
+    /// 64 bits and complex values as the real part's bits followed by the imaginary part's.
+    pub(crate) fn bits(&self, index: usize) -> u64 {
+        match self {
+            PixelData::U8(data) => u64::from(data[index]),
+            PixelData::U16(data) => u64::from(data[index]),
+            PixelData::U32(data) => u64::from(data[index]),
+            PixelData::U64(data) => data[index],
+            PixelData::I8(data) => data[index] as u64,
+            PixelData::I16(data) => data[index] as u64,
+            PixelData::I32(data) => data[index] as u64,
+            PixelData::I64(data) => data[index] as u64,
+            PixelData::F32(data) => u64::from(data[index].to_bits()),
+            PixelData::F64(data) => data[index].to_bits(),
+            PixelData::Complex(data) => u64::from(data[index].0.to_bits()) << 32 | u64::from(data[index].1.to_bits())
+        }
+    }
+
+    /// Sets the value at `index` from a bit pattern laid out as returned by `bits`.
+    pub(crate) fn set_bits(&mut self, index: usize, bits: u64) {
+        match self {
+            PixelData::U8(data) => data[index] = bits as u8,
+            PixelData::U16(data) => data[index] = bits as u16,
+            PixelData::U32(data) => data[index] = bits as u32,
+            PixelData::U64(data) => data[index] = bits,
+            PixelData::I8(data) => data[index] = bits as i8,
+            PixelData::I16(data) => data[index] = bits as i16,
+            PixelData::I32(data) => data[index] = bits as i32,
+            PixelData::I64(data) => data[index] = bits as i64,
+            PixelData::F32(data) => data[index] = f32::from_bits(bits as u32),
+            PixelData::F64(data) => data[index] = f64::from_bits(bits),
+            PixelData::Complex(data) => data[index] = (f32::from_bits((bits >> 32) as u32), f32::from_bits(bits as u32))
+        }
+    }
+}
+
+
+/// Pixels of a rectangle of an image segment, stored band sequentially: every row of the
+/// first requested band, then every row of the next.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PixelBuffer {
+    /// Number of rows
+    pub rows: u32,
+    /// Number of columns
+    pub columns: u32,
+    /// Zero based image bands held, in the order they are stored
+    pub bands: Vec<usize>,
+    /// Pixel values
+    pub data: PixelData,
+    /// Significant bits of each value, the Actual Bits Per Pixel (ABPP) of integer images
+    pub significant_bits: u8,
+    /// Validity of each pixel in `data`, false for pad pixels and pixels of empty blocks. None
+    /// when every pixel is valid.
+    pub valid: Option<Vec<bool>>
+}
+
+impl PixelBuffer {
+
+    /// Returns a zero filled buffer.
+    pub fn new(rows: u32, columns: u32, bands: Vec<usize>, sample_type: SampleType) -> PixelBuffer {
+        let length = rows as usize * columns as usize * bands.len();
+        PixelBuffer {
+            rows,
+            columns,
+            bands,
+            data: PixelData::new(sample_type, length),
+            significant_bits: sample_type.bits(),
+            valid: None
+        }
+    }
+
+    /// Returns the index into `data` of a pixel, where `band` is a position in `bands`.
+    pub fn index(&self, band: usize, row: u32, column: u32) -> usize {
+        (band * self.rows as usize + row as usize) * self.columns as usize + column as usize
+    }
+
+    /// Returns true when the pixel at `index` holds image data.
+    pub fn is_valid(&self, index: usize) -> bool {
+        self.valid.as_ref().map(|valid| valid[index]).unwrap_or(true)
+    }
+
+    /// Marks the pixel at `index` as not holding image data.
+    pub fn set_invalid(&mut self, index: usize) {
+        let length = self.data.len();
+        self.valid.get_or_insert_with(|| vec![true; length])[index] = false;
+    }
+}
+
+
+/// Arrangement of the blocks of an image and of the pixels within each block
+pub(crate) struct BlockLayout {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) blocks_per_row: u32,
+    pub(crate) blocks_per_column: u32,
+    bands: usize,
+    bits: u8,
+    mode: ImageMode
+}
+
+impl BlockLayout {
+
+    pub(crate) fn new(subheader: &ImageSubheader) -> BlockLayout {
+        // A block dimension of zero means the block spans the image, for images over 8192
+        // pixels in a single block
+        let width = match subheader.pixels_per_block_horizontal {
+            0 => subheader.columns,
+            width => width
+        };
+        let height = match subheader.pixels_per_block_vertical {
+            0 => subheader.rows,
+            height => height
+        };
+        BlockLayout {
+            width,
+            height,
+            blocks_per_row: subheader.blocks_per_row,
+            blocks_per_column: subheader.blocks_per_column,
+            bands: subheader.bands.len(),
+            bits: subheader.bits_per_pixel,
+            mode: subheader.mode
+        }
+    }
+
+    /// Returns the number of blocks in each band.
+    pub(crate) fn block_count(&self) -> u64 {
+        u64::from(self.blocks_per_row) * u64::from(self.blocks_per_column)
+    }
+
+    /// Returns the number of blocks stored, which counts each band's blocks separately in
+    /// band sequential mode.
+    pub(crate) fn stored_block_count(&self) -> u64 {
+        match self.mode {
+            ImageMode::Sequential => self.block_count() * self.bands as u64,
+            _ => self.block_count()
+        }
+    }
+
+    /// Returns the number of pixels in a block of one band.
+    pub(crate) fn block_pixels(&self) -> usize {
+        self.width as usize * self.height as usize
+    }
+
+    /// Returns the byte length of a stored block, which holds every band except in band
+    /// sequential mode. Blocks are padded to a whole number of bytes.
+    pub(crate) fn block_length(&self) -> u64 {
+        let bands = match self.mode {
+            ImageMode::Sequential => 1,
+            _ => self.bands as u64
+        };
+        (self.block_pixels() as u64 * bands * u64::from(self.bits)).div_ceil(8)
+    }
+
+    /// Returns the bit offset of a pixel from the start of the stored block holding it.
+    fn bit_offset(&self, band: usize, x: u32, y: u32) -> u64 {
+        let bits = u64::from(self.bits);
+        let (bands, band, width) = (self.bands as u64, band as u64, u64::from(self.width));
+        let (x, y) = (u64::from(x), u64::from(y));
+        match self.mode {
+            ImageMode::Block => (band * self.block_pixels() as u64 + y * width + x) * bits,
+            ImageMode::Pixel => ((y * width + x) * bands + band) * bits,
+            ImageMode::Row => ((y * bands + band) * width + x) * bits,
+            ImageMode::Sequential => (y * width + x) * bits
+        }
+    }
+}
+
+
+impl NITF {
+
+    /// Returns the pixels of `bands` (zero based) within `rectangle` of the image segment at
+    /// `index`. Values are converted from the stored NBPP bits, big endian, to the segment's
+    /// sample type, keeping the ABPP significant bits of integer pixels. JPEG, lossless JPEG,
+    /// CCITT T.4 and vector quantized blocks are decoded first, and YCbCr601 images are
+    /// returned as RGB. JPEG 2000 images are read at full resolution with every layer. Pixels
+    /// of empty blocks and pad pixels of masked images are marked invalid, and hold the pad
+    /// output pixel code (TPXCD) when there is one.
+    pub fn read_pixels(&self, index: usize, rectangle: Rectangle, bands: &[usize]) -> Result<PixelBuffer, NitfError> {
+        let reader = ImageReader::new(self, index, bands)?;
+        if reader.codec == Codec::Jpeg2000 {
+            return reader.jpeg2000_pixels(rectangle, bands, &Jpeg2000Options::default());
+        }
+        let subheader = &reader.segment.subheader;
+        if u64::from(rectangle.row) + u64::from(rectangle.rows) > u64::from(subheader.rows)
+            || u64::from(rectangle.column) + u64::from(rectangle.columns) > u64::from(subheader.columns) {
+            return Err(NitfError::InvalidRequest {
+                message: format!("rectangle {:?} is outside the {} by {} image", rectangle, subheader.rows, subheader.columns)
+            });
+        }
+
+        let layout = &reader.layout;
+        let mut buffer = reader.buffer(rectangle.rows, rectangle.columns, bands);
+        if rectangle.rows == 0 || rectangle.columns == 0 {
+            return Ok(buffer);
+        }
+
+        let last_row = rectangle.row + rectangle.rows - 1;
+        let last_column = rectangle.column + rectangle.columns - 1;
+        for block_row in rectangle.row / layout.height..=last_row / layout.height {
+            for block_column in rectangle.column / layout.width..=last_column / layout.width {
+                let block = reader.block(block_row, block_column, bands)?;
+
+                // Copy the part of the block inside the rectangle
+                let top = block_row * layout.height;
+                let left = block_column * layout.width;
+                let rows = top.max(rectangle.row)..=(top + layout.height - 1).min(last_row);
+                let columns = left.max(rectangle.column)..=(left + layout.width - 1).min(last_column);
+                for band in 0..bands.len() {
+                    for row in rows.clone() {
+                        for column in columns.clone() {
+                            let to = buffer.index(band, row - rectangle.row, column - rectangle.column);
+                            match &block {
+                                Some(block) => {
+                                    let from = block.index(band, row - top, column - left);
+                                    buffer.data.set_bits(to, block.data.bits(from));
+                                    if !block.is_valid(from) {
+                                        buffer.set_invalid(to);
+                                    }
+                                },
+                                None => {
+                                    buffer.data.set_bits(to, reader.pad_value());
+                                    buffer.set_invalid(to);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(buffer)
+    }
+
+    /// Returns the pixels of `bands` (zero based) in one block of the image segment at
+    /// `index`, or None when the block is not recorded in a masked image. Pixels past the
+    /// edge of the image and pad pixels are marked invalid.
+    pub fn read_block(&self, index: usize, block_row: u32, block_column: u32, bands: &[usize]) -> Result<Option<PixelBuffer>, NitfError> {
+        let reader = ImageReader::new(self, index, bands)?;
+        let layout = &reader.layout;
+        if block_row >= layout.blocks_per_column || block_column >= layout.blocks_per_row {
+            return Err(NitfError::InvalidRequest {
+                message: format!("block ({}, {}) is outside the {} by {} blocks", block_row, block_column, layout.blocks_per_column, layout.blocks_per_row)
+            });
+        }
+        reader.block(block_row, block_column, bands)
+    }
+
+    /// Returns the pixels of `bands` (zero based) within `rectangle` of a JPEG 2000 compressed
+    /// image segment, at the resolution and quality chosen by `options`. The rectangle is in
+    /// the reduced resolution image, which is the full size divided by 2^reduction and rounded
+    /// up. The reduction is checked against the decomposition levels of the codestream, and
+    /// the reduction and number of layers against the J2KLRA TRE when there is one. Only the
+    /// main header and the tile-parts of tiles intersecting the rectangle are read. Pixels of
+    /// blocks that are not recorded in a masked image are marked invalid and hold the pad
+    /// output pixel code (TPXCD) when there is one.
+    pub fn read_jpeg2000_pixels(&self, index: usize, rectangle: Rectangle, bands: &[usize], options: &Jpeg2000Options) -> Result<PixelBuffer, NitfError> {
+        let reader = ImageReader::new(self, index, bands)?;
+        if reader.codec != Codec::Jpeg2000 {
+            return Err(NitfError::InvalidRequest {
+                message: format!("image segment {} is not JPEG 2000 compressed", index)
+            });
+        }
+        reader.jpeg2000_pixels(rectangle, bands, options)
+    }
+
+    /// Returns the first band of a one bit per pixel image segment within `rectangle` as a
+    /// mask, row by row. Set pixels, which are black in CCITT T.4 compressed images, are 1 in
+    /// one bit masks, packed most significant bit first with each row padded to a whole
+    /// byte, and 255 in eight bit masks. Pixels of empty blocks and pad pixels are clear.
+    pub fn read_bilevel_mask(&self, index: usize, rectangle: Rectangle, depth: BiLevelDepth) -> Result<Vec<u8>, NitfError> {
+        let subheader = &self.image_segments().get(index).ok_or_else(|| NitfError::InvalidRequest {
+            message: format!("there is no image segment {}", index)
+        })?.subheader;
+        if subheader.bits_per_pixel != 1 {
+            return Err(NitfError::InvalidRequest {
+                message: format!("image segment {} has {} bits per pixel rather than 1", index, subheader.bits_per_pixel)
+            });
+        }
+        let pixels = self.read_pixels(index, rectangle, &[0])?;
+        let set = |row: u32, column: u32| {
+            let index = pixels.index(0, row, column);
+            pixels.is_valid(index) && pixels.data.bits(index) != 0
+        };
+        let (rows, columns) = (rectangle.rows, rectangle.columns);
+        let mask = match depth {
+            BiLevelDepth::OneBit => (0..rows).flat_map(|row| (0..columns.div_ceil(8)).map(move |byte| (row, byte)))
+                .map(|(row, byte)| (0..8).filter(|bit| byte * 8 + bit < columns && set(row, byte * 8 + bit))
+                    .fold(0u8, |value, bit| value | 0x80 >> bit))
+                .collect(),
+            BiLevelDepth::EightBit => (0..rows).flat_map(|row| (0..columns).map(move |column| (row, column)))
+                .map(|(row, column)| if set(row, column) { 255 } else { 0 })
+                .collect()
+        };
+        Ok(mask)
+    }
+
+    /// Returns the pixels of `bands` (zero based) within `rectangle` of the image segment at
+    /// `index` as stored look up table indices, or mapped through each band's look up tables
+    /// (LUTDnm). A band with three tables, IREPBAND = LU of RGB/LUT images, is expanded to
+    /// red, green and blue bands of eight bit values. A mono band with one table maps to
+    /// eight bit values and with two to sixteen bit values, the first table holding the high
+    /// order bytes. Mapped pixels are sixteen bit when any band maps to sixteen bits. Indices
+    /// past the end of the look up tables and pixels `read_pixels` marks invalid are zero and
+    /// marked invalid.
+    pub fn read_lut_pixels(&self, index: usize, rectangle: Rectangle, bands: &[usize], mode: LutMode) -> Result<PixelBuffer, NitfError> {
+        let subheader = &self.image_segments().get(index).ok_or_else(|| NitfError::InvalidRequest {
+            message: format!("there is no image segment {}", index)
+        })?.subheader;
+        let length = usize::try_from(u64::from(rectangle.rows) * u64::from(rectangle.columns)).map_err(|_| NitfError::InvalidRequest {
+            message: format!("a rectangle of {} by {} pixels is too large to read", rectangle.rows, rectangle.columns)
+        })?;
+        let indices = self.read_pixels(index, rectangle, bands)?;
+        if mode == LutMode::Indices {
+            return Ok(indices);
+        }
+
+        // Each output band holds the tables whose bytes make up its values, high order first
+        let mut outputs: Vec<(usize, usize, Vec<&Vec<u8>>)> = Vec::new();
+        for (position, band) in bands.iter().enumerate() {
+            let luts = &subheader.bands[*band].luts;
+            match luts.len() {
+                1 | 2 => outputs.push((position, *band, luts.iter().collect())),
+                3 => outputs.extend(luts.iter().map(|lut| (position, *band, vec![lut]))),
+                count => return Err(NitfError::InvalidRequest {
+                    message: format!("band {} of image segment {} has {} look up tables rather than 1, 2 or 3", band, index, count)
+                })
+            }
+        }
+        let sample_type = match outputs.iter().any(|(_, _, luts)| luts.len() == 2) {
+            true => SampleType::U16,
+            false => SampleType::U8
+        };
+
+        let mut buffer = PixelBuffer::new(rectangle.rows, rectangle.columns, outputs.iter().map(|(_, band, _)| *band).collect(), sample_type);
+        for (output, (position, _, luts)) in outputs.iter().enumerate() {
+            for pixel in 0..length {
+                let stored = position * length + pixel;
+                let entry = indices.data.bits(stored) as usize;
+                let value = luts.iter().try_fold(0, |value, lut| lut.get(entry).map(|byte| value << 8 | u64::from(*byte)))
+                    .filter(|_| indices.is_valid(stored));
+                buffer.data.set_bits(output * length + pixel, value.unwrap_or(0));
+                if value.is_none() {
+                    buffer.set_invalid(output * length + pixel);
+                }
+            }
+        }
+        Ok(buffer)
+    }
+
+    /// Returns the image data mask table of the image segment at `index`, or None when the
+    /// segment is not masked.
+    pub fn mask_table(&self, index: usize) -> Result<Option<MaskTable>, NitfError> {
+        let segment = self.image_segments().get(index).ok_or_else(|| NitfError::InvalidRequest {
+            message: format!("there is no image segment {}", index)
+        })?;
+        Ok(self.cached_mask_table(segment)?.cloned())
+    }
+
+    /// Returns the mask table of an image segment, parsed on the first call.
+    fn cached_mask_table(&self, segment: &Segment<ImageSubheader>) -> Result<Option<&MaskTable>, NitfError> {
+        let mask_table = cached(&self.image_caches[segment.index].mask_table, || {
+            if !segment.subheader.compression.is_masked() {
+                return Ok(None);
+            }
+            let layout = BlockLayout::new(&segment.subheader);
+            let records = layout.stored_block_count() as usize;
+            let length = MaskTable::length(&self.read_segment_data(segment, 0, 10)?, records)?;
+            let bytes = self.read_segment_data(segment, 0, length)?;
+            Ok(Some(MaskTable::parse(&mut FieldReader::new(&bytes, segment.data_offset()), records)?))
+        })?;
+        Ok(mask_table.as_ref())
+    }
+}
+
+
+/// What is learned about the stored blocks of an image segment on its first read, kept so
+/// later reads go straight to the data of the blocks they need
+#[derive(Default)]
+pub(crate) struct ImageCache {
+    mask_table: OnceLock<Option<MaskTable>>,
+    blocks: OnceLock<BlockIndex>
+}
+
+
+/// Location of the stored blocks of a compressed image segment
+struct BlockIndex {
+    /// Codebook of a vector quantized image
+    codebook: Option<Codebook>,
+    /// Offset within the segment data and length of each stored block of a compressed image,
+    /// None for blocks that are not recorded
+    streams: Vec<Option<(u64, u64)>>,
+    /// Main header and tile-parts of a JPEG 2000 codestream, None when it could not be indexed
+    codestream: Option<CodestreamIndex>
+}
+
+
+impl BlockIndex {
+
+    fn new(nitf: &NITF, segment: &Segment<ImageSubheader>, layout: &BlockLayout, mask_table: Option<&MaskTable>, codec: Codec) -> Result<BlockIndex, NitfError> {
+        let locate = |header: u64, stream_length: &dyn Fn(&[u8]) -> Result<usize, String>| {
+            BlockIndex::locate_streams(nitf, segment, layout, mask_table, header, stream_length)
+        };
+        let (codebook, streams) = match codec {
+            Codec::Jpeg2000 => {
+                let start = BlockIndex::data_start(mask_table);
+                let read = |offset, length| nitf.read_segment_data(segment, start + offset, length);
+                let codestream = CodestreamIndex::new(&read, segment.data_length.saturating_sub(start))?;
+                if let Some(codestream) = &codestream {
+                    BlockIndex::check_dimensions(segment, layout, codestream.dimensions(), start)?;
+                }
+                return Ok(BlockIndex { codebook: None, streams: Vec::new(), codestream });
+            },
+            Codec::Jpeg | Codec::LosslessJpeg => (None, locate(0, &jpeg::stream_length)?),
+            Codec::BiLevel(scheme) => {
+                let (width, height) = (layout.width as usize, layout.height as usize);
+                (None, locate(0, &|data| fax::decode(data, width, height, scheme).map(|(_, length)| length))?)
+            },
+            Codec::VectorQuantized => {
+                let codebook = BlockIndex::codebook(nitf, segment, mask_table)?;
+                let streams = BlockIndex::locate_codes(segment, layout, mask_table, &codebook)?;
+                (Some(codebook), streams)
+            },
+            Codec::Uncompressed => (None, Vec::new())
+        };
+        Ok(BlockIndex { codebook, streams, codestream: None })
+    }
+
+    /// Finds the stored blocks of a compressed image, from the block mask when there is one
+    /// and otherwise by measuring each block's stream in turn, starting after `header` bytes
+    /// of the blocked image data. Each stream is measured from a read that doubles until it
+    /// holds the whole stream, so the data is never read all at once.
+    fn locate_streams(nitf: &NITF, segment: &Segment<ImageSubheader>, layout: &BlockLayout, mask_table: Option<&MaskTable>, header: u64,
+                      stream_length: &dyn Fn(&[u8]) -> Result<usize, String>) -> Result<Vec<Option<(u64, u64)>>, NitfError> {
+        if let Some(streams) = BlockIndex::masked_streams(segment, mask_table) {
+            return Ok(streams);
+        }
+        let end = segment.data_length;
+        let mut position = (BlockIndex::data_start(mask_table) + header).min(end);
+        let mut length = 1024;
+        let records = layout.stored_block_count() as usize;
+        let mut streams = Vec::with_capacity(records);
+        while streams.len() < records {
+            let remaining = (end - position) as usize;
+            let length_read = length.min(remaining);
+            let bytes = nitf.read_segment_data(segment, position, length_read)?;
+            // A stream filling the read may run on past it, unless the read reached the end
+            match stream_length(&bytes) {
+                Ok(stream) if stream < length_read || length_read == remaining => {
+                    streams.push(Some((position, stream as u64)));
+                    position += stream as u64;
+                },
+                Err(message) if length_read == remaining => return Err(decode_error(segment, position, message)),
+                _ => length *= 2
+            }
+        }
+        Ok(streams)
+    }
+
+    /// Finds the stored blocks of a vector quantized image, whose blocks of codes all have
+    /// the same length and follow the codebook when there is no block mask.
+    fn locate_codes(segment: &Segment<ImageSubheader>, layout: &BlockLayout, mask_table: Option<&MaskTable>, codebook: &Codebook) -> Result<Vec<Option<(u64, u64)>>, NitfError> {
+        if let Some(streams) = BlockIndex::masked_streams(segment, mask_table) {
+            return Ok(streams);
+        }
+        let length = codebook.block_length() as u64;
+        let first = BlockIndex::data_start(mask_table) + codebook.length as u64;
+        let records = layout.stored_block_count();
+        let available = segment.data_length.saturating_sub(first);
+        if available < records * length {
+            let offset = first + available / length * length;
+            return Err(decode_error(segment, offset, format!("{} bytes of codes where {} were expected", available % length, length)));
+        }
+        Ok((0..records).map(|index| Some((first + index * length, length))).collect())
+    }
+
+    /// Returns the stored blocks a block mask records, None without one.
+    fn masked_streams(segment: &Segment<ImageSubheader>, mask_table: Option<&MaskTable>) -> Option<Vec<Option<(u64, u64)>>> {
+        let mask_table = mask_table.filter(|mask_table| !mask_table.block_offsets.is_empty())?;
+        // A block's stream runs up to the next block's stream or the end of the data
+        let start = BlockIndex::data_start(Some(mask_table));
+        let mut offsets: Vec<u64> = mask_table.block_offsets.iter().flatten().map(|offset| start + u64::from(*offset)).collect();
+        offsets.sort_unstable();
+        offsets.dedup();
+        Some(mask_table.block_offsets.iter().map(|offset| offset.map(|offset| {
+            let offset = start + u64::from(offset);
+            let next = offsets.iter().find(|next| **next > offset).copied().unwrap_or(segment.data_length);
+            (offset, next.saturating_sub(offset))
+        })).collect())
+    }
+
+    /// Returns the offset of the blocked image data within the segment data, past the mask
+    /// table when there is one.
+    fn data_start(mask_table: Option<&MaskTable>) -> u64 {
+        mask_table.map(|mask_table| u64::from(mask_table.data_offset)).unwrap_or(0)
+    }
+
+    /// Checks the image size a JPEG 2000 codestream gives in SIZ against NROWS and NCOLS.
+    /// The image may be offset on the reference grid and may run on to fill its last blocks.
+    /// IMAG and ILOC only place the image against the segment it is attached to, so neither
+    /// changes its size, and a codestream of any other size could ask for billions of tiles.
+    fn check_dimensions(segment: &Segment<ImageSubheader>, layout: &BlockLayout, (rows, columns): (u32, u32), start: u64) -> Result<(), NitfError> {
+        let subheader = &segment.subheader;
+        let blocked_rows = u64::from(layout.height) * u64::from(layout.blocks_per_column);
+        let blocked_columns = u64::from(layout.width) * u64::from(layout.blocks_per_row);
+        if rows < subheader.rows || columns < subheader.columns || u64::from(rows) > blocked_rows.max(u64::from(subheader.rows))
+            || u64::from(columns) > blocked_columns.max(u64::from(subheader.columns)) {
+            return Err(decode_error(segment, start, format!("the codestream is {} by {} pixels but the image is {} by {}",
+                                                            rows, columns, subheader.rows, subheader.columns)));
+        }
+        Ok(())
+    }
+
+    /// Reads the VQ header at the start of the blocked image data.
+    fn codebook(nitf: &NITF, segment: &Segment<ImageSubheader>, mask_table: Option<&MaskTable>) -> Result<Codebook, NitfError> {
+        let start = BlockIndex::data_start(mask_table);
+        let mut length = 0;
+        loop {
+            let bytes = nitf.read_segment_data(segment, start, length)?;
+            length = Codebook::length(&bytes).map_err(|message| decode_error(segment, start, message))?;
+            if length <= bytes.len() {
+                return Codebook::parse(&bytes).map_err(|message| decode_error(segment, start, message));
+            }
+        }
+    }
+
+}
+
+
+/// Returns the value of a cell, setting it first when it is empty.
+fn cached<T>(cell: &OnceLock<T>, init: impl FnOnce() -> Result<T, NitfError>) -> Result<&T, NitfError> {
+    if let Some(value) = cell.get() {
+        return Ok(value);
+    }
+    let value = init()?;
+    Ok(cell.get_or_init(|| value))
+}
+
+
+/// Depth of the masks returned for one bit per pixel images
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BiLevelDepth {
+    /// Eight pixels per byte
+    OneBit,
+    /// One pixel per byte
+    EightBit
+}
+
+
+/// Pixel values returned for images with look up tables
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LutMode {
+    /// The stored look up table indices
+    Indices,
+    /// The values the look up tables map the indices to
+    Mapped
+}
+
+
+/// Everything needed to decode the blocks of one image segment
+struct ImageReader<'a> {
+    nitf: &'a NITF,
+    segment: &'a Segment<ImageSubheader>,
+    layout: BlockLayout,
+    sample_type: SampleType,
+    mask_table: Option<&'a MaskTable>,
+    codec: Codec,
+    blocks: &'a BlockIndex
+}
+
+
+/// Coding of the stored blocks of an image segment
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Codec {
+    /// NC and NM, blocks of packed NBPP bit values
+    Uncompressed,
+    /// C1 and M1, one CCITT T.4 stream per block
+    BiLevel(Scheme),
+    /// C3, M3 and I1, one JPEG stream per block, where an I1 image is one block
+    Jpeg,
+    /// C5 and M5, one lossless JPEG stream per block
+    LosslessJpeg,
+    /// C4 and M4, a VQ header followed by the codes of each block
+    VectorQuantized,
+    /// C8 and M8, one JPEG 2000 codestream for the whole image
+    Jpeg2000
+}
+
+
+/// Stored block data ready to have pixel values taken from it
+enum StoredBlock<'a> {
+    /// Packed NBPP bit values laid out as described by the block layout
+    Packed(Cow<'a, [u8]>),
+    /// Decoded samples of each band held by the block, row by row
+    Decoded {
+        width: u32,
+        height: u32,
+        bands: Vec<Vec<u16>>
+    }
+}
+
+
+impl<'a> ImageReader<'a> {
+
+    fn new(nitf: &'a NITF, index: usize, bands: &[usize]) -> Result<ImageReader<'a>, NitfError> {
+        let segment = nitf.image_segments().get(index).ok_or_else(|| NitfError::InvalidRequest {
+            message: format!("there is no image segment {}", index)
+        })?;
+        let subheader = &segment.subheader;
+        if let Some(band) = bands.iter().find(|band| **band >= subheader.bands.len()) {
+            return Err(NitfError::InvalidRequest {
+                message: format!("band {} is outside the {} bands of the image", band, subheader.bands.len())
+            });
+        }
+        let codec = match subheader.compression {
+            ImageCompression::NotCompressed | ImageCompression::NotCompressedMasked => Codec::Uncompressed,
+            ImageCompression::BiLevel | ImageCompression::BiLevelMasked => match subheader.bilevel_coding() {
+                Some(BiLevelCoding::OneDimensional) => Codec::BiLevel(Scheme::OneDimensional),
+                Some(_) => Codec::BiLevel(Scheme::TwoDimensional),
+                None => return Err(NitfError::Unsupported { field: "COMRAT".to_string(), value: subheader.compression_rate.clone() })
+            },
+            ImageCompression::Jpeg | ImageCompression::JpegMasked | ImageCompression::DownsampledJpeg => Codec::Jpeg,
+            ImageCompression::LosslessJpeg | ImageCompression::LosslessJpegMasked => Codec::LosslessJpeg,
+            ImageCompression::VectorQuantization | ImageCompression::VectorQuantizationMasked => Codec::VectorQuantized,
+            ImageCompression::Jpeg2000 | ImageCompression::Jpeg2000Masked => Codec::Jpeg2000,
+            compression => return Err(NitfError::Unsupported { field: "IC".to_string(), value: compression.code().to_string() })
+        };
+        let layout = BlockLayout::new(subheader);
+        // I1 is a reduced resolution copy of an image kept as one JPEG stream, so the image
+        // is a single block whose stream holds all of it
+        if subheader.compression == ImageCompression::DownsampledJpeg && (layout.blocks_per_row, layout.blocks_per_column) != (1, 1) {
+            return Err(decode_error(segment, 0, format!("I1 images are one block but this one has {} by {}", layout.blocks_per_column, layout.blocks_per_row)));
+        }
+        let sample_type = SampleType::for_image(subheader)?;
+        let mask_table = nitf.cached_mask_table(segment)?;
+        let blocks = cached(&nitf.image_caches[index].blocks, || BlockIndex::new(nitf, segment, &layout, mask_table, codec))?;
+        Ok(ImageReader {
+            nitf,
+            segment,
+            layout,
+            sample_type,
+            mask_table,
+            codec,
+            blocks
+        })
+    }
+
+    /// Returns a zero filled buffer for pixels of the image.
+    fn buffer(&self, rows: u32, columns: u32, bands: &[usize]) -> PixelBuffer {
+        let mut buffer = PixelBuffer::new(rows, columns, bands.to_vec(), self.sample_type);
+        buffer.significant_bits = significant_bits(&self.segment.subheader);
+        buffer
+    }
+
+    /// Returns the value of the pixels of blocks that are not recorded, which is the pad
+    /// output pixel code (TPXCD) of a masked image that has one and otherwise zero.
+    fn pad_value(&self) -> u64 {
+        self.mask_table.and_then(|mask_table| mask_table.pad_code)
+            .map_or(0, |pad_code| sample_bits(&self.segment.subheader, pad_code))
+    }
+
+    /// Decodes one block of the given bands, or returns None when none of them are recorded.
+    fn block(&self, block_row: u32, block_column: u32, bands: &[usize]) -> Result<Option<PixelBuffer>, NitfError> {
+        let subheader = &self.segment.subheader;
+        let layout = &self.layout;
+        let block_index = u64::from(block_row) * u64::from(layout.blocks_per_row) + u64::from(block_column);
+
+        if self.codec == Codec::Jpeg2000 {
+            return self.jpeg2000_block(block_row, block_column, bands);
+        }
+
+        let mut block = self.buffer(layout.height, layout.width, bands);
+        let mut recorded = false;
+        let mut data: Option<(u64, Option<StoredBlock>)> = None;
+        for (position, band) in bands.iter().enumerate() {
+            // Band sequential images store each band's blocks separately
+            let stored = match layout.mode {
+                ImageMode::Sequential => *band as u64 * layout.block_count() + block_index,
+                _ => block_index
+            };
+            if data.as_ref().map(|(stored_read, _)| *stored_read) != Some(stored) {
+                data = Some((stored, self.stored_block(stored)?));
+            }
+            let stored_block = match &data.as_ref().expect("block data was just read").1 {
+                Some(stored_block) => stored_block,
+                None => {
+                    for index in block.index(position, 0, 0)..block.index(position + 1, 0, 0) {
+                        block.data.set_bits(index, self.pad_value());
+                        block.set_invalid(index);
+                    }
+                    continue;
+                }
+            };
+            recorded = true;
+            let pad_code = match &self.mask_table {
+                Some(mask_table) if mask_table.has_pad_pixels(stored as usize) => mask_table.pad_code,
+                _ => None
+            };
+            let decoded_band = match layout.mode {
+                ImageMode::Sequential => 0,
+                _ => *band
+            };
+
+            for y in 0..layout.height {
+                for x in 0..layout.width {
+                    let raw = match stored_block {
+                        StoredBlock::Packed(bytes) => Some(read_bits(bytes, layout.bit_offset(*band, x, y), layout.bits)),
+                        StoredBlock::Decoded { width, height, bands } => (x < *width && y < *height)
+                            .then(|| u64::from(bands[decoded_band][(y * width + x) as usize]))
+                    };
+                    let index = block.index(position, y, x);
+                    block.data.set_bits(index, raw.map(|raw| sample_bits(subheader, raw)).unwrap_or(0));
+                    let outside = block_row * layout.height + y >= subheader.rows || block_column * layout.width + x >= subheader.columns;
+                    if outside || raw.is_none() || pad_code == raw {
+                        block.set_invalid(index);
+                    }
+                }
+            }
+        }
+        Ok(if recorded { Some(block) } else { None })
+    }
+
+    /// Reads a stored block, decoding it when compressed, or returns None when it is not recorded.
+    fn stored_block(&self, stored: u64) -> Result<Option<StoredBlock<'a>>, NitfError> {
+        let layout = &self.layout;
+        match self.codec {
+            Codec::Uncompressed => {
+                let length = layout.block_length();
+                let offset = match &self.mask_table {
+                    Some(mask_table) => mask_table.block_offset(stored as usize, length)
+                        .map(|offset| u64::from(mask_table.data_offset) + offset),
+                    None => Some(stored * length)
+                };
+                match offset {
+                    Some(offset) => Ok(Some(StoredBlock::Packed(self.nitf.read_segment_data(self.segment, offset, length as usize)?))),
+                    None => Ok(None)
+                }
+            },
+            Codec::Jpeg2000 => unreachable!("JPEG 2000 images are not decoded block by block"),
+            Codec::BiLevel(scheme) => {
+                let (offset, length) = match self.blocks.streams.get(stored as usize).copied().flatten() {
+                    Some(stream) => stream,
+                    None => return Ok(None)
+                };
+                if layout.bands > 1 && layout.mode != ImageMode::Sequential {
+                    return Err(self.decode_error(offset, format!("{} bands in one bi-level block", layout.bands)));
+                }
+                let bytes = self.nitf.read_segment_data(self.segment, offset, length as usize)?;
+                let (pixels, _) = fax::decode(&bytes, layout.width as usize, layout.height as usize, scheme)
+                    .map_err(|message| self.decode_error(offset, message))?;
+                Ok(Some(StoredBlock::Decoded {
+                    width: layout.width,
+                    height: layout.height,
+                    bands: vec![pixels.into_iter().map(u16::from).collect()]
+                }))
+            },
+            Codec::VectorQuantized => {
+                let (offset, length) = match self.blocks.streams.get(stored as usize).copied().flatten() {
+                    Some(stream) => stream,
+                    None => return Ok(None)
+                };
+                if layout.bands > 1 && layout.mode != ImageMode::Sequential {
+                    return Err(self.decode_error(offset, format!("{} bands in one vector quantized block", layout.bands)));
+                }
+                let codebook = self.blocks.codebook.as_ref().expect("the codebook is read with the image");
+                let bytes = self.nitf.read_segment_data(self.segment, offset, length as usize)?;
+                let pixels = codebook.decode(&bytes).map_err(|message| self.decode_error(offset, message))?;
+                let (width, height) = codebook.block_size();
+                Ok(Some(StoredBlock::Decoded {
+                    width: width as u32,
+                    height: height as u32,
+                    bands: vec![pixels]
+                }))
+            },
+            Codec::Jpeg | Codec::LosslessJpeg => {
+                let (offset, length) = match self.blocks.streams.get(stored as usize).copied().flatten() {
+                    Some(stream) => stream,
+                    None => return Ok(None)
+                };
+                let bytes = self.nitf.read_segment_data(self.segment, offset, length as usize)?;
+                let image = match self.codec {
+                    Codec::LosslessJpeg => lossless_jpeg::decode(&bytes),
+                    _ => jpeg::decode(&bytes, self.segment.subheader.jpeg_quality())
+                };
+                let mut image = image.map_err(|message| self.decode_error(offset, message))?;
+                let expected = match layout.mode {
+                    ImageMode::Sequential => 1,
+                    _ => layout.bands
+                };
+                if image.components.len() < expected {
+                    return Err(self.decode_error(offset, format!("{} components where {} bands were expected", image.components.len(), expected)));
+                }
+                let subheader = &self.segment.subheader;
+                if subheader.compression == ImageCompression::DownsampledJpeg && (image.height, image.width) != (subheader.rows as usize, subheader.columns as usize) {
+                    return Err(self.decode_error(offset, format!("the stream is {} by {} pixels but the image is {} by {}",
+                                                                 image.height, image.width, subheader.rows, subheader.columns)));
+                }
+                if subheader.representation == ImageRepresentation::YCbCr601 {
+                    jpeg::ycbcr_to_rgb(&mut image.components, image.precision);
+                }
+                Ok(Some(StoredBlock::Decoded {
+                    width: image.width as u32,
+                    height: image.height as u32,
+                    bands: image.components
+                }))
+            }
+        }
+    }
+
+    /// Decodes a region of a JPEG 2000 codestream at the resolution and quality in `options`.
+    fn jpeg2000_pixels(&self, rectangle: Rectangle, bands: &[usize], options: &Jpeg2000Options) -> Result<PixelBuffer, NitfError> {
+        let subheader = &self.segment.subheader;
+        if let Some(layers) = subheader.jpeg2000_layers()? {
+            if options.reduction > layers.levels {
+                return Err(NitfError::InvalidRequest {
+                    message: format!("a reduction of {} is more than the {} levels in J2KLRA", options.reduction, layers.levels)
+                });
+            }
+            if let Some(count) = options.layers.filter(|count| *count == 0 || *count > layers.layer_count) {
+                return Err(NitfError::InvalidRequest {
+                    message: format!("{} layers were requested but J2KLRA records {}", count, layers.layer_count)
+                });
+            }
+        }
+        // Decomposition levels of the codestream bound the reduction, and every codestream
+        // has at most 32
+        let levels = self.blocks.codestream.as_ref().map(|codestream| codestream.levels()).unwrap_or(32);
+        if options.reduction > levels {
+            return Err(NitfError::InvalidRequest {
+                message: format!("a reduction of {} is more than the {} decomposition levels of the codestream", options.reduction, levels)
+            });
+        }
+        let reduced = |size: u32| (u64::from(size) + (1u64 << options.reduction) - 1) >> options.reduction;
+        let (rows, columns) = (reduced(subheader.rows), reduced(subheader.columns));
+        if u64::from(rectangle.row) + u64::from(rectangle.rows) > rows || u64::from(rectangle.column) + u64::from(rectangle.columns) > columns {
+            return Err(NitfError::InvalidRequest {
+                message: format!("rectangle {:?} is outside the {} by {} image at reduction {}", rectangle, rows, columns, options.reduction)
+            });
+        }
+
+        let mut buffer = self.buffer(rectangle.rows, rectangle.columns, bands);
+        if rectangle.rows == 0 || rectangle.columns == 0 || bands.is_empty() {
+            return Ok(buffer);
+        }
+        // Only the tile-parts of the region are read when the codestream could be indexed
+        let start = self.mask_table.map(|mask_table| u64::from(mask_table.data_offset)).unwrap_or(0);
+        let data = match &self.blocks.codestream {
+            Some(codestream) => {
+                let read = |offset, length| self.nitf.read_segment_data(self.segment, start + offset, length);
+                Cow::Owned(codestream.region(&read, rectangle, options.reduction)?)
+            },
+            None => self.nitf.read_segment_data(self.segment, start, self.segment.data_length.saturating_sub(start) as usize)?
+        };
+        let request = Jpeg2000Request {
+            region: rectangle,
+            components: bands.to_vec(),
+            reduction: options.reduction,
+            layers: options.layers
+        };
+        let image = self.nitf.jpeg2000_decoder.decode(&data, &request).map_err(|error| self.decode_error(start, error.to_string()))?;
+        if image.rows != rectangle.rows || image.columns != rectangle.columns || image.components.len() != bands.len() {
+            return Err(self.decode_error(start, format!("the decoder returned {} components of {} by {} pixels", image.components.len(), image.rows, image.columns)));
+        }
+
+        let layout = &self.layout;
+        let mask = |value: i32| (i64::from(value) as u64) & (u64::MAX >> (64 - u32::from(subheader.bits_per_pixel)));
+        for (band, samples) in image.components.iter().enumerate() {
+            for row in 0..rectangle.rows {
+                for column in 0..rectangle.columns {
+                    let index = buffer.index(band, row, column);
+                    buffer.data.set_bits(index, sample_bits(subheader, mask(samples[(row * rectangle.columns + column) as usize])));
+                    // Blocks of a masked image are located at full resolution
+                    let (y, x) = (u64::from(rectangle.row + row) << options.reduction, u64::from(rectangle.column + column) << options.reduction);
+                    let block = (y / u64::from(layout.height)) * u64::from(layout.blocks_per_row) + x / u64::from(layout.width);
+                    let recorded = match &self.mask_table {
+                        Some(mask_table) => mask_table.block_offsets.get(block as usize) != Some(&None),
+                        None => true
+                    };
+                    if !recorded {
+                        buffer.data.set_bits(index, self.pad_value());
+                        buffer.set_invalid(index);
+                    }
+                }
+            }
+        }
+        Ok(buffer)
+    }
+
+    /// Decodes one block of a JPEG 2000 image, or returns None when it is not recorded.
+    fn jpeg2000_block(&self, block_row: u32, block_column: u32, bands: &[usize]) -> Result<Option<PixelBuffer>, NitfError> {
+        let subheader = &self.segment.subheader;
+        let layout = &self.layout;
+        let (top, left) = (block_row * layout.height, block_column * layout.width);
+        let mut block = self.buffer(layout.height, layout.width, bands);
+        let block_index = block_row as usize * layout.blocks_per_row as usize + block_column as usize;
+        if self.mask_table.as_ref().map(|mask_table| mask_table.block_offsets.get(block_index) == Some(&None)) == Some(true) {
+            return Ok(None);
+        }
+        let rectangle = Rectangle::new(top, left, layout.height.min(subheader.rows.saturating_sub(top)), layout.width.min(subheader.columns.saturating_sub(left)));
+        let pixels = self.jpeg2000_pixels(rectangle, bands, &Jpeg2000Options::default())?;
+        for band in 0..bands.len() {
+            for y in 0..layout.height {
+                for x in 0..layout.width {
+                    let index = block.index(band, y, x);
+                    if y < rectangle.rows && x < rectangle.columns {
+                        block.data.set_bits(index, pixels.data.bits(pixels.index(band, y, x)));
+                    } else {
+                        block.set_invalid(index);
+                    }
+                }
+            }
+        }
+        Ok(Some(block))
+    }
+
+    /// Returns a decode error for the stream at `offset` within the segment data.
+    fn decode_error(&self, offset: u64, message: String) -> NitfError {
+        decode_error(self.segment, offset, message)
+    }
+}
+
+
+/// Returns a decode error for the stream at `offset` within the data of an image segment.
+fn decode_error(segment: &Segment<ImageSubheader>, offset: u64, message: String) -> NitfError {
+    NitfError::Decode {
+        compression: segment.subheader.compression.code().to_string(),
+        offset: segment.data_offset() + offset,
+        message
+    }
+}
+
+
+/// Packs the pixels of every band of an uncompressed image into its stored blocks, NBPP bits
+/// per value with pad pixels zero.
+pub(crate) fn pack_pixels(subheader: &ImageSubheader, pixels: &PixelBuffer) -> Result<Vec<u8>, NitfError> {
+    if subheader.compression != ImageCompression::NotCompressed {
+        return Err(NitfError::Unsupported { field: "IC".to_string(), value: subheader.compression.code().to_string() });
+    }
+    let sample_type = SampleType::for_image(subheader)?;
+    let every_band: Vec<usize> = (0..subheader.bands.len()).collect();
+    if (pixels.rows, pixels.columns) != (subheader.rows, subheader.columns) || pixels.bands != every_band {
+        return Err(NitfError::InvalidRequest {
+            message: format!("pixels of {} rows, {} columns and bands {:?} are not the {} by {} image with {} bands",
+                             pixels.rows, pixels.columns, pixels.bands, subheader.rows, subheader.columns, every_band.len())
+        });
+    }
+    if pixels.data.sample_type() != sample_type {
+        return Err(NitfError::InvalidRequest {
+            message: format!("{:?} pixels where the image holds {:?} values", pixels.data.sample_type(), sample_type)
+        });
+    }
+    let layout = BlockLayout::new(subheader);
+    if u64::from(layout.blocks_per_row) * u64::from(layout.width) < u64::from(subheader.columns)
+        || u64::from(layout.blocks_per_column) * u64::from(layout.height) < u64::from(subheader.rows) {
+        return Err(NitfError::InvalidRequest {
+            message: format!("{} by {} blocks of {} by {} pixels do not cover the image", layout.blocks_per_row,
+                             layout.blocks_per_column, layout.width, layout.height)
+        });
+    }
+
+    let block_length = layout.block_length();
+    let mut data = vec![0; (block_length * layout.stored_block_count()) as usize];
+    for band in 0..subheader.bands.len() {
+        for row in 0..subheader.rows {
+            for column in 0..subheader.columns {
+                let (block_row, y) = (row / layout.height, row % layout.height);
+                let (block_column, x) = (column / layout.width, column % layout.width);
+                let block_index = u64::from(block_row) * u64::from(layout.blocks_per_row) + u64::from(block_column);
+                let stored = match layout.mode {
+                    ImageMode::Sequential => band as u64 * layout.block_count() + block_index,
+                    _ => block_index
+                };
+                let raw = stored_bits(subheader, pixels.data.bits(pixels.index(band, row, column)));
+                write_bits(&mut data, stored * block_length * 8 + layout.bit_offset(band, x, y), layout.bits, raw);
+            }
+        }
+    }
+    Ok(data)
+}
+
+
+/// Returns `count` bits, most significant first, starting `offset` bits into `data`.
+pub(crate) fn read_bits(data: &[u8], offset: u64, count: u8) -> u64 {
+    let first = (offset / 8) as usize;
+    let end = (offset + u64::from(count)).div_ceil(8) as usize;
+    let value = data[first..end].iter().fold(0u128, |value, byte| value << 8 | u128::from(*byte));
+    let trailing = end as u64 * 8 - offset - u64::from(count);
+    ((value >> trailing) & ((1u128 << count) - 1)) as u64
+}
+
+
+/// Sets `count` bits, most significant first, starting `offset` bits into `data` to the low
+/// bits of `value`.
+pub(crate) fn write_bits(data: &mut [u8], offset: u64, count: u8, value: u64) {
+    for bit in 0..u64::from(count) {
+        let position = offset + bit;
+        let mask = 0x80 >> (position % 8);
+        match value >> (u64::from(count) - 1 - bit) & 1 {
+            0 => data[(position / 8) as usize] &= !mask,
+            _ => data[(position / 8) as usize] |= mask
+        }
+    }
+}
+
+
+/// Returns the significant bits of each pixel value, ABPP for integers and NBPP otherwise.
+pub(crate) fn significant_bits(subheader: &ImageSubheader) -> u8 {
+    let bits = subheader.bits_per_pixel;
+    match (subheader.pixel_value_type, subheader.actual_bits_per_pixel) {
+        (PixelValueType::Real | PixelValueType::Complex, _) | (_, 0) => bits,
+        (_, actual) => actual.min(bits)
+    }
+}
+
+
+/// Converts a stored NBPP bit value to the bit pattern of its sample type, keeping the ABPP
+/// significant bits of integers according to PJUST and sign extending signed integers.
+pub(crate) fn sample_bits(subheader: &ImageSubheader, raw: u64) -> u64 {
+    let bits = subheader.bits_per_pixel;
+    let significant = significant_bits(subheader);
+    let justified = |raw: u64| match subheader.pixel_justification {
+        PixelJustification::Left => raw >> (bits - significant),
+        PixelJustification::Right if significant < 64 => raw & ((1 << significant) - 1),
+        PixelJustification::Right => raw
+    };
+    match subheader.pixel_value_type {
+        PixelValueType::Integer | PixelValueType::BiLevel => justified(raw),
+        PixelValueType::SignedInteger => {
+            let shift = 64 - u32::from(significant);
+            ((justified(raw) << shift) as i64 >> shift) as u64
+        },
+        PixelValueType::Real | PixelValueType::Complex => raw
+    }
+}
+
+
+/// Converts the bit pattern of a value of the sample type to its stored NBPP bit value, the
+/// inverse of `sample_bits`.
+pub(crate) fn stored_bits(subheader: &ImageSubheader, bits: u64) -> u64 {
+    let significant = significant_bits(subheader);
+    match subheader.pixel_value_type {
+        PixelValueType::Real | PixelValueType::Complex => bits,
+        _ => {
+            let value = if significant < 64 { bits & ((1 << significant) - 1) } else { bits };
+            match subheader.pixel_justification {
+                PixelJustification::Left => value << (subheader.bits_per_pixel - significant),
+                PixelJustification::Right => value
+            }
+        }
+    }
+}
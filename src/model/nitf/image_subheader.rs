@@ -0,0 +1,855 @@
+//! NITF image subheader
+
+use std::collections::BTreeMap;
+
+use super::error::NitfError;
+use super::field::{DateTime, FieldReader, FieldWriter};
+use super::file_header::NitfVersion;
+use super::security::SecurityFields;
+use super::insert_optional;
+use super::jpeg2000::Jpeg2000Layers;
+use super::rpc::RpcModel;
+use super::tre::{self, Tre, TreLocation};
+
+/// Pixel Value Type (PVTYPE)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelValueType {
+    /// INT - unsigned integer
+    Integer,
+    /// SI - two's complement signed integer
+    SignedInteger,
+    /// R - IEEE 754 floating point
+    Real,
+    /// C - complex, a pair of IEEE 754 floating point values
+    Complex,
+    /// B - bi-level
+    BiLevel
+}
+
+impl PixelValueType {
+    /// Returns the pixel value type for the given PVTYPE code.
+    pub fn from_code(code: &str) -> Option<PixelValueType> {
+        match code {
+            "INT" => Some(PixelValueType::Integer),
+            "SI" => Some(PixelValueType::SignedInteger),
+            "R" => Some(PixelValueType::Real),
+            "C" => Some(PixelValueType::Complex),
+            "B" => Some(PixelValueType::BiLevel),
+            _ => None
+        }
+    }
+
+    /// Returns the PVTYPE code for the pixel value type.
+    pub fn code(&self) -> &'static str {
+        match self {
+            PixelValueType::Integer => "INT",
+            PixelValueType::SignedInteger => "SI",
+            PixelValueType::Real => "R",
+            PixelValueType::Complex => "C",
+            PixelValueType::BiLevel => "B"
+        }
+    }
+}
+
+
+/// Image Representation (IREP)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageRepresentation {
+    /// MONO - monochrome
+    Monochrome,
+    /// RGB - red, green, blue true color
+    Rgb,
+    /// RGB/LUT - mapped color through a look up table
+    RgbLut,
+    /// MULTI - multiband
+    Multiband,
+    /// NODISPLY - not intended for display
+    NoDisplay,
+    /// NVECTOR - cartesian coordinates
+    NVector,
+    /// POLAR - polar coordinates
+    Polar,
+    /// VPH - SAR video phase history
+    VideoPhaseHistory,
+    /// YCbCr601 - ITU-R BT.601 luminance and chrominance
+    YCbCr601,
+    /// BILEVEL - one bit per pixel, black and white
+    BiLevel
+}
+
+impl ImageRepresentation {
+    /// Returns the image representation for the given IREP code.
+    pub fn from_code(code: &str) -> Option<ImageRepresentation> {
+        match code {
+            "MONO" => Some(ImageRepresentation::Monochrome),
+            "RGB" => Some(ImageRepresentation::Rgb),
+            "RGB/LUT" => Some(ImageRepresentation::RgbLut),
+            "MULTI" => Some(ImageRepresentation::Multiband),
+            "NODISPLY" => Some(ImageRepresentation::NoDisplay),
+            "NVECTOR" => Some(ImageRepresentation::NVector),
+            "POLAR" => Some(ImageRepresentation::Polar),
+            "VPH" => Some(ImageRepresentation::VideoPhaseHistory),
+            "YCbCr601" => Some(ImageRepresentation::YCbCr601),
+            "BILEVEL" => Some(ImageRepresentation::BiLevel),
+            _ => None
+        }
+    }
+
+    /// Returns the IREP code for the image representation.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ImageRepresentation::Monochrome => "MONO",
+            ImageRepresentation::Rgb => "RGB",
+            ImageRepresentation::RgbLut => "RGB/LUT",
+            ImageRepresentation::Multiband => "MULTI",
+            ImageRepresentation::NoDisplay => "NODISPLY",
+            ImageRepresentation::NVector => "NVECTOR",
+            ImageRepresentation::Polar => "POLAR",
+            ImageRepresentation::VideoPhaseHistory => "VPH",
+            ImageRepresentation::YCbCr601 => "YCbCr601",
+            ImageRepresentation::BiLevel => "BILEVEL"
+        }
+    }
+}
+
+
+/// Image Compression (IC)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageCompression {
+    /// NC - not compressed
+    NotCompressed,
+    /// NM - not compressed, with a block or pad pixel mask
+    NotCompressedMasked,
+    /// C1 - bi-level (CCITT T.4)
+    BiLevel,
+    /// C3 - JPEG
+    Jpeg,
+    /// C4 - vector quantization
+    VectorQuantization,
+    /// C5 - lossless JPEG
+    LosslessJpeg,
+    /// C6 - reserved for JPEG-LS
+    JpegLs,
+    /// C7 - complex SAR
+    ComplexSar,
+    /// C8 - JPEG 2000
+    Jpeg2000,
+    /// I1 - downsampled JPEG
+    DownsampledJpeg,
+    /// M1 - masked bi-level
+    BiLevelMasked,
+    /// M3 - masked JPEG
+    JpegMasked,
+    /// M4 - masked vector quantization
+    VectorQuantizationMasked,
+    /// M5 - masked lossless JPEG
+    LosslessJpegMasked,
+    /// M6 - masked JPEG-LS
+    JpegLsMasked,
+    /// M7 - masked complex SAR
+    ComplexSarMasked,
+    /// M8 - masked JPEG 2000
+    Jpeg2000Masked
+}
+
+impl ImageCompression {
+    /// Returns the image compression for the given IC code.
+    pub fn from_code(code: &str) -> Option<ImageCompression> {
+        match code {
+            "NC" => Some(ImageCompression::NotCompressed),
+            "NM" => Some(ImageCompression::NotCompressedMasked),
+            "C1" => Some(ImageCompression::BiLevel),
+            "C3" => Some(ImageCompression::Jpeg),
+            "C4" => Some(ImageCompression::VectorQuantization),
+            "C5" => Some(ImageCompression::LosslessJpeg),
+            "C6" => Some(ImageCompression::JpegLs),
+            "C7" => Some(ImageCompression::ComplexSar),
+            "C8" => Some(ImageCompression::Jpeg2000),
+            "I1" => Some(ImageCompression::DownsampledJpeg),
+            "M1" => Some(ImageCompression::BiLevelMasked),
+            "M3" => Some(ImageCompression::JpegMasked),
+            "M4" => Some(ImageCompression::VectorQuantizationMasked),
+            "M5" => Some(ImageCompression::LosslessJpegMasked),
+            "M6" => Some(ImageCompression::JpegLsMasked),
+            "M7" => Some(ImageCompression::ComplexSarMasked),
+            "M8" => Some(ImageCompression::Jpeg2000Masked),
+            _ => None
+        }
+    }
+
+    /// Returns the IC code for the image compression.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ImageCompression::NotCompressed => "NC",
+            ImageCompression::NotCompressedMasked => "NM",
+            ImageCompression::BiLevel => "C1",
+            ImageCompression::Jpeg => "C3",
+            ImageCompression::VectorQuantization => "C4",
+            ImageCompression::LosslessJpeg => "C5",
+            ImageCompression::JpegLs => "C6",
+            ImageCompression::ComplexSar => "C7",
+            ImageCompression::Jpeg2000 => "C8",
+            ImageCompression::DownsampledJpeg => "I1",
+            ImageCompression::BiLevelMasked => "M1",
+            ImageCompression::JpegMasked => "M3",
+            ImageCompression::VectorQuantizationMasked => "M4",
+            ImageCompression::LosslessJpegMasked => "M5",
+            ImageCompression::JpegLsMasked => "M6",
+            ImageCompression::ComplexSarMasked => "M7",
+            ImageCompression::Jpeg2000Masked => "M8"
+        }
+    }
+
+    /// Returns true when the image data begins with a block or pad pixel mask table.
+    pub fn is_masked(&self) -> bool {
+        self.code() == "NM" || self.code().starts_with('M')
+    }
+
+    /// Returns true when the subheader carries a compression rate code (COMRAT).
+    pub fn has_compression_rate(&self) -> bool {
+        *self != ImageCompression::NotCompressed && *self != ImageCompression::NotCompressedMasked
+    }
+}
+
+
+/// Quantization of JPEG compressed images, from the Compression Rate Code (COMRAT) of IC = C3,
+/// M3 and I1, written as 00.n
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JpegQuality {
+    /// 00.0 - custom quantization tables, carried in each JPEG stream
+    Custom,
+    /// 00.1 to 00.5 - one of the five default quantization tables, 1 being the lowest quality
+    Default(u8)
+}
+
+impl JpegQuality {
+    /// Returns the JPEG quality for the given COMRAT code.
+    pub fn from_code(code: &str) -> Option<JpegQuality> {
+        match code.as_bytes() {
+            [b'0', b'0', b'.', b'0'] => Some(JpegQuality::Custom),
+            [b'0', b'0', b'.', level @ b'1'..=b'5'] => Some(JpegQuality::Default(level - b'0')),
+            _ => None
+        }
+    }
+
+    /// Returns the COMRAT code for the JPEG quality.
+    pub fn code(&self) -> String {
+        match self {
+            JpegQuality::Custom => "00.0".to_string(),
+            JpegQuality::Default(level) => format!("00.{}", level)
+        }
+    }
+}
+
+
+/// Coding scheme of bi-level images compressed with CCITT T.4, from the Compression Rate Code
+/// (COMRAT) of IC = C1 and M1
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BiLevelCoding {
+    /// 1D - Modified Huffman, every line coded on its own
+    OneDimensional,
+    /// 2DS - Modified READ with K = 2, for standard vertical resolution
+    TwoDimensionalStandard,
+    /// 2DH - Modified READ with K = 4, for high vertical resolution
+    TwoDimensionalHigh
+}
+
+impl BiLevelCoding {
+    /// Returns the coding scheme for the given COMRAT code.
+    pub fn from_code(code: &str) -> Option<BiLevelCoding> {
+        match code.trim() {
+            "1D" => Some(BiLevelCoding::OneDimensional),
+            "2DS" => Some(BiLevelCoding::TwoDimensionalStandard),
+            "2DH" => Some(BiLevelCoding::TwoDimensionalHigh),
+            _ => None
+        }
+    }
+
+    /// Returns the COMRAT code for the coding scheme.
+    pub fn code(&self) -> &'static str {
+        match self {
+            BiLevelCoding::OneDimensional => "1D",
+            BiLevelCoding::TwoDimensionalStandard => "2DS",
+            BiLevelCoding::TwoDimensionalHigh => "2DH"
+        }
+    }
+}
+
+
+/// Image Mode (IMODE)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageMode {
+    /// B - band interleaved by block
+    Block,
+    /// P - band interleaved by pixel
+    Pixel,
+    /// R - band interleaved by row
+    Row,
+    /// S - band sequential
+    Sequential
+}
+
+impl ImageMode {
+    /// Returns the image mode for the given IMODE code.
+    pub fn from_code(code: &str) -> Option<ImageMode> {
+        match code {
+            "B" => Some(ImageMode::Block),
+            "P" => Some(ImageMode::Pixel),
+            "R" => Some(ImageMode::Row),
+            "S" => Some(ImageMode::Sequential),
+            _ => None
+        }
+    }
+
+    /// Returns the IMODE code for the image mode.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ImageMode::Block => "B",
+            ImageMode::Pixel => "P",
+            ImageMode::Row => "R",
+            ImageMode::Sequential => "S"
+        }
+    }
+}
+
+
+/// Pixel Justification (PJUST)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelJustification {
+    /// L - significant bits are left justified
+    Left,
+    /// R - significant bits are right justified
+    Right
+}
+
+impl PixelJustification {
+    /// Returns the pixel justification for the given PJUST code.
+    pub fn from_code(code: &str) -> Option<PixelJustification> {
+        match code {
+            "L" => Some(PixelJustification::Left),
+            "R" => Some(PixelJustification::Right),
+            _ => None
+        }
+    }
+
+    /// Returns the PJUST code for the pixel justification.
+    pub fn code(&self) -> &'static str {
+        match self {
+            PixelJustification::Left => "L",
+            PixelJustification::Right => "R"
+        }
+    }
+}
+
+
+/// Image Coordinate Representation (ICORDS). NITF 2.0 files use U, G and C, with N meaning
+/// there is no IGEOLO rather than UTM northern hemisphere.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CoordinateRepresentation {
+    /// U - UTM expressed in MGRS form
+    Mgrs,
+    /// N - UTM northern hemisphere
+    UtmNorth,
+    /// S - UTM southern hemisphere
+    UtmSouth,
+    /// G - geographic degrees, minutes, seconds
+    Geographic,
+    /// D - decimal degrees
+    DecimalDegrees,
+    /// C - geocentric, NITF 2.0 only
+    Geocentric
+}
+
+impl CoordinateRepresentation {
+    /// Returns the coordinate representation for the given ICORDS code.
+    pub fn from_code(code: &str) -> Option<CoordinateRepresentation> {
+        match code {
+            "U" => Some(CoordinateRepresentation::Mgrs),
+            "N" => Some(CoordinateRepresentation::UtmNorth),
+            "S" => Some(CoordinateRepresentation::UtmSouth),
+            "G" => Some(CoordinateRepresentation::Geographic),
+            "D" => Some(CoordinateRepresentation::DecimalDegrees),
+            _ => None
+        }
+    }
+
+    /// Returns the coordinate representation for the given NITF 2.0 ICORDS code, which is
+    /// None for N, an image without coordinates.
+    fn from_nitf20_code(code: &str) -> Option<Option<CoordinateRepresentation>> {
+        match code {
+            "U" => Some(Some(CoordinateRepresentation::Mgrs)),
+            "G" => Some(Some(CoordinateRepresentation::Geographic)),
+            "C" => Some(Some(CoordinateRepresentation::Geocentric)),
+            "N" => Some(None),
+            _ => None
+        }
+    }
+
+    /// Returns the ICORDS code for the coordinate representation.
+    pub fn code(&self) -> &'static str {
+        match self {
+            CoordinateRepresentation::Mgrs => "U",
+            CoordinateRepresentation::UtmNorth => "N",
+            CoordinateRepresentation::UtmSouth => "S",
+            CoordinateRepresentation::Geographic => "G",
+            CoordinateRepresentation::DecimalDegrees => "D",
+            CoordinateRepresentation::Geocentric => "C"
+        }
+    }
+}
+
+
+/// Per band fields of the image subheader band loop
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Band {
+    /// nth Band Representation (IREPBANDn)
+    pub representation: String,
+    /// nth Band Subcategory (ISUBCATn)
+    pub subcategory: String,
+    /// nth Band Image Filter Condition (IFCn)
+    pub filter_condition: String,
+    /// nth Band Standard Image Filter Code (IMFLTn)
+    pub filter_code: String,
+    /// nth Band Look Up Tables (LUTDnm), each with NELUTn entries
+    pub luts: Vec<Vec<u8>>
+}
+
+
+/// NITF image subheader
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageSubheader {
+    /// Image Identifier 1 (IID1)
+    pub image_id: String,
+    /// Image Date and Time (IDATIM)
+    pub date_time: DateTime,
+    /// Target Identifier (TGTID)
+    pub target_id: String,
+    /// Image Identifier 2 (IID2)
+    pub image_title: String,
+    /// Image Security Fields (ISCLAS through ISCTLN)
+    pub security: SecurityFields,
+    /// Encryption (ENCRYP)
+    pub encryption: u8,
+    /// Image Source (ISORCE)
+    pub source: String,
+    /// Number of Significant Rows in Image (NROWS)
+    pub rows: u32,
+    /// Number of Significant Columns in Image (NCOLS)
+    pub columns: u32,
+    /// Pixel Value Type (PVTYPE)
+    pub pixel_value_type: PixelValueType,
+    /// Image Representation (IREP)
+    pub representation: ImageRepresentation,
+    /// Image Category (ICAT)
+    pub category: String,
+    /// Actual Bits-Per-Pixel Per Band (ABPP)
+    pub actual_bits_per_pixel: u8,
+    /// Pixel Justification (PJUST)
+    pub pixel_justification: PixelJustification,
+    /// Image Coordinate Representation (ICORDS)
+    pub coordinate_representation: Option<CoordinateRepresentation>,
+    /// Image Geographic Location (IGEOLO)
+    pub geographic_location: String,
+    /// Image Comments (ICOMn)
+    pub comments: Vec<String>,
+    /// Image Compression (IC)
+    pub compression: ImageCompression,
+    /// Compression Rate Code (COMRAT)
+    pub compression_rate: String,
+    /// Bands (NBANDS/XBANDS and the band loop)
+    pub bands: Vec<Band>,
+    /// Image Sync Code (ISYNC)
+    pub sync_code: u8,
+    /// Image Mode (IMODE)
+    pub mode: ImageMode,
+    /// Number of Blocks per Row (NBPR)
+    pub blocks_per_row: u32,
+    /// Number of Blocks per Column (NBPC)
+    pub blocks_per_column: u32,
+    /// Number of Pixels per Block Horizontal (NPPBH)
+    pub pixels_per_block_horizontal: u32,
+    /// Number of Pixels per Block Vertical (NPPBV)
+    pub pixels_per_block_vertical: u32,
+    /// Number of Bits per Pixel per Band (NBPP)
+    pub bits_per_pixel: u8,
+    /// Image Display Level (IDLVL)
+    pub display_level: u16,
+    /// Image Attachment Level (IALVL)
+    pub attachment_level: u16,
+    /// Image Location as row and column (ILOC)
+    pub location: (i32, i32),
+    /// Image Magnification (IMAG)
+    pub magnification: String,
+    /// User Defined Overflow (UDOFL)
+    pub user_defined_overflow: u16,
+    /// Image Extended Subheader Overflow (IXSOFL)
+    pub extended_overflow: u16,
+    /// Tagged record extensions from the UDID and IXSHD areas, in file order
+    pub tres: Vec<Tre>
+}
+
+
+impl ImageSubheader {
+
+    /// Parses the image subheader at the reader's position, laid out for the given version.
+    /// NITF 2.0 names IID1 and IID2 IID and ITITLE.
+    pub(crate) fn parse(reader: &mut FieldReader, version: NitfVersion) -> Result<ImageSubheader, NitfError> {
+
+        let nitf20 = version == NitfVersion::Nitf20;
+        reader.literal("IM", "IM")?;
+        let image_id = reader.trimmed("IID1", 10)?;
+        let date_time = match nitf20 {
+            true => reader.legacy_date_time("IDATIM")?,
+            false => reader.date_time("IDATIM")?
+        };
+        let target_id = reader.trimmed("TGTID", 17)?;
+        let image_title = reader.trimmed("IID2", 80)?;
+        let security = SecurityFields::parse(reader, "I", version)?;
+        let encryption = reader.number("ENCRYP", 1)?;
+        let source = reader.trimmed("ISORCE", 42)?;
+        let rows = reader.number("NROWS", 8)?;
+        let columns = reader.number("NCOLS", 8)?;
+        let pixel_value_type = reader.code("PVTYPE", 3, PixelValueType::from_code)?;
+        let representation = reader.code("IREP", 8, ImageRepresentation::from_code)?;
+        let category = reader.trimmed("ICAT", 8)?;
+        let actual_bits_per_pixel = reader.number("ABPP", 2)?;
+        let pixel_justification = reader.code("PJUST", 1, PixelJustification::from_code)?;
+
+        let coordinate_representation = match nitf20 {
+            true => reader.optional_code("ICORDS", 1, CoordinateRepresentation::from_nitf20_code)?.flatten(),
+            false => reader.optional_code("ICORDS", 1, CoordinateRepresentation::from_code)?
+        };
+        let geographic_location = match coordinate_representation {
+            Some(_) => reader.string("IGEOLO", 60)?,
+            None => String::new()
+        };
+
+        let num_of_comments: usize = reader.number("NICOM", 1)?;
+        let comments = (0..num_of_comments).map(|_| reader.trimmed("ICOMn", 80)).collect::<Result<_, _>>()?;
+
+        let compression = reader.code("IC", 2, ImageCompression::from_code)?;
+        let compression_rate = if compression.has_compression_rate() {
+            reader.trimmed("COMRAT", 4)?
+        } else {
+            String::new()
+        };
+
+        let mut num_of_bands: usize = reader.number("NBANDS", 1)?;
+        if num_of_bands == 0 && !nitf20 {
+            num_of_bands = reader.number("XBANDS", 5)?;
+        }
+        let bands = (0..num_of_bands).map(|_| {
+            let representation = reader.trimmed("IREPBANDn", 2)?;
+            let subcategory = reader.trimmed("ISUBCATn", 6)?;
+            let filter_condition = reader.string("IFCn", 1)?;
+            let filter_code = reader.trimmed("IMFLTn", 3)?;
+            let num_of_luts: usize = reader.number("NLUTSn", 1)?;
+            let luts = if num_of_luts > 0 {
+                let num_of_lut_entries = reader.number("NELUTn", 5)?;
+                (0..num_of_luts).map(|_| Ok(reader.bytes("LUTDnm", num_of_lut_entries)?.to_vec())).collect::<Result<_, NitfError>>()?
+            } else {
+                Vec::new()
+            };
+            Ok(Band {
+                representation,
+                subcategory,
+                filter_condition,
+                filter_code,
+                luts
+            })
+        }).collect::<Result<_, NitfError>>()?;
+
+        let sync_code = reader.number("ISYNC", 1)?;
+        let mode = reader.code("IMODE", 1, ImageMode::from_code)?;
+        let blocks_per_row = reader.number("NBPR", 4)?;
+        let blocks_per_column = reader.number("NBPC", 4)?;
+        let pixels_per_block_horizontal = reader.number("NPPBH", 4)?;
+        let pixels_per_block_vertical = reader.number("NPPBV", 4)?;
+        let bits_per_pixel = reader.number("NBPP", 2)?;
+        let display_level = reader.number("IDLVL", 3)?;
+        let attachment_level = reader.number("IALVL", 3)?;
+        let location = (reader.number("ILOC", 5)?, reader.number("ILOC", 5)?);
+        let magnification = reader.trimmed("IMAG", 4)?;
+
+        let (user_defined_overflow, mut tres) = reader.tre_area("UDIDL", "UDOFL", "UDID", TreLocation::ImageUserDefined)?;
+        let (extended_overflow, extended_tres) = reader.tre_area("IXSHDL", "IXSOFL", "IXSHD", TreLocation::ImageExtended)?;
+        tres.extend(extended_tres);
+
+        Ok(ImageSubheader {
+            image_id,
+            date_time,
+            target_id,
+            image_title,
+            security,
+            encryption,
+            source,
+            rows,
+            columns,
+            pixel_value_type,
+            representation,
+            category,
+            actual_bits_per_pixel,
+            pixel_justification,
+            coordinate_representation,
+            geographic_location,
+            comments,
+            compression,
+            compression_rate,
+            bands,
+            sync_code,
+            mode,
+            blocks_per_row,
+            blocks_per_column,
+            pixels_per_block_horizontal,
+            pixels_per_block_vertical,
+            bits_per_pixel,
+            display_level,
+            attachment_level,
+            location,
+            magnification,
+            user_defined_overflow,
+            extended_overflow,
+            tres
+        })
+    }
+
+    /// Writes the NITF 2.1 or NSIF 1.0 image subheader.
+    pub(crate) fn write(&self, writer: &mut FieldWriter) -> Result<(), NitfError> {
+
+        writer.string("IM", "IM", 2)?;
+        writer.string("IID1", &self.image_id, 10)?;
+        writer.date_time("IDATIM", &self.date_time)?;
+        writer.string("TGTID", &self.target_id, 17)?;
+        writer.string("IID2", &self.image_title, 80)?;
+        self.security.write(writer, "I")?;
+        writer.number("ENCRYP", self.encryption, 1)?;
+        writer.string("ISORCE", &self.source, 42)?;
+        writer.number("NROWS", self.rows, 8)?;
+        writer.number("NCOLS", self.columns, 8)?;
+        writer.string("PVTYPE", self.pixel_value_type.code(), 3)?;
+        writer.string("IREP", self.representation.code(), 8)?;
+        writer.string("ICAT", &self.category, 8)?;
+        writer.number("ABPP", self.actual_bits_per_pixel, 2)?;
+        writer.string("PJUST", self.pixel_justification.code(), 1)?;
+
+        match self.coordinate_representation {
+            Some(coordinate_representation) => {
+                writer.string("ICORDS", coordinate_representation.code(), 1)?;
+                writer.string("IGEOLO", &self.geographic_location, 60)?;
+            },
+            None => writer.string("ICORDS", "", 1)?
+        }
+
+        writer.number("NICOM", self.comments.len(), 1)?;
+        for comment in self.comments.iter() {
+            writer.string("ICOMn", comment, 80)?;
+        }
+
+        writer.string("IC", self.compression.code(), 2)?;
+        if self.compression.has_compression_rate() {
+            writer.string("COMRAT", &self.compression_rate, 4)?;
+        }
+
+        if self.bands.len() < 10 {
+            writer.number("NBANDS", self.bands.len(), 1)?;
+        } else {
+            writer.number("NBANDS", 0, 1)?;
+            writer.number("XBANDS", self.bands.len(), 5)?;
+        }
+        for band in self.bands.iter() {
+            writer.string("IREPBANDn", &band.representation, 2)?;
+            writer.string("ISUBCATn", &band.subcategory, 6)?;
+            writer.string("IFCn", &band.filter_condition, 1)?;
+            writer.string("IMFLTn", &band.filter_code, 3)?;
+            writer.number("NLUTSn", band.luts.len(), 1)?;
+            if let Some(first_lut) = band.luts.first() {
+                writer.number("NELUTn", first_lut.len(), 5)?;
+            }
+            for lut in band.luts.iter() {
+                if lut.len() != band.luts[0].len() {
+                    return Err(NitfError::InvalidValue { field: "LUTDnm".to_string(), offset: writer.position(), value: format!("{} entries", lut.len()) });
+                }
+                writer.bytes(lut);
+            }
+        }
+
+        writer.number("ISYNC", self.sync_code, 1)?;
+        writer.string("IMODE", self.mode.code(), 1)?;
+        writer.number("NBPR", self.blocks_per_row, 4)?;
+        writer.number("NBPC", self.blocks_per_column, 4)?;
+        writer.number("NPPBH", self.pixels_per_block_horizontal, 4)?;
+        writer.number("NPPBV", self.pixels_per_block_vertical, 4)?;
+        writer.number("NBPP", self.bits_per_pixel, 2)?;
+        writer.number("IDLVL", self.display_level, 3)?;
+        writer.number("IALVL", self.attachment_level, 3)?;
+        writer.number("ILOC", self.location.0, 5)?;
+        writer.number("ILOC", self.location.1, 5)?;
+        writer.string("IMAG", &self.magnification, 4)?;
+
+        writer.tre_area(("UDIDL", "UDOFL"), self.user_defined_overflow, &self.tres, TreLocation::ImageUserDefined)?;
+        writer.tre_area(("IXSHDL", "IXSOFL"), self.extended_overflow, &self.tres, TreLocation::ImageExtended)
+    }
+
+    /// Returns every tagged record extension in the image subheader with the given tag.
+    pub fn find_tres(&self, tag: &str) -> Vec<&Tre> {
+        tre::find(&self.tres, tag)
+    }
+
+    /// Returns the quantization recorded in COMRAT for JPEG compressed images, or None for
+    /// other compression types and unrecognized codes.
+    pub fn jpeg_quality(&self) -> Option<JpegQuality> {
+        match self.compression {
+            ImageCompression::Jpeg | ImageCompression::JpegMasked | ImageCompression::DownsampledJpeg =>
+                JpegQuality::from_code(&self.compression_rate),
+            _ => None
+        }
+    }
+
+    /// Returns the coding scheme recorded in COMRAT for CCITT T.4 compressed bi-level images,
+    /// or None for other compression types and unrecognized codes.
+    pub fn bilevel_coding(&self) -> Option<BiLevelCoding> {
+        match self.compression {
+            ImageCompression::BiLevel | ImageCompression::BiLevelMasked => BiLevelCoding::from_code(&self.compression_rate),
+            _ => None
+        }
+    }
+
+    /// Returns the layer information from the J2KLRA TRE of a JPEG 2000 compressed image, or
+    /// None when there is no J2KLRA.
+    pub fn jpeg2000_layers(&self) -> Result<Option<Jpeg2000Layers>, NitfError> {
+        match self.tres.iter().find(|tre| tre.tag == "J2KLRA") {
+            Some(tre) => Jpeg2000Layers::from_tre(tre).map(Some),
+            None => Ok(None)
+        }
+    }
+
+    /// Returns the rational polynomial sensor model from the RPC00B TRE, or from RPC00A when
+    /// there is no RPC00B. Returns None when neither is present or the model is marked invalid.
+    pub fn rpc_model(&self) -> Result<Option<RpcModel>, NitfError> {
+        let tre = self.tres.iter().find(|tre| tre.tag == "RPC00B")
+            .or_else(|| self.tres.iter().find(|tre| tre.tag == "RPC00A"));
+        match tre {
+            Some(tre) => RpcModel::from_tre(tre),
+            None => Ok(None)
+        }
+    }
+
+    /// Returns the (latitude, longitude) in degrees of the first row and column, first row
+    /// and last column, last row and last column, and last row and first column from IGEOLO.
+    /// Returns None unless ICORDS is G or D and every corner is well formed.
+    pub fn corners(&self) -> Option<[(f64, f64); 4]> {
+        let corner = |text: &str| match self.coordinate_representation {
+            Some(CoordinateRepresentation::Geographic) => Some((
+                degrees_minutes_seconds(text.get(0..7)?, 'N', 'S')?,
+                degrees_minutes_seconds(text.get(7..15)?, 'E', 'W')?
+            )),
+            Some(CoordinateRepresentation::DecimalDegrees) => Some((
+                text.get(0..7)?.parse().ok()?,
+                text.get(7..15)?.parse().ok()?
+            )),
+            _ => None
+        };
+        let location = &self.geographic_location;
+        Some([
+            corner(location.get(0..15)?)?,
+            corner(location.get(15..30)?)?,
+            corner(location.get(30..45)?)?,
+            corner(location.get(45..60)?)?
+        ])
+    }
+
+    /// Returns the image subheader as a map of field names to display strings.
+    pub fn to_map(&self) -> BTreeMap<String,String> {
+
+        let mut image_subheader = BTreeMap::new();
+
+        image_subheader.insert("IM".to_string(), "IM".to_string());
+        image_subheader.insert("IID1".to_string(), self.image_id.clone());
+        image_subheader.insert("IDATIM".to_string(), self.date_time.to_string());
+        insert_optional(&mut image_subheader, "TGTID", self.target_id.clone());
+        insert_optional(&mut image_subheader, "IID2", self.image_title.clone());
+        self.security.insert_into(&mut image_subheader, "I");
+        image_subheader.insert("ENCRYP".to_string(), self.encryption.to_string());
+        insert_optional(&mut image_subheader, "ISORCE", self.source.clone());
+        image_subheader.insert("NROWS".to_string(), format!("{:08}", self.rows));
+        image_subheader.insert("NCOLS".to_string(), format!("{:08}", self.columns));
+        image_subheader.insert("PVTYPE".to_string(), self.pixel_value_type.code().to_string());
+        image_subheader.insert("IREP".to_string(), self.representation.code().to_string());
+        image_subheader.insert("ICAT".to_string(), self.category.clone());
+        image_subheader.insert("ABPP".to_string(), format!("{:02}", self.actual_bits_per_pixel));
+        image_subheader.insert("PJUST".to_string(), self.pixel_justification.code().to_string());
+
+        if let Some(coordinate_representation) = self.coordinate_representation {
+            image_subheader.insert("ICORDS".to_string(), coordinate_representation.code().to_string());
+            image_subheader.insert("IGEOLO".to_string(), self.geographic_location.clone());
+        }
+
+        image_subheader.insert("NICOM".to_string(), self.comments.len().to_string());
+        for (n, comment) in self.comments.iter().enumerate() {
+            image_subheader.insert(format!("ICOM{:03}", n + 1), comment.clone());
+        }
+
+        image_subheader.insert("IC".to_string(), self.compression.code().to_string());
+        if self.compression.has_compression_rate() {
+            image_subheader.insert("COMRAT".to_string(), self.compression_rate.clone());
+        }
+
+        if self.bands.len() < 10 {
+            image_subheader.insert("NBANDS".to_string(), self.bands.len().to_string());
+        } else {
+            image_subheader.insert("NBANDS".to_string(), "0".to_string());
+            image_subheader.insert("XBANDS".to_string(), format!("{:05}", self.bands.len()));
+        }
+        for (index, band) in self.bands.iter().enumerate() {
+            let n = index + 1;
+            insert_optional(&mut image_subheader, &format!("IREPBAND{:05}", n), band.representation.clone());
+            insert_optional(&mut image_subheader, &format!("ISUBCAT{:05}", n), band.subcategory.clone());
+            image_subheader.insert(format!("IFC{:05}", n), band.filter_condition.clone());
+            insert_optional(&mut image_subheader, &format!("IMFLT{:05}", n), band.filter_code.clone());
+            image_subheader.insert(format!("NLUTS{:05}", n), band.luts.len().to_string());
+            if let Some(first_lut) = band.luts.first() {
+                image_subheader.insert(format!("NELUT{:05}", n), format!("{:05}", first_lut.len()));
+            }
+            for (m, lut) in band.luts.iter().enumerate() {
+                let hex = lut.iter().fold("0x".to_string(), |hex, byte| format!("{}{:02X}", hex, byte));
+                image_subheader.insert(format!("LUTD{:05}{}", n, m + 1), hex);
+            }
+        }
+
+        image_subheader.insert("ISYNC".to_string(), self.sync_code.to_string());
+        image_subheader.insert("IMODE".to_string(), self.mode.code().to_string());
+        image_subheader.insert("NBPR".to_string(), format!("{:04}", self.blocks_per_row));
+        image_subheader.insert("NBPC".to_string(), format!("{:04}", self.blocks_per_column));
+        image_subheader.insert("NPPBH".to_string(), format!("{:04}", self.pixels_per_block_horizontal));
+        image_subheader.insert("NPPBV".to_string(), format!("{:04}", self.pixels_per_block_vertical));
+        image_subheader.insert("NBPP".to_string(), format!("{:02}", self.bits_per_pixel));
+        image_subheader.insert("IDLVL".to_string(), format!("{:03}", self.display_level));
+        image_subheader.insert("IALVL".to_string(), format!("{:03}", self.attachment_level));
+        image_subheader.insert("ILOC".to_string(), format!("{:05}{:05}", self.location.0, self.location.1));
+        image_subheader.insert("IMAG".to_string(), self.magnification.clone());
+
+        tre::insert_area(&mut image_subheader, ("UDIDL", "UDOFL"), self.user_defined_overflow, &self.tres, TreLocation::ImageUserDefined);
+        tre::insert_area(&mut image_subheader, ("IXSHDL", "IXSOFL"), self.extended_overflow, &self.tres, TreLocation::ImageExtended);
+        tre::insert_overflow(&mut image_subheader, &self.tres);
+
+        image_subheader
+    }
+}
+
+
+/// Returns the degrees of a ddmmssX latitude or dddmmssY longitude, negative when the
+/// hemisphere is `negative`.
+fn degrees_minutes_seconds(text: &str, positive: char, negative: char) -> Option<f64> {
+    let hemisphere = text.chars().last()?;
+    let digits = &text[..text.len() - hemisphere.len_utf8()];
+    let degree_digits = digits.len().checked_sub(4)?;
+    let degrees: f64 = digits.get(..degree_digits)?.parse().ok()?;
+    let minutes: f64 = digits.get(degree_digits..degree_digits + 2)?.parse().ok()?;
+    let seconds: f64 = digits.get(degree_digits + 2..)?.parse().ok()?;
+    let value = degrees + minutes / 60.0 + seconds / 3600.0;
+    match hemisphere {
+        hemisphere if hemisphere == positive => Some(value),
+        hemisphere if hemisphere == negative => Some(-value),
+        _ => None
+    }
+}
@@ -0,0 +1,650 @@
+//! Sequential DCT JPEG decoding (ITU-T T.81 baseline and extended, 8 and 12 bit Huffman
+//! coding) for image segments compressed with IC = C3, M3 and I1
+
+use std::f32::consts::PI;
+
+use super::image_subheader::JpegQuality;
+
+/// Natural order index of each coefficient in zig-zag order
+const ZIGZAG: [usize; 64] = [
+     0,  1,  8, 16,  9,  2,  3, 10,
+    17, 24, 32, 25, 18, 11,  4,  5,
+    12, 19, 26, 33, 40, 48, 41, 34,
+    27, 20, 13,  6,  7, 14, 21, 28,
+    35, 42, 49, 56, 57, 50, 43, 36,
+    29, 22, 15, 23, 30, 37, 44, 51,
+    58, 59, 52, 45, 38, 31, 39, 46,
+    53, 60, 61, 54, 47, 55, 62, 63
+];
+
+/// Default quantization tables of MIL-STD-188-198A for eight bit images, in zig-zag order,
+/// used by streams without DQT segments at COMRAT 00.1 to 00.5
+const DEFAULT_QUANTIZATION_TABLES: [[u8; 64]; 5] = [
+    [
+          8,  72,  72,  72,  72,  72,  72,  72,
+         72,  72,  78,  74,  76,  74,  78,  89,
+         81,  84,  84,  81,  89, 106,  93,  94,
+         99,  94,  93, 106, 129, 111, 108, 116,
+        116, 108, 111, 129, 135, 128, 136, 145,
+        136, 128, 135, 155, 160, 177, 177, 160,
+        155, 193, 213, 228, 213, 193, 255, 255,
+        255, 255, 255, 255, 255, 255, 255, 255
+    ],
+    [
+          8,  36,  36,  36,  36,  36,  36,  36,
+         36,  36,  39,  37,  38,  37,  39,  45,
+         41,  42,  42,  41,  45,  53,  47,  47,
+         50,  47,  47,  53,  65,  56,  54,  59,
+         59,  54,  56,  65,  68,  64,  69,  73,
+         69,  64,  68,  78,  81,  89,  89,  81,
+         78,  98, 108, 115, 108,  98, 130, 144,
+        144, 130, 178, 190, 178, 243, 243, 255
+    ],
+    [
+          8,  10,  10,  10,  10,  10,  10,  10,
+         10,  10,  11,  10,  11,  10,  11,  13,
+         11,  12,  12,  11,  13,  15,  13,  13,
+         14,  13,  13,  15,  18,  16,  15,  16,
+         16,  15,  16,  18,  19,  18,  19,  21,
+         19,  18,  19,  22,  23,  25,  25,  23,
+         22,  27,  30,  32,  30,  27,  36,  40,
+         40,  36,  50,  53,  50,  68,  68,  91
+    ],
+    [
+          8,   7,   7,   7,   7,   7,   7,   7,
+          7,   7,   8,   7,   8,   7,   8,   9,
+          8,   8,   8,   8,   9,  11,   9,   9,
+         10,   9,   9,  11,  13,  11,  11,  12,
+         12,  11,  11,  13,  14,  13,  14,  15,
+         14,  13,  14,  16,  16,  18,  18,  16,
+         16,  20,  22,  23,  22,  20,  26,  29,
+         29,  26,  36,  38,  36,  49,  49,  65
+    ],
+    [
+          4,   4,   4,   4,   4,   4,   4,   4,
+          4,   4,   4,   4,   4,   4,   4,   5,
+          5,   5,   5,   5,   5,   6,   5,   5,
+          6,   5,   5,   6,   7,   6,   6,   6,
+          6,   6,   6,   7,   8,   7,   8,   8,
+          8,   7,   8,   9,   9,  10,  10,   9,
+          9,  11,  12,  13,  12,  11,  14,  16,
+         16,  14,  20,  21,  20,  27,  27,  36
+    ]
+];
+
+/// Number of bits looked up at once when decoding Huffman codes
+const LOOKUP_BITS: u32 = 9;
+
+/// Start of image
+const SOI: u8 = 0xD8;
+/// End of image
+pub(crate) const EOI: u8 = 0xD9;
+/// Start of scan
+pub(crate) const SOS: u8 = 0xDA;
+/// Define quantization tables
+const DQT: u8 = 0xDB;
+/// Define Huffman tables
+pub(crate) const DHT: u8 = 0xC4;
+/// Define restart interval
+pub(crate) const DRI: u8 = 0xDD;
+/// Baseline sequential DCT frame
+const SOF0: u8 = 0xC0;
+/// Extended sequential DCT frame
+const SOF1: u8 = 0xC1;
+
+
+/// Decoded image with each component upsampled to the full image size
+pub(crate) struct DecodedImage {
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    /// Sample precision in bits
+    pub(crate) precision: u8,
+    /// Samples of each component, row by row
+    pub(crate) components: Vec<Vec<u16>>
+}
+
+
+struct Component {
+    id: u8,
+    horizontal: usize,
+    vertical: usize,
+    quantization_table: usize,
+    dc_table: usize,
+    ac_table: usize,
+    /// Width and height of the component in blocks, padded to whole MCUs
+    blocks_wide: usize,
+    blocks_high: usize,
+    coefficients: Vec<[i32; 64]>,
+    prediction: i32
+}
+
+
+struct Frame {
+    precision: u8,
+    width: usize,
+    height: usize,
+    components: Vec<Component>,
+    max_horizontal: usize,
+    max_vertical: usize
+}
+
+
+/// Canonical Huffman table with a lookup for short codes
+pub(crate) struct Huffman {
+    /// Code length and value for each LOOKUP_BITS bit prefix, zero length when the code is longer
+    lookup: Vec<(u8, u8)>,
+    max_code: [i32; 17],
+    min_code: [i32; 17],
+    value_index: [usize; 17],
+    values: Vec<u8>
+}
+
+impl Huffman {
+    fn new(counts: &[u8], values: &[u8]) -> Result<Huffman, String> {
+        let mut huffman = Huffman {
+            lookup: vec![(0, 0); 1 << LOOKUP_BITS],
+            max_code: [-1; 17],
+            min_code: [0; 17],
+            value_index: [0; 17],
+            values: values.to_vec()
+        };
+        let mut code = 0i32;
+        let mut index = 0usize;
+        for length in 1..=16 {
+            let count = usize::from(counts[length - 1]);
+            if count > 0 {
+                huffman.value_index[length] = index;
+                huffman.min_code[length] = code;
+                for _ in 0..count {
+                    if code >= 1 << length {
+                        return Err("invalid Huffman table".to_string());
+                    }
+                    if length as u32 <= LOOKUP_BITS {
+                        let shift = LOOKUP_BITS - length as u32;
+                        for fill in 0..1 << shift {
+                            huffman.lookup[((code as usize) << shift) | fill] = (length as u8, values[index]);
+                        }
+                    }
+                    code += 1;
+                    index += 1;
+                }
+                huffman.max_code[length] = code - 1;
+            }
+            code <<= 1;
+        }
+        Ok(huffman)
+    }
+}
+
+
+/// Reader of entropy coded data that removes stuffed zero bytes and stops at markers
+pub(crate) struct BitReader<'a> {
+    data: &'a [u8],
+    pub(crate) position: usize,
+    buffer: u64,
+    bits: u32,
+    at_marker: bool
+}
+
+impl<'a> BitReader<'a> {
+    pub(crate) fn new(data: &'a [u8], position: usize) -> BitReader<'a> {
+        BitReader {
+            data,
+            position,
+            buffer: 0,
+            bits: 0,
+            at_marker: false
+        }
+    }
+
+    /// Fills the buffer to at least 57 bits, feeding zeros once a marker or the end is reached.
+    fn fill(&mut self) {
+        while self.bits <= 56 {
+            let mut byte = 0;
+            if !self.at_marker && self.position < self.data.len() {
+                byte = self.data[self.position];
+                if byte == 0xFF {
+                    match self.data.get(self.position + 1) {
+                        Some(0) => self.position += 2,
+                        _ => {
+                            self.at_marker = true;
+                            byte = 0;
+                        }
+                    }
+                } else {
+                    self.position += 1;
+                }
+            }
+            self.buffer |= u64::from(byte) << (56 - self.bits);
+            self.bits += 8;
+        }
+    }
+
+    pub(crate) fn bits(&mut self, count: u32) -> u32 {
+        if count == 0 {
+            return 0;
+        }
+        self.fill();
+        let value = (self.buffer >> (64 - count)) as u32;
+        self.buffer <<= count;
+        self.bits -= count;
+        value
+    }
+
+    /// Reads `count` bits as a signed magnitude (the JPEG EXTEND procedure).
+    pub(crate) fn receive_extend(&mut self, count: u32) -> i32 {
+        if count == 0 {
+            return 0;
+        }
+        let value = self.bits(count) as i32;
+        if value < 1 << (count - 1) {
+            value - (1 << count) + 1
+        } else {
+            value
+        }
+    }
+
+    pub(crate) fn decode(&mut self, huffman: &Huffman) -> Result<u8, String> {
+        self.fill();
+        let (length, value) = huffman.lookup[(self.buffer >> (64 - LOOKUP_BITS)) as usize];
+        if length > 0 {
+            self.buffer <<= length;
+            self.bits -= u32::from(length);
+            return Ok(value);
+        }
+        for length in LOOKUP_BITS as usize + 1..=16 {
+            let code = (self.buffer >> (64 - length)) as i32;
+            if code <= huffman.max_code[length] {
+                self.buffer <<= length;
+                self.bits -= length as u32;
+                return Ok(huffman.values[huffman.value_index[length] + (code - huffman.min_code[length]) as usize]);
+            }
+        }
+        Err("invalid Huffman code".to_string())
+    }
+
+    /// Discards buffered bits and skips the restart marker that should follow.
+    pub(crate) fn restart(&mut self) -> Result<(), String> {
+        self.buffer = 0;
+        self.bits = 0;
+        self.at_marker = false;
+        while self.data.get(self.position) == Some(&0xFF) && self.data.get(self.position + 1) == Some(&0xFF) {
+            self.position += 1;
+        }
+        match self.data.get(self.position..self.position + 2) {
+            Some([0xFF, marker]) if (0xD0..=0xD7).contains(marker) => {
+                self.position += 2;
+                Ok(())
+            },
+            _ => Err("missing restart marker".to_string())
+        }
+    }
+}
+
+
+/// Marker segments common to the JPEG processes
+pub(crate) struct Markers<'a> {
+    data: &'a [u8],
+    pub(crate) position: usize
+}
+
+impl<'a> Markers<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Result<Markers<'a>, String> {
+        if data.get(0..2) != Some(&[0xFF, SOI]) {
+            return Err("missing start of image marker".to_string());
+        }
+        Ok(Markers {
+            data,
+            position: 2
+        })
+    }
+
+    /// Returns true when the data at the current position is a marker other than a restart.
+    fn at_marker(&self) -> bool {
+        self.data[self.position] == 0xFF && !matches!(self.data[self.position + 1], 0x00 | 0xFF | 0xD0..=0xD7)
+    }
+
+    /// Returns the next marker and its segment, which is empty for markers without one.
+    pub(crate) fn next(&mut self) -> Result<(u8, &'a [u8]), String> {
+        // Skip anything up to the next marker, such as padding left after entropy coded data
+        while self.position + 1 < self.data.len() && !self.at_marker() {
+            self.position += 1;
+        }
+        let marker = *self.data.get(self.position + 1).ok_or("missing end of image marker")?;
+        self.position += 2;
+        if marker == EOI || marker == SOI || (0xD0..=0xD7).contains(&marker) {
+            return Ok((marker, &[]));
+        }
+        let length = self.data.get(self.position..self.position + 2)
+            .map(|length| usize::from(length[0]) << 8 | usize::from(length[1]))
+            .ok_or("truncated marker segment")?;
+        let segment = self.data.get(self.position + 2..self.position + length)
+            .ok_or("truncated marker segment")?;
+        self.position += length;
+        Ok((marker, segment))
+    }
+}
+
+
+/// Returns the length of the JPEG stream starting at `data[0]`, through its end of image marker.
+pub(crate) fn stream_length(data: &[u8]) -> Result<usize, String> {
+    let mut markers = Markers::new(data)?;
+    loop {
+        let (marker, _) = markers.next()?;
+        if marker == EOI {
+            return Ok(markers.position);
+        }
+    }
+}
+
+
+/// Reads the Huffman tables of a DHT segment into the DC (lossless) and AC tables.
+pub(crate) fn define_huffman_tables(segment: &[u8], dc_tables: &mut [Option<Huffman>; 4], ac_tables: &mut [Option<Huffman>; 4]) -> Result<(), String> {
+    let mut cursor = 0;
+    while cursor < segment.len() {
+        let class = segment[cursor] >> 4;
+        let table = usize::from(segment[cursor] & 0x0F);
+        let counts = segment.get(cursor + 1..cursor + 17).ok_or("truncated Huffman table")?;
+        let total = counts.iter().map(|count| usize::from(*count)).sum::<usize>();
+        let values = segment.get(cursor + 17..cursor + 17 + total).ok_or("truncated Huffman table")?;
+        let huffman = Some(Huffman::new(counts, values)?);
+        match class {
+            0 => *dc_tables.get_mut(table).ok_or("invalid Huffman table")? = huffman,
+            _ => *ac_tables.get_mut(table).ok_or("invalid Huffman table")? = huffman
+        }
+        cursor += 17 + total;
+    }
+    Ok(())
+}
+
+
+/// Returns the number of MCUs between restart markers from a DRI segment.
+pub(crate) fn parse_restart_interval(segment: &[u8]) -> Result<usize, String> {
+    segment.get(0..2).map(|value| usize::from(value[0]) << 8 | usize::from(value[1]))
+        .ok_or_else(|| "truncated restart interval".to_string())
+}
+
+
+/// Decodes a baseline or extended sequential DCT JPEG stream. Quantization tables the stream
+/// does not define are the default tables of the `quality` recorded in COMRAT.
+pub(crate) fn decode(data: &[u8], quality: Option<JpegQuality>) -> Result<DecodedImage, String> {
+    let mut markers = Markers::new(data)?;
+    let mut quantization_tables: [Option<[u16; 64]>; 4] = [None; 4];
+    let mut dc_tables: [Option<Huffman>; 4] = [None, None, None, None];
+    let mut ac_tables: [Option<Huffman>; 4] = [None, None, None, None];
+    let mut restart_interval = 0;
+    let mut frame: Option<Frame> = None;
+
+    loop {
+        let (marker, segment) = markers.next()?;
+        match marker {
+            DQT => {
+                let mut cursor = 0;
+                while cursor < segment.len() {
+                    let precision = segment[cursor] >> 4;
+                    let table = usize::from(segment[cursor] & 0x0F);
+                    let size = if precision == 0 { 64 } else { 128 };
+                    let values = segment.get(cursor + 1..cursor + 1 + size).ok_or("truncated quantization table")?;
+                    let destination = quantization_tables.get_mut(table).ok_or("invalid quantization table")?
+                        .get_or_insert([0; 64]);
+                    for (index, value) in destination.iter_mut().enumerate() {
+                        *value = if precision == 0 {
+                            u16::from(values[index])
+                        } else {
+                            u16::from(values[2 * index]) << 8 | u16::from(values[2 * index + 1])
+                        };
+                    }
+                    cursor += 1 + size;
+                }
+            },
+            DHT => define_huffman_tables(segment, &mut dc_tables, &mut ac_tables)?,
+            DRI => restart_interval = parse_restart_interval(segment)?,
+            SOF0 | SOF1 => frame = Some(parse_frame(segment)?),
+            0xC2..=0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF =>
+                return Err(format!("JPEG process of marker 0x{:02X} is not supported", marker)),
+            SOS => {
+                let frame = frame.as_mut().ok_or("scan before frame header")?;
+                let end = decode_scan(frame, segment, data, markers.position, restart_interval, &dc_tables, &ac_tables)?;
+                markers.position = end;
+            },
+            EOI => break,
+            _ => {}
+        }
+    }
+
+    let frame = frame.ok_or("missing frame header")?;
+    let mut tables = [[0u16; 64]; 4];
+    for component in frame.components.iter() {
+        let index = component.quantization_table;
+        tables[index] = match (quantization_tables[index], quality) {
+            (Some(table), _) => table,
+            (None, Some(JpegQuality::Default(level @ 1..=5))) if frame.precision == 8 => {
+                let mut table = [0; 64];
+                for (value, default) in table.iter_mut().zip(DEFAULT_QUANTIZATION_TABLES[usize::from(level) - 1].iter()) {
+                    *value = u16::from(*default);
+                }
+                table
+            },
+            (None, _) => return Err(format!("quantization table {} is not defined and COMRAT has no default table for it", index))
+        };
+    }
+    Ok(output(&frame, &tables))
+}
+
+
+fn parse_frame(segment: &[u8]) -> Result<Frame, String> {
+    if segment.len() < 6 {
+        return Err("truncated frame header".to_string());
+    }
+    let precision = segment[0];
+    if precision != 8 && precision != 12 {
+        return Err(format!("{} bit sample precision is not supported", precision));
+    }
+    let height = usize::from(segment[1]) << 8 | usize::from(segment[2]);
+    let width = usize::from(segment[3]) << 8 | usize::from(segment[4]);
+    if width == 0 || height == 0 {
+        return Err("image without a size".to_string());
+    }
+    let count = usize::from(segment[5]);
+    let mut components = Vec::new();
+    for index in 0..count {
+        let bytes = segment.get(6 + 3 * index..9 + 3 * index).ok_or("truncated frame header")?;
+        let (horizontal, vertical) = (usize::from(bytes[1] >> 4), usize::from(bytes[1] & 0x0F));
+        if !(1..=4).contains(&horizontal) || !(1..=4).contains(&vertical) || bytes[2] > 3 {
+            return Err("invalid frame component".to_string());
+        }
+        components.push(Component {
+            id: bytes[0],
+            horizontal,
+            vertical,
+            quantization_table: usize::from(bytes[2]),
+            dc_table: 0,
+            ac_table: 0,
+            blocks_wide: 0,
+            blocks_high: 0,
+            coefficients: Vec::new(),
+            prediction: 0
+        });
+    }
+    let max_horizontal = components.iter().map(|component| component.horizontal).max().unwrap_or(1);
+    let max_vertical = components.iter().map(|component| component.vertical).max().unwrap_or(1);
+    let mcus_wide = width.div_ceil(8 * max_horizontal);
+    let mcus_high = height.div_ceil(8 * max_vertical);
+    for component in components.iter_mut() {
+        component.blocks_wide = mcus_wide * component.horizontal;
+        component.blocks_high = mcus_high * component.vertical;
+        component.coefficients = vec![[0; 64]; component.blocks_wide * component.blocks_high];
+    }
+    Ok(Frame {
+        precision,
+        width,
+        height,
+        components,
+        max_horizontal,
+        max_vertical
+    })
+}
+
+
+/// Decodes the entropy coded data of a scan, returning the position following it.
+fn decode_scan(frame: &mut Frame, segment: &[u8], data: &[u8], position: usize, restart_interval: usize,
+               dc_tables: &[Option<Huffman>; 4], ac_tables: &[Option<Huffman>; 4]) -> Result<usize, String> {
+    let count = usize::from(*segment.first().ok_or("truncated scan header")?);
+    let mut scan_components = Vec::new();
+    for index in 0..count {
+        let bytes = segment.get(1 + 2 * index..3 + 2 * index).ok_or("truncated scan header")?;
+        let component = frame.components.iter().position(|component| component.id == bytes[0])
+            .ok_or("scan component is not in the frame")?;
+        frame.components[component].dc_table = usize::from(bytes[1] >> 4) & 3;
+        frame.components[component].ac_table = usize::from(bytes[1] & 0x0F) & 3;
+        frame.components[component].prediction = 0;
+        scan_components.push(component);
+    }
+
+    // A single component scan codes the component's blocks one at a time, rather than in MCUs
+    let (mcus_wide, mcus_high) = match scan_components.as_slice() {
+        [component] => {
+            let component = &frame.components[*component];
+            ((frame.width * component.horizontal).div_ceil(frame.max_horizontal).div_ceil(8),
+             (frame.height * component.vertical).div_ceil(frame.max_vertical).div_ceil(8))
+        },
+        _ => (frame.width.div_ceil(8 * frame.max_horizontal), frame.height.div_ceil(8 * frame.max_vertical))
+    };
+
+    // Differences of DC coefficients take up to 11 bits at 8 bit precision and 15 at 12 bit,
+    // AC coefficients up to 10 and 14
+    let dc_limit = frame.precision + 3;
+    let ac_limit = u32::from(frame.precision) + 2;
+    let mut reader = BitReader::new(data, position);
+    for mcu in 0..mcus_wide * mcus_high {
+        if restart_interval > 0 && mcu > 0 && mcu % restart_interval == 0 {
+            reader.restart()?;
+            for component in scan_components.iter() {
+                frame.components[*component].prediction = 0;
+            }
+        }
+        let (mcu_row, mcu_column) = (mcu / mcus_wide, mcu % mcus_wide);
+        for index in scan_components.iter() {
+            let component = &mut frame.components[*index];
+            let (blocks_wide, blocks_high) = match scan_components.len() {
+                1 => (1, 1),
+                _ => (component.horizontal, component.vertical)
+            };
+            for block_row in 0..blocks_high {
+                for block_column in 0..blocks_wide {
+                    let row = mcu_row * blocks_high + block_row;
+                    let column = mcu_column * blocks_wide + block_column;
+                    let dc = dc_tables[component.dc_table].as_ref().ok_or("missing DC Huffman table")?;
+                    let ac = ac_tables[component.ac_table].as_ref().ok_or("missing AC Huffman table")?;
+                    let block = &mut component.coefficients[row * component.blocks_wide + column];
+
+                    let size = reader.decode(dc)?;
+                    if size > dc_limit {
+                        return Err(format!("DC difference category {} is past {}", size, dc_limit));
+                    }
+                    component.prediction = component.prediction.checked_add(reader.receive_extend(u32::from(size)))
+                        .ok_or("DC coefficient out of range")?;
+                    block[0] = component.prediction;
+                    let mut index = 1;
+                    while index < 64 {
+                        let symbol = reader.decode(ac)?;
+                        let (run, size) = (usize::from(symbol >> 4), u32::from(symbol & 0x0F));
+                        if size == 0 {
+                            if run != 15 {
+                                break;
+                            }
+                            index += 16;
+                            continue;
+                        }
+                        if size > ac_limit {
+                            return Err(format!("AC coefficient size {} is past {}", size, ac_limit));
+                        }
+                        index += run;
+                        if index > 63 {
+                            return Err("coefficient index past the end of the block".to_string());
+                        }
+                        block[index] = reader.receive_extend(size);
+                        index += 1;
+                    }
+                }
+            }
+        }
+    }
+    Ok(reader.position)
+}
+
+
+/// Dequantizes and transforms every block, then upsamples each component to the image size.
+fn output(frame: &Frame, quantization_tables: &[[u16; 64]; 4]) -> DecodedImage {
+    let mut cosines = [[0f32; 8]; 8];
+    for (x, row) in cosines.iter_mut().enumerate() {
+        for (u, cosine) in row.iter_mut().enumerate() {
+            let scale = if u == 0 { 1.0 / 2f32.sqrt() } else { 1.0 };
+            *cosine = scale * ((2 * x + 1) as f32 * u as f32 * PI / 16.0).cos() / 2.0;
+        }
+    }
+    let center = (1u32 << (frame.precision - 1)) as f32;
+    let maximum = ((1u32 << frame.precision) - 1) as f32;
+
+    let mut components = Vec::new();
+    for component in frame.components.iter() {
+        let quantization = &quantization_tables[component.quantization_table];
+        let stride = component.blocks_wide * 8;
+        let mut samples = vec![0u16; stride * component.blocks_high * 8];
+        for (index, block) in component.coefficients.iter().enumerate() {
+            let mut natural = [0f32; 64];
+            for (zigzag, coefficient) in block.iter().enumerate() {
+                natural[ZIGZAG[zigzag]] = coefficient.saturating_mul(i32::from(quantization[zigzag])) as f32;
+            }
+            // Separable inverse DCT, rows then columns
+            let mut rows = [0f32; 64];
+            for v in 0..8 {
+                for x in 0..8 {
+                    rows[v * 8 + x] = (0..8).map(|u| cosines[x][u] * natural[v * 8 + u]).sum();
+                }
+            }
+            let (top, left) = (index / component.blocks_wide * 8, index % component.blocks_wide * 8);
+            for y in 0..8 {
+                for x in 0..8 {
+                    let value: f32 = (0..8).map(|v| cosines[y][v] * rows[v * 8 + x]).sum();
+                    samples[(top + y) * stride + left + x] = (value + center).round().clamp(0.0, maximum) as u16;
+                }
+            }
+        }
+
+        let mut upsampled = vec![0u16; frame.width * frame.height];
+        for y in 0..frame.height {
+            let row = y * component.vertical / frame.max_vertical * stride;
+            for x in 0..frame.width {
+                upsampled[y * frame.width + x] = samples[row + x * component.horizontal / frame.max_horizontal];
+            }
+        }
+        components.push(upsampled);
+    }
+
+    DecodedImage {
+        width: frame.width,
+        height: frame.height,
+        precision: frame.precision,
+        components
+    }
+}
+
+
+/// Converts full range ITU-R BT.601 YCbCr samples of the given precision to RGB in place.
+pub(crate) fn ycbcr_to_rgb(components: &mut [Vec<u16>], precision: u8) {
+    if components.len() < 3 {
+        return;
+    }
+    let center = (1u32 << (precision - 1)) as f32;
+    let maximum = ((1u32 << precision) - 1) as f32;
+    let (luma, chroma) = components.split_at_mut(1);
+    let (blue_difference, red_difference) = chroma.split_at_mut(1);
+    for ((y, cb), cr) in luma[0].iter_mut().zip(blue_difference[0].iter_mut()).zip(red_difference[0].iter_mut()) {
+        let (luma, blue, red) = (f32::from(*y), f32::from(*cb) - center, f32::from(*cr) - center);
+        let clamp = |value: f32| value.round().clamp(0.0, maximum) as u16;
+        *y = clamp(luma + 1.402 * red);
+        *cb = clamp(luma - 0.344_136 * blue - 0.714_136 * red);
+        *cr = clamp(luma + 1.772 * blue);
+    }
+}
@@ -0,0 +1,1572 @@
+//! JPEG 2000 codestream decoding (ITU-T T.800) for image segments compressed with IC = C8 and
+//! M8, behind a trait so that another decoder can be substituted
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::error::Error;
+
+use rayon::prelude::*;
+
+use super::ebcot::{self, CodewordSegment, Orientation, BYPASS, TERMINATE_ALL};
+use super::error::NitfError;
+use super::image::Rectangle;
+use super::tre::Tre;
+use super::tre_definition::{TreField, TreRegistry};
+
+const SOC: u16 = 0xFF4F;
+const SIZ: u16 = 0xFF51;
+const COD: u16 = 0xFF52;
+const COC: u16 = 0xFF53;
+const QCD: u16 = 0xFF5C;
+const QCC: u16 = 0xFF5D;
+const TLM: u16 = 0xFF55;
+const RGN: u16 = 0xFF5E;
+const POC: u16 = 0xFF5F;
+const PPM: u16 = 0xFF60;
+const PPT: u16 = 0xFF61;
+const SOT: u16 = 0xFF90;
+const SOP: u16 = 0xFF91;
+const EPH: u16 = 0xFF92;
+const SOD: u16 = 0xFF93;
+const EOC: u16 = 0xFFD9;
+
+/// Lifting coefficients of the irreversible 9-7 wavelet
+const ALPHA: f32 = -1.586_134_3;
+const BETA: f32 = -0.052_980_117;
+const GAMMA: f32 = 0.882_911_1;
+const DELTA: f32 = 0.443_506_87;
+const K: f32 = 1.230_174_1;
+
+
+/// Decoder of the JPEG 2000 codestreams held by image segments with IC = C8 or M8. NITFs use
+/// `BuiltInJpeg2000Decoder` unless another decoder is set with `NITF::set_jpeg2000_decoder`.
+pub trait Jpeg2000Decoder: Send + Sync {
+    /// Decodes the requested region and components of a codestream.
+    fn decode(&self, codestream: &[u8], request: &Jpeg2000Request) -> Result<Jpeg2000Image, Box<dyn Error + Send + Sync>>;
+}
+
+
+/// Part of a codestream to decode
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Jpeg2000Request {
+    /// Region of the reduced resolution image, relative to the image origin
+    pub region: Rectangle,
+    /// Zero based components to decode, in the order they are returned
+    pub components: Vec<usize>,
+    /// Number of resolution levels discarded, each halving the image width and height
+    pub reduction: u8,
+    /// Number of quality layers decoded, or None for every layer
+    pub layers: Option<u16>
+}
+
+
+/// Decoded samples of a requested region
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Jpeg2000Image {
+    /// Number of rows
+    pub rows: u32,
+    /// Number of columns
+    pub columns: u32,
+    /// Samples of each requested component, row by row
+    pub components: Vec<Vec<i32>>
+}
+
+
+/// Options for reading JPEG 2000 compressed image segments
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Jpeg2000Options {
+    /// Number of resolution levels discarded, each halving the image width and height
+    pub reduction: u8,
+    /// Number of quality layers decoded, or None for every layer
+    pub layers: Option<u16>
+}
+
+
+/// Layer information of a JPEG 2000 compressed image from its J2KLRA TRE
+#[derive(Clone, Debug, PartialEq)]
+pub struct Jpeg2000Layers {
+    /// Original Compressed Data (ORIG): 0, 2 and 8 for original NPJE, EPJE and other
+    /// codestreams, 1, 3 and 9 for codestreams parsed from them
+    pub original: u8,
+    /// Number of wavelet decomposition levels in the codestream (NLEVELS_I when parsed,
+    /// otherwise NLEVELS_O)
+    pub levels: u8,
+    /// Number of bands in the codestream (NBANDS_I when parsed, otherwise NBANDS_O)
+    pub bands: u32,
+    /// Number of layers in the codestream (NLAYERS_I when parsed, otherwise NLAYERS_O)
+    pub layer_count: u16,
+    /// Identifier and bitrate in bits per pixel of each layer of the original codestream
+    /// (LAYER_ID, BITRATE)
+    pub layers: Vec<(u16, f64)>
+}
+
+impl Jpeg2000Layers {
+
+    /// Returns the layer information held in a J2KLRA TRE.
+    pub fn from_tre(tre: &Tre) -> Result<Jpeg2000Layers, NitfError> {
+        if tre.tag != "J2KLRA" {
+            return Err(NitfError::InvalidValue { field: "CETAG".to_string(), offset: tre.offset, value: tre.tag.clone() });
+        }
+        let fields = TreRegistry::built_in().decode(tre)?.unwrap_or_default();
+        let integer = |name: &str| fields.iter().find(|field| field.name == name).and_then(|field| field.value.as_integer());
+        let parsed = |input: &str, original: &str| integer(input).or_else(|| integer(original)).unwrap_or(0);
+        let ids: Vec<&TreField> = fields.iter().filter(|field| field.name == "LAYER_ID").collect();
+        let bitrates: Vec<&TreField> = fields.iter().filter(|field| field.name == "BITRATE").collect();
+        Ok(Jpeg2000Layers {
+            original: integer("ORIG").unwrap_or(0) as u8,
+            levels: parsed("NLEVELS_I", "NLEVELS_O") as u8,
+            bands: parsed("NBANDS_I", "NBANDS_O") as u32,
+            layer_count: parsed("NLAYERS_I", "NLAYERS_O") as u16,
+            layers: ids.iter().zip(bitrates.iter()).map(|(id, bitrate)| {
+                (id.value.as_integer().unwrap_or(0) as u16, bitrate.value.as_real().unwrap_or(0.0))
+            }).collect()
+        })
+    }
+
+    /// Returns the number of leading layers whose cumulative bitrate is within `bitrate` bits
+    /// per pixel, for reading a codestream at a chosen quality.
+    pub fn layers_for_bitrate(&self, bitrate: f64) -> u16 {
+        let within = self.layers.iter().take_while(|(_, layer_bitrate)| *layer_bitrate <= bitrate).count() as u16;
+        within.min(self.layer_count).max(1)
+    }
+}
+
+
+/// JPEG 2000 Part 1 decoder written in Rust, supporting both wavelets, every progression
+/// order, precincts, tiles and the code-block style options. Tiles outside the requested
+/// region and resolution levels above the requested one are not decoded.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BuiltInJpeg2000Decoder;
+
+impl Jpeg2000Decoder for BuiltInJpeg2000Decoder {
+    fn decode(&self, codestream: &[u8], request: &Jpeg2000Request) -> Result<Jpeg2000Image, Box<dyn Error + Send + Sync>> {
+        Ok(decode(codestream, request)?)
+    }
+}
+
+
+/// Returns the codestream of a JP2 file, or the data itself when it is already a codestream.
+pub(crate) fn codestream(data: &[u8]) -> &[u8] {
+    if data.get(4..8) != Some(b"jP  ") {
+        return data;
+    }
+    let mut position = 0;
+    while position + 8 <= data.len() {
+        let length = u32::from_be_bytes([data[position], data[position + 1], data[position + 2], data[position + 3]]) as usize;
+        let (header, length) = match length {
+            0 => (8, data.len() - position),
+            1 => match data.get(position + 8..position + 16) {
+                Some(bytes) => (16, bytes.iter().fold(0u64, |length, byte| length << 8 | u64::from(*byte)) as usize),
+                None => break
+            },
+            length => (8, length)
+        };
+        if &data[position + 4..position + 8] == b"jp2c" {
+            return &data[(position + header).min(data.len())..(position + length).min(data.len())];
+        }
+        if length < header {
+            break;
+        }
+        position += length;
+    }
+    data
+}
+
+
+/// Reader of big endian marker segment fields
+struct Fields<'a> {
+    data: &'a [u8],
+    position: usize
+}
+
+impl<'a> Fields<'a> {
+    fn new(data: &'a [u8]) -> Fields<'a> {
+        Fields {
+            data,
+            position: 0
+        }
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let bytes = self.data.get(self.position..self.position + length).ok_or("truncated marker segment")?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from(bytes[0]) << 8 | u16::from(bytes[1]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len().saturating_sub(self.position)
+    }
+}
+
+
+/// Image and tile size (SIZ)
+struct Size {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+    tile_x0: u32,
+    tile_y0: u32,
+    tile_width: u32,
+    tile_height: u32,
+    components: Vec<ComponentSize>
+}
+
+struct ComponentSize {
+    precision: u8,
+    signed: bool,
+    dx: u32,
+    dy: u32
+}
+
+impl Size {
+    fn parse(segment: &[u8]) -> Result<Size, String> {
+        let mut fields = Fields::new(segment);
+        fields.u16()?;
+        let (x1, y1, x0, y0) = (fields.u32()?, fields.u32()?, fields.u32()?, fields.u32()?);
+        let (tile_width, tile_height, tile_x0, tile_y0) = (fields.u32()?, fields.u32()?, fields.u32()?, fields.u32()?);
+        let count = fields.u16()?;
+        let components = (0..count).map(|_| {
+            let depth = fields.u8()?;
+            let (dx, dy) = (u32::from(fields.u8()?), u32::from(fields.u8()?));
+            if dx == 0 || dy == 0 || (depth & 0x7F) > 37 {
+                return Err("invalid component size".to_string());
+            }
+            Ok(ComponentSize {
+                precision: (depth & 0x7F) + 1,
+                signed: depth & 0x80 != 0,
+                dx,
+                dy
+            })
+        }).collect::<Result<Vec<_>, String>>()?;
+        if x1 <= x0 || y1 <= y0 || tile_width == 0 || tile_height == 0 || tile_x0 > x0 || tile_y0 > y0 || components.is_empty() {
+            return Err("invalid image and tile size".to_string());
+        }
+        let size = Size {
+            x0,
+            y0,
+            x1,
+            y1,
+            tile_x0,
+            tile_y0,
+            tile_width,
+            tile_height,
+            components
+        };
+        // Tile indices (Isot) run from 0 to 65534
+        if u64::from(size.tiles_wide()) * u64::from(size.tiles_high()) > 65535 {
+            return Err("too many tiles".to_string());
+        }
+        Ok(size)
+    }
+
+    /// Returns the image size on the reference grid as (rows, columns).
+    fn dimensions(&self) -> (u32, u32) {
+        (self.y1 - self.y0, self.x1 - self.x0)
+    }
+
+    fn tiles_wide(&self) -> u32 {
+        (self.x1 - self.tile_x0).div_ceil(self.tile_width)
+    }
+
+    fn tiles_high(&self) -> u32 {
+        (self.y1 - self.tile_y0).div_ceil(self.tile_height)
+    }
+
+    /// Returns the reference grid area of a tile as (x0, y0, x1, y1).
+    fn tile(&self, index: usize) -> (u32, u32, u32, u32) {
+        let (p, q) = (index as u32 % self.tiles_wide(), index as u32 / self.tiles_wide());
+        let x0 = (u64::from(self.tile_x0) + u64::from(p) * u64::from(self.tile_width)).max(u64::from(self.x0));
+        let y0 = (u64::from(self.tile_y0) + u64::from(q) * u64::from(self.tile_height)).max(u64::from(self.y0));
+        let x1 = (u64::from(self.tile_x0) + u64::from(p + 1) * u64::from(self.tile_width)).min(u64::from(self.x1));
+        let y1 = (u64::from(self.tile_y0) + u64::from(q + 1) * u64::from(self.tile_height)).min(u64::from(self.y1));
+        (x0 as u32, y0 as u32, x1 as u32, y1 as u32)
+    }
+}
+
+
+/// Coding style of one component (SPcod or SPcoc)
+#[derive(Clone, Debug)]
+struct ComponentCoding {
+    levels: u8,
+    block_width: u8,
+    block_height: u8,
+    style: u8,
+    reversible: bool,
+    /// Precinct width and height exponents of each resolution level
+    precincts: Vec<(u8, u8)>
+}
+
+impl ComponentCoding {
+    fn parse(fields: &mut Fields, precincts_defined: bool) -> Result<ComponentCoding, String> {
+        let levels = fields.u8()?;
+        let block_width = fields.u8()? + 2;
+        let block_height = fields.u8()? + 2;
+        let style = fields.u8()?;
+        let reversible = fields.u8()? == 1;
+        if levels > 32 || block_width > 10 || block_height > 10 || block_width + block_height > 12 {
+            return Err("invalid coding style".to_string());
+        }
+        let precincts = (0..=levels).map(|level| match precincts_defined {
+            true => fields.u8().map(|size| (size & 0x0F, size >> 4)).and_then(|(width, height)| match level > 0 && (width == 0 || height == 0) {
+                true => Err("invalid precinct size".to_string()),
+                false => Ok((width, height))
+            }),
+            false => Ok((15, 15))
+        }).collect::<Result<_, _>>()?;
+        Ok(ComponentCoding {
+            levels,
+            block_width,
+            block_height,
+            style,
+            reversible,
+            precincts
+        })
+    }
+}
+
+
+/// Coding style defaults (COD)
+#[derive(Clone, Debug)]
+struct Coding {
+    progression: u8,
+    layers: u16,
+    transform: bool,
+    sop: bool,
+    eph: bool,
+    component: ComponentCoding
+}
+
+
+/// Quantization of one component (QCD or QCC)
+#[derive(Clone, Debug)]
+struct Quantization {
+    style: u8,
+    guard_bits: u8,
+    /// Exponent and mantissa of each subband's step size
+    steps: Vec<(u8, u16)>
+}
+
+impl Quantization {
+    fn parse(fields: &mut Fields) -> Result<Quantization, String> {
+        let style = fields.u8()?;
+        let mut steps = Vec::new();
+        match style & 0x1F {
+            0 => while fields.remaining() > 0 {
+                steps.push((fields.u8()? >> 3, 0));
+            },
+            1 | 2 => while fields.remaining() > 1 {
+                let step = fields.u16()?;
+                steps.push(((step >> 11) as u8, step & 0x7FF));
+            },
+            _ => return Err("invalid quantization style".to_string())
+        }
+        if steps.is_empty() {
+            return Err("quantization without step sizes".to_string());
+        }
+        Ok(Quantization {
+            style: style & 0x1F,
+            guard_bits: style >> 5,
+            steps
+        })
+    }
+
+    /// Returns the exponent and mantissa of a subband, numbered in the order step sizes are
+    /// recorded, at the given decomposition level.
+    fn step(&self, band: usize, level: u8, levels: u8) -> (u8, u16) {
+        match self.style {
+            // Scalar derived quantization signals the LL step size only
+            1 => (self.steps[0].0.saturating_add(level).saturating_sub(levels), self.steps[0].1),
+            _ => self.steps.get(band).copied().unwrap_or(*self.steps.last().expect("quantization has step sizes"))
+        }
+    }
+}
+
+
+/// Coding and quantization marker segments of the main header or of one tile's headers
+#[derive(Clone, Default)]
+struct Markers {
+    coding: Option<Coding>,
+    component_coding: HashMap<usize, ComponentCoding>,
+    quantization: Option<Quantization>,
+    component_quantization: HashMap<usize, Quantization>,
+    region_shift: HashMap<usize, u8>
+}
+
+impl Markers {
+    fn parse(&mut self, marker: u16, segment: &[u8], components: usize) -> Result<(), String> {
+        let mut fields = Fields::new(segment);
+        let component = |fields: &mut Fields| -> Result<usize, String> {
+            let index = if components < 257 { usize::from(fields.u8()?) } else { usize::from(fields.u16()?) };
+            if index >= components {
+                return Err("marker segment for a component that does not exist".to_string());
+            }
+            Ok(index)
+        };
+        match marker {
+            COD => {
+                let style = fields.u8()?;
+                let progression = fields.u8()?;
+                let layers = fields.u16()?;
+                let transform = fields.u8()? == 1;
+                if progression > 4 || layers == 0 {
+                    return Err("invalid coding style".to_string());
+                }
+                self.coding = Some(Coding {
+                    progression,
+                    layers,
+                    transform,
+                    sop: style & 0x02 != 0,
+                    eph: style & 0x04 != 0,
+                    component: ComponentCoding::parse(&mut fields, style & 0x01 != 0)?
+                });
+            },
+            COC => {
+                let index = component(&mut fields)?;
+                let style = fields.u8()?;
+                self.component_coding.insert(index, ComponentCoding::parse(&mut fields, style & 0x01 != 0)?);
+            },
+            QCD => self.quantization = Some(Quantization::parse(&mut fields)?),
+            QCC => {
+                let index = component(&mut fields)?;
+                self.component_quantization.insert(index, Quantization::parse(&mut fields)?);
+            },
+            RGN => {
+                let index = component(&mut fields)?;
+                if fields.u8()? != 0 {
+                    return Err("unknown region of interest style".to_string());
+                }
+                self.region_shift.insert(index, fields.u8()?);
+            },
+            POC => return Err("progression order changes are not supported".to_string()),
+            PPM | PPT => return Err("packed packet headers are not supported".to_string()),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+
+/// Parsed codestream, with the data of each tile's tile-parts in order
+struct Codestream<'a> {
+    size: Size,
+    main: Markers,
+    tiles: Vec<(Markers, Vec<&'a [u8]>)>
+}
+
+impl<'a> Codestream<'a> {
+    fn parse(data: &'a [u8]) -> Result<Codestream<'a>, String> {
+        let mut fields = Fields::new(data);
+        if fields.u16()? != SOC {
+            return Err("missing start of codestream marker".to_string());
+        }
+        let mut size: Option<Size> = None;
+        let mut main = Markers::default();
+        let mut tiles: Vec<(Markers, Vec<&[u8]>)> = Vec::new();
+
+        loop {
+            let start = fields.position;
+            let marker = match fields.u16() {
+                Ok(marker) => marker,
+                Err(_) => break
+            };
+            if marker == EOC {
+                break;
+            }
+            if marker < 0xFF30 {
+                return Err(format!("invalid marker 0x{:04X}", marker));
+            }
+            let length = usize::from(fields.u16()?);
+            let segment = fields.bytes(length.checked_sub(2).ok_or("invalid marker segment length")?)?;
+            match (marker, &size) {
+                (SIZ, None) => {
+                    let parsed = Size::parse(segment)?;
+                    let count = parsed.tiles_wide() as usize * parsed.tiles_high() as usize;
+                    tiles = vec![(Markers::default(), Vec::new()); count];
+                    size = Some(parsed);
+                },
+                (SOT, Some(size)) => {
+                    let mut sot = Fields::new(segment);
+                    let tile = usize::from(sot.u16()?);
+                    let length = sot.u32()? as usize;
+                    let (markers, parts) = tiles.get_mut(tile).ok_or("tile-part for a tile that does not exist")?;
+                    loop {
+                        let marker = fields.u16()?;
+                        if marker == SOD {
+                            break;
+                        }
+                        let length = usize::from(fields.u16()?);
+                        let segment = fields.bytes(length.checked_sub(2).ok_or("invalid marker segment length")?)?;
+                        markers.parse(marker, segment, size.components.len())?;
+                    }
+                    let end = match length {
+                        // The last tile-part may run to the end of the codestream
+                        0 => data.len() - if data.ends_with(&[0xFF, 0xD9]) { 2 } else { 0 },
+                        length => (start + length).min(data.len())
+                    };
+                    parts.push(data.get(fields.position..end).ok_or("invalid tile-part length")?);
+                    fields.position = end;
+                },
+                (_, Some(_)) => main.parse(marker, segment, size.as_ref().map(|size| size.components.len()).unwrap_or(0))?,
+                (_, None) => return Err("missing image and tile size marker".to_string())
+            }
+        }
+
+        Ok(Codestream {
+            size: size.ok_or("missing image and tile size marker")?,
+            main,
+            tiles
+        })
+    }
+}
+
+
+/// Tile index, offset and length of a tile-part
+type TilePart = (usize, u64, u64);
+
+
+/// Locations of the main header and tile-parts of a codestream within the image data, for
+/// reading only the tile-parts that a region needs
+pub(crate) struct CodestreamIndex {
+    /// Main header, from SOC up to the first SOT marker segment
+    header: Vec<u8>,
+    size: Size,
+    /// Fewest decomposition levels of any component in the main header
+    levels: u8,
+    /// Tile index, offset and length of each tile-part in codestream order
+    tile_parts: Vec<TilePart>
+}
+
+impl CodestreamIndex {
+
+    /// Indexes the codestream, or the JP2 file holding one, in the first `length` bytes read
+    /// through `read`. Tile-parts are located from the TLM marker segments of the main header
+    /// when it has them and otherwise by following the length of each SOT marker segment.
+    /// Returns None when the data is not laid out as expected, leaving the decoder to report
+    /// the problem from the whole codestream.
+    pub(crate) fn new<'a>(read: &dyn Fn(u64, usize) -> Result<Cow<'a, [u8]>, NitfError>, length: u64) -> Result<Option<CodestreamIndex>, NitfError> {
+        let (start, end) = match codestream_range(read, length)? {
+            Some(range) => range,
+            None => return Ok(None)
+        };
+
+        // Read the main header, growing the bytes read until the first SOT marker is found
+        let available = (end - start) as usize;
+        let mut header = read(start, available.min(4096))?.into_owned();
+        let mut segments = Vec::new();
+        let mut position = 2;
+        if !header.starts_with(&SOC.to_be_bytes()) {
+            return Ok(None);
+        }
+        loop {
+            while position + 4 > header.len() || position + 2 + usize::from(u16::from_be_bytes([header[position + 2], header[position + 3]])) > header.len() {
+                if header.len() == available {
+                    return Ok(None);
+                }
+                header = read(start, (header.len() * 2).min(available))?.into_owned();
+            }
+            let marker = u16::from_be_bytes([header[position], header[position + 1]]);
+            if marker == SOT {
+                break;
+            }
+            let segment_length = usize::from(u16::from_be_bytes([header[position + 2], header[position + 3]]));
+            if marker < 0xFF30 || segment_length < 2 {
+                return Ok(None);
+            }
+            segments.push((marker, position + 4..position + 2 + segment_length));
+            position += 2 + segment_length;
+        }
+        header.truncate(position);
+
+        let size = match segments.iter().find(|(marker, _)| *marker == SIZ).map(|(_, range)| Size::parse(&header[range.clone()])) {
+            Some(Ok(size)) => size,
+            _ => return Ok(None)
+        };
+        let mut main = Markers::default();
+        for (marker, range) in &segments {
+            if *marker != SIZ && main.parse(*marker, &header[range.clone()], size.components.len()).is_err() {
+                return Ok(None);
+            }
+        }
+        let levels = match &main.coding {
+            Some(coding) => main.component_coding.values().map(|coding| coding.levels).fold(coding.component.levels, u8::min),
+            None => return Ok(None)
+        };
+        if levels > 32 {
+            return Ok(None);
+        }
+
+        let first = start + position as u64;
+        let tile_count = size.tiles_wide() as usize * size.tiles_high() as usize;
+        let mut tlm: Vec<&[u8]> = segments.iter().filter(|(marker, _)| *marker == TLM).map(|(_, range)| &header[range.clone()]).collect();
+        let tile_parts = match tlm.is_empty() {
+            false => {
+                tlm.sort_by_key(|segment| segment.first().copied());
+                CodestreamIndex::listed_tile_parts(&tlm, first)
+            },
+            true => CodestreamIndex::walk_tile_parts(read, first, end)?
+        };
+        match tile_parts {
+            Some(tile_parts) if tile_parts.iter().all(|(tile, offset, length)| *tile < tile_count && offset + length <= end) => Ok(Some(CodestreamIndex {
+                header,
+                size,
+                levels,
+                tile_parts
+            })),
+            _ => Ok(None)
+        }
+    }
+
+    /// Returns the tile-parts listed by TLM marker segments in index (Ztlm) order, the first
+    /// starting at `first`.
+    fn listed_tile_parts(tlm: &[&[u8]], first: u64) -> Option<Vec<TilePart>> {
+        let mut tile_parts = Vec::new();
+        let mut offset = first;
+        for segment in tlm {
+            let style = *segment.get(1)?;
+            let tile_bytes = usize::from(style >> 4 & 0x03);
+            let length_bytes = if style & 0x40 != 0 { 4 } else { 2 };
+            if tile_bytes == 3 {
+                return None;
+            }
+            for entry in segment.get(2..)?.chunks(tile_bytes + length_bytes) {
+                if entry.len() != tile_bytes + length_bytes {
+                    return None;
+                }
+                let value = |bytes: &[u8]| bytes.iter().fold(0u64, |value, byte| value << 8 | u64::from(*byte));
+                // Without tile numbers each tile has one tile-part, in tile order
+                let tile = match tile_bytes {
+                    0 => tile_parts.len(),
+                    _ => value(&entry[..tile_bytes]) as usize
+                };
+                let length = value(&entry[tile_bytes..]);
+                if length == 0 {
+                    return None;
+                }
+                tile_parts.push((tile, offset, length));
+                offset += length;
+            }
+        }
+        Some(tile_parts)
+    }
+
+    /// Returns the tile-parts found by reading each SOT marker segment from `first` and
+    /// skipping its tile-part length (Psot) to the next, up to EOC or `end`.
+    fn walk_tile_parts<'a>(read: &dyn Fn(u64, usize) -> Result<Cow<'a, [u8]>, NitfError>, first: u64, end: u64) -> Result<Option<Vec<TilePart>>, NitfError> {
+        let mut tile_parts = Vec::new();
+        let mut offset = first;
+        while offset + 2 <= end {
+            let sot = read(offset, (end - offset).min(12) as usize)?;
+            if sot.starts_with(&EOC.to_be_bytes()) {
+                break;
+            }
+            if sot.len() < 12 || !sot.starts_with(&SOT.to_be_bytes()) {
+                return Ok(None);
+            }
+            let tile = usize::from(u16::from_be_bytes([sot[4], sot[5]]));
+            let length = match u64::from(u32::from_be_bytes([sot[6], sot[7], sot[8], sot[9]])) {
+                // The last tile-part may run to the end of the codestream
+                0 => end - offset - if read(end - 2, 2)?[..] == EOC.to_be_bytes() { 2 } else { 0 },
+                length => length
+            };
+            tile_parts.push((tile, offset, length));
+            offset += length;
+        }
+        Ok(Some(tile_parts))
+    }
+
+    /// Returns the fewest decomposition levels of any component in the main header.
+    pub(crate) fn levels(&self) -> u8 {
+        self.levels
+    }
+
+    /// Returns the image size given by SIZ as (rows, columns).
+    pub(crate) fn dimensions(&self) -> (u32, u32) {
+        self.size.dimensions()
+    }
+
+    /// Returns a codestream holding the main header and only the tile-parts of the tiles that
+    /// intersect `region` of the image reduced by `reduction` levels, read through `read`.
+    pub(crate) fn region<'a>(&self, read: &dyn Fn(u64, usize) -> Result<Cow<'a, [u8]>, NitfError>, region: Rectangle, reduction: u8) -> Result<Vec<u8>, NitfError> {
+        let tiles = region_tiles(&self.size, region, u32::from(reduction));
+        let mut codestream = self.header.clone();
+        for (_, offset, length) in self.tile_parts.iter().filter(|(tile, _, _)| tiles.contains(tile)) {
+            codestream.extend_from_slice(&read(*offset, *length as usize)?);
+        }
+        codestream.extend_from_slice(&EOC.to_be_bytes());
+        Ok(codestream)
+    }
+}
+
+
+/// Returns the offset and end of the codestream in the first `length` bytes read through
+/// `read`, which may be a JP2 file holding it in a contiguous codestream box.
+fn codestream_range<'a>(read: &dyn Fn(u64, usize) -> Result<Cow<'a, [u8]>, NitfError>, length: u64) -> Result<Option<(u64, u64)>, NitfError> {
+    if length < 8 {
+        return Ok(None);
+    }
+    if &read(0, 8)?[4..8] != b"jP  " {
+        return Ok(Some((0, length)));
+    }
+    let mut position = 0;
+    while position + 8 <= length {
+        let header = read(position, (length - position).min(16) as usize)?;
+        let (header_length, box_length) = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+            0 => (8, length - position),
+            1 if header.len() == 16 => (16, header[8..16].iter().fold(0u64, |length, byte| length << 8 | u64::from(*byte))),
+            1 => return Ok(None),
+            box_length => (8, u64::from(box_length))
+        };
+        if &header[4..8] == b"jp2c" {
+            return Ok(Some((position + header_length, (position + box_length).min(length))));
+        }
+        if box_length < header_length {
+            break;
+        }
+        position += box_length;
+    }
+    Ok(None)
+}
+
+
+/// Returns the tiles that intersect `region` of the image reduced by `reduction` levels,
+/// found from the tile grid rather than by testing every tile.
+fn region_tiles(size: &Size, region: Rectangle, reduction: u32) -> Vec<usize> {
+    if region.rows == 0 || region.columns == 0 {
+        return Vec::new();
+    }
+    // A tile holds reduced samples first to last when it starts at or before the reference
+    // grid sample last << reduction and ends after first << reduction
+    let span = |origin: u32, first: u32, count: u32, tile_origin: u32, tile_size: u32, tiles: u32| {
+        let first = u64::from(ceil_shift(origin, reduction)) + u64::from(first);
+        let tile = |x: u64| ((x - u64::from(tile_origin)) / u64::from(tile_size)).min(u64::from(tiles) - 1) as usize;
+        tile(first << reduction)..=tile((first + u64::from(count) - 1) << reduction)
+    };
+    let columns = span(size.x0, region.column, region.columns, size.tile_x0, size.tile_width, size.tiles_wide());
+    let rows = span(size.y0, region.row, region.rows, size.tile_y0, size.tile_height, size.tiles_high());
+    let tiles_wide = size.tiles_wide() as usize;
+    rows.flat_map(|q| columns.clone().map(move |p| q * tiles_wide + p)).collect()
+}
+
+
+/// Coding parameters of one tile, with tile header markers taking precedence over the main
+/// header and component specific markers over defaults
+struct TileCoding {
+    progression: u8,
+    layers: u16,
+    transform: bool,
+    sop: bool,
+    eph: bool,
+    components: Vec<(ComponentCoding, Quantization, u8)>
+}
+
+impl TileCoding {
+    fn new(main: &Markers, tile: &Markers, components: usize) -> Result<TileCoding, String> {
+        let coding = tile.coding.as_ref().or(main.coding.as_ref()).ok_or("missing coding style marker")?;
+        let components = (0..components).map(|index| {
+            let component_coding = tile.component_coding.get(&index)
+                .or(tile.coding.as_ref().map(|coding| &coding.component))
+                .or(main.component_coding.get(&index))
+                .unwrap_or(&coding.component);
+            let quantization = tile.component_quantization.get(&index)
+                .or(tile.quantization.as_ref())
+                .or(main.component_quantization.get(&index))
+                .or(main.quantization.as_ref())
+                .ok_or("missing quantization marker")?;
+            let shift = tile.region_shift.get(&index).or(main.region_shift.get(&index)).copied().unwrap_or(0);
+            Ok((component_coding.clone(), quantization.clone(), shift))
+        }).collect::<Result<_, String>>()?;
+        Ok(TileCoding {
+            progression: coding.progression,
+            layers: coding.layers,
+            transform: coding.transform,
+            sop: coding.sop,
+            eph: coding.eph,
+            components
+        })
+    }
+}
+
+
+/// Tag tree (T.800 B.10.2) coding a value for each code-block of a precinct
+struct TagTree {
+    /// Width and height of each level, from the leaves to the root
+    levels: Vec<(usize, usize)>,
+    /// Value, or u32::MAX while unknown, and current lower bound of each node
+    nodes: Vec<Vec<(u32, u32)>>
+}
+
+impl TagTree {
+    fn new(width: usize, height: usize) -> TagTree {
+        let (mut levels, mut nodes) = (Vec::new(), Vec::new());
+        let (mut width, mut height) = (width.max(1), height.max(1));
+        loop {
+            levels.push((width, height));
+            nodes.push(vec![(u32::MAX, 0); width * height]);
+            if width == 1 && height == 1 {
+                break;
+            }
+            width = width.div_ceil(2);
+            height = height.div_ceil(2);
+        }
+        TagTree {
+            levels,
+            nodes
+        }
+    }
+
+    /// Returns true when the value of the leaf is below `threshold`, reading as much of the
+    /// tree as needed to tell.
+    fn decode(&mut self, bits: &mut HeaderBits, x: usize, y: usize, threshold: u32) -> Result<bool, String> {
+        let mut low = 0;
+        for level in (0..self.levels.len()).rev() {
+            let index = (y >> level) * self.levels[level].0 + (x >> level);
+            let node = &mut self.nodes[level][index];
+            if low > node.1 {
+                node.1 = low;
+            } else {
+                low = node.1;
+            }
+            while low < threshold && low < node.0 {
+                if bits.bit()? == 1 {
+                    node.0 = low;
+                } else {
+                    low += 1;
+                }
+            }
+            node.1 = low;
+        }
+        Ok(self.nodes[0][y * self.levels[0].0 + x].0 < threshold)
+    }
+
+    /// Returns the value of a leaf, reading it completely.
+    fn value(&mut self, bits: &mut HeaderBits, x: usize, y: usize) -> Result<u32, String> {
+        let mut threshold = 1;
+        while !self.decode(bits, x, y, threshold)? {
+            threshold += 1;
+        }
+        Ok(threshold - 1)
+    }
+}
+
+
+/// Reader of packet header bits, which skips the zero bit stuffed after each 0xFF byte
+struct HeaderBits<'a> {
+    data: &'a [u8],
+    position: usize,
+    byte: u8,
+    count: u32
+}
+
+impl<'a> HeaderBits<'a> {
+    fn new(data: &'a [u8], position: usize) -> HeaderBits<'a> {
+        HeaderBits {
+            data,
+            position,
+            byte: 0,
+            count: 0
+        }
+    }
+
+    fn bit(&mut self) -> Result<u32, String> {
+        if self.count == 0 {
+            self.count = if self.byte == 0xFF { 7 } else { 8 };
+            self.byte = *self.data.get(self.position).ok_or("truncated packet header")?;
+            self.position += 1;
+        }
+        self.count -= 1;
+        Ok(u32::from(self.byte >> self.count) & 1)
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32, String> {
+        (0..count).try_fold(0, |value, _| Ok(value << 1 | self.bit()?))
+    }
+
+    /// Skips to the end of the header, including the byte stuffed after a final 0xFF.
+    fn align(&mut self) {
+        if self.byte == 0xFF {
+            self.position += 1;
+        }
+        self.count = 0;
+    }
+
+    /// Number of coding passes (T.800 Table B.4)
+    fn passes(&mut self) -> Result<u32, String> {
+        if self.bit()? == 0 {
+            return Ok(1);
+        }
+        if self.bit()? == 0 {
+            return Ok(2);
+        }
+        match self.bits(2)? {
+            3 => match self.bits(5)? {
+                31 => Ok(37 + self.bits(7)?),
+                passes => Ok(6 + passes)
+            },
+            passes => Ok(3 + passes)
+        }
+    }
+}
+
+
+/// Code-block and what has been read of it so far
+struct CodeBlock {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+    included: bool,
+    length_bits: u32,
+    bitplanes: u32,
+    /// Coding passes read from every layer, including layers that are not decoded
+    passes: u32,
+    /// Passes that still fit in the last codeword segment
+    segment_room: u32,
+    segments: Vec<CodewordSegment>
+}
+
+
+/// The code-blocks of one subband within one precinct
+struct PrecinctBand {
+    blocks_wide: usize,
+    inclusion: TagTree,
+    zero_bitplanes: TagTree,
+    blocks: Vec<CodeBlock>
+}
+
+
+struct Band {
+    orientation: Orientation,
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+    /// Magnitude bit-planes (Mb) plus any region of interest shift
+    bitplanes: u32,
+    step: f32,
+    precincts: Vec<PrecinctBand>,
+    coefficients: Vec<f32>
+}
+
+
+struct Resolution {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+    precinct_width: u8,
+    precinct_height: u8,
+    precincts_wide: u32,
+    precincts_high: u32,
+    bands: Vec<Band>
+}
+
+
+struct TileComponent {
+    coding: ComponentCoding,
+    region_shift: u8,
+    resolutions: Vec<Resolution>
+}
+
+
+fn ceil_shift(value: u32, shift: u32) -> u32 {
+    ((u64::from(value) + (1u64 << shift) - 1) >> shift) as u32
+}
+
+
+impl TileComponent {
+    fn new(area: (u32, u32, u32, u32), size: &ComponentSize, (coding, quantization, region_shift): &(ComponentCoding, Quantization, u8)) -> Result<TileComponent, String> {
+        let (x0, y0) = (area.0.div_ceil(size.dx), area.1.div_ceil(size.dy));
+        let (x1, y1) = (area.2.div_ceil(size.dx), area.3.div_ceil(size.dy));
+        let levels = u32::from(coding.levels);
+        let mut resolutions = Vec::new();
+        for resolution in 0..=levels {
+            let shift = levels - resolution;
+            let (rx0, ry0, rx1, ry1) = (ceil_shift(x0, shift), ceil_shift(y0, shift), ceil_shift(x1, shift), ceil_shift(y1, shift));
+            let (precinct_width, precinct_height) = coding.precincts[resolution as usize];
+            let (pw, ph) = (u32::from(precinct_width), u32::from(precinct_height));
+            let precincts_wide = if rx1 > rx0 { ceil_shift(rx1, pw) - (rx0 >> pw) } else { 0 };
+            let precincts_high = if ry1 > ry0 { ceil_shift(ry1, ph) - (ry0 >> ph) } else { 0 };
+
+            let orientations: &[(Orientation, u32, u32)] = match resolution {
+                0 => &[(Orientation::LowLow, 0, 0)],
+                _ => &[(Orientation::HighLow, 1, 0), (Orientation::LowHigh, 0, 1), (Orientation::HighHigh, 1, 1)]
+            };
+            let level = if resolution == 0 { levels } else { levels - resolution + 1 };
+            let mut bands = Vec::new();
+            for (number, (orientation, xo, yo)) in orientations.iter().enumerate() {
+                let band_edge = |value: u32, offset: u32| -> u32 {
+                    if level == 0 {
+                        return value;
+                    }
+                    let shifted = i64::from(value) - (i64::from(offset) << (level - 1));
+                    (shifted + (1i64 << level) - 1).div_euclid(1i64 << level).max(0) as u32
+                };
+                let (bx0, by0, bx1, by1) = (band_edge(x0, *xo), band_edge(y0, *yo), band_edge(x1, *xo), band_edge(y1, *yo));
+
+                let index = if resolution == 0 { 0 } else { 3 * (resolution as usize - 1) + number + 1 };
+                let (exponent, mantissa) = quantization.step(index, level as u8, coding.levels);
+                let gain = match orientation {
+                    Orientation::LowLow => 0,
+                    Orientation::HighHigh => 2,
+                    _ => 1
+                };
+                let bitplanes = (u32::from(quantization.guard_bits) + u32::from(exponent)).saturating_sub(1) + u32::from(*region_shift);
+                if bitplanes > 31 {
+                    return Err("too many magnitude bit-planes".to_string());
+                }
+                let step = match coding.reversible {
+                    true => 1.0,
+                    false => 2f32.powi(i32::from(size.precision) + gain - i32::from(exponent)) * (1.0 + f32::from(mantissa) / 2048.0)
+                };
+
+                // Precincts and code-blocks partition the subband at half the resolution's size
+                let (pw_band, ph_band) = if resolution == 0 { (pw, ph) } else { (pw - 1, ph - 1) };
+                let block_width = u32::from(coding.block_width).min(pw_band);
+                let block_height = u32::from(coding.block_height).min(ph_band);
+                let start_x = ((rx0 >> pw) << pw) >> (pw - pw_band);
+                let start_y = ((ry0 >> ph) << ph) >> (ph - ph_band);
+                let mut precincts = Vec::new();
+                for precinct in 0..precincts_wide * precincts_high {
+                    let (i, j) = (precinct % precincts_wide, precinct / precincts_wide);
+                    let px0 = (u64::from(start_x) + (u64::from(i) << pw_band)).max(u64::from(bx0)) as u32;
+                    let py0 = (u64::from(start_y) + (u64::from(j) << ph_band)).max(u64::from(by0)) as u32;
+                    let px1 = (u64::from(start_x) + (u64::from(i + 1) << pw_band)).min(u64::from(bx1)) as u32;
+                    let py1 = (u64::from(start_y) + (u64::from(j + 1) << ph_band)).min(u64::from(by1)) as u32;
+                    let (mut blocks_wide, mut blocks_high, mut blocks) = (0, 0, Vec::new());
+                    if px1 > px0 && py1 > py0 {
+                        let (cx0, cy0) = ((px0 >> block_width) << block_width, (py0 >> block_height) << block_height);
+                        blocks_wide = ((ceil_shift(px1, block_width) << block_width) - cx0) as usize >> block_width;
+                        blocks_high = ((ceil_shift(py1, block_height) << block_height) - cy0) as usize >> block_height;
+                        for index in 0..blocks_wide * blocks_high {
+                            let (bi, bj) = ((index % blocks_wide) as u32, (index / blocks_wide) as u32);
+                            blocks.push(CodeBlock {
+                                x0: (cx0 + (bi << block_width)).max(px0),
+                                y0: (cy0 + (bj << block_height)).max(py0),
+                                x1: (cx0 + ((bi + 1) << block_width)).min(px1),
+                                y1: (cy0 + ((bj + 1) << block_height)).min(py1),
+                                included: false,
+                                length_bits: 3,
+                                bitplanes: 0,
+                                passes: 0,
+                                segment_room: 0,
+                                segments: Vec::new()
+                            });
+                        }
+                    }
+                    precincts.push(PrecinctBand {
+                        blocks_wide,
+                        inclusion: TagTree::new(blocks_wide, blocks_high),
+                        zero_bitplanes: TagTree::new(blocks_wide, blocks_high),
+                        blocks
+                    });
+                }
+                bands.push(Band {
+                    orientation: *orientation,
+                    x0: bx0,
+                    y0: by0,
+                    x1: bx1,
+                    y1: by1,
+                    bitplanes,
+                    step,
+                    precincts,
+                    coefficients: Vec::new()
+                });
+            }
+            resolutions.push(Resolution {
+                x0: rx0,
+                y0: ry0,
+                x1: rx1,
+                y1: ry1,
+                precinct_width,
+                precinct_height,
+                precincts_wide,
+                precincts_high,
+                bands
+            });
+        }
+        Ok(TileComponent {
+            coding: coding.clone(),
+            region_shift: *region_shift,
+            resolutions
+        })
+    }
+}
+
+
+/// Returns the number of passes in the codeword segment starting with the given pass.
+fn segment_passes(style: u8, first_pass: u32) -> u32 {
+    if style & TERMINATE_ALL != 0 {
+        1
+    } else if style & BYPASS != 0 {
+        // The first four bit-planes are arithmetic coded, then raw significance and
+        // refinement passes alternate with arithmetic coded cleanup passes
+        match first_pass {
+            0..=9 => 10 - first_pass,
+            pass if (pass - 10) % 3 == 0 => 2,
+            _ => 1
+        }
+    } else {
+        u32::MAX
+    }
+}
+
+
+/// Returns the order of the packets of a tile as (layer, resolution, component, precinct).
+fn packet_order(area: (u32, u32, u32, u32), components: &[TileComponent], sizes: &[ComponentSize], coding: &TileCoding) -> Vec<(u16, usize, usize, usize)> {
+    let layers = coding.layers;
+    let resolutions = components.iter().map(|component| component.resolutions.len()).max().unwrap_or(0);
+    let precincts = |c: usize, r: usize| components[c].resolutions.get(r)
+        .map(|resolution| (resolution.precincts_wide * resolution.precincts_high) as usize).unwrap_or(0);
+    let mut order = Vec::new();
+
+    // Position driven orders visit each precinct at the first reference grid point it covers
+    let (tx0, ty0, tx1, ty1) = area;
+    let mut step_x = u64::MAX;
+    let mut step_y = u64::MAX;
+    for (component, size) in components.iter().zip(sizes.iter()) {
+        let levels = component.resolutions.len() as u32 - 1;
+        for (r, resolution) in component.resolutions.iter().enumerate() {
+            step_x = step_x.min(u64::from(size.dx) << (u32::from(resolution.precinct_width) + levels - r as u32));
+            step_y = step_y.min(u64::from(size.dy) << (u32::from(resolution.precinct_height) + levels - r as u32));
+        }
+    }
+    let precinct_at = |c: usize, r: usize, x: u64, y: u64| -> Option<usize> {
+        let component = &components[c];
+        let resolution = component.resolutions.get(r)?;
+        let size = &sizes[c];
+        let level = component.resolutions.len() as u32 - 1 - r as u32;
+        let (rpx, rpy) = (u32::from(resolution.precinct_width) + level, u32::from(resolution.precinct_height) + level);
+        let (rx0, ry0) = (u64::from(resolution.x0), u64::from(resolution.y0));
+        let at_y = y % (u64::from(size.dy) << rpy) == 0 || (y == u64::from(ty0) && (ry0 << level) % (1 << rpy) != 0);
+        let at_x = x % (u64::from(size.dx) << rpx) == 0 || (x == u64::from(tx0) && (rx0 << level) % (1 << rpx) != 0);
+        if !at_x || !at_y || resolution.precincts_wide == 0 || resolution.precincts_high == 0 {
+            return None;
+        }
+        let i = (x.div_ceil(u64::from(size.dx) << level) >> resolution.precinct_width) - (rx0 >> resolution.precinct_width);
+        let j = (y.div_ceil(u64::from(size.dy) << level) >> resolution.precinct_height) - (ry0 >> resolution.precinct_height);
+        Some((i + j * u64::from(resolution.precincts_wide)) as usize)
+    };
+    let positions = || {
+        let mut positions = Vec::new();
+        let mut y = u64::from(ty0);
+        while y < u64::from(ty1) {
+            let mut x = u64::from(tx0);
+            while x < u64::from(tx1) {
+                positions.push((x, y));
+                x += step_x - x % step_x;
+            }
+            y += step_y - y % step_y;
+        }
+        positions
+    };
+
+    match coding.progression {
+        0 => for l in 0..layers {
+            for r in 0..resolutions {
+                for c in 0..components.len() {
+                    order.extend((0..precincts(c, r)).map(|p| (l, r, c, p)));
+                }
+            }
+        },
+        1 => for r in 0..resolutions {
+            for l in 0..layers {
+                for c in 0..components.len() {
+                    order.extend((0..precincts(c, r)).map(|p| (l, r, c, p)));
+                }
+            }
+        },
+        2 => for r in 0..resolutions {
+            for (x, y) in positions() {
+                for c in 0..components.len() {
+                    if let Some(p) = precinct_at(c, r, x, y) {
+                        order.extend((0..layers).map(|l| (l, r, c, p)));
+                    }
+                }
+            }
+        },
+        3 => for (x, y) in positions() {
+            for (c, component) in components.iter().enumerate() {
+                for r in 0..component.resolutions.len() {
+                    if let Some(p) = precinct_at(c, r, x, y) {
+                        order.extend((0..layers).map(|l| (l, r, c, p)));
+                    }
+                }
+            }
+        },
+        _ => for (c, component) in components.iter().enumerate() {
+            for (x, y) in positions() {
+                for r in 0..component.resolutions.len() {
+                    if let Some(p) = precinct_at(c, r, x, y) {
+                        order.extend((0..layers).map(|l| (l, r, c, p)));
+                    }
+                }
+            }
+        }
+    }
+    order
+}
+
+
+/// Passes, length and whether a new codeword segment starts, of code-block data in a packet
+type Chunk = (u32, usize, bool);
+
+
+/// Reads every packet of a tile, keeping the code-block data of the first `layers` layers.
+fn read_packets(components: &mut [TileComponent], order: &[(u16, usize, usize, usize)], data: &[u8], coding: &TileCoding, layers: u16) -> Result<(), String> {
+    let mut position = 0;
+    for &(layer, r, c, p) in order {
+        if position >= data.len() {
+            // Packets missing from a truncated codestream are treated as empty
+            break;
+        }
+        if coding.sop && data[position..].starts_with(&SOP.to_be_bytes()) {
+            position += 6;
+        }
+        let style = components[c].coding.style;
+        let resolution = &mut components[c].resolutions[r];
+        let mut bits = HeaderBits::new(data, position);
+        let mut contributions: Vec<(usize, usize, Vec<Chunk>)> = Vec::new();
+        if bits.bit()? == 1 {
+            for (band_index, band) in resolution.bands.iter_mut().enumerate() {
+                let precinct = &mut band.precincts[p];
+                for block_index in 0..precinct.blocks.len() {
+                    let (x, y) = (block_index % precinct.blocks_wide, block_index / precinct.blocks_wide);
+                    let first = !precinct.blocks[block_index].included;
+                    let included = match first {
+                        true => precinct.inclusion.decode(&mut bits, x, y, u32::from(layer) + 1)?,
+                        false => bits.bit()? == 1
+                    };
+                    if !included {
+                        continue;
+                    }
+                    if first {
+                        let zero_bitplanes = precinct.zero_bitplanes.value(&mut bits, x, y)?;
+                        let block = &mut precinct.blocks[block_index];
+                        block.included = true;
+                        block.bitplanes = band.bitplanes.checked_sub(zero_bitplanes).ok_or("more missing bit-planes than magnitude bit-planes")?;
+                    }
+                    let block = &mut precinct.blocks[block_index];
+                    let mut passes = bits.passes()?;
+                    while bits.bit()? == 1 {
+                        block.length_bits += 1;
+                    }
+                    let mut chunks = Vec::new();
+                    while passes > 0 {
+                        let new_segment = block.segment_room == 0;
+                        if new_segment {
+                            block.segment_room = segment_passes(style, block.passes);
+                        }
+                        let chunk = passes.min(block.segment_room);
+                        let length = bits.bits(block.length_bits + (31 - chunk.leading_zeros()))? as usize;
+                        chunks.push((chunk, length, new_segment));
+                        block.segment_room -= chunk;
+                        block.passes += chunk;
+                        passes -= chunk;
+                    }
+                    contributions.push((band_index, block_index, chunks));
+                }
+            }
+        }
+        bits.align();
+        position = bits.position;
+        if coding.eph && data[position.min(data.len())..].starts_with(&EPH.to_be_bytes()) {
+            position += 2;
+        }
+
+        for (band_index, block_index, chunks) in contributions {
+            let block = &mut resolution.bands[band_index].precincts[p].blocks[block_index];
+            for (passes, length, new_segment) in chunks {
+                let bytes = data.get(position..(position + length).min(data.len())).unwrap_or(&[]);
+                position += length;
+                if layer >= layers {
+                    continue;
+                }
+                match (new_segment, block.segments.last_mut()) {
+                    (false, Some(segment)) => {
+                        segment.data.extend_from_slice(bytes);
+                        segment.passes += passes;
+                    },
+                    _ => block.segments.push(CodewordSegment { data: bytes.to_vec(), passes })
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+
+/// Decodes every code-block of the resolution levels up to `levels` into subband coefficients.
+fn decode_blocks(component: &mut TileComponent, levels: usize) {
+    let style = component.coding.style;
+    let reversible = component.coding.reversible;
+    let region_shift = u32::from(component.region_shift);
+    for resolution in component.resolutions.iter_mut().take(levels + 1) {
+        for band in resolution.bands.iter_mut() {
+            let width = (band.x1 - band.x0) as usize;
+            band.coefficients = vec![0.0; width * (band.y1 - band.y0) as usize];
+            let orientation = band.orientation;
+            let blocks: Vec<&CodeBlock> = band.precincts.iter().flat_map(|precinct| precinct.blocks.iter())
+                .filter(|block| !block.segments.is_empty()).collect();
+            let decoded: Vec<(Vec<i32>, u32)> = blocks.par_iter().map(|block| {
+                let (block_width, block_height) = ((block.x1 - block.x0) as usize, (block.y1 - block.y0) as usize);
+                ebcot::decode(block_width, block_height, orientation, style, block.bitplanes, &block.segments)
+            }).collect();
+
+            for (block, (values, lowest_plane)) in blocks.iter().zip(decoded) {
+                let block_width = (block.x1 - block.x0) as usize;
+                for (index, value) in values.into_iter().enumerate() {
+                    if value == 0 {
+                        continue;
+                    }
+                    let mut magnitude = value.unsigned_abs();
+                    // Coefficients of a region of interest were scaled above the background
+                    if region_shift > 0 && magnitude >= 1 << region_shift {
+                        magnitude >>= region_shift;
+                    }
+                    // Reconstruct at the middle of the interval left by undecoded bit-planes
+                    let magnitude = match (reversible, lowest_plane) {
+                        (true, 0) => magnitude as f32,
+                        (true, plane) => (magnitude + (1 << (plane - 1))) as f32,
+                        (false, plane) => magnitude as f32 + (1u32 << plane) as f32 / 2.0
+                    } * band.step;
+                    let (x, y) = (block.x0 - band.x0 + (index % block_width) as u32, block.y0 - band.y0 + (index / block_width) as u32);
+                    band.coefficients[y as usize * width + x as usize] = if value < 0 { -magnitude } else { magnitude };
+                }
+            }
+        }
+    }
+}
+
+
+/// One dimensional wavelet synthesis of `line`, whose first sample has coordinate `start`.
+fn synthesize(line: &mut [f32], start: u32, reversible: bool) {
+    let length = line.len();
+    if length == 1 {
+        if start % 2 == 1 {
+            line[0] = if reversible { (line[0] / 2.0).trunc() } else { line[0] / 2.0 };
+        }
+        return;
+    }
+    // Whole-sample symmetric extension at both ends
+    let reflect = |index: isize| -> usize {
+        let last = length as isize - 1;
+        let index = index.abs();
+        (if index > last { 2 * last - index } else { index }) as usize
+    };
+    let even_first = start % 2 == 0;
+    let positions = |even: bool| (0..length).filter(move |index| (index % 2 == 0) == (even == even_first));
+    let lift = |line: &mut [f32], even: bool, step: &dyn Fn(f32, f32, f32) -> f32| {
+        for index in positions(even) {
+            let (before, after) = (line[reflect(index as isize - 1)], line[reflect(index as isize + 1)]);
+            line[index] = step(line[index], before, after);
+        }
+    };
+    if reversible {
+        lift(line, true, &|value, before, after| value - ((before + after + 2.0) / 4.0).floor());
+        lift(line, false, &|value, before, after| value + ((before + after) / 2.0).floor());
+    } else {
+        for index in positions(true) {
+            line[index] *= K;
+        }
+        for index in positions(false) {
+            line[index] /= K;
+        }
+        lift(line, true, &|value, before, after| value - DELTA * (before + after));
+        lift(line, false, &|value, before, after| value - GAMMA * (before + after));
+        lift(line, true, &|value, before, after| value - BETA * (before + after));
+        lift(line, false, &|value, before, after| value - ALPHA * (before + after));
+    }
+}
+
+
+/// Reconstructs a tile-component at resolution level `level` from its subbands, returning
+/// the samples of the resolution's area.
+fn reconstruct(component: &TileComponent, level: usize) -> Vec<f32> {
+    let reversible = component.coding.reversible;
+    let mut samples = component.resolutions[0].bands[0].coefficients.clone();
+    for resolution in component.resolutions.iter().take(level + 1).skip(1) {
+        let (x0, y0) = (resolution.x0, resolution.y0);
+        let (width, height) = ((resolution.x1 - x0) as usize, (resolution.y1 - y0) as usize);
+        if width == 0 || height == 0 {
+            samples = Vec::new();
+            continue;
+        }
+        let (low_width, high_width) = ((resolution.x1.div_ceil(2) - x0.div_ceil(2)) as usize, (resolution.x1 / 2 - x0 / 2) as usize);
+        let mut interleaved = vec![0f32; width * height];
+        // Low pass samples sit at even coordinates and high pass samples at odd ones
+        let index = |coordinate: usize, origin: u32| match coordinate % 2 {
+            0 => (false, coordinate / 2 - origin.div_ceil(2) as usize),
+            _ => (true, coordinate / 2 - origin as usize / 2)
+        };
+        for y in 0..height {
+            let (high_row, row) = index(y0 as usize + y, y0);
+            for x in 0..width {
+                let (high_column, column) = index(x0 as usize + x, x0);
+                interleaved[y * width + x] = match (high_column, high_row) {
+                    (false, false) => samples[row * low_width + column],
+                    (true, false) => resolution.bands[0].coefficients[row * high_width + column],
+                    (false, true) => resolution.bands[1].coefficients[row * low_width + column],
+                    (true, true) => resolution.bands[2].coefficients[row * high_width + column]
+                };
+            }
+        }
+        for row in interleaved.chunks_mut(width) {
+            synthesize(row, x0, reversible);
+        }
+        let mut column = vec![0f32; height];
+        for x in 0..width {
+            for y in 0..height {
+                column[y] = interleaved[y * width + x];
+            }
+            synthesize(&mut column, y0, reversible);
+            for y in 0..height {
+                interleaved[y * width + x] = column[y];
+            }
+        }
+        samples = interleaved;
+    }
+    samples
+}
+
+
+/// Decoded samples of one tile-component at the requested resolution, with their area
+struct TileSamples {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+    samples: Vec<f32>
+}
+
+
+fn decode_tile(codestream: &Codestream, tile: usize, needed: &[bool], reduction: u8, layers: u16) -> Result<Vec<Option<TileSamples>>, String> {
+    let size = &codestream.size;
+    let (markers, parts) = &codestream.tiles[tile];
+    let coding = TileCoding::new(&codestream.main, markers, size.components.len())?;
+    let area = size.tile(tile);
+    let mut components = size.components.iter().zip(coding.components.iter())
+        .map(|(component_size, component_coding)| TileComponent::new(area, component_size, component_coding))
+        .collect::<Result<Vec<_>, String>>()?;
+    let data: Vec<u8> = parts.concat();
+    let order = packet_order(area, &components, &size.components, &coding);
+    read_packets(&mut components, &order, &data, &coding, layers)?;
+
+    // The multiple component transform needs the first three components together
+    let transform = coding.transform && components.len() >= 3;
+    let mut samples = Vec::new();
+    for (index, component) in components.iter_mut().enumerate() {
+        let wanted = needed[index] || (transform && index < 3 && needed[..3].iter().any(|needed| *needed));
+        if !wanted {
+            samples.push(None);
+            continue;
+        }
+        let level = usize::from(component.coding.levels).checked_sub(usize::from(reduction))
+            .ok_or_else(|| format!("a reduction of {} is more than the {} decomposition levels", reduction, component.coding.levels))?;
+        decode_blocks(component, level);
+        let resolution = &component.resolutions[level];
+        samples.push(Some(TileSamples {
+            x0: resolution.x0,
+            y0: resolution.y0,
+            x1: resolution.x1,
+            y1: resolution.y1,
+            samples: reconstruct(component, level)
+        }));
+    }
+
+    if transform {
+        if let [Some(first), Some(second), Some(third)] = &mut samples[..3] {
+            if first.samples.len() == second.samples.len() && first.samples.len() == third.samples.len() {
+                let reversible = components[0].coding.reversible;
+                for ((y, u), v) in first.samples.iter_mut().zip(second.samples.iter_mut()).zip(third.samples.iter_mut()) {
+                    let (red, green, blue) = match reversible {
+                        true => {
+                            let green = *y - ((*u + *v) / 4.0).floor();
+                            (*v + green, green, *u + green)
+                        },
+                        false => (*y + 1.402 * *v, *y - 0.344_136 * *u - 0.714_136 * *v, *y + 1.772 * *u)
+                    };
+                    *y = red;
+                    *u = green;
+                    *v = blue;
+                }
+            }
+        }
+    }
+    Ok(samples)
+}
+
+
+/// Decodes the requested region and components of a codestream.
+pub(crate) fn decode(data: &[u8], request: &Jpeg2000Request) -> Result<Jpeg2000Image, String> {
+    let codestream = Codestream::parse(codestream(data))?;
+    let size = &codestream.size;
+    let reduction = u32::from(request.reduction);
+    if reduction > 32 {
+        return Err(format!("a reduction of {} is more than any codestream allows", reduction));
+    }
+    if let Some(component) = request.components.iter().find(|component| **component >= size.components.len()) {
+        return Err(format!("component {} is outside the {} components of the codestream", component, size.components.len()));
+    }
+    let (origin_x, origin_y) = (ceil_shift(size.x0, reduction), ceil_shift(size.y0, reduction));
+    let (width, height) = (ceil_shift(size.x1, reduction) - origin_x, ceil_shift(size.y1, reduction) - origin_y);
+    let region = request.region;
+    if u64::from(region.column) + u64::from(region.columns) > u64::from(width) || u64::from(region.row) + u64::from(region.rows) > u64::from(height) {
+        return Err(format!("region {:?} is outside the {} by {} image", region, height, width));
+    }
+    let (left, top) = (origin_x + region.column, origin_y + region.row);
+
+    let mut needed = vec![false; size.components.len()];
+    for component in request.components.iter() {
+        needed[*component] = true;
+    }
+    let tiles = region_tiles(size, region, reduction);
+    let layers = request.layers.unwrap_or(u16::MAX);
+    let decoded = tiles.par_iter()
+        .map(|tile| decode_tile(&codestream, *tile, &needed, request.reduction, layers))
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let mut components = vec![vec![0i32; region.rows as usize * region.columns as usize]; request.components.len()];
+    for (output, component) in components.iter_mut().zip(request.components.iter()) {
+        for samples in decoded.iter().filter_map(|tile| tile[*component].as_ref()) {
+            let component_size = &size.components[*component];
+            let precision = u32::from(component_size.precision);
+            let (minimum, maximum, shift) = match component_size.signed {
+                true => (-(1i64 << (precision - 1)), (1i64 << (precision - 1)) - 1, 0),
+                false => (0, (1i64 << precision) - 1, 1i64 << (precision - 1))
+            };
+            let width = (samples.x1 - samples.x0) as usize;
+            for row in 0..region.rows {
+                for column in 0..region.columns {
+                    // Subsampled components cover several reference grid samples each
+                    let (x, y) = ((left + column) / component_size.dx, (top + row) / component_size.dy);
+                    if x < samples.x0 || x >= samples.x1 || y < samples.y0 || y >= samples.y1 {
+                        continue;
+                    }
+                    let value = samples.samples[(y - samples.y0) as usize * width + (x - samples.x0) as usize];
+                    output[(row * region.columns + column) as usize] = (value.round() as i64 + shift).clamp(minimum, maximum) as i32;
+                }
+            }
+        }
+    }
+
+    Ok(Jpeg2000Image {
+        rows: region.rows,
+        columns: region.columns,
+        components
+    })
+}
@@ -0,0 +1,108 @@
+//! NITF 2.0 label subheader
+
+use std::collections::BTreeMap;
+
+use super::error::NitfError;
+use super::field::{Color, FieldReader};
+use super::file_header::NitfVersion;
+use super::security::SecurityFields;
+use super::tre::{self, Tre, TreLocation};
+
+/// NITF 2.0 label subheader. Labels are short runs of text drawn over the image, replaced in
+/// NITF 2.1 by text in CGM graphics.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LabelSubheader {
+    /// Label Identifier (LID)
+    pub label_id: String,
+    /// Label Security Fields (LSCLAS through LSDEVT)
+    pub security: SecurityFields,
+    /// Encryption (ENCRYP)
+    pub encryption: u8,
+    /// Label Font Style (LFS)
+    pub font_style: String,
+    /// Label Cell Width (LCW)
+    pub cell_width: u8,
+    /// Label Cell Height (LCH)
+    pub cell_height: u8,
+    /// Label Display Level (LDLVL)
+    pub display_level: u16,
+    /// Label Attachment Level (LALVL)
+    pub attachment_level: u16,
+    /// Label Location as row and column (LLOC)
+    pub location: (i32, i32),
+    /// Label Text Color (LTC)
+    pub text_color: Color,
+    /// Label Background Color (LBC)
+    pub background_color: Color,
+    /// Extended Subheader Overflow (LXSOFL)
+    pub extended_overflow: u16,
+    /// Tagged record extensions from the LXSHD area, in file order
+    pub tres: Vec<Tre>
+}
+
+
+impl LabelSubheader {
+
+    /// Parses the label subheader at the reader's position.
+    pub(crate) fn parse(reader: &mut FieldReader) -> Result<LabelSubheader, NitfError> {
+
+        reader.literal("LA", "LA")?;
+        let label_id = reader.trimmed("LID", 10)?;
+        let security = SecurityFields::parse(reader, "L", NitfVersion::Nitf20)?;
+        let encryption = reader.number("ENCRYP", 1)?;
+        let font_style = reader.trimmed("LFS", 1)?;
+        let cell_width = reader.number("LCW", 2)?;
+        let cell_height = reader.number("LCH", 2)?;
+        let display_level = reader.number("LDLVL", 3)?;
+        let attachment_level = reader.number("LALVL", 3)?;
+        let location = (reader.number("LLOC", 5)?, reader.number("LLOC", 5)?);
+        let text_color = reader.color("LTC")?;
+        let background_color = reader.color("LBC")?;
+        let (extended_overflow, tres) = reader.tre_area("LXSHDL", "LXSOFL", "LXSHD", TreLocation::LabelExtended)?;
+
+        Ok(LabelSubheader {
+            label_id,
+            security,
+            encryption,
+            font_style,
+            cell_width,
+            cell_height,
+            display_level,
+            attachment_level,
+            location,
+            text_color,
+            background_color,
+            extended_overflow,
+            tres
+        })
+    }
+
+    /// Returns every tagged record extension in the label subheader with the given tag.
+    pub fn find_tres(&self, tag: &str) -> Vec<&Tre> {
+        tre::find(&self.tres, tag)
+    }
+
+    /// Returns the label subheader as a map of field names to display strings.
+    pub fn to_map(&self) -> BTreeMap<String,String> {
+
+        let mut label_subheader = BTreeMap::new();
+
+        label_subheader.insert("LA".to_string(), "LA".to_string());
+        label_subheader.insert("LID".to_string(), self.label_id.clone());
+        self.security.insert_into(&mut label_subheader, "L");
+        label_subheader.insert("ENCRYP".to_string(), self.encryption.to_string());
+        label_subheader.insert("LFS".to_string(), self.font_style.clone());
+        label_subheader.insert("LCW".to_string(), format!("{:02}", self.cell_width));
+        label_subheader.insert("LCH".to_string(), format!("{:02}", self.cell_height));
+        label_subheader.insert("LDLVL".to_string(), format!("{:03}", self.display_level));
+        label_subheader.insert("LALVL".to_string(), format!("{:03}", self.attachment_level));
+        label_subheader.insert("LLOC".to_string(), format!("{:05}{:05}", self.location.0, self.location.1));
+        label_subheader.insert("LTC".to_string(), self.text_color.to_string());
+        label_subheader.insert("LBC".to_string(), self.background_color.to_string());
+
+        tre::insert_area(&mut label_subheader, ("LXSHDL", "LXSOFL"), self.extended_overflow, &self.tres, TreLocation::LabelExtended);
+        tre::insert_overflow(&mut label_subheader, &self.tres);
+
+        label_subheader
+    }
+}
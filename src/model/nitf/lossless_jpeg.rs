@@ -0,0 +1,228 @@
+//! Lossless JPEG decoding (ITU-T T.81 process 14, predictive Huffman coding of 2 to 16 bit
+//! samples) for image segments compressed with IC = C5 and M5
+
+use super::jpeg::{self, BitReader, DecodedImage, Huffman, Markers, DHT, DRI, EOI, SOS};
+
+/// Lossless sequential frame, Huffman coding
+const SOF3: u8 = 0xC3;
+
+
+struct Component {
+    id: u8,
+    horizontal: usize,
+    vertical: usize,
+    table: usize,
+    /// Width of the sample grid, padded to whole MCUs
+    stride: usize,
+    /// Samples before the point transform, row by row
+    samples: Vec<u16>
+}
+
+
+struct Frame {
+    precision: u8,
+    width: usize,
+    height: usize,
+    components: Vec<Component>,
+    max_horizontal: usize,
+    max_vertical: usize
+}
+
+
+/// Decodes a lossless JPEG stream.
+pub(crate) fn decode(data: &[u8]) -> Result<DecodedImage, String> {
+    let mut markers = Markers::new(data)?;
+    let mut tables: [Option<Huffman>; 4] = [None, None, None, None];
+    let mut unused_tables: [Option<Huffman>; 4] = [None, None, None, None];
+    let mut restart_interval = 0;
+    let mut frame: Option<Frame> = None;
+
+    loop {
+        let (marker, segment) = markers.next()?;
+        match marker {
+            DHT => jpeg::define_huffman_tables(segment, &mut tables, &mut unused_tables)?,
+            DRI => restart_interval = jpeg::parse_restart_interval(segment)?,
+            SOF3 => frame = Some(parse_frame(segment)?),
+            0xC0..=0xC2 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF =>
+                return Err(format!("JPEG process of marker 0x{:02X} is not lossless", marker)),
+            SOS => {
+                let frame = frame.as_mut().ok_or("scan before frame header")?;
+                markers.position = decode_scan(frame, segment, data, markers.position, restart_interval, &tables)?;
+            },
+            EOI => break,
+            _ => {}
+        }
+    }
+
+    let frame = frame.ok_or("missing frame header")?;
+    Ok(output(&frame))
+}
+
+
+fn parse_frame(segment: &[u8]) -> Result<Frame, String> {
+    if segment.len() < 6 {
+        return Err("truncated frame header".to_string());
+    }
+    let precision = segment[0];
+    if !(2..=16).contains(&precision) {
+        return Err(format!("{} bit sample precision is not supported", precision));
+    }
+    let height = usize::from(segment[1]) << 8 | usize::from(segment[2]);
+    let width = usize::from(segment[3]) << 8 | usize::from(segment[4]);
+    if width == 0 || height == 0 {
+        return Err("image without a size".to_string());
+    }
+    let count = usize::from(segment[5]);
+    let mut components = Vec::new();
+    for index in 0..count {
+        let bytes = segment.get(6 + 3 * index..9 + 3 * index).ok_or("truncated frame header")?;
+        let (horizontal, vertical) = (usize::from(bytes[1] >> 4), usize::from(bytes[1] & 0x0F));
+        if !(1..=4).contains(&horizontal) || !(1..=4).contains(&vertical) {
+            return Err("invalid frame component".to_string());
+        }
+        components.push(Component {
+            id: bytes[0],
+            horizontal,
+            vertical,
+            table: 0,
+            stride: 0,
+            samples: Vec::new()
+        });
+    }
+    let max_horizontal = components.iter().map(|component| component.horizontal).max().unwrap_or(1);
+    let max_vertical = components.iter().map(|component| component.vertical).max().unwrap_or(1);
+    let mcus_wide = width.div_ceil(max_horizontal);
+    let mcus_high = height.div_ceil(max_vertical);
+    for component in components.iter_mut() {
+        component.stride = mcus_wide * component.horizontal;
+        component.samples = vec![0; component.stride * mcus_high * component.vertical];
+    }
+    Ok(Frame {
+        precision,
+        width,
+        height,
+        components,
+        max_horizontal,
+        max_vertical
+    })
+}
+
+
+/// Decodes the entropy coded differences of a scan, returning the position following it.
+fn decode_scan(frame: &mut Frame, segment: &[u8], data: &[u8], position: usize, restart_interval: usize,
+               tables: &[Option<Huffman>; 4]) -> Result<usize, String> {
+    let count = usize::from(*segment.first().ok_or("truncated scan header")?);
+    let mut scan_components = Vec::new();
+    for index in 0..count {
+        let bytes = segment.get(1 + 2 * index..3 + 2 * index).ok_or("truncated scan header")?;
+        let component = frame.components.iter().position(|component| component.id == bytes[0])
+            .ok_or("scan component is not in the frame")?;
+        frame.components[component].table = usize::from(bytes[1] >> 4) & 3;
+        scan_components.push(component);
+    }
+    let parameters = segment.get(1 + 2 * count..4 + 2 * count).ok_or("truncated scan header")?;
+    let predictor = parameters[0];
+    let point_transform = parameters[2] & 0x0F;
+    if !(1..=7).contains(&predictor) {
+        return Err(format!("predictor {} is not supported", predictor));
+    }
+    if point_transform >= frame.precision {
+        return Err(format!("point transform of {} bits for {} bit samples", point_transform, frame.precision));
+    }
+    let initial = 1i32 << (frame.precision - point_transform - 1);
+
+    // A single component scan codes one sample at a time, rather than in MCUs
+    let (mcus_wide, mcus_high) = match scan_components.as_slice() {
+        [component] => {
+            let component = &frame.components[*component];
+            ((frame.width * component.horizontal).div_ceil(frame.max_horizontal),
+             (frame.height * component.vertical).div_ceil(frame.max_vertical))
+        },
+        _ => (frame.width.div_ceil(frame.max_horizontal), frame.height.div_ceil(frame.max_vertical))
+    };
+
+    let mut reader = BitReader::new(data, position);
+    // The first row of the scan, and of each restart interval, is predicted from the left,
+    // and the first sample of each from 2^(P - Pt - 1), even when the interval starts
+    // within a row
+    let mut first_mcu = 0;
+    let mut first_mcu_row = 0;
+    for mcu in 0..mcus_wide * mcus_high {
+        if restart_interval > 0 && mcu > 0 && mcu % restart_interval == 0 {
+            reader.restart()?;
+            first_mcu = mcu;
+            first_mcu_row = mcu / mcus_wide;
+        }
+        let (mcu_row, mcu_column) = (mcu / mcus_wide, mcu % mcus_wide);
+        for index in scan_components.iter() {
+            let component = &mut frame.components[*index];
+            let (units_wide, units_high) = match scan_components.len() {
+                1 => (1, 1),
+                _ => (component.horizontal, component.vertical)
+            };
+            let table = tables[component.table].as_ref().ok_or("missing Huffman table")?;
+            for unit_row in 0..units_high {
+                for unit_column in 0..units_wide {
+                    let (y, x) = (mcu_row * units_high + unit_row, mcu_column * units_wide + unit_column);
+                    let stride = component.stride;
+                    let sample = |y: usize, x: usize| i32::from(component.samples[y * stride + x]);
+                    let prediction = if y == first_mcu_row * units_high {
+                        if x == 0 || (mcu == first_mcu && unit_column == 0) { initial } else { sample(y, x - 1) }
+                    } else if x == 0 {
+                        sample(y - 1, x)
+                    } else {
+                        let (a, b, c) = (sample(y, x - 1), sample(y - 1, x), sample(y - 1, x - 1));
+                        match predictor {
+                            1 => a,
+                            2 => b,
+                            3 => c,
+                            4 => a + b - c,
+                            5 => a + ((b - c) >> 1),
+                            6 => b + ((a - c) >> 1),
+                            _ => (a + b) >> 1
+                        }
+                    };
+                    let size = reader.decode(table)?;
+                    let difference = match size {
+                        0..=15 => reader.receive_extend(u32::from(size)),
+                        16 => 32768,
+                        _ => return Err(format!("difference category {} is past 16", size))
+                    };
+                    component.samples[y * stride + x] = (prediction + difference) as u16;
+                }
+            }
+        }
+    }
+
+    // Undo the point transform
+    for index in scan_components.iter() {
+        let component = &mut frame.components[*index];
+        let maximum = (1u32 << frame.precision) - 1;
+        for sample in component.samples.iter_mut() {
+            *sample = ((u32::from(*sample) << point_transform) & maximum) as u16;
+        }
+    }
+    Ok(reader.position)
+}
+
+
+/// Upsamples each component to the image size.
+fn output(frame: &Frame) -> DecodedImage {
+    let components = frame.components.iter().map(|component| {
+        let mut upsampled = vec![0u16; frame.width * frame.height];
+        for y in 0..frame.height {
+            let row = y * component.vertical / frame.max_vertical * component.stride;
+            for x in 0..frame.width {
+                upsampled[y * frame.width + x] = component.samples[row + x * component.horizontal / frame.max_horizontal];
+            }
+        }
+        upsampled
+    }).collect();
+
+    DecodedImage {
+        width: frame.width,
+        height: frame.height,
+        precision: frame.precision,
+        components
+    }
+}
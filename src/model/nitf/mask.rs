@@ -0,0 +1,97 @@
+//! Image data mask table at the start of masked image segments (IC = NM and Mx)
+
+use super::error::NitfError;
+use super::field::FieldReader;
+
+/// Value of a block or pad pixel mask record for a block that is not recorded or holds no pad pixels
+const NOT_RECORDED: u64 = 0xFFFF_FFFF;
+
+/// Image data mask table, which locates each stored block and flags blocks holding pad pixels.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MaskTable {
+    /// Blocked Image Data Offset (IMDATOFF) from the start of the image data
+    pub data_offset: u32,
+    /// Block Mask Record Length (BMRLNTH), zero when there is no block mask
+    pub block_mask_record_length: u16,
+    /// Pad Pixel Mask Record Length (TMRLNTH), zero when there is no pad pixel mask
+    pub pad_mask_record_length: u16,
+    /// Pad Output Pixel Code Length (TPXCDLNTH) in bits
+    pub pad_code_length: u16,
+    /// Pad Output Pixel Code (TPXCD)
+    pub pad_code: Option<u64>,
+    /// Block Mask Records (BMRnBNDm), the offset of each block from the start of the blocked
+    /// image data, or None for blocks that are not recorded. Empty when there is no block mask.
+    pub block_offsets: Vec<Option<u32>>,
+    /// Pad Pixel Mask Records (TMRnBNDm), Some for blocks holding pad pixels. Empty when there
+    /// is no pad pixel mask.
+    pub pad_offsets: Vec<Option<u32>>
+}
+
+impl MaskTable {
+
+    /// Returns the length of the mask table from its first ten bytes, given the number of
+    /// records in each mask (blocks, times bands in band sequential mode).
+    pub(crate) fn length(start: &[u8], records: usize) -> Result<usize, NitfError> {
+        let mut reader = FieldReader::new(start, 0);
+        reader.bytes("IMDATOFF", 4)?;
+        let block_mask_record_length = reader.binary("BMRLNTH", 2)? as usize;
+        let pad_mask_record_length = reader.binary("TMRLNTH", 2)? as usize;
+        let pad_code_length = reader.binary("TPXCDLNTH", 2)? as usize;
+        Ok(10 + pad_code_length.div_ceil(8) + (block_mask_record_length + pad_mask_record_length) * records)
+    }
+
+    /// Parses the mask table with the given number of records in each mask.
+    pub(crate) fn parse(reader: &mut FieldReader, records: usize) -> Result<MaskTable, NitfError> {
+        let data_offset = reader.binary("IMDATOFF", 4)? as u32;
+        let mut record_length = |field: &str| -> Result<u16, NitfError> {
+            let offset = reader.position();
+            match reader.binary(field, 2)? {
+                length @ (0 | 4) => Ok(length as u16),
+                length => Err(NitfError::InvalidValue { field: field.to_string(), offset, value: length.to_string() })
+            }
+        };
+        let block_mask_record_length = record_length("BMRLNTH")?;
+        let pad_mask_record_length = record_length("TMRLNTH")?;
+        let pad_code_length = reader.binary("TPXCDLNTH", 2)? as u16;
+        let pad_code = match pad_code_length {
+            0 => None,
+            bits => Some(reader.binary("TPXCD", usize::from(bits).div_ceil(8))?)
+        };
+
+        let mut records_of = |field: &str, length: u16| -> Result<Vec<Option<u32>>, NitfError> {
+            if length == 0 {
+                return Ok(Vec::new());
+            }
+            (0..records).map(|_| match reader.binary(field, 4)? {
+                NOT_RECORDED => Ok(None),
+                offset => Ok(Some(offset as u32))
+            }).collect()
+        };
+        let block_offsets = records_of("BMRnBNDm", block_mask_record_length)?;
+        let pad_offsets = records_of("TMRnBNDm", pad_mask_record_length)?;
+
+        Ok(MaskTable {
+            data_offset,
+            block_mask_record_length,
+            pad_mask_record_length,
+            pad_code_length,
+            pad_code,
+            block_offsets,
+            pad_offsets
+        })
+    }
+
+    /// Returns the offset of a stored block from the start of the blocked image data, or None
+    /// when the block is not recorded. Blocks are stored contiguously without a block mask.
+    pub fn block_offset(&self, block: usize, block_length: u64) -> Option<u64> {
+        if self.block_offsets.is_empty() {
+            return Some(block as u64 * block_length);
+        }
+        self.block_offsets.get(block).copied().flatten().map(u64::from)
+    }
+
+    /// Returns true when a stored block is flagged as holding pad pixels.
+    pub fn has_pad_pixels(&self, block: usize) -> bool {
+        self.pad_offsets.get(block).map(Option::is_some).unwrap_or(false)
+    }
+}
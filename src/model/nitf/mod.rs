@@ -1,33 +1,98 @@
 //! NITF related module
 
-use std::io::prelude::*;
+use std::borrow::Cow;
 use std::collections::BTreeMap;
 use std::fmt;
-use std::sync::mpsc::channel;
 use std::fs::File;
+use std::io::{self, BufReader, Read, Seek};
 
 use rayon::prelude::*;
 
 use crate::base::Model;
-
-/// NITF (National Imagery Transmission Format) model
+use self::error::LengthChecks;
+use self::field::FieldReader;
+use self::file_header::UNKNOWN_FILE_LENGTH;
+use self::image::ImageCache;
+use self::source::{Source, StreamSource};
+
+mod error;
+mod field;
+mod security;
+mod file_header;
+mod image_subheader;
+mod image;
+mod fax;
+mod jpeg;
+mod lossless_jpeg;
+mod jpeg2000;
+mod ebcot;
+mod vq;
+mod mask;
+mod graphic_subheader;
+mod cgm;
+mod symbol_subheader;
+mod label_subheader;
+mod text_subheader;
+mod des_subheader;
+mod res_subheader;
+mod rpc;
+mod segment;
+mod source;
+mod tre;
+mod tre_definition;
+mod writer;
+mod validation;
+
+pub use self::error::NitfError;
+pub use self::field::{Color, Date, DateTime};
+pub use self::security::{Classification, SecurityFields, SecurityMarking};
+pub use self::file_header::{FileHeader, NitfVersion, SegmentLengths};
+pub use self::image_subheader::{Band, BiLevelCoding, CoordinateRepresentation, ImageCompression, ImageMode, ImageRepresentation, ImageSubheader, JpegQuality, PixelJustification, PixelValueType};
+pub use self::image::{BiLevelDepth, LutMode, PixelBuffer, PixelData, Rectangle, SampleType};
+pub use self::jpeg2000::{BuiltInJpeg2000Decoder, Jpeg2000Decoder, Jpeg2000Image, Jpeg2000Layers, Jpeg2000Options, Jpeg2000Request};
+pub use self::mask::MaskTable;
+pub use self::graphic_subheader::{GraphicColor, GraphicSubheader};
+pub use self::cgm::{ArcClosure, CgmPicture, CgmPoint, FillStyle, GraphicPrimitive, LineStyle, LineType};
+pub use self::symbol_subheader::{SymbolSubheader, SymbolType};
+pub use self::label_subheader::LabelSubheader;
+pub use self::text_subheader::{TextFormat, TextSubheader};
+pub use self::des_subheader::{DesSubheader, XmlDataContent};
+pub use self::res_subheader::ResSubheader;
+pub use self::rpc::RpcModel;
+pub use self::segment::Segment;
+pub use self::tre::{Tre, TreLocation};
+pub use self::tre_definition::{TreComparison, TreCondition, TreCount, TreDefinition, TreField, TreFieldType, TreItem, TreRegistry, TreValue};
+pub use self::writer::NitfWriter;
+pub use self::validation::{required_complexity_level, validate, Finding, Severity};
+
+/// NITF (National Imagery Transmission Format) model. Headers are read when the NITF is
+/// opened and segment data is read from the underlying source on demand.
 pub struct NITF {
-    metadata: NITFmetadata
+    metadata: NITFmetadata,
+    source: Box<dyn Source>,
+    jpeg2000_decoder: Box<dyn Jpeg2000Decoder>,
+    /// What has been learned about the blocks of each image segment, in file order
+    image_caches: Vec<ImageCache>
 }
 
 
 struct NITFmetadata {
-    file_header: BTreeMap<String,String>,
-    image_subheaders: Vec<BTreeMap<String, String>>,
-    graphic_subheaders: Vec<BTreeMap<String, String>>,
-    text_subheaders: Vec<BTreeMap<String, String>>,
-    data_ext_subheaders: Vec<BTreeMap<String, String>>
+    file_header: FileHeader,
+    image_segments: Vec<Segment<ImageSubheader>>,
+    graphic_segments: Vec<Segment<GraphicSubheader>>,
+    symbol_segments: Vec<Segment<SymbolSubheader>>,
+    label_segments: Vec<Segment<LabelSubheader>>,
+    text_segments: Vec<Segment<TextSubheader>>,
+    data_ext_segments: Vec<Segment<DesSubheader>>,
+    res_segments: Vec<Segment<ResSubheader>>,
+    length_mismatches: Vec<NitfError>
 }
 
 
 impl Model for NITF {
 
     type MyType = NITF;
+    type Error = NitfError;
 
     /// Returns a Model for the given NITF file
     ///
@@ -36,85 +101,14 @@ impl Model for NITF {
     /// * `filename` - A string of the path to the nitf file.
     ///
     /// # Examples
-    /// ```
+    /// ```no_run
     /// use ossim_oxide::base::Model;
-    /// use ossim_oxide::model::nitf;
-    /// let myNitf = NITF::new("/path/to/nitf/file.NTF");
+    /// use ossim_oxide::model::nitf::NITF;
+    /// let my_nitf = NITF::new("/path/to/nitf/file.NTF".to_string());
     /// ```
-    fn new(filename: String) -> std::io::Result<NITF> {
-
-        let mut file = File::open(filename)?;
-        let nitf = &mut Vec::new();
-        file.read_to_end(nitf).unwrap();
-        drop(file);
-
-        let file_header = NITF::parse_header(&nitf).unwrap();
-
-        let mut offset = file_header.get("HL").unwrap().parse::<usize>().unwrap();
-
-        // Calculate the offset to each image header
-        let num_of_image_seg = file_header.get("NUMI").unwrap().parse::<usize>().unwrap();
-        let mut image_offsets = Vec::new();
-        for i in 1..=num_of_image_seg {
-            image_offsets.push(offset);
-            offset += file_header.get(&format!("LISH{:03}",i)).unwrap().parse::<usize>().unwrap() +
-                    file_header.get(&format!("LI{:03}",i)).unwrap().parse::<usize>().unwrap();
-        }
-
-        // Sync up return values of parallel parsing of image headers
-        let (img_sender, img_receiver) = channel();
-        image_offsets.into_par_iter().for_each_with(img_sender, |s, offset| s.send(NITF::parse_image_subheader(&nitf, offset).unwrap()).unwrap());
-        let image_subheaders: Vec<_> = img_receiver.iter().collect();
-
-        let num_of_graphic_seg = file_header.get("NUMS").unwrap().parse::<usize>().unwrap();
-        let mut graphic_offsets = Vec::new();
-        for i in 1..=num_of_graphic_seg {
-            graphic_offsets.push(offset);
-            offset += file_header.get(&format!("LSSH{:03}",i)).unwrap().parse::<usize>().unwrap() +
-                    file_header.get(&format!("LS{:03}",i)).unwrap().parse::<usize>().unwrap();
-        }
-
-        let (graphic_sender, graphic_receiver) = channel();
-        graphic_offsets.into_par_iter().for_each_with(graphic_sender, |s, offset| s.send(NITF::parse_graphic_subheader(&nitf, offset).unwrap()).unwrap());
-        let graphic_subheaders: Vec<_> = graphic_receiver.iter().collect();
-
-        let num_of_text_seg = file_header.get("NUMT").unwrap().parse::<usize>().unwrap();
-        let mut text_offsets = Vec::new();
-        for i in 1..=num_of_text_seg {
-            text_offsets.push(offset);
-            offset += file_header.get(&format!("LTSH{:03}",i)).unwrap().parse::<usize>().unwrap() +
-                    file_header.get(&format!("LT{:03}",i)).unwrap().parse::<usize>().unwrap();
-        }
-
-        let (text_sender, text_receiver) = channel();
-        text_offsets.into_par_iter().for_each_with(text_sender, |s, offset| s.send(NITF::parse_text_subheader(&nitf, offset).unwrap()).unwrap());
-        let text_subheaders: Vec<_> = text_receiver.iter().collect();
-
-        let num_of_data_ext_seg = file_header.get("NUMDES").unwrap().parse::<usize>().unwrap();
-        let mut data_ext_offsets = Vec::new();
-        for i in 1..=num_of_data_ext_seg {
-            data_ext_offsets.push(offset);
-            offset += file_header.get(&format!("LDSH{:03}",i)).unwrap().parse::<usize>().unwrap() +
-                    file_header.get(&format!("LD{:03}",i)).unwrap().parse::<usize>().unwrap();
-        }
-
-        let (data_sender, data_receiver) = channel();
-        data_ext_offsets.into_par_iter().for_each_with(data_sender, |s, offset| s.send(NITF::parse_data_ext_seg_subheader(&nitf, offset).unwrap()).unwrap());
-        let data_ext_subheaders: Vec<_> = data_receiver.iter().collect();
-
-
-        let metadata = NITFmetadata {
-            file_header: file_header,
-            image_subheaders: image_subheaders,
-            graphic_subheaders: graphic_subheaders,
-            text_subheaders: text_subheaders,
-            data_ext_subheaders: data_ext_subheaders
-        };
-
-        Ok(NITF {
-            metadata: metadata
-        })
-
+    fn new(filename: String) -> Result<NITF, NitfError> {
+        let file = File::open(filename)?;
+        NITF::from_reader(BufReader::new(file))
     }
 }
 
@@ -122,605 +116,377 @@ impl Model for NITF {
 impl fmt::Display for NITF {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut retval = "".to_string();
-        for (field, value) in &self.metadata.file_header {
+        for (field, value) in &self.metadata.file_header.to_map() {
             retval = format!("{}\nNITF::{}: {}", retval, field, value);
         }
-        for (index, image_subheader) in (&self.metadata.image_subheaders).iter().enumerate() {
-            for (field, value) in image_subheader {
-                retval = format!("{}\nNITF::IMAGE{:03}::{}: {}", retval, index, field, value);
+        for segment in &self.metadata.image_segments {
+            for (field, value) in &segment.subheader.to_map() {
+                retval = format!("{}\nNITF::IMAGE{:03}::{}: {}", retval, segment.index, field, value);
             }
         }
-        for (index, graphic_subheader) in (&self.metadata.graphic_subheaders).iter().enumerate() {
-            for (field, value) in graphic_subheader {
-                retval = format!("{}\nNITF::GRAPHIC{:03}::{}: {}", retval, index, field, value);
+        for segment in &self.metadata.graphic_segments {
+            for (field, value) in &segment.subheader.to_map() {
+                retval = format!("{}\nNITF::GRAPHIC{:03}::{}: {}", retval, segment.index, field, value);
             }
         }
-        for (index, text_subheader) in (&self.metadata.text_subheaders).iter().enumerate() {
-            for (field, value) in text_subheader {
-                retval = format!("{}\nNITF::TEXT{:03}::{}: {}", retval, index, field, value);
+        for segment in &self.metadata.symbol_segments {
+            for (field, value) in &segment.subheader.to_map() {
+                retval = format!("{}\nNITF::SYMBOL{:03}::{}: {}", retval, segment.index, field, value);
             }
         }
-        for (index, data_ext_subheader) in (&self.metadata.data_ext_subheaders).iter().enumerate() {
-            for (field, value) in data_ext_subheader {
-                retval = format!("{}\nNITF::DES{:03}::{}: {}", retval, index, field, value);
+        for segment in &self.metadata.label_segments {
+            for (field, value) in &segment.subheader.to_map() {
+                retval = format!("{}\nNITF::LABEL{:03}::{}: {}", retval, segment.index, field, value);
             }
         }
-        write!(f, "{}", retval)
-    }
-}
-
-
-impl NITF {
-
-
-
-    fn parse_header(nitf: &Vec<u8>) -> std::io::Result<BTreeMap<String,String>> {
-
-        let mut cursor = 0;
-
-        let mut file_header = BTreeMap::new();
-
-        // File Profile Name
-        file_header.insert("FHDR".to_string(),String::from_utf8(nitf[cursor..cursor+4].to_vec()).unwrap());
-        cursor = cursor + 4;
-
-        // File Version
-        file_header.insert("FVER".to_string(),String::from_utf8(nitf[cursor..cursor+5].to_vec()).unwrap());
-        cursor = cursor + 5;
-
-        // Complexity Level
-        file_header.insert("CLEVEL".to_string(),String::from_utf8(nitf[cursor..cursor+2].to_vec()).unwrap());
-        cursor = cursor + 2;
-
-        // Standard Type
-        file_header.insert("STYPE".to_string(),String::from_utf8(nitf[cursor..cursor+4].to_vec()).unwrap());
-        cursor = cursor + 4;
-
-        // Originating Station ID
-        file_header.insert("OSTAID".to_string(),String::from_utf8(nitf[cursor..cursor+10].to_vec()).unwrap().trim().to_string());
-        cursor = cursor + 10;
-
-        // File Data and Time
-        file_header.insert("FDT".to_string(),
-            // Year
-            String::from_utf8(nitf[cursor..cursor+4].to_vec()).unwrap() + "/" +
-            // Month
-            &String::from_utf8(nitf[cursor+4..cursor+6].to_vec()).unwrap() + "/" +
-            // Day
-            &String::from_utf8(nitf[cursor+6..cursor+8].to_vec()).unwrap() + " " +
-            // Hour
-            &String::from_utf8(nitf[cursor+8..cursor+10].to_vec()).unwrap() + ":" +
-            // Minute
-            &String::from_utf8(nitf[cursor+10..cursor+12].to_vec()).unwrap() + ":" +
-            // Second
-            &String::from_utf8(nitf[cursor+12..cursor+14].to_vec()).unwrap()
-        );
-        cursor = cursor + 14;
-
-        // File Title
-        if !String::from_utf8(nitf[cursor..cursor+80].to_vec()).unwrap().trim().to_string().is_empty() {
-            file_header.insert("FTITLE".to_string(),String::from_utf8(nitf[cursor..cursor+80].to_vec()).unwrap().trim().to_string());
-        }
-        cursor = cursor + 80;
-
-        // File Security Classification
-        file_header.insert("FSCLAS".to_string(),String::from_utf8(nitf[cursor..cursor+1].to_vec()).unwrap());
-        cursor = cursor + 1;
-
-        // File Secruity Classification System
-        if !String::from_utf8(nitf[cursor..cursor+2].to_vec()).unwrap().trim().to_string().is_empty() {
-            file_header.insert("FSCLSY".to_string(),String::from_utf8(nitf[cursor..cursor+2].to_vec()).unwrap());
-        }
-        cursor = cursor + 2;
-
-        // File Codewords
-        if !String::from_utf8(nitf[cursor..cursor+11].to_vec()).unwrap().trim().to_string().is_empty() {
-            file_header.insert("FSCODE".to_string(),String::from_utf8(nitf[cursor..cursor+11].to_vec()).unwrap().trim().to_string());
-        }
-        cursor = cursor + 11;
-
-        // File Control and Handling
-        if !String::from_utf8(nitf[cursor..cursor+2].to_vec()).unwrap().trim().to_string().is_empty() {
-            file_header.insert("FSCTLH".to_string(),String::from_utf8(nitf[cursor..cursor+2].to_vec()).unwrap().trim().to_string());
-        }
-        cursor = cursor + 2;
-
-        // File Releasing Instructions
-        if !String::from_utf8(nitf[cursor..cursor+20].to_vec()).unwrap().trim().to_string().is_empty() {
-            file_header.insert("FSREL".to_string(),String::from_utf8(nitf[cursor..cursor+20].to_vec()).unwrap().trim().to_string());
-        }
-        cursor = cursor + 20;
-
-        // File Declassification type
-        if !String::from_utf8(nitf[cursor..cursor+2].to_vec()).unwrap().trim().to_string().is_empty() {
-            file_header.insert("FSDCTP".to_string(),String::from_utf8(nitf[cursor..cursor+2].to_vec()).unwrap().trim().to_string());
-        }
-        cursor = cursor + 2;
-
-        // File Declassification Date
-        if !String::from_utf8(nitf[cursor..cursor+8].to_vec()).unwrap().trim().to_string().is_empty() {
-            file_header.insert("FSDCDT".to_string(),
-                // Year
-                (String::from_utf8(nitf[cursor..cursor+4].to_vec()).unwrap() + "/" +
-                // Month
-                &String::from_utf8(nitf[cursor+4..cursor+6].to_vec()).unwrap() + "/" +
-                // Day
-                &String::from_utf8(nitf[cursor+6..cursor+8].to_vec()).unwrap()).trim().to_string()
-
-            );
-        }
-        cursor = cursor + 8;
-
-        // File Declassification Exemption
-        if !String::from_utf8(nitf[cursor..cursor+4].to_vec()).unwrap().trim().to_string().is_empty() {
-            file_header.insert("FSDCXM".to_string(),String::from_utf8(nitf[cursor..cursor+4].to_vec()).unwrap().trim().to_string());
-        }
-        cursor = cursor + 4;
-
-        // File Downgrade
-        if !String::from_utf8(nitf[cursor..cursor+1].to_vec()).unwrap().trim().to_string().is_empty() {
-            file_header.insert("FSDG".to_string(),String::from_utf8(nitf[cursor..cursor+1].to_vec()).unwrap().trim().to_string());
-        }
-        cursor = cursor + 1;
-
-        // File Downgrade Date
-        if !String::from_utf8(nitf[cursor..cursor+8].to_vec()).unwrap().trim().to_string().is_empty() {
-            file_header.insert("FSDGDT".to_string(),
-                // Year
-                String::from_utf8(nitf[cursor..cursor+4].to_vec()).unwrap() + "/" +
-                // Month
-                &String::from_utf8(nitf[cursor+4..cursor+6].to_vec()).unwrap() + "/" +
-                // Day
-                &String::from_utf8(nitf[cursor+6..cursor+8].to_vec()).unwrap()
-
-            );
-        }
-        cursor = cursor + 8;
-
-        // File Classification Text
-        if !String::from_utf8(nitf[cursor..cursor+43].to_vec()).unwrap().trim().to_string().is_empty() {
-            file_header.insert("FSCLTX".to_string(),String::from_utf8(nitf[cursor..cursor+43].to_vec()).unwrap().trim().to_string());
-        }
-        cursor = cursor + 43;
-
-        // File Classification Authority Type
-        if !String::from_utf8(nitf[cursor..cursor+1].to_vec()).unwrap().trim().to_string().is_empty() {
-            file_header.insert("FSCATP".to_string(),String::from_utf8(nitf[cursor..cursor+1].to_vec()).unwrap());
-        }
-        cursor = cursor + 1;
-
-        // File Classification Authority
-        if !String::from_utf8(nitf[cursor..cursor+40].to_vec()).unwrap().trim().to_string().is_empty() {
-            file_header.insert("FSCAUT".to_string(),String::from_utf8(nitf[cursor..cursor+40].to_vec()).unwrap().trim().to_string());
-        }
-        cursor = cursor + 40;
-
-        // File Classification Reason
-        if !String::from_utf8(nitf[cursor..cursor+1].to_vec()).unwrap().trim().to_string().is_empty() {
-            file_header.insert("FSCRSN".to_string(),String::from_utf8(nitf[cursor..cursor+1].to_vec()).unwrap());
-        }
-        cursor = cursor + 1;
-
-        // File Security Source Date
-        if !String::from_utf8(nitf[cursor..cursor+8].to_vec()).unwrap().trim().to_string().is_empty() {
-            file_header.insert("FSSRDT".to_string(),
-                // Year
-                (String::from_utf8(nitf[cursor..cursor+4].to_vec()).unwrap() + "/" +
-                // Month
-                &String::from_utf8(nitf[cursor+4..cursor+6].to_vec()).unwrap() + "/" +
-                // Day
-                &String::from_utf8(nitf[cursor+6..cursor+8].to_vec()).unwrap()).trim().to_string()
-
-            );
-        }
-        cursor = cursor + 8;
-
-        // File Security Control Number
-        if !String::from_utf8(nitf[cursor..cursor+15].to_vec()).unwrap().trim().to_string().is_empty() {
-            file_header.insert("FSCLTN".to_string(),String::from_utf8(nitf[cursor..cursor+15].to_vec()).unwrap().trim().to_string());
-        }
-        cursor = cursor + 15;
-
-        // File Copy Number
-        file_header.insert("FSCOP".to_string(),String::from_utf8(nitf[cursor..cursor+5].to_vec()).unwrap().trim().to_string());
-        cursor = cursor + 5;
-
-        // File Number of Copies
-        file_header.insert("FSCPYS".to_string(),String::from_utf8(nitf[cursor..cursor+5].to_vec()).unwrap().trim().to_string());
-        cursor = cursor + 5;
-
-        // Encryption
-        file_header.insert("ENCRYP".to_string(),String::from_utf8(nitf[cursor..cursor+1].to_vec()).unwrap().trim().to_string());
-        cursor = cursor + 1;
-
-        // File Background Color
-        file_header.insert("FBKGC".to_string(),format!("0x{:02X}{:02X}{:02X}",nitf[cursor],nitf[cursor+1],nitf[cursor+2]));
-        cursor = cursor + 3;
-
-        // Originator's Name
-        if !String::from_utf8(nitf[cursor..cursor+24].to_vec()).unwrap().trim().to_string().is_empty() {
-            file_header.insert("ONAME".to_string(),String::from_utf8(nitf[cursor..cursor+24].to_vec()).unwrap().trim().to_string());
-        }
-        cursor = cursor + 24;
-
-        // Originator's Phone
-        if !String::from_utf8(nitf[cursor..cursor+18].to_vec()).unwrap().trim().to_string().is_empty() {
-            file_header.insert("OPHONE".to_string(),String::from_utf8(nitf[cursor..cursor+18].to_vec()).unwrap().trim().to_string());
-        }
-        cursor = cursor + 18;
-
-        // File Length
-        file_header.insert("FL".to_string(),String::from_utf8(nitf[cursor..cursor+12].to_vec()).unwrap());
-        cursor = cursor + 12;
-
-        // NITF File Header Length
-        file_header.insert("HL".to_string(),String::from_utf8(nitf[cursor..cursor+6].to_vec()).unwrap());
-        cursor = cursor + 6;
-
-        // Number of Image Segments
-        file_header.insert("NUMI".to_string(),String::from_utf8(nitf[cursor..cursor+3].to_vec()).unwrap());
-        let mut num_of_image_seg = 0;
-        for (index, value) in nitf[cursor..cursor+3].to_vec().iter().rev().enumerate() {
-            num_of_image_seg += (*value as i32-48)*10_i32.pow(index as u32);
-        }
-        cursor = cursor + 3;
-
-        for n in 1..=num_of_image_seg{
-            // Length of nth Image Subheader
-            file_header.insert(format!("LISH{:03}",n),String::from_utf8(nitf[cursor..cursor+6].to_vec()).unwrap());
-            // Length of nth Image Segment
-            file_header.insert(format!("LI{:03}",n),String::from_utf8(nitf[cursor+6..cursor+16].to_vec()).unwrap());
-            cursor = cursor + 16;
-        }
-
-        // Number of Graphic Segments
-        file_header.insert("NUMS".to_string(),String::from_utf8(nitf[cursor..cursor+3].to_vec()).unwrap());
-        let mut num_of_graphic_seg = 0;
-        for (index, value) in nitf[cursor..cursor+3].to_vec().iter().rev().enumerate() {
-            num_of_graphic_seg += (*value as i32-48)*10_i32.pow(index as u32);
-        }
-        cursor = cursor + 3;
-
-        for n in 1..=num_of_graphic_seg{
-            // Length of nth Graphic Subheader
-            file_header.insert(format!("LSSH{:03}",n),String::from_utf8(nitf[cursor..cursor+4].to_vec()).unwrap());
-            // Length of nth Graphic Segment
-            file_header.insert(format!("LS{:03}",n),String::from_utf8(nitf[cursor+4..cursor+10].to_vec()).unwrap());
-            cursor = cursor + 10;
-        }
-
-        // Reserved for Future Use
-        file_header.insert("NUMX".to_string(),String::from_utf8(nitf[cursor..cursor+3].to_vec()).unwrap());
-        cursor = cursor + 3;
-
-        // Number of Text Segments
-        file_header.insert("NUMT".to_string(),String::from_utf8(nitf[cursor..cursor+3].to_vec()).unwrap());
-        let mut num_of_text_seg = 0;
-        for (index, value) in nitf[cursor..cursor+3].to_vec().iter().rev().enumerate() {
-            num_of_text_seg += (*value as i32-48)*10_i32.pow(index as u32);
-        }
-        cursor = cursor + 3;
-
-        for n in 1..=num_of_text_seg{
-            // Length of nth Text Subheader
-            file_header.insert(format!("LTSH{:03}",n),String::from_utf8(nitf[cursor..cursor+4].to_vec()).unwrap());
-            // Length of nth Text Segment
-            file_header.insert(format!("LT{:03}",n),String::from_utf8(nitf[cursor+4..cursor+9].to_vec()).unwrap());
-            cursor = cursor + 9;
-        }
-
-        // Number of Data Extension Segments
-        file_header.insert("NUMDES".to_string(),String::from_utf8(nitf[cursor..cursor+3].to_vec()).unwrap());
-        let mut num_of_data_ext_seg = 0;
-        for (index, value) in nitf[cursor..cursor+3].to_vec().iter().rev().enumerate() {
-            num_of_data_ext_seg += (*value as i32-48)*10_i32.pow(index as u32);
-        }
-        cursor = cursor + 3;
-
-        for n in 1..=num_of_data_ext_seg{
-            // Length of nth Data Extension Segment Subheader
-            file_header.insert(format!("LDSH{:03}",n),String::from_utf8(nitf[cursor..cursor+4].to_vec()).unwrap());
-            // Length of nth Data Extension Segment
-            file_header.insert(format!("LD{:03}",n),String::from_utf8(nitf[cursor+4..cursor+13].to_vec()).unwrap());
-            cursor = cursor + 13;
-        }
-
-        // Number of Reserved Extension Segments
-        file_header.insert("NUMRES".to_string(),String::from_utf8(nitf[cursor..cursor+3].to_vec()).unwrap());
-        let mut num_of_reserved_ext_seg = 0;
-        for (index, value) in nitf[cursor..cursor+3].to_vec().iter().rev().enumerate() {
-            num_of_reserved_ext_seg += (*value as i32-48)*10_i32.pow(index as u32);
-        }
-        cursor = cursor + 3;
-
-        for n in 1..=num_of_reserved_ext_seg{
-            // Length of nth Reserved Extension Segment Subheader
-            file_header.insert(format!("LRESH{:03}",n),String::from_utf8(nitf[cursor..cursor+4].to_vec()).unwrap());
-            // Length of nth Reserved Extension Segment
-            file_header.insert(format!("LRE{:03}",n),String::from_utf8(nitf[cursor+4..cursor+11].to_vec()).unwrap());
-            cursor = cursor + 11;
-        }
-
-        // User Defined Header Data Length
-        file_header.insert("UDHDL".to_string(),String::from_utf8(nitf[cursor..cursor+5].to_vec()).unwrap());
-        let mut user_defined_header_data_length = 0;
-        for (index, value) in nitf[cursor..cursor+5].to_vec().iter().rev().enumerate() {
-            user_defined_header_data_length += (*value as i32-48)*10_i32.pow(index as u32);
-        }
-        cursor = cursor + 5;
-
-        if user_defined_header_data_length > 0 {
-            // User Defined Header Overflow Length
-            file_header.insert("UDHOFL".to_string(),String::from_utf8(nitf[cursor..cursor+3].to_vec()).unwrap());
-            cursor = cursor + 3;
-
-            let mut i: usize = 0;
-            while i < user_defined_header_data_length as usize {
-                let tag = String::from_utf8(nitf[cursor+i..cursor+i+6].to_vec()).unwrap();
-                i += 6;
-                let mut length = 0;
-                for (index, value) in nitf[cursor+i..cursor+i+5].to_vec().iter().rev().enumerate() {
-                    length += (*value as i32-48)*10_i32.pow(index as u32);
-                }
-                i += 5;
-                // User-Defined
-                file_header.insert(tag,String::from_utf8(nitf[cursor+i..cursor+i+length as usize].to_vec()).unwrap().trim().to_string());
-                i += length as usize;
+        for segment in &self.metadata.text_segments {
+            for (field, value) in &segment.subheader.to_map() {
+                retval = format!("{}\nNITF::TEXT{:03}::{}: {}", retval, segment.index, field, value);
             }
-            cursor = cursor + i;
         }
-
-        // Extended Header Data Length
-        file_header.insert("XHDL".to_string(),String::from_utf8(nitf[cursor..cursor+5].to_vec()).unwrap());
-        let mut extended_header_data_length = 0;
-        for (index, value) in nitf[cursor..cursor+5].to_vec().iter().rev().enumerate() {
-            extended_header_data_length += (*value as i32-48)*10_i32.pow(index as u32);
+        for segment in &self.metadata.data_ext_segments {
+            for (field, value) in &segment.subheader.to_map() {
+                retval = format!("{}\nNITF::DES{:03}::{}: {}", retval, segment.index, field, value);
+            }
         }
-        cursor = cursor + 5;
-
-        if extended_header_data_length > 0 {
-            // Extended Header Overflow Length
-            file_header.insert("XHOFL".to_string(),String::from_utf8(nitf[cursor..cursor+3].to_vec()).unwrap().trim().to_string());
-            cursor = cursor + 3;
-
-            let mut i: usize = 0;
-            while i < extended_header_data_length as usize - 3 {
-                let tag = String::from_utf8(nitf[cursor+i..cursor+i+6].to_vec()).unwrap();
-                i += 6;
-                let mut length = 0;
-                for (index, value) in nitf[cursor+i..cursor+i+5].to_vec().iter().rev().enumerate() {
-                    length += (*value as i32-48)*10_i32.pow(index as u32);
-                }
-                i += 5;
-                // Extended
-                file_header.insert(tag,String::from_utf8(nitf[cursor+i..cursor+i+length as usize].to_vec()).unwrap().trim().to_string());
-                i += length as usize;
+        for segment in &self.metadata.res_segments {
+            for (field, value) in &segment.subheader.to_map() {
+                retval = format!("{}\nNITF::RES{:03}::{}: {}", retval, segment.index, field, value);
             }
         }
-
-        Ok(file_header)
+        write!(f, "{}", retval)
     }
+}
 
 
+impl NITF {
 
-    fn parse_image_subheader(nitf: &Vec<u8>, offset: usize) -> std::io::Result<BTreeMap<String,String>> {
-
-        let mut image_subheader = BTreeMap::new();
-
-        let mut cursor = offset;
-
-        // File Part Type
-        image_subheader.insert("IM".to_string(),String::from_utf8(nitf[cursor..cursor+2].to_vec()).unwrap());
-        cursor = cursor + 2;
-
-        // Image Identifier 1
-        image_subheader.insert("IID1".to_string(),String::from_utf8(nitf[cursor..cursor+10].to_vec()).unwrap());
-        cursor = cursor + 10;
-
-        // Image Data and Time
-        image_subheader.insert("IDATIM".to_string(),
-            // Year
-            String::from_utf8(nitf[cursor..cursor+4].to_vec()).unwrap() + "/" +
-            // Month
-            &String::from_utf8(nitf[cursor+4..cursor+6].to_vec()).unwrap() + "/" +
-            // Day
-            &String::from_utf8(nitf[cursor+6..cursor+8].to_vec()).unwrap() + " " +
-            // Hour
-            &String::from_utf8(nitf[cursor+8..cursor+10].to_vec()).unwrap() + ":" +
-            // Minute
-            &String::from_utf8(nitf[cursor+10..cursor+12].to_vec()).unwrap() + ":" +
-            // Second
-            &String::from_utf8(nitf[cursor+12..cursor+14].to_vec()).unwrap()
-        );
-        cursor = cursor + 14;
-
-        // Target Identifier
-        if !String::from_utf8(nitf[cursor..cursor+17].to_vec()).unwrap().trim().to_string().is_empty() {
-            image_subheader.insert("TGTID".to_string(),String::from_utf8(nitf[cursor..cursor+17].to_vec()).unwrap().trim().to_string());
-        }
-        cursor = cursor + 17;
-
-        // Image Identifier 2
-        if !String::from_utf8(nitf[cursor..cursor+80].to_vec()).unwrap().trim().to_string().is_empty() {
-            image_subheader.insert("IID2".to_string(),String::from_utf8(nitf[cursor..cursor+80].to_vec()).unwrap().trim().to_string());
-        }
-        cursor = cursor + 80;
-
-        // Image Security Classification
-        image_subheader.insert("ISCLAS".to_string(),String::from_utf8(nitf[cursor..cursor+1].to_vec()).unwrap());
-        cursor = cursor + 1;
-
-        // Image Security Classifcation System
-        if !String::from_utf8(nitf[cursor..cursor+2].to_vec()).unwrap().trim().to_string().is_empty() {
-            image_subheader.insert("ISCLSY".to_string(),String::from_utf8(nitf[cursor..cursor+2].to_vec()).unwrap().trim().to_string());
-        }
-        cursor = cursor + 2;
-
-        // Image Codewords
-        if !String::from_utf8(nitf[cursor..cursor+11].to_vec()).unwrap().trim().to_string().is_empty() {
-            image_subheader.insert("ISCODE".to_string(),String::from_utf8(nitf[cursor..cursor+11].to_vec()).unwrap().trim().to_string());
-        }
-        cursor = cursor + 11;
-
-        // Image Control and Handling
-        if !String::from_utf8(nitf[cursor..cursor+2].to_vec()).unwrap().trim().to_string().is_empty() {
-            image_subheader.insert("ISCTLH".to_string(),String::from_utf8(nitf[cursor..cursor+2].to_vec()).unwrap().trim().to_string());
-        }
-        cursor = cursor + 2;
-
-        // Image Releasing Instructions
-        if !String::from_utf8(nitf[cursor..cursor+20].to_vec()).unwrap().trim().to_string().is_empty() {
-            image_subheader.insert("ISREL".to_string(),String::from_utf8(nitf[cursor..cursor+20].to_vec()).unwrap().trim().to_string());
-        }
-        cursor = cursor + 20;
+    /// Returns a NITF read from any seekable reader, e.g. a `File` or a `Cursor` over bytes
+    /// already in memory.
+    pub fn from_reader<R: Read + Seek + Send + 'static>(reader: R) -> Result<NITF, NitfError> {
+        NITF::open(Box::new(StreamSource::new(reader)?), LengthChecks::new(false))
+    }
 
-        // Image Declassification Type
-        if !String::from_utf8(nitf[cursor..cursor+2].to_vec()).unwrap().trim().to_string().is_empty() {
-            image_subheader.insert("ISDCTP".to_string(),String::from_utf8(nitf[cursor..cursor+2].to_vec()).unwrap().trim().to_string());
-        }
-        cursor = cursor + 2;
-
-        // Image Declassification Date
-        if !String::from_utf8(nitf[cursor..cursor+8].to_vec()).unwrap().trim().to_string().is_empty() {
-            image_subheader.insert("ISDCDT".to_string(),
-                // Year
-                (String::from_utf8(nitf[cursor..cursor+4].to_vec()).unwrap() + "/" +
-                // Month
-                &String::from_utf8(nitf[cursor+4..cursor+6].to_vec()).unwrap() + "/" +
-                // Day
-                &String::from_utf8(nitf[cursor+6..cursor+8].to_vec()).unwrap()).trim().to_string()
-
-            );
-        }
-        cursor = cursor + 8;
+    /// Returns a NITF read from any seekable reader like `from_reader`, except that FL, HL and
+    /// subheader lengths disagreeing with the file are recorded in `length_mismatches` rather
+    /// than failing the read. The segments are located by their recorded lengths and the file
+    /// header by the length actually parsed.
+    pub fn from_reader_lenient<R: Read + Seek + Send + 'static>(reader: R) -> Result<NITF, NitfError> {
+        NITF::open(Box::new(StreamSource::new(reader)?), LengthChecks::new(true))
+    }
 
-        // Image Declassification Excemption
-        if !String::from_utf8(nitf[cursor..cursor+4].to_vec()).unwrap().trim().to_string().is_empty() {
-            image_subheader.insert("ISDCXM".to_string(),String::from_utf8(nitf[cursor..cursor+4].to_vec()).unwrap().trim().to_string());
-        }
-        cursor = cursor + 4;
+    /// Returns a NITF read through a memory map of the given file.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while the NITF is in use.
+    #[cfg(feature = "mmap")]
+    pub unsafe fn open_mmap(filename: String) -> Result<NITF, NitfError> {
+        let file = File::open(filename)?;
+        NITF::open(Box::new(source::MmapSource::new(&file)?), LengthChecks::new(false))
+    }
 
-        // Image Downgrade
-        if !String::from_utf8(nitf[cursor..cursor+1].to_vec()).unwrap().trim().to_string().is_empty() {
-            image_subheader.insert("ISDG".to_string(),String::from_utf8(nitf[cursor..cursor+1].to_vec()).unwrap().trim().to_string());
-        }
-        cursor = cursor + 1;
-
-        // Image Downgrade Date
-        if !String::from_utf8(nitf[cursor..cursor+8].to_vec()).unwrap().trim().to_string().is_empty() {
-            image_subheader.insert("ISDGDT".to_string(),
-                // Year
-                (String::from_utf8(nitf[cursor..cursor+4].to_vec()).unwrap() + "/" +
-                // Month
-                &String::from_utf8(nitf[cursor+4..cursor+6].to_vec()).unwrap() + "/" +
-                // Day
-                &String::from_utf8(nitf[cursor+6..cursor+8].to_vec()).unwrap()).trim().to_string()
-
-            );
+    fn open(source: Box<dyn Source>, mut checks: LengthChecks) -> Result<NITF, NitfError> {
+
+        let (mut file_header, mut offset) = read_file_header(source.as_ref(), &mut checks)?;
+
+        let version = file_header.nitf_version().expect("the file header was parsed with a supported version");
+        let mut image_segments = parse_segments(source.as_ref(), &mut offset, &mut checks, &file_header.image_segments, "LISH",
+                                            |reader| ImageSubheader::parse(reader, version))?;
+        // NITF 2.0 has symbols in place of graphics, followed by labels
+        let (mut graphic_segments, mut symbol_segments, mut label_segments) = match version {
+            NitfVersion::Nitf20 => (
+                Vec::new(),
+                parse_segments(source.as_ref(), &mut offset, &mut checks, &file_header.graphic_segments, "LSSH", SymbolSubheader::parse)?,
+                parse_segments(source.as_ref(), &mut offset, &mut checks, &file_header.label_segments, "LLSH", LabelSubheader::parse)?
+            ),
+            _ => (
+                parse_segments(source.as_ref(), &mut offset, &mut checks, &file_header.graphic_segments, "LSSH", GraphicSubheader::parse)?,
+                Vec::new(),
+                Vec::new()
+            )
+        };
+        let mut text_segments = parse_segments(source.as_ref(), &mut offset, &mut checks, &file_header.text_segments, "LTSH",
+                                           |reader| TextSubheader::parse(reader, version))?;
+        let data_ext_segments = parse_segments(source.as_ref(), &mut offset, &mut checks, &file_header.data_ext_segments, "LDSH",
+                                               |reader| DesSubheader::parse(reader, version))?;
+        let res_segments = parse_segments(source.as_ref(), &mut offset, &mut checks, &file_header.reserved_ext_segments, "LRESH",
+                                          |reader| ResSubheader::parse(reader, version))?;
+
+        if offset > source.len() {
+            return Err(NitfError::Truncated { field: "segment data".to_string(), offset: source.len() });
+        }
+        // The header and segments must account for every byte of a file of known length, which
+        // is only checked when FL already matches the size of the file
+        let file_size_mismatch = checks.mismatches.iter().any(|mismatch| matches!(mismatch, NitfError::LengthMismatch { field, .. } if field == "FL"));
+        if file_header.file_length != UNKNOWN_FILE_LENGTH && offset != file_header.file_length && !file_size_mismatch {
+            checks.record(NitfError::LengthMismatch {
+                field: "FL".to_string(),
+                offset: file_header.file_length_offset(),
+                expected: file_header.file_length,
+                actual: offset
+            })?;
+        }
+
+        // Extensions that overflowed a header area are added to the extensions of that header
+        for segment in &data_ext_segments {
+            let (location, item) = match segment.subheader.overflow {
+                Some(overflow) => overflow,
+                None => continue
+            };
+            let data = read_at(source.as_ref(), "DESDATA", segment.data_offset(), segment.data_length as usize)?;
+            let tres = Tre::parse_all(&mut FieldReader::new(&data, segment.data_offset()), TreLocation::Overflow)?;
+            let index = usize::from(item).wrapping_sub(1);
+            let target = match location {
+                TreLocation::FileUserDefined | TreLocation::FileExtended if item == 0 => Some(&mut file_header.tres),
+                TreLocation::ImageUserDefined | TreLocation::ImageExtended => image_segments.get_mut(index).map(|segment| &mut segment.subheader.tres),
+                TreLocation::GraphicExtended if version == NitfVersion::Nitf20 => symbol_segments.get_mut(index).map(|segment| &mut segment.subheader.tres),
+                TreLocation::GraphicExtended => graphic_segments.get_mut(index).map(|segment| &mut segment.subheader.tres),
+                TreLocation::LabelExtended => label_segments.get_mut(index).map(|segment| &mut segment.subheader.tres),
+                TreLocation::TextExtended => text_segments.get_mut(index).map(|segment| &mut segment.subheader.tres),
+                _ => None
+            };
+            target.ok_or_else(|| NitfError::InvalidValue {
+                field: "DESITEM".to_string(),
+                offset: segment.offset,
+                value: item.to_string()
+            })?.extend(tres);
         }
-        cursor = cursor + 8;
 
-        // Image Classification Text
-        if !String::from_utf8(nitf[cursor..cursor+43].to_vec()).unwrap().trim().to_string().is_empty() {
-            image_subheader.insert("ISCLTX".to_string(),String::from_utf8(nitf[cursor..cursor+43].to_vec()).unwrap().trim().to_string());
-        }
-        cursor = cursor + 43;
+        let metadata = NITFmetadata {
+            file_header,
+            image_segments,
+            graphic_segments,
+            symbol_segments,
+            label_segments,
+            text_segments,
+            data_ext_segments,
+            res_segments,
+            length_mismatches: checks.mismatches
+        };
 
-        // Image Classification Authority Type
-        if !String::from_utf8(nitf[cursor..cursor+1].to_vec()).unwrap().trim().to_string().is_empty() {
-            image_subheader.insert("ISCATP".to_string(),String::from_utf8(nitf[cursor..cursor+1].to_vec()).unwrap().trim().to_string());
-        }
-        cursor = cursor + 1;
+        let image_caches = metadata.image_segments.iter().map(|_| ImageCache::default()).collect();
+        Ok(NITF {
+            metadata,
+            source,
+            jpeg2000_decoder: Box::new(BuiltInJpeg2000Decoder),
+            image_caches
+        })
+    }
 
-        // Image Classification Authority
-        if !String::from_utf8(nitf[cursor..cursor+40].to_vec()).unwrap().trim().to_string().is_empty() {
-            image_subheader.insert("ISCAUT".to_string(),String::from_utf8(nitf[cursor..cursor+40].to_vec()).unwrap().trim().to_string());
-        }
-        cursor = cursor + 40;
+    /// Replaces the decoder used for JPEG 2000 compressed image segments.
+    pub fn set_jpeg2000_decoder(&mut self, decoder: Box<dyn Jpeg2000Decoder>) {
+        self.jpeg2000_decoder = decoder;
+    }
 
-        // Image Classification Reason
-        if !String::from_utf8(nitf[cursor..cursor+1].to_vec()).unwrap().trim().to_string().is_empty() {
-            image_subheader.insert("ISCRSN".to_string(),String::from_utf8(nitf[cursor..cursor+1].to_vec()).unwrap().trim().to_string());
-        }
-        cursor = cursor + 1;
-
-        // Image Security Source Date
-        if !String::from_utf8(nitf[cursor..cursor+8].to_vec()).unwrap().trim().to_string().is_empty() {
-            image_subheader.insert("ISSRDT".to_string(),
-                // Year
-                (String::from_utf8(nitf[cursor..cursor+4].to_vec()).unwrap() + "/" +
-                // Month
-                &String::from_utf8(nitf[cursor+4..cursor+6].to_vec()).unwrap() + "/" +
-                // Day
-                &String::from_utf8(nitf[cursor+6..cursor+8].to_vec()).unwrap()).trim().to_string()
-
-            );
-        }
-        cursor = cursor + 8;
+    /// Returns the data of the given segment.
+    pub fn segment_data<T>(&self, segment: &Segment<T>) -> Result<Cow<'_, [u8]>, NitfError> {
+        self.read_segment_data(segment, 0, segment.data_length as usize)
+    }
 
-        // Image Classification Reason
-        if !String::from_utf8(nitf[cursor..cursor+15].to_vec()).unwrap().trim().to_string().is_empty() {
-            image_subheader.insert("ISCTLN".to_string(),String::from_utf8(nitf[cursor..cursor+15].to_vec()).unwrap().trim().to_string());
+    /// Returns `length` bytes of the given segment's data starting `offset` bytes into it.
+    pub fn read_segment_data<T>(&self, segment: &Segment<T>, offset: u64, length: usize) -> Result<Cow<'_, [u8]>, NitfError> {
+        let start = segment.data_offset() + offset;
+        if offset + length as u64 > segment.data_length {
+            return Err(NitfError::Truncated { field: "segment data".to_string(), offset: start });
         }
-        cursor = cursor + 15;
+        read_at(self.source.as_ref(), "segment data", start, length)
+    }
 
-        Ok(image_subheader)
+    /// Returns the highest classification of the file header and every segment subheader.
+    pub fn highest_classification(&self) -> Classification {
+        self.security_fields().map(|security| security.classification).max().unwrap_or(Classification::Unclassified)
     }
 
+    /// Returns whether the file is classified lower (FSCLAS) than some segment in it, which
+    /// the standard does not allow since the file classification must be the highest of all.
+    pub fn is_under_classified(&self) -> bool {
+        self.metadata.file_header.security.classification < self.highest_classification()
+    }
 
+    /// Returns the security fields of the file header followed by those of every segment
+    /// subheader in file order.
+    fn security_fields(&self) -> impl Iterator<Item = &SecurityFields> {
+        let metadata = &self.metadata;
+        std::iter::once(&metadata.file_header.security)
+            .chain(metadata.image_segments.iter().map(|segment| &segment.subheader.security))
+            .chain(metadata.graphic_segments.iter().map(|segment| &segment.subheader.security))
+            .chain(metadata.symbol_segments.iter().map(|segment| &segment.subheader.security))
+            .chain(metadata.label_segments.iter().map(|segment| &segment.subheader.security))
+            .chain(metadata.text_segments.iter().map(|segment| &segment.subheader.security))
+            .chain(metadata.data_ext_segments.iter().map(|segment| &segment.subheader.security))
+            .chain(metadata.res_segments.iter().map(|segment| &segment.subheader.security))
+    }
 
-    fn parse_graphic_subheader(nitf: &Vec<u8>, offset: usize) -> std::io::Result<BTreeMap<String,String>> {
+    /// Returns the length fields that disagree with the file, as LengthMismatch errors in the
+    /// order they were found. Only a lenient read records them, since any mismatch fails a
+    /// strict read.
+    pub fn length_mismatches(&self) -> &[NitfError] {
+        &self.metadata.length_mismatches
+    }
 
-        let mut graphic_subheader = BTreeMap::new();
+    /// Returns the file header.
+    pub fn file_header(&self) -> &FileHeader {
+        &self.metadata.file_header
+    }
 
-        let mut cursor = offset;
+    /// Returns the image segments in file order.
+    pub fn image_segments(&self) -> &[Segment<ImageSubheader>] {
+        &self.metadata.image_segments
+    }
 
-        // File Part Type
-        graphic_subheader.insert("SY".to_string(),String::from_utf8(nitf[cursor..cursor+2].to_vec()).unwrap());
-        cursor = cursor + 2;
+    /// Returns the rational polynomial sensor model of the image segment at `index`, or None
+    /// when the segment has no valid RPC00A or RPC00B TRE.
+    pub fn rpc_model(&self, index: usize) -> Result<Option<RpcModel>, NitfError> {
+        let segment = self.metadata.image_segments.get(index).ok_or_else(|| NitfError::InvalidRequest {
+            message: format!("there is no image segment {}", index)
+        })?;
+        segment.subheader.rpc_model()
+    }
 
-        // Graphic Identifier
-        graphic_subheader.insert("SID".to_string(),String::from_utf8(nitf[cursor..cursor+10].to_vec()).unwrap());
-        cursor = cursor + 10;
+    /// Returns the graphic segments in file order, which NITF 2.0 files do not have.
+    pub fn graphic_segments(&self) -> &[Segment<GraphicSubheader>] {
+        &self.metadata.graphic_segments
+    }
 
-        Ok(graphic_subheader)
+    /// Returns the symbol segments of a NITF 2.0 file in file order.
+    pub fn symbol_segments(&self) -> &[Segment<SymbolSubheader>] {
+        &self.metadata.symbol_segments
     }
 
+    /// Returns the label segments of a NITF 2.0 file in file order.
+    pub fn label_segments(&self) -> &[Segment<LabelSubheader>] {
+        &self.metadata.label_segments
+    }
 
+    /// Returns the text segments in file order.
+    pub fn text_segments(&self) -> &[Segment<TextSubheader>] {
+        &self.metadata.text_segments
+    }
 
-    fn parse_text_subheader(nitf: &Vec<u8>, offset: usize) -> std::io::Result<BTreeMap<String,String>> {
+    /// Returns the data of the text segment at `index` decoded as its TXTFMT describes, with
+    /// its line breaks as stored.
+    pub fn read_text(&self, index: usize) -> Result<String, NitfError> {
+        let segment = self.metadata.text_segments.get(index).ok_or_else(|| NitfError::InvalidRequest {
+            message: format!("there is no text segment {}", index)
+        })?;
+        let format = segment.subheader.format;
+        format.decode(&self.segment_data(segment)?).map_err(|position| NitfError::Decode {
+            compression: format.code().to_string(),
+            offset: segment.data_offset() + position as u64,
+            message: format!("byte {} is not {} text", position, format.code())
+        })
+    }
 
-        let mut text_subheader = BTreeMap::new();
+    /// Returns the XML document held by the XML_DATA_CONTENT data extension segment at
+    /// `index`, whose user defined subheader fields are in its subheader.
+    pub fn read_xml_data_content(&self, index: usize) -> Result<String, NitfError> {
+        let segment = self.metadata.data_ext_segments.get(index)
+            .filter(|segment| segment.subheader.des_id == des_subheader::XML_DATA_CONTENT)
+            .ok_or_else(|| NitfError::InvalidRequest {
+                message: format!("there is no {} data extension segment {}", des_subheader::XML_DATA_CONTENT, index)
+            })?;
+        let data = self.segment_data(segment)?;
+        String::from_utf8(data.into_owned()).map_err(|error| NitfError::Decode {
+            compression: "XML".to_string(),
+            offset: segment.data_offset() + error.utf8_error().valid_up_to() as u64,
+            message: "the document is not UTF-8".to_string()
+        })
+    }
 
-        let mut cursor = offset;
+    /// Returns the data extension segments in file order. The data of a segment is returned
+    /// by `segment_data`.
+    pub fn data_ext_segments(&self) -> &[Segment<DesSubheader>] {
+        &self.metadata.data_ext_segments
+    }
 
-        // File Part Type
-        text_subheader.insert("TE".to_string(),String::from_utf8(nitf[cursor..cursor+2].to_vec()).unwrap());
-        cursor = cursor + 2;
+    /// Returns the reserved extension segments in file order. The raw data of a segment, whose
+    /// layout is defined by its RESID, is returned by `segment_data`.
+    pub fn res_segments(&self) -> &[Segment<ResSubheader>] {
+        &self.metadata.res_segments
+    }
+}
 
-        // Graphic Identifier
-        text_subheader.insert("TEXTID".to_string(),String::from_utf8(nitf[cursor..cursor+7].to_vec()).unwrap());
-        cursor = cursor + 7;
 
-        Ok(text_subheader)
+/// Reads the file header, starting with a short prefix of the file and growing it until
+/// the whole header has been read. Returns the header with the number of bytes it spans.
+fn read_file_header(source: &dyn Source, checks: &mut LengthChecks) -> Result<(FileHeader, u64), NitfError> {
+    let mut length = 1024;
+    loop {
+        let length_read = length.min(source.len() as usize);
+        let bytes = read_at(source, "FHDR", 0, length_read)?;
+        let mut reader = FieldReader::new(&bytes, 0);
+        let recorded = checks.mismatches.len();
+        match FileHeader::parse(&mut reader, source.len(), checks) {
+            Err(NitfError::Truncated { .. }) if length_read < source.len() as usize => {
+                checks.mismatches.truncate(recorded);
+                length *= 2;
+            },
+            result => return result.map(|file_header| (file_header, reader.position()))
+        }
     }
+}
 
 
+/// Reads the subheaders of one segment type and parses them in parallel, returning them in
+/// file order. `offset` is advanced past the segments. `length_field` names the subheader
+/// length field (e.g. LISH), whose length each subheader must span exactly.
+fn parse_segments<T: Send>(source: &dyn Source, offset: &mut u64, checks: &mut LengthChecks, segments: &[SegmentLengths], length_field: &str,
+                           parse: impl Fn(&mut FieldReader) -> Result<T, NitfError> + Sync) -> Result<Vec<Segment<T>>, NitfError> {
 
-    fn parse_data_ext_seg_subheader(nitf: &Vec<u8>, offset: usize) -> std::io::Result<BTreeMap<String,String>> {
+    // Read each subheader, which also calculates the offset to each one
+    let mut subheaders = Vec::new();
+    for (index, lengths) in segments.iter().enumerate() {
+        let field = format!("{}{:03}", length_field, index + 1);
+        subheaders.push((*offset, read_at(source, &field, *offset, lengths.subheader_length as usize)?));
+        *offset += lengths.subheader_length + lengths.data_length;
+    }
 
-        let mut data_ext_seg_subheader = BTreeMap::new();
+    let parsed: Vec<(Segment<T>, Option<NitfError>)> = subheaders.into_par_iter().zip(segments).enumerate().map(|(index, ((offset, bytes), lengths))| {
+        let mut reader = FieldReader::new(&bytes, offset);
+        let subheader = parse(&mut reader)?;
+        let parsed_length = reader.position() - offset;
+        let mismatch = match parsed_length == lengths.subheader_length {
+            true => None,
+            false => Some(NitfError::LengthMismatch {
+                field: format!("{}{:03}", length_field, index + 1),
+                offset,
+                expected: lengths.subheader_length,
+                actual: parsed_length
+            })
+        };
+        Ok((Segment {
+            index,
+            offset,
+            subheader_length: lengths.subheader_length,
+            data_length: lengths.data_length,
+            subheader
+        }, mismatch))
+    }).collect::<Result<_, NitfError>>()?;
+
+    let mut parsed_segments = Vec::with_capacity(parsed.len());
+    for (segment, mismatch) in parsed {
+        if let Some(mismatch) = mismatch {
+            checks.record(mismatch)?;
+        }
+        parsed_segments.push(segment);
+    }
+    Ok(parsed_segments)
+}
 
-        let mut cursor = offset;
 
-        // File Part Type
-        data_ext_seg_subheader.insert("DE".to_string(),String::from_utf8(nitf[cursor..cursor+2].to_vec()).unwrap());
-        cursor = cursor + 2;
+/// Reads from the source, reporting a range past the end of the file as a truncated field.
+fn read_at<'a>(source: &'a dyn Source, field: &str, offset: u64, length: usize) -> Result<Cow<'a, [u8]>, NitfError> {
+    source.read_at(offset, length).map_err(|error| match error.kind() {
+        io::ErrorKind::UnexpectedEof => NitfError::Truncated { field: field.to_string(), offset },
+        _ => NitfError::Io(error)
+    })
+}
 
-        // Graphic Identifier
-        data_ext_seg_subheader.insert("DESID".to_string(),String::from_utf8(nitf[cursor..cursor+25].to_vec()).unwrap());
-        cursor = cursor + 25;
 
-        Ok(data_ext_seg_subheader)
+/// Inserts a conditional field only when its value is not blank.
+fn insert_optional(header: &mut BTreeMap<String,String>, field: &str, value: String) {
+    if !value.trim().is_empty() {
+        header.insert(field.to_string(), value);
     }
 }
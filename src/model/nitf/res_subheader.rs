@@ -0,0 +1,85 @@
+//! NITF reserved extension segment (RES) subheader
+
+use std::collections::BTreeMap;
+
+use super::error::NitfError;
+use super::field::{FieldReader, FieldWriter};
+use super::file_header::NitfVersion;
+use super::insert_optional;
+use super::security::SecurityFields;
+
+/// NITF reserved extension segment subheader
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResSubheader {
+    /// Unique RES Type Identifier (RESID)
+    pub res_id: String,
+    /// Version of the Data Definition (RESVER)
+    pub version: u8,
+    /// RES Security Fields (RESCLAS through RESCTLN)
+    pub security: SecurityFields,
+    /// RES User-defined Subheader Fields (RESSHF), RESSHL bytes long
+    pub user_defined: Vec<u8>
+}
+
+
+impl Default for ResSubheader {
+    /// Returns the subheader of an unclassified version 1 reserved extension without user
+    /// defined subheader fields.
+    fn default() -> ResSubheader {
+        ResSubheader {
+            res_id: String::new(),
+            version: 1,
+            security: SecurityFields::default(),
+            user_defined: Vec::new()
+        }
+    }
+}
+
+
+impl ResSubheader {
+
+    /// Parses the reserved extension segment subheader at the reader's position.
+    pub(crate) fn parse(reader: &mut FieldReader, version: NitfVersion) -> Result<ResSubheader, NitfError> {
+
+        reader.literal("RE", "RE")?;
+        let res_id = reader.trimmed("RESID", 25)?;
+        let res_version = reader.number("RESVER", 2)?;
+        let security = SecurityFields::parse(reader, "RE", version)?;
+        let user_defined_length = reader.number("RESSHL", 4)?;
+        let user_defined = reader.bytes("RESSHF", user_defined_length)?.to_vec();
+
+        Ok(ResSubheader {
+            res_id,
+            version: res_version,
+            security,
+            user_defined
+        })
+    }
+
+    /// Writes the reserved extension segment subheader.
+    pub(crate) fn write(&self, writer: &mut FieldWriter) -> Result<(), NitfError> {
+
+        writer.string("RE", "RE", 2)?;
+        writer.string("RESID", &self.res_id, 25)?;
+        writer.number("RESVER", self.version, 2)?;
+        self.security.write(writer, "RE")?;
+        writer.number("RESSHL", self.user_defined.len(), 4)?;
+        writer.bytes(&self.user_defined);
+        Ok(())
+    }
+
+    /// Returns the reserved extension segment subheader as a map of field names to display strings.
+    pub fn to_map(&self) -> BTreeMap<String,String> {
+
+        let mut res_subheader = BTreeMap::new();
+
+        res_subheader.insert("RE".to_string(), "RE".to_string());
+        res_subheader.insert("RESID".to_string(), self.res_id.clone());
+        res_subheader.insert("RESVER".to_string(), format!("{:02}", self.version));
+        self.security.insert_into(&mut res_subheader, "RE");
+        res_subheader.insert("RESSHL".to_string(), format!("{:04}", self.user_defined.len()));
+        insert_optional(&mut res_subheader, "RESSHF", String::from_utf8_lossy(&self.user_defined).trim().to_string());
+
+        res_subheader
+    }
+}
@@ -0,0 +1,204 @@
+//! Rational polynomial coefficient sensor model from the RPC00A and RPC00B TREs
+
+use super::error::NitfError;
+use super::tre::Tre;
+use super::tre_definition::{TreField, TreRegistry};
+
+/// Number of coefficients in each polynomial
+const TERMS: usize = 20;
+
+/// Largest number of iterations made when projecting from image to ground
+const MAX_ITERATIONS: usize = 50;
+
+/// Error in line and sample, in pixels, below which image to ground projection has converged
+const CONVERGENCE: f64 = 1e-8;
+
+/// Change in normalized latitude and longitude below which image to ground projection has
+/// converged, for models whose scale leaves the pixel error above CONVERGENCE at f64 precision
+const NORMALIZED_CONVERGENCE: f64 = 1e-13;
+
+/// Rational polynomial sensor model mapping latitude, longitude and height above the
+/// ellipsoid to image line and sample. Coefficients are held in RPC00B term order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RpcModel {
+    /// Error - Bias (ERR_BIAS) in meters
+    pub error_bias: f64,
+    /// Error - Random (ERR_RAND) in meters
+    pub error_random: f64,
+    /// Line Offset (LINE_OFF)
+    pub line_offset: f64,
+    /// Sample Offset (SAMP_OFF)
+    pub sample_offset: f64,
+    /// Geodetic Latitude Offset (LAT_OFF)
+    pub latitude_offset: f64,
+    /// Geodetic Longitude Offset (LONG_OFF)
+    pub longitude_offset: f64,
+    /// Geodetic Height Offset (HEIGHT_OFF)
+    pub height_offset: f64,
+    /// Line Scale (LINE_SCALE)
+    pub line_scale: f64,
+    /// Sample Scale (SAMP_SCALE)
+    pub sample_scale: f64,
+    /// Geodetic Latitude Scale (LAT_SCALE)
+    pub latitude_scale: f64,
+    /// Geodetic Longitude Scale (LONG_SCALE)
+    pub longitude_scale: f64,
+    /// Geodetic Height Scale (HEIGHT_SCALE)
+    pub height_scale: f64,
+    /// Line Numerator Coefficients (LINE_NUM_COEFF)
+    pub line_numerator: [f64; TERMS],
+    /// Line Denominator Coefficients (LINE_DEN_COEFF)
+    pub line_denominator: [f64; TERMS],
+    /// Sample Numerator Coefficients (SAMP_NUM_COEFF)
+    pub sample_numerator: [f64; TERMS],
+    /// Sample Denominator Coefficients (SAMP_DEN_COEFF)
+    pub sample_denominator: [f64; TERMS]
+}
+
+impl RpcModel {
+
+    /// Returns the model held in an RPC00A or RPC00B TRE, or None when the TRE's SUCCESS
+    /// field marks the coefficients as invalid.
+    pub fn from_tre(tre: &Tre) -> Result<Option<RpcModel>, NitfError> {
+        let ordering: fn([f64; TERMS]) -> [f64; TERMS] = match tre.tag.as_str() {
+            "RPC00A" => a_to_b_order,
+            "RPC00B" => |coefficients| coefficients,
+            _ => return Err(NitfError::InvalidValue { field: "CETAG".to_string(), offset: tre.offset, value: tre.tag.clone() })
+        };
+        let fields = TreRegistry::built_in().decode(tre)?.unwrap_or_default();
+
+        if value(&fields, "SUCCESS") != 1.0 {
+            return Ok(None);
+        }
+        Ok(Some(RpcModel {
+            error_bias: value(&fields, "ERR_BIAS"),
+            error_random: value(&fields, "ERR_RAND"),
+            line_offset: value(&fields, "LINE_OFF"),
+            sample_offset: value(&fields, "SAMP_OFF"),
+            latitude_offset: value(&fields, "LAT_OFF"),
+            longitude_offset: value(&fields, "LONG_OFF"),
+            height_offset: value(&fields, "HEIGHT_OFF"),
+            line_scale: value(&fields, "LINE_SCALE"),
+            sample_scale: value(&fields, "SAMP_SCALE"),
+            latitude_scale: value(&fields, "LAT_SCALE"),
+            longitude_scale: value(&fields, "LONG_SCALE"),
+            height_scale: value(&fields, "HEIGHT_SCALE"),
+            line_numerator: ordering(coefficients(&fields, "LINE_NUM_COEFF")),
+            line_denominator: ordering(coefficients(&fields, "LINE_DEN_COEFF")),
+            sample_numerator: ordering(coefficients(&fields, "SAMP_NUM_COEFF")),
+            sample_denominator: ordering(coefficients(&fields, "SAMP_DEN_COEFF"))
+        }))
+    }
+
+    /// Projects a ground point in degrees and meters above the ellipsoid to its image
+    /// (line, sample).
+    pub fn ground_to_image(&self, latitude: f64, longitude: f64, height: f64) -> (f64, f64) {
+        let (line, sample) = self.normalized_image(&self.terms(latitude, longitude, height));
+        (line * self.line_scale + self.line_offset, sample * self.sample_scale + self.sample_offset)
+    }
+
+    /// Projects an image (line, sample) to the ground (latitude, longitude) in degrees at the
+    /// given height above the ellipsoid, iterating from the center of the model. Returns None
+    /// when the projection does not converge.
+    pub fn image_to_ground(&self, line: f64, sample: f64, height: f64) -> Option<(f64, f64)> {
+        let mut latitude = self.latitude_offset;
+        let mut longitude = self.longitude_offset;
+
+        for _ in 0..MAX_ITERATIONS {
+            let terms = self.terms(latitude, longitude, height);
+            let (line_estimate, sample_estimate) = self.ground_to_image(latitude, longitude, height);
+            let line_error = line - line_estimate;
+            let sample_error = sample - sample_estimate;
+            if line_error.abs() < CONVERGENCE && sample_error.abs() < CONVERGENCE {
+                return Some((latitude, longitude));
+            }
+
+            // Solve the linearized model for the change in normalized latitude and longitude
+            let (line_by_latitude, line_by_longitude) = self.partials(&terms, &self.line_numerator, &self.line_denominator);
+            let (sample_by_latitude, sample_by_longitude) = self.partials(&terms, &self.sample_numerator, &self.sample_denominator);
+            let (a, b) = (line_by_latitude * self.line_scale, line_by_longitude * self.line_scale);
+            let (c, d) = (sample_by_latitude * self.sample_scale, sample_by_longitude * self.sample_scale);
+            let determinant = a * d - b * c;
+            if determinant == 0.0 || !determinant.is_finite() {
+                return None;
+            }
+            let latitude_change = (d * line_error - b * sample_error) / determinant;
+            let longitude_change = (a * sample_error - c * line_error) / determinant;
+            latitude += latitude_change * self.latitude_scale;
+            longitude += longitude_change * self.longitude_scale;
+            if latitude_change.abs() < NORMALIZED_CONVERGENCE && longitude_change.abs() < NORMALIZED_CONVERGENCE {
+                return Some((latitude, longitude));
+            }
+        }
+        None
+    }
+
+    /// Returns the polynomial terms of a ground point and their derivatives by normalized
+    /// latitude and longitude.
+    fn terms(&self, latitude: f64, longitude: f64, height: f64) -> Terms {
+        let p = (latitude - self.latitude_offset) / self.latitude_scale;
+        let l = (longitude - self.longitude_offset) / self.longitude_scale;
+        let h = (height - self.height_offset) / self.height_scale;
+        Terms {
+            values: [1.0, l, p, h, l*p, l*h, p*h, l*l, p*p, h*h,
+                     p*l*h, l*l*l, l*p*p, l*h*h, l*l*p, p*p*p, p*h*h, l*l*h, p*p*h, h*h*h],
+            by_latitude: [0.0, 0.0, 1.0, 0.0, l, 0.0, h, 0.0, 2.0*p, 0.0,
+                          l*h, 0.0, 2.0*l*p, 0.0, l*l, 3.0*p*p, h*h, 0.0, 2.0*p*h, 0.0],
+            by_longitude: [0.0, 1.0, 0.0, 0.0, p, h, 0.0, 2.0*l, 0.0, 0.0,
+                           p*h, 3.0*l*l, p*p, h*h, 2.0*l*p, 0.0, 0.0, 2.0*l*h, 0.0, 0.0]
+        }
+    }
+
+    fn normalized_image(&self, terms: &Terms) -> (f64, f64) {
+        (dot(&self.line_numerator, &terms.values) / dot(&self.line_denominator, &terms.values),
+         dot(&self.sample_numerator, &terms.values) / dot(&self.sample_denominator, &terms.values))
+    }
+
+    /// Returns the derivatives of a normalized rational polynomial by normalized latitude
+    /// and longitude.
+    fn partials(&self, terms: &Terms, numerator: &[f64; TERMS], denominator: &[f64; TERMS]) -> (f64, f64) {
+        let n = dot(numerator, &terms.values);
+        let d = dot(denominator, &terms.values);
+        let quotient = |by: &[f64; TERMS]| (dot(numerator, by) * d - n * dot(denominator, by)) / (d * d);
+        (quotient(&terms.by_latitude), quotient(&terms.by_longitude))
+    }
+}
+
+
+/// Polynomial terms of a normalized ground point and their derivatives
+struct Terms {
+    values: [f64; TERMS],
+    by_latitude: [f64; TERMS],
+    by_longitude: [f64; TERMS]
+}
+
+
+fn dot(coefficients: &[f64; TERMS], terms: &[f64; TERMS]) -> f64 {
+    coefficients.iter().zip(terms.iter()).map(|(coefficient, term)| coefficient * term).sum()
+}
+
+
+/// Reorders RPC00A coefficients into RPC00B order. RPC00A places the PLH term seventh, ahead
+/// of the squared terms, where RPC00B places it after them.
+fn a_to_b_order(a: [f64; TERMS]) -> [f64; TERMS] {
+    let mut b = a;
+    b[7] = a[8];
+    b[8] = a[9];
+    b[9] = a[10];
+    b[10] = a[7];
+    b
+}
+
+
+fn value(fields: &[TreField], name: &str) -> f64 {
+    fields.iter().find(|field| field.name == name).and_then(|field| field.value.as_real()).unwrap_or(0.0)
+}
+
+
+fn coefficients(fields: &[TreField], name: &str) -> [f64; TERMS] {
+    let mut coefficients = [0.0; TERMS];
+    for field in fields.iter().filter(|field| field.name == name) {
+        coefficients[field.indices[0]] = field.value.as_real().unwrap_or(0.0);
+    }
+    coefficients
+}
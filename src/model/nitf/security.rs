@@ -0,0 +1,303 @@
+//! Security classification fields shared by the file header and every segment subheader
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::error::NitfError;
+use super::field::{Date, FieldReader, FieldWriter};
+use super::file_header::NitfVersion;
+use super::insert_optional;
+
+/// Security classification level (e.g. FSCLAS, ISCLAS)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Classification {
+    /// U
+    Unclassified,
+    /// R
+    Restricted,
+    /// C
+    Confidential,
+    /// S
+    Secret,
+    /// T
+    TopSecret
+}
+
+impl Classification {
+    /// Returns the classification for the given single character code.
+    pub fn from_code(code: &str) -> Option<Classification> {
+        match code {
+            "U" => Some(Classification::Unclassified),
+            "R" => Some(Classification::Restricted),
+            "C" => Some(Classification::Confidential),
+            "S" => Some(Classification::Secret),
+            "T" => Some(Classification::TopSecret),
+            _ => None
+        }
+    }
+
+    /// Returns the single character code for the classification.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Classification::Unclassified => "U",
+            Classification::Restricted => "R",
+            Classification::Confidential => "C",
+            Classification::Secret => "S",
+            Classification::TopSecret => "T"
+        }
+    }
+
+    /// Returns the name of the classification as written in a banner line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Classification::Unclassified => "UNCLASSIFIED",
+            Classification::Restricted => "RESTRICTED",
+            Classification::Confidential => "CONFIDENTIAL",
+            Classification::Secret => "SECRET",
+            Classification::TopSecret => "TOP SECRET"
+        }
+    }
+}
+
+
+/// Security group found in the file header and each segment subheader. Blank fields are
+/// stored as empty strings or None, as are the fields of the other version for NITF 2.0 and
+/// 2.1 files.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SecurityFields {
+    /// Security Classification (xSCLAS)
+    pub classification: Classification,
+    /// Security Classification System (xSCLSY)
+    pub classification_system: String,
+    /// Codewords (xSCODE)
+    pub codewords: String,
+    /// Control and Handling (xSCTLH)
+    pub control_and_handling: String,
+    /// Releasing Instructions (xSREL)
+    pub releasing_instructions: String,
+    /// Declassification Type (xSDCTP)
+    pub declassification_type: String,
+    /// Declassification Date (xSDCDT)
+    pub declassification_date: Option<Date>,
+    /// Declassification Exemption (xSDCXM)
+    pub declassification_exemption: String,
+    /// Downgrade (xSDG)
+    pub downgrade: String,
+    /// Downgrade Date (xSDGDT)
+    pub downgrade_date: Option<Date>,
+    /// Classification Text (xSCLTX)
+    pub classification_text: String,
+    /// Classification Authority Type (xSCATP)
+    pub authority_type: String,
+    /// Classification Authority (xSCAUT)
+    pub authority: String,
+    /// Classification Reason (xSCRSN)
+    pub reason: String,
+    /// Security Source Date (xSSRDT)
+    pub source_date: Option<Date>,
+    /// Security Control Number (xSCTLN)
+    pub control_number: String,
+    /// Security Downgrade (xSDWNG), a YYMMDD date, 999999 for originating agency
+    /// determination or 999998 for a downgrading event. NITF 2.0 only.
+    pub downgrading: String,
+    /// Downgrading Event (xSDEVT), when xSDWNG is 999998. NITF 2.0 only.
+    pub downgrading_event: String
+}
+
+impl Default for SecurityFields {
+    /// Returns the fields of an unclassified segment with every other field blank.
+    fn default() -> SecurityFields {
+        SecurityFields {
+            classification: Classification::Unclassified,
+            classification_system: String::new(),
+            codewords: String::new(),
+            control_and_handling: String::new(),
+            releasing_instructions: String::new(),
+            declassification_type: String::new(),
+            declassification_date: None,
+            declassification_exemption: String::new(),
+            downgrade: String::new(),
+            downgrade_date: None,
+            classification_text: String::new(),
+            authority_type: String::new(),
+            authority: String::new(),
+            reason: String::new(),
+            source_date: None,
+            control_number: String::new(),
+            downgrading: String::new(),
+            downgrading_event: String::new()
+        }
+    }
+}
+
+
+impl SecurityFields {
+
+    /// Parses the security group, where `prefix` is the segment's field prefix (F, I, S, T, DE,
+    /// RE, and L for NITF 2.0 labels).
+    pub(crate) fn parse(reader: &mut FieldReader, prefix: &str, version: NitfVersion) -> Result<SecurityFields, NitfError> {
+        if version == NitfVersion::Nitf20 {
+            return SecurityFields::parse_nitf20(reader, prefix);
+        }
+        Ok(SecurityFields {
+            classification: reader.code(&format!("{}SCLAS", prefix), 1, Classification::from_code)?,
+            classification_system: reader.trimmed(&format!("{}SCLSY", prefix), 2)?,
+            codewords: reader.trimmed(&format!("{}SCODE", prefix), 11)?,
+            control_and_handling: reader.trimmed(&format!("{}SCTLH", prefix), 2)?,
+            releasing_instructions: reader.trimmed(&format!("{}SREL", prefix), 20)?,
+            declassification_type: reader.trimmed(&format!("{}SDCTP", prefix), 2)?,
+            declassification_date: reader.date(&format!("{}SDCDT", prefix))?,
+            declassification_exemption: reader.trimmed(&format!("{}SDCXM", prefix), 4)?,
+            downgrade: reader.trimmed(&format!("{}SDG", prefix), 1)?,
+            downgrade_date: reader.date(&format!("{}SDGDT", prefix))?,
+            classification_text: reader.trimmed(&format!("{}SCLTX", prefix), 43)?,
+            authority_type: reader.trimmed(&format!("{}SCATP", prefix), 1)?,
+            authority: reader.trimmed(&format!("{}SCAUT", prefix), 40)?,
+            reason: reader.trimmed(&format!("{}SCRSN", prefix), 1)?,
+            source_date: reader.date(&format!("{}SSRDT", prefix))?,
+            control_number: reader.trimmed(&format!("{}SCTLN", prefix), 15)?,
+            downgrading: String::new(),
+            downgrading_event: String::new()
+        })
+    }
+
+    /// Parses the NITF 2.0 security group, whose downgrading event is only present when the
+    /// downgrade is 999998.
+    fn parse_nitf20(reader: &mut FieldReader, prefix: &str) -> Result<SecurityFields, NitfError> {
+        let classification = reader.code(&format!("{}SCLAS", prefix), 1, Classification::from_code)?;
+        let codewords = reader.trimmed(&format!("{}SCODE", prefix), 40)?;
+        let control_and_handling = reader.trimmed(&format!("{}SCTLH", prefix), 40)?;
+        let releasing_instructions = reader.trimmed(&format!("{}SREL", prefix), 40)?;
+        let authority = reader.trimmed(&format!("{}SCAUT", prefix), 20)?;
+        let control_number = reader.trimmed(&format!("{}SCTLN", prefix), 20)?;
+        let downgrading = reader.trimmed(&format!("{}SDWNG", prefix), 6)?;
+        let downgrading_event = match downgrading.as_str() {
+            "999998" => reader.trimmed(&format!("{}SDEVT", prefix), 40)?,
+            _ => String::new()
+        };
+        Ok(SecurityFields {
+            classification,
+            codewords,
+            control_and_handling,
+            releasing_instructions,
+            authority,
+            control_number,
+            downgrading,
+            downgrading_event,
+            ..SecurityFields::default()
+        })
+    }
+
+    /// Writes the security group, where `prefix` is the segment's field prefix.
+    pub(crate) fn write(&self, writer: &mut FieldWriter, prefix: &str) -> Result<(), NitfError> {
+        writer.string(&format!("{}SCLAS", prefix), self.classification.code(), 1)?;
+        writer.string(&format!("{}SCLSY", prefix), &self.classification_system, 2)?;
+        writer.string(&format!("{}SCODE", prefix), &self.codewords, 11)?;
+        writer.string(&format!("{}SCTLH", prefix), &self.control_and_handling, 2)?;
+        writer.string(&format!("{}SREL", prefix), &self.releasing_instructions, 20)?;
+        writer.string(&format!("{}SDCTP", prefix), &self.declassification_type, 2)?;
+        writer.date(&format!("{}SDCDT", prefix), &self.declassification_date)?;
+        writer.string(&format!("{}SDCXM", prefix), &self.declassification_exemption, 4)?;
+        writer.string(&format!("{}SDG", prefix), &self.downgrade, 1)?;
+        writer.date(&format!("{}SDGDT", prefix), &self.downgrade_date)?;
+        writer.string(&format!("{}SCLTX", prefix), &self.classification_text, 43)?;
+        writer.string(&format!("{}SCATP", prefix), &self.authority_type, 1)?;
+        writer.string(&format!("{}SCAUT", prefix), &self.authority, 40)?;
+        writer.string(&format!("{}SCRSN", prefix), &self.reason, 1)?;
+        writer.date(&format!("{}SSRDT", prefix), &self.source_date)?;
+        writer.string(&format!("{}SCTLN", prefix), &self.control_number, 15)
+    }
+
+    /// Inserts the security fields into a string map view using the segment's field prefix.
+    pub(crate) fn insert_into(&self, header: &mut BTreeMap<String,String>, prefix: &str) {
+        let date = |date: &Option<Date>| date.map(|date| date.to_string()).unwrap_or_default();
+
+        header.insert(format!("{}SCLAS", prefix), self.classification.code().to_string());
+        insert_optional(header, &format!("{}SCLSY", prefix), self.classification_system.clone());
+        insert_optional(header, &format!("{}SCODE", prefix), self.codewords.clone());
+        insert_optional(header, &format!("{}SCTLH", prefix), self.control_and_handling.clone());
+        insert_optional(header, &format!("{}SREL", prefix), self.releasing_instructions.clone());
+        insert_optional(header, &format!("{}SDCTP", prefix), self.declassification_type.clone());
+        insert_optional(header, &format!("{}SDCDT", prefix), date(&self.declassification_date));
+        insert_optional(header, &format!("{}SDCXM", prefix), self.declassification_exemption.clone());
+        insert_optional(header, &format!("{}SDG", prefix), self.downgrade.clone());
+        insert_optional(header, &format!("{}SDGDT", prefix), date(&self.downgrade_date));
+        insert_optional(header, &format!("{}SCLTX", prefix), self.classification_text.clone());
+        insert_optional(header, &format!("{}SCATP", prefix), self.authority_type.clone());
+        insert_optional(header, &format!("{}SCAUT", prefix), self.authority.clone());
+        insert_optional(header, &format!("{}SCRSN", prefix), self.reason.clone());
+        insert_optional(header, &format!("{}SSRDT", prefix), date(&self.source_date));
+        insert_optional(header, &format!("{}SCTLN", prefix), self.control_number.clone());
+        insert_optional(header, &format!("{}SDWNG", prefix), self.downgrading.clone());
+        insert_optional(header, &format!("{}SDEVT", prefix), self.downgrading_event.clone());
+    }
+}
+
+
+/// Security marking of a header, with the space separated lists of its security fields split
+/// into their codes. Its banner line joins the classification and each non-empty category
+/// with double slashes, e.g. `SECRET//SI/TK//NF//REL TO US, UK`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SecurityMarking {
+    /// Security Classification (xSCLAS)
+    pub classification: Classification,
+    /// Country code of the classification system (xSCLSY), blank for NITF 2.0
+    pub system: String,
+    /// Codewords (xSCODE)
+    pub codewords: Vec<String>,
+    /// Control and handling codes (xSCTLH)
+    pub control_and_handling: Vec<String>,
+    /// Country codes the information may be released to (xSREL)
+    pub release_to: Vec<String>
+}
+
+impl SecurityMarking {
+
+    /// Returns the marking given by a header's security fields.
+    pub fn from_fields(fields: &SecurityFields) -> SecurityMarking {
+        let codes = |field: &str| field.split_whitespace().map(str::to_string).collect();
+        SecurityMarking {
+            classification: fields.classification,
+            system: fields.classification_system.trim().to_string(),
+            codewords: codes(&fields.codewords),
+            control_and_handling: codes(&fields.control_and_handling),
+            release_to: codes(&fields.releasing_instructions)
+        }
+    }
+
+    /// Returns the banner line of the marking. A classification of a system other than the
+    /// US one is written after its country code as a non-US marking, e.g. `//UK SECRET`.
+    pub fn banner(&self) -> String {
+        self.join(self.classification.name())
+    }
+
+    /// Returns the portion mark of the marking, the banner line with the classification
+    /// abbreviated to its code and enclosed in parentheses, e.g. `(S//SI/TK//NF)`.
+    pub fn portion_mark(&self) -> String {
+        format!("({})", self.join(self.classification.code()))
+    }
+
+    /// Joins the classification and the other categories of the marking.
+    fn join(&self, classification: &str) -> String {
+        let mut categories = vec![match self.system.as_str() {
+            "" | "US" => classification.to_string(),
+            system => format!("//{} {}", system, classification)
+        }];
+        if !self.codewords.is_empty() {
+            categories.push(self.codewords.join("/"));
+        }
+        if !self.control_and_handling.is_empty() {
+            categories.push(self.control_and_handling.join("/"));
+        }
+        if !self.release_to.is_empty() {
+            categories.push(format!("REL TO {}", self.release_to.join(", ")));
+        }
+        categories.join("//")
+    }
+}
+
+impl fmt::Display for SecurityMarking {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.banner())
+    }
+}
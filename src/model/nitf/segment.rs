@@ -0,0 +1,23 @@
+//! Location of a segment within a NITF
+
+/// Parsed segment subheader along with where the segment sits in the file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Segment<T> {
+    /// Zero based index of the segment among the segments of its type
+    pub index: usize,
+    /// Byte offset of the segment subheader from the start of the file
+    pub offset: u64,
+    /// Length of the segment subheader
+    pub subheader_length: u64,
+    /// Length of the segment data
+    pub data_length: u64,
+    /// Parsed segment subheader
+    pub subheader: T
+}
+
+impl<T> Segment<T> {
+    /// Returns the byte offset of the segment data from the start of the file.
+    pub fn data_offset(&self) -> u64 {
+        self.offset + self.subheader_length
+    }
+}
@@ -0,0 +1,82 @@
+//! Random access byte sources a NITF is read from
+
+use std::borrow::Cow;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::Mutex;
+
+/// Random access source of the bytes of a NITF file.
+pub(crate) trait Source: Send + Sync {
+    /// Returns the length of the file in bytes.
+    fn len(&self) -> u64;
+
+    /// Returns `length` bytes starting at `offset`, or an UnexpectedEof error when the range
+    /// runs past the end of the file.
+    fn read_at(&self, offset: u64, length: usize) -> io::Result<Cow<'_, [u8]>>;
+}
+
+
+/// Source that seeks and reads through any `Read + Seek` implementation.
+pub(crate) struct StreamSource<R> {
+    reader: Mutex<R>,
+    length: u64
+}
+
+impl<R: Read + Seek> StreamSource<R> {
+    pub(crate) fn new(mut reader: R) -> io::Result<StreamSource<R>> {
+        let length = reader.seek(SeekFrom::End(0))?;
+        Ok(StreamSource {
+            reader: Mutex::new(reader),
+            length
+        })
+    }
+}
+
+impl<R: Read + Seek + Send> Source for StreamSource<R> {
+    fn len(&self) -> u64 {
+        self.length
+    }
+
+    fn read_at(&self, offset: u64, length: usize) -> io::Result<Cow<'_, [u8]>> {
+        if offset + length as u64 > self.length {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+        let mut reader = self.reader.lock().map_err(|_| io::Error::other("NITF reader lock poisoned"))?;
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut bytes = vec![0; length];
+        reader.read_exact(&mut bytes)?;
+        Ok(Cow::Owned(bytes))
+    }
+}
+
+
+/// Source backed by a memory map of the file.
+#[cfg(feature = "mmap")]
+pub(crate) struct MmapSource {
+    map: memmap2::Mmap
+}
+
+#[cfg(feature = "mmap")]
+impl MmapSource {
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while it is mapped.
+    pub(crate) unsafe fn new(file: &std::fs::File) -> io::Result<MmapSource> {
+        Ok(MmapSource {
+            map: memmap2::Mmap::map(file)?
+        })
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl Source for MmapSource {
+    fn len(&self) -> u64 {
+        self.map.len() as u64
+    }
+
+    fn read_at(&self, offset: u64, length: usize) -> io::Result<Cow<'_, [u8]>> {
+        let start = offset as usize;
+        self.map.get(start..start + length)
+            .map(Cow::Borrowed)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))
+    }
+}
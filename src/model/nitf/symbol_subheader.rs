@@ -0,0 +1,176 @@
+//! NITF 2.0 symbol subheader
+
+use std::collections::BTreeMap;
+
+use super::error::NitfError;
+use super::field::{Color, FieldReader};
+use super::file_header::NitfVersion;
+use super::insert_optional;
+use super::security::SecurityFields;
+use super::tre::{self, Tre, TreLocation};
+
+/// Symbol Type (STYPE)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymbolType {
+    /// B - bit-mapped
+    Bitmap,
+    /// C - Computer Graphics Metafile
+    Cgm,
+    /// O - object from a standard symbol set, identified by SNUM
+    Object
+}
+
+impl SymbolType {
+    /// Returns the symbol type for the given STYPE code.
+    pub fn from_code(code: &str) -> Option<SymbolType> {
+        match code {
+            "B" => Some(SymbolType::Bitmap),
+            "C" => Some(SymbolType::Cgm),
+            "O" => Some(SymbolType::Object),
+            _ => None
+        }
+    }
+
+    /// Returns the STYPE code for the symbol type.
+    pub fn code(&self) -> &'static str {
+        match self {
+            SymbolType::Bitmap => "B",
+            SymbolType::Cgm => "C",
+            SymbolType::Object => "O"
+        }
+    }
+}
+
+
+/// NITF 2.0 symbol subheader. Symbol segments take the place of NITF 2.1 graphic segments.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SymbolSubheader {
+    /// Symbol Identifier (SID)
+    pub symbol_id: String,
+    /// Symbol Name (SNAME)
+    pub name: String,
+    /// Symbol Security Fields (SSCLAS through SSDEVT)
+    pub security: SecurityFields,
+    /// Encryption (ENCRYP)
+    pub encryption: u8,
+    /// Symbol Type (STYPE)
+    pub symbol_type: SymbolType,
+    /// Number of Lines per Symbol (NLIPS), for bit-mapped symbols
+    pub lines: u32,
+    /// Number of Pixels per Line (NPIXPL), for bit-mapped symbols
+    pub pixels_per_line: u32,
+    /// Line Width (NWDTH)
+    pub line_width: u32,
+    /// Number of Bits per Pixel (NBPP), for bit-mapped symbols
+    pub bits_per_pixel: u8,
+    /// Display Level (SDLVL)
+    pub display_level: u16,
+    /// Attachment Level (SALVL)
+    pub attachment_level: u16,
+    /// Symbol Location as row and column (SLOC)
+    pub location: (i32, i32),
+    /// Second Symbol Location as row and column (SLOC2)
+    pub second_location: (i32, i32),
+    /// Symbol Color (SCOLOR)
+    pub color: String,
+    /// Symbol Number (SNUM), for object symbols
+    pub number: String,
+    /// Symbol Rotation in degrees (SROT)
+    pub rotation: u16,
+    /// Symbol Look Up Table (DLUT), NELUT colors for bit-mapped symbols
+    pub lut: Vec<Color>,
+    /// Extended Subheader Overflow (SXSOFL)
+    pub extended_overflow: u16,
+    /// Tagged record extensions from the SXSHD area, in file order
+    pub tres: Vec<Tre>
+}
+
+
+impl SymbolSubheader {
+
+    /// Parses the symbol subheader at the reader's position.
+    pub(crate) fn parse(reader: &mut FieldReader) -> Result<SymbolSubheader, NitfError> {
+
+        reader.literal("SY", "SY")?;
+        let symbol_id = reader.trimmed("SID", 10)?;
+        let name = reader.trimmed("SNAME", 20)?;
+        let security = SecurityFields::parse(reader, "S", NitfVersion::Nitf20)?;
+        let encryption = reader.number("ENCRYP", 1)?;
+        let symbol_type = reader.code("STYPE", 1, SymbolType::from_code)?;
+        let lines = reader.number("NLIPS", 4)?;
+        let pixels_per_line = reader.number("NPIXPL", 4)?;
+        let line_width = reader.number("NWDTH", 4)?;
+        let bits_per_pixel = reader.number("NBPP", 1)?;
+        let display_level = reader.number("SDLVL", 3)?;
+        let attachment_level = reader.number("SALVL", 3)?;
+        let location = (reader.number("SLOC", 5)?, reader.number("SLOC", 5)?);
+        let second_location = (reader.number("SLOC2", 5)?, reader.number("SLOC2", 5)?);
+        let color = reader.trimmed("SCOLOR", 1)?;
+        let number = reader.trimmed("SNUM", 6)?;
+        let rotation = reader.number("SROT", 3)?;
+        let num_of_lut_entries: usize = reader.number("NELUT", 3)?;
+        let lut = (0..num_of_lut_entries).map(|_| reader.color("DLUT")).collect::<Result<_, _>>()?;
+        let (extended_overflow, tres) = reader.tre_area("SXSHDL", "SXSOFL", "SXSHD", TreLocation::GraphicExtended)?;
+
+        Ok(SymbolSubheader {
+            symbol_id,
+            name,
+            security,
+            encryption,
+            symbol_type,
+            lines,
+            pixels_per_line,
+            line_width,
+            bits_per_pixel,
+            display_level,
+            attachment_level,
+            location,
+            second_location,
+            color,
+            number,
+            rotation,
+            lut,
+            extended_overflow,
+            tres
+        })
+    }
+
+    /// Returns every tagged record extension in the symbol subheader with the given tag.
+    pub fn find_tres(&self, tag: &str) -> Vec<&Tre> {
+        tre::find(&self.tres, tag)
+    }
+
+    /// Returns the symbol subheader as a map of field names to display strings.
+    pub fn to_map(&self) -> BTreeMap<String,String> {
+
+        let mut symbol_subheader = BTreeMap::new();
+
+        symbol_subheader.insert("SY".to_string(), "SY".to_string());
+        symbol_subheader.insert("SID".to_string(), self.symbol_id.clone());
+        insert_optional(&mut symbol_subheader, "SNAME", self.name.clone());
+        self.security.insert_into(&mut symbol_subheader, "S");
+        symbol_subheader.insert("ENCRYP".to_string(), self.encryption.to_string());
+        symbol_subheader.insert("STYPE".to_string(), self.symbol_type.code().to_string());
+        symbol_subheader.insert("NLIPS".to_string(), format!("{:04}", self.lines));
+        symbol_subheader.insert("NPIXPL".to_string(), format!("{:04}", self.pixels_per_line));
+        symbol_subheader.insert("NWDTH".to_string(), format!("{:04}", self.line_width));
+        symbol_subheader.insert("NBPP".to_string(), self.bits_per_pixel.to_string());
+        symbol_subheader.insert("SDLVL".to_string(), format!("{:03}", self.display_level));
+        symbol_subheader.insert("SALVL".to_string(), format!("{:03}", self.attachment_level));
+        symbol_subheader.insert("SLOC".to_string(), format!("{:05}{:05}", self.location.0, self.location.1));
+        symbol_subheader.insert("SLOC2".to_string(), format!("{:05}{:05}", self.second_location.0, self.second_location.1));
+        insert_optional(&mut symbol_subheader, "SCOLOR", self.color.clone());
+        insert_optional(&mut symbol_subheader, "SNUM", self.number.clone());
+        symbol_subheader.insert("SROT".to_string(), format!("{:03}", self.rotation));
+        symbol_subheader.insert("NELUT".to_string(), format!("{:03}", self.lut.len()));
+        if !self.lut.is_empty() {
+            let hex = self.lut.iter().fold("0x".to_string(), |hex, color| format!("{}{:02X}{:02X}{:02X}", hex, color.red, color.green, color.blue));
+            symbol_subheader.insert("DLUT".to_string(), hex);
+        }
+
+        tre::insert_area(&mut symbol_subheader, ("SXSHDL", "SXSOFL"), self.extended_overflow, &self.tres, TreLocation::GraphicExtended);
+        tre::insert_overflow(&mut symbol_subheader, &self.tres);
+
+        symbol_subheader
+    }
+}
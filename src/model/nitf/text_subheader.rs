@@ -0,0 +1,178 @@
+//! NITF text subheader
+
+use std::collections::BTreeMap;
+
+use super::error::NitfError;
+use super::field::{DateTime, FieldReader, FieldWriter};
+use super::file_header::NitfVersion;
+use super::insert_optional;
+use super::security::SecurityFields;
+use super::tre::{self, Tre, TreLocation};
+
+/// Text Format (TXTFMT)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextFormat {
+    /// STA - basic character set text
+    Standard,
+    /// MTF - USMTF formatted message
+    MessageTextFormat,
+    /// UT1 - extended character set text, one byte per character as in ISO 8859-1
+    ExtendedCharacters,
+    /// U8S - UTF-8 text of the extended character set
+    Utf8
+}
+
+impl TextFormat {
+    /// Returns the text format for the given TXTFMT code.
+    pub fn from_code(code: &str) -> Option<TextFormat> {
+        match code {
+            "STA" => Some(TextFormat::Standard),
+            "MTF" => Some(TextFormat::MessageTextFormat),
+            "UT1" => Some(TextFormat::ExtendedCharacters),
+            "U8S" => Some(TextFormat::Utf8),
+            _ => None
+        }
+    }
+
+    /// Returns the TXTFMT code for the text format.
+    pub fn code(&self) -> &'static str {
+        match self {
+            TextFormat::Standard => "STA",
+            TextFormat::MessageTextFormat => "MTF",
+            TextFormat::ExtendedCharacters => "UT1",
+            TextFormat::Utf8 => "U8S"
+        }
+    }
+
+    /// Decodes text data in the format, returning the offset of the first byte that cannot be
+    /// decoded on failure. STA and MTF text must be ASCII.
+    pub(crate) fn decode(&self, data: &[u8]) -> Result<String, usize> {
+        match self {
+            TextFormat::Standard | TextFormat::MessageTextFormat => match data.iter().position(|byte| !byte.is_ascii()) {
+                Some(position) => Err(position),
+                None => Ok(data.iter().map(|byte| char::from(*byte)).collect())
+            },
+            TextFormat::ExtendedCharacters => Ok(data.iter().map(|byte| char::from(*byte)).collect()),
+            TextFormat::Utf8 => String::from_utf8(data.to_vec()).map_err(|error| error.utf8_error().valid_up_to())
+        }
+    }
+}
+
+
+/// NITF text subheader
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextSubheader {
+    /// Text Identifier (TEXTID)
+    pub text_id: String,
+    /// Text Attachment Level (TXTALVL), zero in NITF 2.0
+    pub attachment_level: u16,
+    /// Text Date and Time (TXTDT)
+    pub date_time: DateTime,
+    /// Text Title (TXTITL)
+    pub title: String,
+    /// Text Security Fields (TSCLAS through TSSRDT)
+    pub security: SecurityFields,
+    /// Encryption (ENCRYP)
+    pub encryption: u8,
+    /// Text Format (TXTFMT)
+    pub format: TextFormat,
+    /// Extended Subheader Overflow (TXSOFL)
+    pub extended_overflow: u16,
+    /// Tagged record extensions from the TXSHD area, in file order
+    pub tres: Vec<Tre>
+}
+
+
+impl Default for TextSubheader {
+    /// Returns the subheader of an unclassified basic character set text attached to the file.
+    fn default() -> TextSubheader {
+        TextSubheader {
+            text_id: String::new(),
+            attachment_level: 0,
+            date_time: DateTime::default(),
+            title: String::new(),
+            security: SecurityFields::default(),
+            encryption: 0,
+            format: TextFormat::Standard,
+            extended_overflow: 0,
+            tres: Vec::new()
+        }
+    }
+}
+
+
+impl TextSubheader {
+
+    /// Parses the text subheader at the reader's position. In NITF 2.0 TEXTID is ten
+    /// characters long, there is no TXTALVL and TXTDT is DDHHMMSSZMONYY.
+    pub(crate) fn parse(reader: &mut FieldReader, version: NitfVersion) -> Result<TextSubheader, NitfError> {
+
+        let nitf20 = version == NitfVersion::Nitf20;
+        reader.literal("TE", "TE")?;
+        let text_id = reader.trimmed("TEXTID", if nitf20 { 10 } else { 7 })?;
+        let attachment_level = match nitf20 {
+            true => 0,
+            false => reader.number("TXTALVL", 3)?
+        };
+        let date_time = match nitf20 {
+            true => reader.legacy_date_time("TXTDT")?,
+            false => reader.date_time("TXTDT")?
+        };
+        let title = reader.trimmed("TXTITL", 80)?;
+        let security = SecurityFields::parse(reader, "T", version)?;
+        let encryption = reader.number("ENCRYP", 1)?;
+        let format = reader.code("TXTFMT", 3, TextFormat::from_code)?;
+        let (extended_overflow, tres) = reader.tre_area("TXSHDL", "TXSOFL", "TXSHD", TreLocation::TextExtended)?;
+
+        Ok(TextSubheader {
+            text_id,
+            attachment_level,
+            date_time,
+            title,
+            security,
+            encryption,
+            format,
+            extended_overflow,
+            tres
+        })
+    }
+
+    /// Writes the text subheader.
+    pub(crate) fn write(&self, writer: &mut FieldWriter) -> Result<(), NitfError> {
+
+        writer.string("TE", "TE", 2)?;
+        writer.string("TEXTID", &self.text_id, 7)?;
+        writer.number("TXTALVL", self.attachment_level, 3)?;
+        writer.date_time("TXTDT", &self.date_time)?;
+        writer.string("TXTITL", &self.title, 80)?;
+        self.security.write(writer, "T")?;
+        writer.number("ENCRYP", self.encryption, 1)?;
+        writer.string("TXTFMT", self.format.code(), 3)?;
+        writer.tre_area(("TXSHDL", "TXSOFL"), self.extended_overflow, &self.tres, TreLocation::TextExtended)
+    }
+
+    /// Returns every tagged record extension in the text subheader with the given tag.
+    pub fn find_tres(&self, tag: &str) -> Vec<&Tre> {
+        tre::find(&self.tres, tag)
+    }
+
+    /// Returns the text subheader as a map of field names to display strings.
+    pub fn to_map(&self) -> BTreeMap<String,String> {
+
+        let mut text_subheader = BTreeMap::new();
+
+        text_subheader.insert("TE".to_string(), "TE".to_string());
+        text_subheader.insert("TEXTID".to_string(), self.text_id.clone());
+        text_subheader.insert("TXTALVL".to_string(), format!("{:03}", self.attachment_level));
+        text_subheader.insert("TXTDT".to_string(), self.date_time.to_string());
+        insert_optional(&mut text_subheader, "TXTITL", self.title.clone());
+        self.security.insert_into(&mut text_subheader, "T");
+        text_subheader.insert("ENCRYP".to_string(), self.encryption.to_string());
+        text_subheader.insert("TXTFMT".to_string(), self.format.code().to_string());
+
+        tre::insert_area(&mut text_subheader, ("TXSHDL", "TXSOFL"), self.extended_overflow, &self.tres, TreLocation::TextExtended);
+        tre::insert_overflow(&mut text_subheader, &self.tres);
+
+        text_subheader
+    }
+}
@@ -0,0 +1,114 @@
+//! Tagged record extensions (TREs) carried in the user defined and extended data areas
+
+use std::collections::BTreeMap;
+
+use super::error::NitfError;
+use super::field::FieldReader;
+
+/// Header area a tagged record extension was read from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TreLocation {
+    /// File header User Defined Header Data (UDHD)
+    FileUserDefined,
+    /// File header Extended Header Data (XHD)
+    FileExtended,
+    /// Image subheader User Defined Image Data (UDID)
+    ImageUserDefined,
+    /// Image subheader Image Extended Subheader Data (IXSHD)
+    ImageExtended,
+    /// Graphic subheader, or NITF 2.0 symbol subheader, Extended Subheader Data (SXSHD)
+    GraphicExtended,
+    /// NITF 2.0 label subheader Extended Subheader Data (LXSHD)
+    LabelExtended,
+    /// Text subheader Extended Subheader Data (TXSHD)
+    TextExtended,
+    /// TRE_OVERFLOW data extension segment, whose extensions are added to those of the
+    /// header they overflow from
+    Overflow
+}
+
+
+/// Tagged record extension with its undecoded data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tre {
+    /// Unique Extension Type Identifier (CETAG), with trailing spaces removed
+    pub tag: String,
+    /// User Defined or Extended Data (CEDATA), CEL bytes long
+    pub data: Vec<u8>,
+    /// Header area the extension was read from
+    pub location: TreLocation,
+    /// Byte offset of the CETAG field from the start of the file
+    pub offset: u64
+}
+
+impl Tre {
+
+    /// Parses the tagged record extensions filling the reader's data.
+    pub(crate) fn parse_all(reader: &mut FieldReader, location: TreLocation) -> Result<Vec<Tre>, NitfError> {
+        let mut tres = Vec::new();
+        while reader.remaining() > 0 {
+            let offset = reader.position();
+            let tag = reader.string("CETAG", 6)?.trim_end().to_string();
+            let length = reader.number("CEL", 5)?;
+            let data = reader.bytes(&tag, length)?.to_vec();
+            tres.push(Tre {
+                tag,
+                data,
+                location,
+                offset
+            });
+        }
+        Ok(tres)
+    }
+
+    /// Returns the length of the extension including its tag and length fields.
+    pub fn length(&self) -> usize {
+        self.data.len() + 11
+    }
+}
+
+
+/// Returns every extension in `tres` with the given tag, in file order.
+pub(crate) fn find<'a>(tres: &'a [Tre], tag: &str) -> Vec<&'a Tre> {
+    tres.iter().filter(|tre| tre.tag == tag).collect()
+}
+
+
+/// Inserts a user defined or extended data area into a string map view, with one entry per
+/// extension. Repeated tags are numbered from the second occurrence (e.g. ACFTB_2).
+pub(crate) fn insert_area(header: &mut BTreeMap<String,String>, fields: (&str, &str), overflow: u16, tres: &[Tre], location: TreLocation) {
+    let tres: Vec<&Tre> = tres.iter().filter(|tre| tre.location == location).collect();
+    if tres.is_empty() && overflow == 0 {
+        header.insert(fields.0.to_string(), "00000".to_string());
+        return;
+    }
+    let length: usize = tres.iter().map(|tre| tre.length()).sum();
+    header.insert(fields.0.to_string(), format!("{:05}", length + 3));
+    header.insert(fields.1.to_string(), format!("{:03}", overflow));
+
+    let mut occurrences = BTreeMap::new();
+    for tre in tres {
+        let occurrence = occurrences.entry(tre.tag.as_str()).or_insert(0);
+        *occurrence += 1;
+        let key = match *occurrence {
+            1 => tre.tag.clone(),
+            n => format!("{}_{}", tre.tag, n)
+        };
+        header.insert(key, String::from_utf8_lossy(&tre.data).trim().to_string());
+    }
+}
+
+
+/// Inserts the extensions that overflowed into a TRE_OVERFLOW data extension segment into a
+/// string map view, numbering a tag already in the view from its next occurrence.
+pub(crate) fn insert_overflow(header: &mut BTreeMap<String,String>, tres: &[Tre]) {
+    for tre in tres.iter().filter(|tre| tre.location == TreLocation::Overflow) {
+        let mut key = tre.tag.clone();
+        let mut occurrence = 1;
+        while header.contains_key(&key) {
+            occurrence += 1;
+            key = format!("{}_{}", tre.tag, occurrence);
+        }
+        header.insert(key, String::from_utf8_lossy(&tre.data).trim().to_string());
+    }
+}
@@ -0,0 +1,437 @@
+//! Data driven definitions that decode tagged record extensions into named fields
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use super::error::NitfError;
+use super::field::FieldReader;
+use super::tre::Tre;
+
+/// Definitions shipped with the crate, see the file for the layout format.
+const BUILT_IN_DEFINITIONS: &str = include_str!("tre_definitions.xml");
+
+/// Type a TRE field is decoded as
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TreFieldType {
+    /// string - BCS characters with surrounding spaces removed
+    String,
+    /// integer - signed decimal integer
+    Integer,
+    /// real - decimal or exponential floating point
+    Real,
+    /// binary - raw bytes
+    Binary
+}
+
+impl TreFieldType {
+    /// Returns the field type for the given definition type name.
+    pub fn from_code(code: &str) -> Option<TreFieldType> {
+        match code {
+            "string" => Some(TreFieldType::String),
+            "integer" => Some(TreFieldType::Integer),
+            "real" => Some(TreFieldType::Real),
+            "binary" => Some(TreFieldType::Binary),
+            _ => None
+        }
+    }
+
+    /// Returns the definition type name for the field type.
+    pub fn code(&self) -> &'static str {
+        match self {
+            TreFieldType::String => "string",
+            TreFieldType::Integer => "integer",
+            TreFieldType::Real => "real",
+            TreFieldType::Binary => "binary"
+        }
+    }
+}
+
+
+/// Length of a field, or number of iterations of a loop
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TreCount {
+    /// Fixed by the definition
+    Fixed(usize),
+    /// Held in an earlier field of the extension
+    Field(String)
+}
+
+
+/// Comparison made by one alternative of a condition
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TreComparison {
+    /// =
+    Equal,
+    /// !=
+    NotEqual,
+    /// <
+    Less,
+    /// >
+    Greater
+}
+
+
+/// Condition on earlier fields, which holds when any of its alternatives hold.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TreCondition {
+    /// Field, comparison and value of each alternative
+    pub alternatives: Vec<(String, TreComparison, String)>
+}
+
+impl TreCondition {
+
+    /// Parses a condition such as `ORIG=1`, `NUMAIS!=0` or `ORIG=1|ORIG=3`.
+    pub fn parse(condition: &str) -> Option<TreCondition> {
+        let mut alternatives = Vec::new();
+        for alternative in condition.split('|') {
+            let (field, comparison, value) = [("!=", TreComparison::NotEqual), ("=", TreComparison::Equal),
+                                              ("<", TreComparison::Less), (">", TreComparison::Greater)].iter()
+                .find_map(|(operator, comparison)| alternative.find(operator)
+                    .map(|index| (&alternative[..index], *comparison, &alternative[index+operator.len()..])))?;
+            if field.trim().is_empty() {
+                return None;
+            }
+            alternatives.push((field.trim().to_string(), comparison, value.trim().to_string()));
+        }
+        Some(TreCondition {
+            alternatives
+        })
+    }
+
+    fn holds(&self, values: &HashMap<String, String>) -> bool {
+        self.alternatives.iter().any(|(field, comparison, value)| {
+            let actual = values.get(field).map(String::as_str).unwrap_or("");
+            // Compare as numbers when both sides are numeric so 01 equals 1
+            let ordering = match (actual.parse::<f64>(), value.parse::<f64>()) {
+                (Ok(actual), Ok(value)) => actual.partial_cmp(&value),
+                _ => Some(actual.cmp(value.as_str()))
+            };
+            match comparison {
+                TreComparison::Equal => ordering == Some(std::cmp::Ordering::Equal),
+                TreComparison::NotEqual => ordering != Some(std::cmp::Ordering::Equal),
+                TreComparison::Less => ordering == Some(std::cmp::Ordering::Less),
+                TreComparison::Greater => ordering == Some(std::cmp::Ordering::Greater)
+            }
+        })
+    }
+}
+
+
+/// Entry in the layout of a tagged record extension
+#[derive(Clone, Debug, PartialEq)]
+pub enum TreItem {
+    /// Single field
+    Field {
+        name: String,
+        length: TreCount,
+        field_type: TreFieldType
+    },
+    /// Items repeated a number of times
+    Loop {
+        iterations: TreCount,
+        items: Vec<TreItem>
+    },
+    /// Items present only when the condition holds
+    If {
+        condition: TreCondition,
+        items: Vec<TreItem>
+    }
+}
+
+
+/// Layout of one tagged record extension.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TreDefinition {
+    /// Unique Extension Type Identifier (CETAG)
+    pub tag: String,
+    /// Required length of the extension data (CEL), when the length is fixed
+    pub length: Option<usize>,
+    /// Fields, loops and conditionals in the order they appear
+    pub items: Vec<TreItem>
+}
+
+impl TreDefinition {
+
+    /// Decodes the extension into its fields in file order. When the data is not as long as
+    /// the definition, returns a LengthMismatch on the CEL field whose `expected` is the CEL and
+    /// whose `actual` is the length the definition fixes or consumed.
+    pub fn decode(&self, tre: &Tre) -> Result<Vec<TreField>, NitfError> {
+        if let Some(length) = self.length {
+            // The CEL is what the file expects, the definition's length what it actually holds
+            if tre.data.len() != length {
+                return Err(NitfError::LengthMismatch { field: "CEL".to_string(), offset: tre.offset + 6, expected: tre.data.len() as u64, actual: length as u64 });
+            }
+        }
+
+        let mut reader = FieldReader::new(&tre.data, tre.offset + 11);
+        let mut fields = Vec::new();
+        decode_items(&self.items, &mut reader, &mut Vec::new(), &mut HashMap::new(), &mut fields)?;
+
+        // Bytes left over after the last item mean the CEL is longer than the definition consumed
+        if reader.remaining() > 0 {
+            let decoded = tre.data.len() - reader.remaining();
+            return Err(NitfError::LengthMismatch { field: "CEL".to_string(), offset: tre.offset + 6, expected: tre.data.len() as u64, actual: decoded as u64 });
+        }
+        Ok(fields)
+    }
+}
+
+
+/// Decoded value of a TRE field
+#[derive(Clone, Debug, PartialEq)]
+pub enum TreValue {
+    /// String field, or a numeric field left blank
+    Text(String),
+    /// Integer field
+    Integer(i64),
+    /// Real field
+    Real(f64),
+    /// Binary field
+    Binary(Vec<u8>)
+}
+
+impl TreValue {
+    /// Returns the value of an integer field.
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            TreValue::Integer(value) => Some(*value),
+            _ => None
+        }
+    }
+
+    /// Returns the value of an integer or real field as a floating point number.
+    pub fn as_real(&self) -> Option<f64> {
+        match self {
+            TreValue::Integer(value) => Some(*value as f64),
+            TreValue::Real(value) => Some(*value),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for TreValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TreValue::Text(value) => write!(f, "{}", value),
+            TreValue::Integer(value) => write!(f, "{}", value),
+            TreValue::Real(value) => write!(f, "{}", value),
+            TreValue::Binary(value) => value.iter().try_for_each(|byte| write!(f, "{:02X}", byte))
+        }
+    }
+}
+
+
+/// Field decoded from a tagged record extension.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TreField {
+    /// Field name from the definition
+    pub name: String,
+    /// Zero based iteration of each enclosing loop, outermost first
+    pub indices: Vec<usize>,
+    /// Decoded value
+    pub value: TreValue
+}
+
+
+/// Registry of TRE definitions keyed by tag. New definitions replace built in ones with the
+/// same tag, so vendor TREs can be added or corrected from an external file.
+#[derive(Clone, Debug)]
+pub struct TreRegistry {
+    definitions: HashMap<String, TreDefinition>
+}
+
+impl Default for TreRegistry {
+    fn default() -> TreRegistry {
+        TreRegistry::new()
+    }
+}
+
+impl TreRegistry {
+
+    /// Returns a registry holding the built in definitions.
+    pub fn new() -> TreRegistry {
+        let mut registry = TreRegistry::empty();
+        registry.load_xml(BUILT_IN_DEFINITIONS).expect("built in TRE definitions are valid");
+        registry
+    }
+
+    /// Returns a shared registry holding the built in definitions.
+    pub(crate) fn built_in() -> &'static TreRegistry {
+        static BUILT_IN: OnceLock<TreRegistry> = OnceLock::new();
+        BUILT_IN.get_or_init(TreRegistry::new)
+    }
+
+    /// Returns a registry without any definitions.
+    pub fn empty() -> TreRegistry {
+        TreRegistry {
+            definitions: HashMap::new()
+        }
+    }
+
+    /// Adds the definitions in an XML file laid out like `tre_definitions.xml`.
+    pub fn load_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), NitfError> {
+        self.load_xml(&fs::read_to_string(path)?)
+    }
+
+    /// Adds the definitions in an XML document laid out like `tre_definitions.xml`.
+    pub fn load_xml(&mut self, xml: &str) -> Result<(), NitfError> {
+        let document = roxmltree::Document::parse(xml)
+            .map_err(|error| NitfError::InvalidDefinition { message: error.to_string() })?;
+        let root = document.root_element();
+        if root.tag_name().name() != "tres" {
+            return Err(NitfError::InvalidDefinition { message: format!("expected <tres> but found <{}>", root.tag_name().name()) });
+        }
+        let mut definitions = Vec::new();
+        for node in root.children().filter(|node| node.is_element()) {
+            definitions.push(parse_definition(node)?);
+        }
+        for definition in definitions {
+            self.insert(definition);
+        }
+        Ok(())
+    }
+
+    /// Adds a definition, replacing any with the same tag.
+    pub fn insert(&mut self, definition: TreDefinition) {
+        self.definitions.insert(definition.tag.clone(), definition);
+    }
+
+    /// Returns the definition for the given tag.
+    pub fn get(&self, tag: &str) -> Option<&TreDefinition> {
+        self.definitions.get(tag)
+    }
+
+    /// Decodes the extension into its fields, or returns None when its tag has no definition.
+    pub fn decode(&self, tre: &Tre) -> Result<Option<Vec<TreField>>, NitfError> {
+        self.get(&tre.tag).map(|definition| definition.decode(tre)).transpose()
+    }
+}
+
+
+fn decode_items(items: &[TreItem], reader: &mut FieldReader, indices: &mut Vec<usize>,
+                values: &mut HashMap<String, String>, fields: &mut Vec<TreField>) -> Result<(), NitfError> {
+    for item in items {
+        match item {
+            TreItem::Field { name, length, field_type } => {
+                let offset = reader.position();
+                let length = count(length, values, offset)?;
+                let bytes = reader.bytes(name, length)?;
+                let text = String::from_utf8_lossy(bytes).trim().to_string();
+                let value = match field_type {
+                    TreFieldType::Binary => TreValue::Binary(bytes.to_vec()),
+                    _ if text.is_empty() => TreValue::Text(text.clone()),
+                    TreFieldType::Integer => TreValue::Integer(text.parse()
+                        .map_err(|_| NitfError::NonNumeric { field: name.clone(), offset, value: text.clone() })?),
+                    TreFieldType::Real => TreValue::Real(text.parse()
+                        .map_err(|_| NitfError::NonNumeric { field: name.clone(), offset, value: text.clone() })?),
+                    TreFieldType::String => TreValue::Text(text.clone())
+                };
+                values.insert(name.clone(), text);
+                fields.push(TreField {
+                    name: name.clone(),
+                    indices: indices.clone(),
+                    value
+                });
+            },
+            TreItem::Loop { iterations, items } => {
+                let iterations = count(iterations, values, reader.position())?;
+                for iteration in 0..iterations {
+                    indices.push(iteration);
+                    decode_items(items, reader, indices, values, fields)?;
+                    indices.pop();
+                }
+            },
+            TreItem::If { condition, items } => {
+                if condition.holds(values) {
+                    decode_items(items, reader, indices, values, fields)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+
+/// Returns a fixed count, or the count held in an earlier field.
+fn count(count: &TreCount, values: &HashMap<String, String>, offset: u64) -> Result<usize, NitfError> {
+    match count {
+        TreCount::Fixed(count) => Ok(*count),
+        TreCount::Field(field) => {
+            let value = values.get(field).cloned().unwrap_or_default();
+            value.parse().map_err(|_| NitfError::InvalidValue { field: field.clone(), offset, value })
+        }
+    }
+}
+
+
+fn parse_definition(node: roxmltree::Node) -> Result<TreDefinition, NitfError> {
+    if node.tag_name().name() != "tre" {
+        return Err(NitfError::InvalidDefinition { message: format!("expected <tre> but found <{}>", node.tag_name().name()) });
+    }
+    let tag = node.attribute("name")
+        .ok_or_else(|| NitfError::InvalidDefinition { message: "<tre> without a name".to_string() })?;
+    let length = node.attribute("length").map(|length| length.parse()
+        .map_err(|_| NitfError::InvalidDefinition { message: format!("{} has an invalid length \"{}\"", tag, length) }))
+        .transpose()?;
+    Ok(TreDefinition {
+        tag: tag.to_string(),
+        length,
+        items: parse_items(tag, node)?
+    })
+}
+
+
+fn parse_items(tag: &str, parent: roxmltree::Node) -> Result<Vec<TreItem>, NitfError> {
+    let invalid = |message: String| NitfError::InvalidDefinition { message: format!("{}: {}", tag, message) };
+
+    let mut items = Vec::new();
+    for node in parent.children().filter(|node| node.is_element()) {
+        let item = match node.tag_name().name() {
+            "field" => {
+                let name = node.attribute("name").ok_or_else(|| invalid("<field> without a name".to_string()))?;
+                let length = match (node.attribute("length"), node.attribute("length_var")) {
+                    (Some(length), None) => TreCount::Fixed(length.parse()
+                        .map_err(|_| invalid(format!("{} has an invalid length \"{}\"", name, length)))?),
+                    (None, Some(field)) => TreCount::Field(field.to_string()),
+                    _ => return Err(invalid(format!("{} needs one of length or length_var", name)))
+                };
+                let field_type = match node.attribute("type") {
+                    Some(code) => TreFieldType::from_code(code)
+                        .ok_or_else(|| invalid(format!("{} has an invalid type \"{}\"", name, code)))?,
+                    None => TreFieldType::String
+                };
+                TreItem::Field {
+                    name: name.to_string(),
+                    length,
+                    field_type
+                }
+            },
+            "loop" => {
+                let iterations = match (node.attribute("iterations"), node.attribute("counter")) {
+                    (Some(iterations), None) => TreCount::Fixed(iterations.parse()
+                        .map_err(|_| invalid(format!("invalid loop iterations \"{}\"", iterations)))?),
+                    (None, Some(field)) => TreCount::Field(field.to_string()),
+                    _ => return Err(invalid("<loop> needs one of iterations or counter".to_string()))
+                };
+                TreItem::Loop {
+                    iterations,
+                    items: parse_items(tag, node)?
+                }
+            },
+            "if" => {
+                let condition = node.attribute("cond").ok_or_else(|| invalid("<if> without a cond".to_string()))?;
+                TreItem::If {
+                    condition: TreCondition::parse(condition)
+                        .ok_or_else(|| invalid(format!("invalid condition \"{}\"", condition)))?,
+                    items: parse_items(tag, node)?
+                }
+            },
+            other => return Err(invalid(format!("unexpected <{}>", other)))
+        };
+        items.push(item);
+    }
+    Ok(items)
+}
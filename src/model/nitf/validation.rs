@@ -0,0 +1,365 @@
+//! Conformance checks of a NITF against MIL-STD-2500C
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use super::error::NitfError;
+use super::field::FieldWriter;
+use super::file_header::{NitfVersion, UNKNOWN_FILE_LENGTH};
+use super::security::SecurityFields;
+use super::tre::Tre;
+use super::tre_definition::TreRegistry;
+use super::{read_at, NITF};
+
+/// Complexity levels a NITF 2.1 or NSIF 1.0 file may declare, lowest first. Files beyond the
+/// limits of level 07 are level 09.
+const COMPLEXITY_LEVELS: [u8; 4] = [3, 5, 6, 7];
+
+/// Fields that may hold extended characters (ECS-A) rather than only BCS-A.
+const EXTENDED_CHARACTER_FIELDS: [&str; 6] = ["FTITLE", "ONAME", "IID2", "ICOMn", "SNAME", "TXTITL"];
+
+/// Image categories (ICAT) defined by the standard.
+const IMAGE_CATEGORIES: [&str; 30] = [
+    "VIS", "SL", "TI", "FL", "RD", "EO", "OP", "HR", "HS", "CP", "BP", "SAR", "SARIQ", "IR", "MAP", "MS",
+    "FP", "MRI", "XRAY", "CAT", "VD", "PAT", "LEG", "DTEM", "MATR", "LOCG", "BARO", "CURRENT", "DEPTH", "WIND"
+];
+
+
+/// Severity of a finding
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The check could not be made or the value is merely unusual
+    Info,
+    /// The file can be read but does not follow a recommendation of the standard
+    Warning,
+    /// The file violates the standard
+    Error
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error")
+        }
+    }
+}
+
+
+/// Problem found in a NITF, located by the field and its byte offset from the start of the
+/// file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Finding {
+    /// How serious the problem is
+    pub severity: Severity,
+    /// Name of the field, e.g. CLEVEL or the tag of an extension
+    pub field: String,
+    /// Byte offset of the field from the start of the file
+    pub offset: u64,
+    /// Description of the problem, naming the field and offset
+    pub message: String
+}
+
+impl Finding {
+
+    /// Returns a finding whose message describes the field after its name and offset.
+    fn new(severity: Severity, field: &str, offset: u64, description: String) -> Finding {
+        let message = format!("{} at offset {} {}", field, offset, description);
+        Finding { severity, field: field.to_string(), offset, message }
+    }
+
+    /// Returns the error finding for a file that could not be read, with the field and offset
+    /// of the error when it has them.
+    pub fn from_error(error: &NitfError) -> Finding {
+        let (field, offset) = match error {
+            NitfError::Truncated { field, offset } |
+            NitfError::InvalidCharacters { field, offset } |
+            NitfError::NonNumeric { field, offset, .. } |
+            NitfError::InvalidValue { field, offset, .. } |
+            NitfError::LengthMismatch { field, offset, .. } => (field.as_str(), *offset),
+            NitfError::Unsupported { field, .. } => (field.as_str(), 0),
+            NitfError::Decode { compression, offset, .. } => (compression.as_str(), *offset),
+            _ => ("", 0)
+        };
+        Finding { severity: Severity::Error, field: field.to_string(), offset, message: error.to_string() }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+
+/// Checks a parsed NITF against the standard. The findings are sorted by offset.
+///
+/// Coded values the parser depends on are already checked when the file is read, so a file
+/// that cannot be read is reported with `Finding::from_error`. Length fields are checked by
+/// the read too, and a file read with `NITF::from_reader_lenient` has each length mismatch
+/// reported here alongside the other findings. The headers of NITF 2.1 and NSIF 1.0 files
+/// are also checked field by field for their characters, security codes and complexity
+/// level, reading only the bytes of the headers from the file.
+pub fn validate(nitf: &NITF) -> Vec<Finding> {
+    let mut findings: Vec<Finding> = nitf.length_mismatches().iter().map(Finding::from_error).collect();
+    let file_header = nitf.file_header();
+
+    if file_header.file_length == UNKNOWN_FILE_LENGTH {
+        findings.push(Finding::new(Severity::Warning, "FL", file_header.file_length_offset(),
+            "is 999999999999, the value for an unknown length".to_string()));
+    }
+
+    for tre in nitf.tres() {
+        if let Err(error) = TreRegistry::built_in().decode(tre) {
+            findings.push(Finding::new(Severity::Error, &tre.tag, tre.offset, format!("does not match its definition: {}", error)));
+        }
+    }
+
+    match file_header.nitf_version() {
+        Some(NitfVersion::Nitf21) | Some(NitfVersion::Nsif10) => check_headers(nitf, &mut findings),
+        _ => findings.push(Finding::new(Severity::Info, "FVER", 4,
+            format!("is {}, whose headers are only checked for their lengths and extensions", file_header.version)))
+    }
+
+    findings.sort_by_key(|finding| finding.offset);
+    findings
+}
+
+
+/// Returns the lowest complexity level (CLEVEL) of a NITF 2.1 or NSIF 1.0 file with the
+/// images, segments and length of the given file, following table A-10 of MIL-STD-2500C.
+/// The common coordinate system extent is taken from the location and size of each image.
+pub fn required_complexity_level(nitf: &NITF) -> u8 {
+    let file_header = nitf.file_header();
+    let mut level = level_for(file_header.file_length, [50 << 20, 1 << 30, 2 << 30, 10 << 30]);
+    level = level.max(level_for(file_header.image_segments.len() as u64, [20, 20, 100, 100]));
+    level = level.max(level_for(file_header.graphic_segments.len() as u64, [100, 100, 100, 100]));
+    level = level.max(level_for(file_header.text_segments.len() as u64, [32, 32, 32, 32]));
+
+    for segment in nitf.image_segments() {
+        let image = &segment.subheader;
+        let extent = (i64::from(image.location.0) + i64::from(image.rows)).max(i64::from(image.location.1) + i64::from(image.columns));
+        level = level.max(level_for(extent.max(0) as u64, [2048, 8192, 65536, 99_999_999]));
+        level = level.max(level_for(u64::from(image.rows.max(image.columns)), [2048, 8192, 65536, 99_999_999]));
+        // Images more than 8192 pixels across may leave NPPBH or NPPBV zero for a single block
+        let block_width = match image.pixels_per_block_horizontal {
+            0 => image.columns,
+            width => width
+        };
+        let block_height = match image.pixels_per_block_vertical {
+            0 => image.rows,
+            height => height
+        };
+        level = level.max(level_for(u64::from(block_width.max(block_height)), [2048, 8192, 8192, 8192]));
+        level = level.max(level_for(image.bands.len() as u64, [9, 256, 256, 999]));
+    }
+    level
+}
+
+
+/// Returns the lowest complexity level whose limit the value is within.
+fn level_for(value: u64, limits: [u64; 4]) -> u8 {
+    COMPLEXITY_LEVELS.iter().zip(limits.iter())
+        .find(|(_, limit)| value <= **limit)
+        .map(|(level, _)| *level)
+        .unwrap_or(9)
+}
+
+
+/// Character fields of a header laid out by its writer, keyed by name with the file offset
+/// and length of each occurrence.
+struct Layout {
+    /// Field the header begins with, its file offset and its length
+    header: (String, u64, u64),
+    fields: BTreeMap<String, Vec<(u64, usize)>>
+}
+
+impl Layout {
+
+    /// Lays out a header written at `offset`, or returns None with a finding when the layout
+    /// does not span the header's recorded length.
+    fn new(offset: u64, length: u64, field: &str, write: impl Fn(&mut FieldWriter) -> Result<(), NitfError>,
+           findings: &mut Vec<Finding>) -> Option<Layout> {
+        let mut writer = FieldWriter::new(offset);
+        if write(&mut writer).is_err() || writer.position() - offset != length {
+            findings.push(Finding::new(Severity::Info, field, offset, "begins a header whose fields could not be laid out to check them".to_string()));
+            return None;
+        }
+        let mut fields = BTreeMap::new();
+        for (name, offset, length) in writer.fields() {
+            fields.entry(name.clone()).or_insert_with(Vec::new).push((*offset, *length));
+        }
+        Some(Layout { header: (field.to_string(), offset, length), fields })
+    }
+
+    /// Returns the offset of the first occurrence of a field.
+    fn offset(&self, field: &str) -> u64 {
+        self.fields.get(field).and_then(|occurrences| occurrences.first()).map(|(offset, _)| *offset).unwrap_or(0)
+    }
+
+    /// Adds a finding for each field holding characters outside BCS-A, or ECS-A for the
+    /// fields that allow it, reading the header from the file.
+    fn check_characters(&self, nitf: &NITF, findings: &mut Vec<Finding>) {
+        let (header, header_offset, header_length) = &self.header;
+        let bytes = match read_at(nitf.source.as_ref(), header, *header_offset, *header_length as usize) {
+            Ok(bytes) => bytes,
+            Err(error) => {
+                findings.push(Finding::from_error(&error));
+                return;
+            }
+        };
+        for (field, occurrences) in &self.fields {
+            let extended = EXTENDED_CHARACTER_FIELDS.contains(&field.as_str());
+            for (offset, length) in occurrences {
+                let start = (offset - header_offset) as usize;
+                let value = match bytes.get(start..start + length) {
+                    Some(value) => value,
+                    None => {
+                        findings.push(Finding::new(Severity::Error, field, *offset, format!("runs past the end of the {} bytes of its header", bytes.len())));
+                        continue;
+                    }
+                };
+                let invalid = value.iter().find(|byte| !(matches!(byte, 0x20..=0x7E) || extended && **byte >= 0xA0));
+                if let Some(byte) = invalid {
+                    let character_set = if extended { "ECS-A" } else { "BCS-A" };
+                    findings.push(Finding::new(Severity::Error, field, *offset, format!("holds the byte 0x{:02X}, which is not in {}", byte, character_set)));
+                }
+            }
+        }
+    }
+
+    /// Adds a finding for each security code not defined by the standard and for encryption.
+    fn check_security(&self, security: &SecurityFields, prefix: &str, findings: &mut Vec<Finding>) {
+        let codes: [(&str, &str, &[&str]); 4] = [
+            ("SDCTP", &security.declassification_type, &["", "DD", "DE", "GD", "GE", "O", "X"]),
+            ("SDG", &security.downgrade, &["", "S", "C", "R"]),
+            ("SCATP", &security.authority_type, &["", "O", "D", "M"]),
+            ("SCRSN", &security.reason, &["", "A", "B", "C", "D", "E", "F", "G"])
+        ];
+        for (name, value, allowed) in codes.iter() {
+            if !allowed.contains(value) {
+                let field = format!("{}{}", prefix, name);
+                findings.push(Finding::new(Severity::Error, &field, self.offset(&field), format!("is \"{}\", which is not a defined code", value)));
+            }
+        }
+        if !security.classification_system.is_empty() && !security.classification_system.chars().all(|c| c.is_ascii_uppercase()) {
+            let field = format!("{}SCLSY", prefix);
+            findings.push(Finding::new(Severity::Error, &field, self.offset(&field),
+                format!("is \"{}\", which is not a country code", security.classification_system)));
+        }
+    }
+
+    /// Adds a finding when ENCRYP is not 0, the only value the standard defines.
+    fn check_encryption(&self, encryption: u8, findings: &mut Vec<Finding>) {
+        if encryption != 0 {
+            findings.push(Finding::new(Severity::Error, "ENCRYP", self.offset("ENCRYP"), format!("is {} but only 0 is defined", encryption)));
+        }
+    }
+}
+
+
+/// Returns the length a header actually spans, which differs from the length recorded in
+/// `length_field` when a lenient read found them to disagree.
+fn parsed_length(nitf: &NITF, length_field: &str, recorded: u64) -> u64 {
+    nitf.length_mismatches().iter().find_map(|mismatch| match mismatch {
+        NitfError::LengthMismatch { field, actual, .. } if field == length_field => Some(*actual),
+        _ => None
+    }).unwrap_or(recorded)
+}
+
+
+/// Checks the fields of each header of a NITF 2.1 or NSIF 1.0 file.
+fn check_headers(nitf: &NITF, findings: &mut Vec<Finding>) {
+    let file_header = nitf.file_header();
+    let header_length = parsed_length(nitf, "HL", file_header.header_length);
+    if let Some(layout) = Layout::new(0, header_length, "FHDR", |writer| file_header.write(writer), findings) {
+        layout.check_characters(nitf, findings);
+        layout.check_security(&file_header.security, "F", findings);
+        layout.check_encryption(file_header.encryption, findings);
+        if file_header.standard_type != "BF01" {
+            findings.push(Finding::new(Severity::Error, "STYPE", layout.offset("STYPE"), format!("is \"{}\" but only BF01 is defined", file_header.standard_type)));
+        }
+
+        let declared = file_header.complexity_level;
+        let required = required_complexity_level(nitf);
+        if !COMPLEXITY_LEVELS.contains(&declared) && declared != 9 {
+            findings.push(Finding::new(Severity::Error, "CLEVEL", layout.offset("CLEVEL"), format!("is {:02}, which is not a defined level", declared)));
+        } else if declared < required {
+            findings.push(Finding::new(Severity::Error, "CLEVEL", layout.offset("CLEVEL"),
+                format!("is {:02}, lower than the {:02} the file requires", declared, required)));
+        } else if declared > required {
+            findings.push(Finding::new(Severity::Warning, "CLEVEL", layout.offset("CLEVEL"),
+                format!("is {:02}, higher than the {:02} the file requires", declared, required)));
+        }
+    }
+
+    // Display levels identify images and graphics, so no two may share one
+    let mut display_levels = BTreeSet::new();
+
+    for segment in nitf.image_segments() {
+        let image = &segment.subheader;
+        let length = parsed_length(nitf, &format!("LISH{:03}", segment.index + 1), segment.subheader_length);
+        if let Some(layout) = Layout::new(segment.offset, length, "IM", |writer| image.write(writer), findings) {
+            layout.check_characters(nitf, findings);
+            layout.check_security(&image.security, "I", findings);
+            layout.check_encryption(image.encryption, findings);
+            if !IMAGE_CATEGORIES.contains(&image.category.as_str()) {
+                findings.push(Finding::new(Severity::Error, "ICAT", layout.offset("ICAT"), format!("is \"{}\", which is not a defined category", image.category)));
+            }
+            if !display_levels.insert(image.display_level) {
+                findings.push(Finding::new(Severity::Error, "IDLVL", layout.offset("IDLVL"), format!("is {}, which another image or graphic already uses", image.display_level)));
+            }
+        }
+    }
+    for segment in nitf.graphic_segments() {
+        let graphic = &segment.subheader;
+        let length = parsed_length(nitf, &format!("LSSH{:03}", segment.index + 1), segment.subheader_length);
+        if let Some(layout) = Layout::new(segment.offset, length, "SY", |writer| graphic.write(writer), findings) {
+            layout.check_characters(nitf, findings);
+            layout.check_security(&graphic.security, "S", findings);
+            layout.check_encryption(graphic.encryption, findings);
+            if !display_levels.insert(graphic.display_level) {
+                findings.push(Finding::new(Severity::Error, "SDLVL", layout.offset("SDLVL"), format!("is {}, which another image or graphic already uses", graphic.display_level)));
+            }
+        }
+    }
+    for segment in nitf.text_segments() {
+        let text = &segment.subheader;
+        let length = parsed_length(nitf, &format!("LTSH{:03}", segment.index + 1), segment.subheader_length);
+        if let Some(layout) = Layout::new(segment.offset, length, "TE", |writer| text.write(writer), findings) {
+            layout.check_characters(nitf, findings);
+            layout.check_security(&text.security, "T", findings);
+            layout.check_encryption(text.encryption, findings);
+        }
+    }
+    for segment in nitf.data_ext_segments() {
+        let length = parsed_length(nitf, &format!("LDSH{:03}", segment.index + 1), segment.subheader_length);
+        if let Some(layout) = Layout::new(segment.offset, length, "DE", |writer| segment.subheader.write(writer), findings) {
+            layout.check_characters(nitf, findings);
+            layout.check_security(&segment.subheader.security, "DE", findings);
+        }
+    }
+    for segment in nitf.res_segments() {
+        let length = parsed_length(nitf, &format!("LRESH{:03}", segment.index + 1), segment.subheader_length);
+        if let Some(layout) = Layout::new(segment.offset, length, "RE", |writer| segment.subheader.write(writer), findings) {
+            layout.check_characters(nitf, findings);
+            layout.check_security(&segment.subheader.security, "RE", findings);
+        }
+    }
+}
+
+
+impl NITF {
+
+    /// Returns every tagged record extension of the file header and segment subheaders,
+    /// including those read from overflow segments.
+    fn tres(&self) -> impl Iterator<Item = &Tre> {
+        self.file_header().tres.iter()
+            .chain(self.image_segments().iter().flat_map(|segment| segment.subheader.tres.iter()))
+            .chain(self.graphic_segments().iter().flat_map(|segment| segment.subheader.tres.iter()))
+            .chain(self.symbol_segments().iter().flat_map(|segment| segment.subheader.tres.iter()))
+            .chain(self.label_segments().iter().flat_map(|segment| segment.subheader.tres.iter()))
+            .chain(self.text_segments().iter().flat_map(|segment| segment.subheader.tres.iter()))
+    }
+}
@@ -0,0 +1,163 @@
+//! Vector quantization decoding of the image data held by image segments compressed with
+//! IC = C4 and M4, as laid out by MIL-STD-188-199
+
+use super::image::read_bits;
+
+/// Length of the image display parameters, compression section and compression lookup
+/// subheaders that start the VQ header
+const SUBHEADERS_LENGTH: usize = 21;
+
+/// Offset of the compression lookup subsection within the VQ header, from which lookup table
+/// offsets are counted
+const LOOKUP_SUBSECTION: usize = 15;
+
+/// Length of one compression lookup offset record
+const OFFSET_RECORD_LENGTH: usize = 14;
+
+/// Compression algorithm identifier of vector quantization
+const VECTOR_QUANTIZATION: u64 = 1;
+
+
+fn read_unsigned(data: &[u8], offset: usize, length: usize) -> u64 {
+    data[offset..offset + length].iter().fold(0, |value, byte| value << 8 | u64::from(*byte))
+}
+
+
+/// Codebook and code layout from the VQ header that starts the image data
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Codebook {
+    /// Length of the VQ header, after which the codes of the blocks begin
+    pub(crate) length: usize,
+    /// Rows of codes in each block
+    rows: usize,
+    /// Codes in each row of a block
+    columns: usize,
+    /// Bits in each code
+    code_bits: u8,
+    /// Number of codes in the codebook
+    codes: usize,
+    /// Pixel values of each code's kernel, one lookup table per kernel row, holding the
+    /// kernel width values of every code in turn
+    tables: Vec<Vec<u16>>,
+    /// Kernel width, the number of values in each lookup record
+    kernel_width: usize
+}
+
+impl Codebook {
+
+    /// Returns the length of the VQ header at the start of `data`, or the length of the part
+    /// of it needed to find its length when `data` is shorter.
+    pub(crate) fn length(data: &[u8]) -> Result<usize, String> {
+        if data.len() < SUBHEADERS_LENGTH {
+            return Ok(SUBHEADERS_LENGTH);
+        }
+        let tables = read_unsigned(data, 11, 2) as usize;
+        let table_offset = read_unsigned(data, 15, 4) as usize;
+        let record_length = read_unsigned(data, 19, 2) as usize;
+        if record_length < OFFSET_RECORD_LENGTH {
+            return Err(format!("compression lookup offset records of {} bytes", record_length));
+        }
+        let records_end = LOOKUP_SUBSECTION + table_offset + tables * record_length;
+        if data.len() < records_end {
+            return Ok(records_end);
+        }
+        Ok((0..tables).map(|table| {
+            let record = LOOKUP_SUBSECTION + table_offset + table * record_length;
+            let values = read_unsigned(data, record + 2, 4) * read_unsigned(data, record + 6, 2) * read_unsigned(data, record + 8, 2);
+            LOOKUP_SUBSECTION + read_unsigned(data, record + 10, 4) as usize + values.div_ceil(8) as usize
+        }).fold(records_end, usize::max))
+    }
+
+    /// Parses a whole VQ header.
+    pub(crate) fn parse(data: &[u8]) -> Result<Codebook, String> {
+        let length = Codebook::length(data)?;
+        if data.len() < length {
+            return Err(format!("VQ header of {} bytes where {} were expected", data.len(), length));
+        }
+        let rows = read_unsigned(data, 0, 4) as usize;
+        let columns = read_unsigned(data, 4, 4) as usize;
+        let code_bits = data[8];
+        if !(1..=32).contains(&code_bits) {
+            return Err(format!("image codes of {} bits", code_bits));
+        }
+        let algorithm = read_unsigned(data, 9, 2);
+        if algorithm != VECTOR_QUANTIZATION {
+            return Err(format!("compression algorithm {} is not vector quantization", algorithm));
+        }
+        let table_count = read_unsigned(data, 11, 2) as usize;
+        let table_offset = read_unsigned(data, 15, 4) as usize;
+        let record_length = read_unsigned(data, 19, 2) as usize;
+        if table_count == 0 {
+            return Err("there are no compression lookup tables".to_string());
+        }
+
+        // Each lookup table holds one row of the kernel of every code
+        let mut layout = None;
+        let mut tables = Vec::with_capacity(table_count);
+        for table in 0..table_count {
+            let record = LOOKUP_SUBSECTION + table_offset + table * record_length;
+            let codes = read_unsigned(data, record + 2, 4) as usize;
+            let kernel_width = read_unsigned(data, record + 6, 2) as usize;
+            let value_bits = read_unsigned(data, record + 8, 2);
+            let offset = LOOKUP_SUBSECTION + read_unsigned(data, record + 10, 4) as usize;
+            if !(1..=16).contains(&value_bits) {
+                return Err(format!("compression lookup values of {} bits", value_bits));
+            }
+            if *layout.get_or_insert((codes, kernel_width)) != (codes, kernel_width) {
+                return Err(format!("compression lookup table {} holds {} records of {} values unlike the first", table + 1, codes, kernel_width));
+            }
+            let bytes = &data[offset..];
+            tables.push((0..codes * kernel_width)
+                .map(|value| read_bits(bytes, value as u64 * value_bits, value_bits as u8) as u16)
+                .collect());
+        }
+        let (codes, kernel_width) = layout.expect("there is at least one lookup table");
+        if kernel_width == 0 {
+            return Err("compression lookup records hold no values".to_string());
+        }
+
+        Ok(Codebook {
+            length,
+            rows,
+            columns,
+            code_bits,
+            codes,
+            tables,
+            kernel_width
+        })
+    }
+
+    /// Returns the byte length of the codes of one block.
+    pub(crate) fn block_length(&self) -> usize {
+        (self.rows * self.columns * usize::from(self.code_bits)).div_ceil(8)
+    }
+
+    /// Returns the width and height in pixels of a decoded block.
+    pub(crate) fn block_size(&self) -> (usize, usize) {
+        (self.columns * self.kernel_width, self.rows * self.tables.len())
+    }
+
+    /// Decodes the codes of one block to pixel values row by row.
+    pub(crate) fn decode(&self, data: &[u8]) -> Result<Vec<u16>, String> {
+        if data.len() < self.block_length() {
+            return Err(format!("{} bytes of codes where {} were expected", data.len(), self.block_length()));
+        }
+        let (width, height) = self.block_size();
+        let kernel_height = self.tables.len();
+        let mut pixels = vec![0; width * height];
+        for code_row in 0..self.rows {
+            for code_column in 0..self.columns {
+                let position = (code_row * self.columns + code_column) as u64 * u64::from(self.code_bits);
+                let code = read_bits(data, position, self.code_bits) as usize;
+                if code >= self.codes {
+                    return Err(format!("code {} is outside the {} codes of the codebook", code, self.codes));
+                }
+                for (y, table) in self.tables.iter().enumerate() {
+                    let row = (code_row * kernel_height + y) * width + code_column * self.kernel_width;
+                    pixels[row..row + self.kernel_width].copy_from_slice(&table[code * self.kernel_width..(code + 1) * self.kernel_width]);
+                }
+            }
+        }
+        Ok(pixels)
+    }
+}
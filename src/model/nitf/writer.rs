@@ -0,0 +1,182 @@
+//! Writing of NITF 2.1 and NSIF 1.0 files
+
+use std::io::Write;
+
+use super::des_subheader::DesSubheader;
+use super::error::NitfError;
+use super::field::FieldWriter;
+use super::file_header::{FileHeader, NitfVersion, SegmentLengths};
+use super::graphic_subheader::GraphicSubheader;
+use super::image::{self, BlockLayout, PixelBuffer};
+use super::image_subheader::{ImageCompression, ImageSubheader};
+use super::res_subheader::ResSubheader;
+use super::text_subheader::TextSubheader;
+
+/// Writer of a new NITF file from a file header and the subheaders and data of its segments.
+/// FL, HL and the segment length tables are computed when the file is written, replacing
+/// those of the file header, so a written file reads back with the same headers.
+///
+/// # Examples
+/// ```no_run
+/// use std::fs::File;
+/// use ossim_oxide::model::nitf::{FileHeader, NitfWriter, TextSubheader};
+/// let mut writer = NitfWriter::new(FileHeader::default());
+/// writer.add_text(TextSubheader { text_id: "NOTE".to_string(), ..TextSubheader::default() }, b"Chip of scene 12".to_vec());
+/// writer.write(File::create("/path/to/nitf/file.NTF").unwrap()).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct NitfWriter {
+    file_header: FileHeader,
+    image_segments: Vec<(ImageSubheader, Vec<u8>)>,
+    graphic_segments: Vec<(GraphicSubheader, Vec<u8>)>,
+    text_segments: Vec<(TextSubheader, Vec<u8>)>,
+    data_ext_segments: Vec<(DesSubheader, Vec<u8>)>,
+    res_segments: Vec<(ResSubheader, Vec<u8>)>
+}
+
+
+impl NitfWriter {
+
+    /// Returns a writer of a file with the given header and no segments.
+    pub fn new(file_header: FileHeader) -> NitfWriter {
+        NitfWriter {
+            file_header,
+            image_segments: Vec::new(),
+            graphic_segments: Vec::new(),
+            text_segments: Vec::new(),
+            data_ext_segments: Vec::new(),
+            res_segments: Vec::new()
+        }
+    }
+
+    /// Adds an uncompressed image segment holding `pixels`, which must cover every band of the
+    /// image in band order with the sample type `read_pixels` returns for the subheader. The
+    /// pixels are stored in the blocks and image mode the subheader describes.
+    pub fn add_image(&mut self, subheader: ImageSubheader, pixels: &PixelBuffer) -> Result<(), NitfError> {
+        let data = image::pack_pixels(&subheader, pixels)?;
+        self.image_segments.push((subheader, data));
+        Ok(())
+    }
+
+    /// Adds an image segment whose data is already stored as the subheader describes, such as
+    /// compressed data or data copied from another file with `NITF::segment_data`. The length
+    /// of uncompressed data is checked against the blocks of the image.
+    pub fn add_image_data(&mut self, subheader: ImageSubheader, data: Vec<u8>) -> Result<(), NitfError> {
+        if subheader.compression == ImageCompression::NotCompressed {
+            let layout = BlockLayout::new(&subheader);
+            let expected = layout.block_length() * layout.stored_block_count();
+            if data.len() as u64 != expected {
+                return Err(NitfError::InvalidRequest {
+                    message: format!("{} bytes of image data where the blocks of the image hold {}", data.len(), expected)
+                });
+            }
+        }
+        self.image_segments.push((subheader, data));
+        Ok(())
+    }
+
+    /// Adds a graphic segment holding CGM data.
+    pub fn add_graphic(&mut self, subheader: GraphicSubheader, data: Vec<u8>) {
+        self.graphic_segments.push((subheader, data));
+    }
+
+    /// Adds a text segment whose data is encoded as its TXTFMT describes.
+    pub fn add_text(&mut self, subheader: TextSubheader, data: Vec<u8>) {
+        self.text_segments.push((subheader, data));
+    }
+
+    /// Adds a data extension segment.
+    pub fn add_data_extension(&mut self, subheader: DesSubheader, data: Vec<u8>) {
+        self.data_ext_segments.push((subheader, data));
+    }
+
+    /// Adds a reserved extension segment.
+    pub fn add_reserved_extension(&mut self, subheader: ResSubheader, data: Vec<u8>) {
+        self.res_segments.push((subheader, data));
+    }
+
+    /// Returns the file header as it will be written, with computed FL, HL and segment length
+    /// tables.
+    pub fn file_header(&self) -> Result<FileHeader, NitfError> {
+        Ok(self.layout()?.0)
+    }
+
+    /// Writes the file, returning its length.
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<u64, NitfError> {
+        let (file_header, subheaders) = self.layout()?;
+        let mut header = FieldWriter::new(0);
+        file_header.write(&mut header)?;
+        writer.write_all(&header.into_bytes())?;
+
+        let data = self.image_segments.iter().map(|(_, data)| data)
+            .chain(self.graphic_segments.iter().map(|(_, data)| data))
+            .chain(self.text_segments.iter().map(|(_, data)| data))
+            .chain(self.data_ext_segments.iter().map(|(_, data)| data))
+            .chain(self.res_segments.iter().map(|(_, data)| data));
+        for (subheader, data) in subheaders.iter().zip(data) {
+            writer.write_all(subheader)?;
+            writer.write_all(data)?;
+        }
+        writer.flush()?;
+        Ok(file_header.file_length)
+    }
+
+    /// Returns the file header with its lengths filled in, along with the bytes of every
+    /// segment subheader in file order.
+    fn layout(&self) -> Result<(FileHeader, Vec<Vec<u8>>), NitfError> {
+        let mut file_header = self.file_header.clone();
+        match file_header.nitf_version() {
+            Some(NitfVersion::Nitf21) | Some(NitfVersion::Nsif10) => {},
+            _ => return Err(NitfError::Unsupported {
+                field: "FHDR and FVER".to_string(),
+                value: format!("{}{}", file_header.profile_name, file_header.version)
+            })
+        }
+
+        // Subheaders are written at offset zero, so errors give offsets within the subheader
+        let written = |write: &dyn Fn(&mut FieldWriter) -> Result<(), NitfError>| {
+            let mut writer = FieldWriter::new(0);
+            write(&mut writer).map(|_| writer.into_bytes())
+        };
+        let mut subheaders = Vec::new();
+        let mut lengths = |subheader: Vec<u8>, data: &Vec<u8>| {
+            let segment = SegmentLengths {
+                subheader_length: subheader.len() as u64,
+                data_length: data.len() as u64
+            };
+            subheaders.push(subheader);
+            segment
+        };
+
+        file_header.image_segments = self.image_segments.iter()
+            .map(|(subheader, data)| Ok(lengths(written(&|writer| subheader.write(writer))?, data)))
+            .collect::<Result<_, NitfError>>()?;
+        file_header.graphic_segments = self.graphic_segments.iter()
+            .map(|(subheader, data)| Ok(lengths(written(&|writer| subheader.write(writer))?, data)))
+            .collect::<Result<_, NitfError>>()?;
+        file_header.text_segments = self.text_segments.iter()
+            .map(|(subheader, data)| Ok(lengths(written(&|writer| subheader.write(writer))?, data)))
+            .collect::<Result<_, NitfError>>()?;
+        file_header.data_ext_segments = self.data_ext_segments.iter()
+            .map(|(subheader, data)| Ok(lengths(written(&|writer| subheader.write(writer))?, data)))
+            .collect::<Result<_, NitfError>>()?;
+        file_header.reserved_ext_segments = self.res_segments.iter()
+            .map(|(subheader, data)| Ok(lengths(written(&|writer| subheader.write(writer))?, data)))
+            .collect::<Result<_, NitfError>>()?;
+        file_header.label_segments = Vec::new();
+
+        // The header length does not depend on the values of FL and HL
+        let mut header = FieldWriter::new(0);
+        file_header.write(&mut header)?;
+        file_header.header_length = header.position();
+        file_header.file_length = file_header.header_length + [
+            &file_header.image_segments,
+            &file_header.graphic_segments,
+            &file_header.text_segments,
+            &file_header.data_ext_segments,
+            &file_header.reserved_ext_segments
+        ].iter().flat_map(|segments| segments.iter()).map(|lengths| lengths.subheader_length + lengths.data_length).sum::<u64>();
+
+        Ok((file_header, subheaders))
+    }
+}
@@ -0,0 +1,69 @@
+//! CGM graphics decode to vector primitives
+
+use std::io::Cursor;
+
+use ossim_oxide::model::nitf::*;
+
+/// Returns a binary encoded element with short form parameters.
+fn element(class: u16, id: u16, parameters: &[u8]) -> Vec<u8> {
+    let header = class << 12 | id << 5 | parameters.len() as u16;
+    let mut bytes = header.to_be_bytes().to_vec();
+    bytes.extend_from_slice(parameters);
+    if parameters.len() % 2 == 1 {
+        bytes.push(0);
+    }
+    bytes
+}
+
+
+fn words(values: &[i16]) -> Vec<u8> {
+    values.iter().flat_map(|value| value.to_be_bytes().to_vec()).collect()
+}
+
+
+#[test]
+fn graphic_decodes_to_primitives() {
+    let red = Color { red: 255, green: 0, blue: 0 };
+    let cgm = [
+        element(0, 1, b"\x04test"),
+        element(0, 3, b"\x07picture"),
+        element(2, 6, &words(&[0, 100, 200, 0])),
+        element(0, 4, &[]),
+        element(5, 3, &words(&[3])),
+        element(5, 4, &[255, 0, 0]),
+        element(4, 1, &words(&[0, 0, 10, 10, 20, 5])),
+        element(5, 22, &words(&[1])),
+        element(5, 23, &[255, 0, 0]),
+        element(4, 12, &words(&[50, 50, 7])),
+        element(5, 14, &[255, 0, 0]),
+        element(5, 15, &words(&[12])),
+        element(4, 4, &[words(&[5, 95, 1]), b"\x05label".to_vec()].concat()),
+        element(0, 5, &[]),
+        element(0, 2, &[])
+    ].concat();
+
+    let mut writer = NitfWriter::new(FileHeader::default());
+    writer.add_graphic(GraphicSubheader { graphic_id: "MARKS".to_string(), ..GraphicSubheader::default() }, cgm);
+    let mut bytes = Vec::new();
+    writer.write(&mut bytes).unwrap();
+    let nitf = NITF::from_reader(Cursor::new(bytes)).unwrap();
+
+    let pictures = nitf.read_graphic(0).unwrap();
+    assert_eq!(pictures.len(), 1);
+    assert_eq!(pictures[0].name, "picture");
+    assert_eq!(pictures[0].vdc_extent, Some(((0, 100), (200, 0))));
+    assert_eq!(pictures[0].primitives, vec![
+        GraphicPrimitive::Polyline {
+            points: vec![(0, 0), (10, 10), (20, 5)],
+            line: LineStyle { color: red, width: 3, line_type: LineType::Solid }
+        },
+        GraphicPrimitive::Ellipse {
+            center: (50, 50),
+            first_diameter: (57, 50),
+            second_diameter: (50, 57),
+            fill: FillStyle::Solid(red),
+            edge: None
+        },
+        GraphicPrimitive::Text { position: (5, 95), text: "label".to_string(), color: red, height: 12, font: 1 }
+    ]);
+}
@@ -0,0 +1,205 @@
+//! Files shared by the integration tests, written by NitfWriter or built field by field
+
+#![allow(dead_code)]
+
+use std::io::Cursor;
+
+use ossim_oxide::model::nitf::*;
+
+/// Returns the subheader of a single block image of `bands` bands of `bits` bit unsigned
+/// integers, stored uncompressed and interleaved by block. Tests change the fields their
+/// image data needs.
+pub fn image_subheader(rows: u32, columns: u32, bands: usize, bits: u8) -> ImageSubheader {
+    let (representation, band_representations) = match bands {
+        1 => (ImageRepresentation::Monochrome, vec!["M"]),
+        3 => (ImageRepresentation::Rgb, vec!["R", "G", "B"]),
+        _ => (ImageRepresentation::Multiband, vec!["M"; bands])
+    };
+    ImageSubheader {
+        image_id: "TEST".to_string(),
+        date_time: DateTime { year: 2021, month: 3, day: 4, hour: 5, minute: 6, second: 7 },
+        target_id: String::new(),
+        image_title: String::new(),
+        security: SecurityFields::default(),
+        encryption: 0,
+        source: String::new(),
+        rows,
+        columns,
+        pixel_value_type: PixelValueType::Integer,
+        representation,
+        category: if bands == 1 || bands == 3 { "VIS" } else { "MS" }.to_string(),
+        actual_bits_per_pixel: bits,
+        pixel_justification: PixelJustification::Right,
+        coordinate_representation: None,
+        geographic_location: String::new(),
+        comments: Vec::new(),
+        compression: ImageCompression::NotCompressed,
+        compression_rate: String::new(),
+        bands: band_representations.into_iter()
+            .map(|representation| Band { representation: representation.to_string(), ..Band::default() })
+            .collect(),
+        sync_code: 0,
+        mode: ImageMode::Block,
+        blocks_per_row: 1,
+        blocks_per_column: 1,
+        pixels_per_block_horizontal: columns,
+        pixels_per_block_vertical: rows,
+        bits_per_pixel: bits,
+        display_level: 1,
+        attachment_level: 0,
+        location: (0, 0),
+        magnification: "1.0".to_string(),
+        user_defined_overflow: 0,
+        extended_overflow: 0,
+        tres: Vec::new()
+    }
+}
+
+
+/// Returns a file holding one image segment with the given subheader and stored data.
+pub fn image_file(subheader: ImageSubheader, data: Vec<u8>) -> NITF {
+    let mut writer = NitfWriter::new(FileHeader::default());
+    writer.add_image_data(subheader, data).unwrap();
+    let mut bytes = Vec::new();
+    writer.write(&mut bytes).unwrap();
+    NITF::from_reader(Cursor::new(bytes)).unwrap()
+}
+
+
+/// Returns every sample of a pixel buffer, band by band, widened to i64.
+pub fn samples(pixels: &PixelBuffer) -> Vec<i64> {
+    match &pixels.data {
+        PixelData::U8(data) => data.iter().map(|value| i64::from(*value)).collect(),
+        PixelData::U16(data) => data.iter().map(|value| i64::from(*value)).collect(),
+        PixelData::U32(data) => data.iter().map(|value| i64::from(*value)).collect(),
+        PixelData::I8(data) => data.iter().map(|value| i64::from(*value)).collect(),
+        PixelData::I16(data) => data.iter().map(|value| i64::from(*value)).collect(),
+        PixelData::I32(data) => data.iter().map(|value| i64::from(*value)).collect(),
+        other => panic!("{:?} samples are not integers that fit an i64", other.sample_type())
+    }
+}
+
+
+/// Appends a field holding text padded with spaces to its length.
+pub fn text(bytes: &mut Vec<u8>, value: &str, length: usize) {
+    assert!(value.len() <= length, "\"{}\" is longer than {} characters", value, length);
+    bytes.extend_from_slice(format!("{:<1$}", value, length).as_bytes());
+}
+
+
+/// Appends a field holding a number padded with zeros to its length.
+pub fn number(bytes: &mut Vec<u8>, value: usize, length: usize) {
+    bytes.extend_from_slice(format!("{:01$}", value, length).as_bytes());
+}
+
+
+/// Appends the 167 bytes of NITF 2.1 security fields of an unclassified header.
+pub fn security(bytes: &mut Vec<u8>) {
+    text(bytes, "U", 167);
+}
+
+
+/// Appends an extension area (e.g. UDHD) with its length and overflow fields, which is only
+/// the length field when there are no extensions.
+pub fn extensions(bytes: &mut Vec<u8>, tres: &[u8], length: usize) {
+    match tres.len() {
+        0 => number(bytes, 0, length),
+        _ => {
+            number(bytes, tres.len() + 3, length);
+            number(bytes, 0, 3);
+            bytes.extend_from_slice(tres);
+        }
+    }
+}
+
+
+/// Returns a tagged record extension of the given tag and data.
+pub fn tre(tag: &str, data: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    text(&mut bytes, tag, 6);
+    number(&mut bytes, data.len(), 5);
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+
+/// Returns the subheader of a single block, 8 bit monochrome image stored uncompressed,
+/// with the extensions in UDID and IXSHD.
+pub fn image_fields(image_id: &str, rows: usize, columns: usize, user_defined: &[u8], extended: &[u8]) -> Vec<u8> {
+    let mut bytes = b"IM".to_vec();
+    for (value, length) in &[(image_id, 10), ("20210304050607", 14), ("", 17), ("", 80)] {
+        text(&mut bytes, value, *length);
+    }
+    security(&mut bytes);
+    text(&mut bytes, "0", 1);
+    text(&mut bytes, "", 42);
+    number(&mut bytes, rows, 8);
+    number(&mut bytes, columns, 8);
+    for (value, length) in &[("INT", 3), ("MONO", 8), ("VIS", 8), ("08", 2), ("R", 1), ("", 1), ("0", 1), ("NC", 2), ("1", 1),
+                             ("M", 2), ("", 6), ("N", 1), ("", 3), ("0", 1), ("0", 1), ("B", 1), ("0001", 4), ("0001", 4)] {
+        text(&mut bytes, value, *length);
+    }
+    number(&mut bytes, columns, 4);
+    number(&mut bytes, rows, 4);
+    for (value, length) in &[("08", 2), ("001", 3), ("000", 3), ("0000000000", 10), ("1.0", 4)] {
+        text(&mut bytes, value, *length);
+    }
+    extensions(&mut bytes, user_defined, 5);
+    extensions(&mut bytes, extended, 5);
+    bytes
+}
+
+
+/// Returns the subheader of a text segment of BCS-A characters without extensions.
+pub fn text_fields(text_id: &str) -> Vec<u8> {
+    let mut bytes = b"TE".to_vec();
+    for (value, length) in &[(text_id, 7), ("000", 3), ("20210304050607", 14), ("", 80)] {
+        text(&mut bytes, value, *length);
+    }
+    security(&mut bytes);
+    for (value, length) in &[("0", 1), ("STA", 3), ("00000", 5)] {
+        text(&mut bytes, value, *length);
+    }
+    bytes
+}
+
+
+/// Returns a NITF 2.1 file of the image and text segments, each a subheader and its data,
+/// with the file header extensions in UDHD and XHD.
+pub fn nitf21(images: &[(Vec<u8>, Vec<u8>)], texts: &[(Vec<u8>, Vec<u8>)], user_defined: &[u8], extended: &[u8]) -> Vec<u8> {
+    let header = |file_length: usize, header_length: usize| {
+        let mut bytes = Vec::new();
+        for (value, length) in &[("NITF", 4), ("02.10", 5), ("03", 2), ("BF01", 4), ("OSSIM", 10), ("20210304050607", 14), ("Built", 80)] {
+            text(&mut bytes, value, *length);
+        }
+        security(&mut bytes);
+        for (value, length) in &[("00001", 5), ("00001", 5), ("0", 1)] {
+            text(&mut bytes, value, *length);
+        }
+        bytes.extend_from_slice(&[0x10, 0x20, 0x30]);
+        text(&mut bytes, "Originator", 24);
+        text(&mut bytes, "555 0100", 18);
+        number(&mut bytes, file_length, 12);
+        number(&mut bytes, header_length, 6);
+        number(&mut bytes, images.len(), 3);
+        for (subheader, data) in images {
+            number(&mut bytes, subheader.len(), 6);
+            number(&mut bytes, data.len(), 10);
+        }
+        number(&mut bytes, 0, 3);
+        number(&mut bytes, 0, 3);
+        number(&mut bytes, texts.len(), 3);
+        for (subheader, data) in texts {
+            number(&mut bytes, subheader.len(), 4);
+            number(&mut bytes, data.len(), 5);
+        }
+        number(&mut bytes, 0, 3);
+        number(&mut bytes, 0, 3);
+        extensions(&mut bytes, user_defined, 5);
+        extensions(&mut bytes, extended, 5);
+        bytes
+    };
+    let body: Vec<u8> = images.iter().chain(texts).flat_map(|(subheader, data)| [&subheader[..], &data[..]].concat()).collect();
+    let header_length = header(0, 0).len();
+    [header(header_length + body.len(), header_length), body].concat()
+}
@@ -0,0 +1,110 @@
+//! Malformed files are reported with the field and byte offset that could not be read
+
+mod common;
+
+use std::io::Cursor;
+
+use ossim_oxide::model::nitf::*;
+
+use common::{image_fields, nitf21};
+
+/// Offset of NROWS within an image subheader, after the identification and security fields.
+const NROWS: usize = 333;
+
+/// Returns a file with one 2 by 3 image.
+fn file() -> Vec<u8> {
+    nitf21(&[(image_fields("IMAGE", 2, 3, b"", b""), vec![0; 6])], &[], b"", b"")
+}
+
+
+/// Returns the file length and header length a file records.
+fn lengths(bytes: &[u8]) -> (usize, usize) {
+    let field = |range: std::ops::Range<usize>| String::from_utf8_lossy(&bytes[range]).parse().unwrap();
+    (field(342..354), field(354..360))
+}
+
+
+/// Returns the error of reading the file.
+fn error(bytes: Vec<u8>) -> NitfError {
+    match NITF::from_reader(Cursor::new(bytes)) {
+        Err(error) => error,
+        Ok(_) => panic!("a malformed file was read")
+    }
+}
+
+
+#[test]
+fn fields_past_the_end_are_truncated() {
+    // ONAME follows the background color at offset 300
+    match error(file()[..310].to_vec()) {
+        NitfError::Truncated { field, offset } => assert_eq!((field.as_str(), offset), ("ONAME", 300)),
+        other => panic!("unexpected error {:?}", other)
+    }
+
+    // The image subheader runs past the end of a file whose FL records the cut length
+    let bytes = file();
+    let (_, header_length) = lengths(&bytes);
+    let mut cut = bytes[..header_length + 100].to_vec();
+    cut[342..354].copy_from_slice(format!("{:012}", header_length + 100).as_bytes());
+    match error(cut) {
+        NitfError::Truncated { field, offset } => assert_eq!((field.as_str(), offset), ("LISH001", header_length as u64)),
+        other => panic!("unexpected error {:?}", other)
+    }
+}
+
+
+#[test]
+fn numbers_must_be_numeric() {
+    let mut bytes = file();
+    let (_, header_length) = lengths(&bytes);
+    let offset = header_length + NROWS;
+    bytes[offset..offset + 8].copy_from_slice(b"00000x02");
+    match error(bytes) {
+        NitfError::NonNumeric { field, offset: read_offset, value } => {
+            assert_eq!((field.as_str(), read_offset, value.as_str()), ("NROWS", offset as u64, "00000x02"));
+        },
+        other => panic!("unexpected error {:?}", other)
+    }
+}
+
+
+#[test]
+fn coded_values_must_be_defined() {
+    // PVTYPE follows NROWS and NCOLS
+    let mut bytes = file();
+    let (_, header_length) = lengths(&bytes);
+    let offset = header_length + NROWS + 16;
+    bytes[offset..offset + 3].copy_from_slice(b"XYZ");
+    match error(bytes) {
+        NitfError::InvalidValue { field, offset: read_offset, value } => {
+            assert_eq!((field.as_str(), read_offset, value.as_str()), ("PVTYPE", offset as u64, "XYZ"));
+        },
+        other => panic!("unexpected error {:?}", other)
+    }
+}
+
+
+#[test]
+fn lengths_must_match_the_file() {
+    let bytes = file();
+    let (file_length, header_length) = lengths(&bytes);
+    assert_eq!(file_length, bytes.len());
+
+    let mut long = bytes.clone();
+    long[342..354].copy_from_slice(format!("{:012}", file_length + 5).as_bytes());
+    match error(long) {
+        NitfError::LengthMismatch { field, offset, expected, actual } => {
+            assert_eq!((field.as_str(), offset, expected, actual), ("FL", 342, file_length as u64 + 5, file_length as u64));
+        },
+        other => panic!("unexpected error {:?}", other)
+    }
+
+    let mut short = bytes;
+    short[354..360].copy_from_slice(format!("{:06}", header_length - 1).as_bytes());
+    match error(short) {
+        NitfError::LengthMismatch { field, offset, expected, actual } => {
+            assert_eq!((field.as_str(), offset, expected, actual), ("HL", 354, header_length as u64 - 1, header_length as u64));
+        },
+        other => panic!("unexpected error {:?}", other)
+    }
+}
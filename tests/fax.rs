@@ -0,0 +1,80 @@
+//! CCITT T.4 compressed bi-level images decode with each coding scheme of COMRAT
+
+mod common;
+
+use ossim_oxide::model::nitf::*;
+
+use common::{image_file, image_subheader, samples};
+
+const ROWS: u32 = 24;
+const COLUMNS: u32 = 150;
+
+/// The shapes coded one dimensionally, row by row (COMRAT 1D).
+const ONE_DIMENSIONAL: &[u8] = include_bytes!("data/shapes_1d.fax");
+
+/// The shapes coded two dimensionally with K = 2 (COMRAT 2DS).
+const STANDARD_RESOLUTION: &[u8] = include_bytes!("data/shapes_2ds.fax");
+
+/// The shapes coded two dimensionally with K = 4 (COMRAT 2DH).
+const HIGH_RESOLUTION: &[u8] = include_bytes!("data/shapes_2dh.fax");
+
+/// Returns whether the pixel of the shapes is black: a black row, a white row, a band that
+/// widens and drifts right faster than vertical mode can follow, and stripes that end and
+/// begin between rows.
+fn black(row: u32, column: u32) -> bool {
+    row == 5 || (row != 12 && ((row * 4 + 10..row * 5 + 30).contains(&column)
+        || ((100..140).contains(&column) && (column / 7 + row / 6) % 2 == 0)))
+}
+
+
+/// Returns a file holding the stream as a bi-level image coded as COMRAT describes.
+fn fax_file(stream: &[u8], compression_rate: &str) -> NITF {
+    let mut subheader = image_subheader(ROWS, COLUMNS, 1, 1);
+    subheader.pixel_value_type = PixelValueType::BiLevel;
+    subheader.representation = ImageRepresentation::BiLevel;
+    subheader.compression = ImageCompression::BiLevel;
+    subheader.compression_rate = compression_rate.to_string();
+    image_file(subheader, stream.to_vec())
+}
+
+
+#[test]
+fn each_coding_scheme_decodes_the_rows() {
+    let expected: Vec<i64> = (0..ROWS)
+        .flat_map(|row| (0..COLUMNS).map(move |column| black(row, column) as i64))
+        .collect();
+    for &(stream, compression_rate) in &[(ONE_DIMENSIONAL, "1D"), (STANDARD_RESOLUTION, "2DS"), (HIGH_RESOLUTION, "2DH")] {
+        let nitf = fax_file(stream, compression_rate);
+        let pixels = nitf.read_pixels(0, Rectangle::new(0, 0, ROWS, COLUMNS), &[0]).unwrap();
+        assert_eq!(samples(&pixels), expected, "COMRAT {}", compression_rate);
+    }
+}
+
+
+#[test]
+fn rows_read_as_masks() {
+    let nitf = fax_file(STANDARD_RESOLUTION, "2DS");
+    let rectangle = Rectangle::new(10, 95, 4, 12);
+    let mask = nitf.read_bilevel_mask(0, rectangle, BiLevelDepth::EightBit).unwrap();
+    let expected: Vec<u8> = (10..14)
+        .flat_map(|row| (95..107).map(move |column| if black(row, column) { 255 } else { 0 }))
+        .collect();
+    assert_eq!(mask, expected);
+
+    // Twelve pixels a row, packed into two bytes with the last four bits clear
+    let packed = nitf.read_bilevel_mask(0, rectangle, BiLevelDepth::OneBit).unwrap();
+    let expected: Vec<u8> = expected.chunks(12)
+        .flat_map(|row| row.chunks(8).map(|byte| byte.iter().enumerate().fold(0, |bits, (index, value)| bits | (value & 0x80) >> index)).collect::<Vec<u8>>())
+        .collect();
+    assert_eq!(packed, expected);
+}
+
+
+#[test]
+fn two_dimensional_rows_fail_to_decode_as_one_dimensional() {
+    let nitf = fax_file(HIGH_RESOLUTION, "1D");
+    match nitf.read_pixels(0, Rectangle::new(0, 0, ROWS, COLUMNS), &[0]) {
+        Err(NitfError::Decode { compression, .. }) => assert_eq!(compression, "C1"),
+        other => panic!("2DH rows read as 1D gave {:?}", other.map(|pixels| pixels.rows))
+    }
+}
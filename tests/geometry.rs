@@ -0,0 +1,64 @@
+//! Image corners are read from IGEOLO for each coordinate representation of ICORDS
+
+mod common;
+
+use ossim_oxide::model::nitf::*;
+
+use common::{image_file, image_subheader};
+
+/// Returns the corners of an image written with the given ICORDS and IGEOLO and read back.
+fn corners(representation: CoordinateRepresentation, location: &str) -> Option<[(f64, f64); 4]> {
+    let mut subheader = image_subheader(1, 1, 1, 8);
+    subheader.coordinate_representation = Some(representation);
+    subheader.geographic_location = location.to_string();
+    let nitf = image_file(subheader, vec![0]);
+    let read = &nitf.image_segments()[0].subheader;
+    assert_eq!(read.coordinate_representation, Some(representation));
+    assert_eq!(read.geographic_location, location);
+    read.corners()
+}
+
+
+#[test]
+fn geographic_corners_are_degrees_minutes_and_seconds() {
+    let read = corners(CoordinateRepresentation::Geographic,
+                       "350000N1171500W350000N1171400W345900N1171400W345900N1171500W").unwrap();
+    assert_eq!(read[0], (35.0, -117.25));
+    assert_eq!(read[1], (35.0, -(117.0 + 14.0 / 60.0)));
+    assert_eq!(read[2], (34.0 + 59.0 / 60.0, -(117.0 + 14.0 / 60.0)));
+    assert_eq!(read[3], (34.0 + 59.0 / 60.0, -117.25));
+
+    // Seconds and the southern and eastern hemispheres
+    let read = corners(CoordinateRepresentation::Geographic,
+                       "123036S0450018E123036S0450118E123136S0450118E123136S0450018E").unwrap();
+    assert_eq!(read[0], (-(12.0 + 30.0 / 60.0 + 36.0 / 3600.0), 45.0 + 18.0 / 3600.0));
+    assert_eq!(read[2], (-(12.0 + 31.0 / 60.0 + 36.0 / 3600.0), 45.0 + 1.0 / 60.0 + 18.0 / 3600.0));
+}
+
+
+#[test]
+fn decimal_corners_are_signed_degrees() {
+    let read = corners(CoordinateRepresentation::DecimalDegrees,
+                       "+35.000-117.250+35.000-117.233+34.983-117.233+34.983-117.250").unwrap();
+    assert_eq!(read, [(35.0, -117.25), (35.0, -117.233), (34.983, -117.233), (34.983, -117.25)]);
+}
+
+
+#[test]
+fn malformed_corners_are_none() {
+    assert_eq!(corners(CoordinateRepresentation::Geographic,
+                       "350000N1171500W350000N1171400W345900X1171400W345900N1171500W"), None);
+    assert_eq!(corners(CoordinateRepresentation::DecimalDegrees,
+                       "+35.000-117.250+35.000-117.233+34.983-117.233+34.983-117.2x0"), None);
+}
+
+
+#[test]
+fn utm_and_mgrs_corners_are_not_converted() {
+    // Zone, easting and northing of each corner in UTM zone 11
+    let utm = concat!("113841463883487", "113842533883487", "113842533881637", "113841463881637");
+    assert_eq!(corners(CoordinateRepresentation::UtmNorth, utm), None);
+    assert_eq!(corners(CoordinateRepresentation::UtmSouth, utm), None);
+    let mgrs = concat!("11SMT8414683487", "11SMT8425383487", "11SMT8425381637", "11SMT8414681637");
+    assert_eq!(corners(CoordinateRepresentation::Mgrs, mgrs), None);
+}
@@ -0,0 +1,54 @@
+//! Headers read into typed fields, with the string map of each kept as a derived view
+
+mod common;
+
+use std::io::Cursor;
+
+use ossim_oxide::model::nitf::*;
+
+use common::{image_fields, nitf21, text_fields};
+
+/// Returns a file with one 2 by 3 image and one text segment, read back.
+fn file() -> (Vec<u8>, NITF) {
+    let bytes = nitf21(&[(image_fields("IMAGE", 2, 3, b"", b""), vec![0; 6])], &[(text_fields("NOTE"), b"Note".to_vec())], b"", b"");
+    let nitf = NITF::from_reader(Cursor::new(bytes.clone())).unwrap();
+    (bytes, nitf)
+}
+
+
+#[test]
+fn file_header_fields_are_typed() {
+    let (bytes, nitf) = file();
+    let header = nitf.file_header();
+    assert_eq!((header.profile_name.as_str(), header.version.as_str()), ("NITF", "02.10"));
+    assert_eq!(header.nitf_version(), Some(NitfVersion::Nitf21));
+    assert_eq!((header.complexity_level, header.standard_type.as_str()), (3, "BF01"));
+    assert_eq!(header.date_time, DateTime { year: 2021, month: 3, day: 4, hour: 5, minute: 6, second: 7 });
+    assert_eq!(header.security.classification, Classification::Unclassified);
+    assert_eq!((header.copy_number, header.number_of_copies, header.encryption), (1, 1, 0));
+    assert_eq!(header.background_color, Color { red: 0x10, green: 0x20, blue: 0x30 });
+    assert_eq!((header.originator_name.as_str(), header.originator_phone.as_str()), ("Originator", "555 0100"));
+    assert_eq!(header.file_length, bytes.len() as u64);
+    let subheader_length = nitf.image_segments()[0].subheader_length;
+    assert_eq!(header.image_segments, vec![SegmentLengths { subheader_length, data_length: 6 }]);
+    assert_eq!(header.text_segments, vec![SegmentLengths { subheader_length: text_fields("NOTE").len() as u64, data_length: 4 }]);
+    assert!(header.graphic_segments.is_empty() && header.data_ext_segments.is_empty() && header.reserved_ext_segments.is_empty());
+}
+
+
+#[test]
+fn string_maps_are_derived_from_the_fields() {
+    let (bytes, nitf) = file();
+    let map = nitf.file_header().to_map();
+    assert_eq!(map["FDT"], "2021/03/04 05:06:07");
+    assert_eq!(map["FBKGC"], "0x102030");
+    assert_eq!(map["FL"], format!("{:012}", bytes.len()));
+    assert_eq!((map["NUMI"].as_str(), map["LI001"].as_str()), ("001", "0000000006"));
+    assert_eq!((map["NUMT"].as_str(), map["LT001"].as_str()), ("001", "00004"));
+    assert_eq!(map["FSCLAS"], "U");
+
+    let text = &nitf.text_segments()[0].subheader;
+    assert_eq!((text.text_id.as_str(), text.format), ("NOTE", TextFormat::Standard));
+    let map = text.to_map();
+    assert_eq!((map["TEXTID"].as_str(), map["TXTDT"].as_str(), map["TXTFMT"].as_str()), ("NOTE", "2021/03/04 05:06:07", "STA"));
+}
@@ -0,0 +1,117 @@
+//! Uncompressed pixels are found in each image mode and justified within NBPP bits
+
+mod common;
+
+use ossim_oxide::model::nitf::*;
+
+use common::{image_file, image_subheader, samples};
+
+/// Returns the value of a pixel of the two band image of 2 rows and 4 columns.
+fn value(band: u32, row: u32, column: u32) -> u8 {
+    (band * 100 + row * 10 + column) as u8
+}
+
+
+/// Returns the position of a pixel within its 2 by 2 block of two bands, which band
+/// sequential images store one band at a time.
+fn position(mode: ImageMode, band: u32, row: u32, column: u32) -> usize {
+    let position = match mode {
+        ImageMode::Block | ImageMode::Sequential => band * 4 + row * 2 + column,
+        ImageMode::Pixel => (row * 2 + column) * 2 + band,
+        ImageMode::Row => row * 4 + band * 2 + column
+    };
+    position as usize
+}
+
+
+/// Returns the pixels of the two band image stored as the image mode lays them out, in two
+/// blocks of 2 by 2 pixels side by side.
+fn stored(mode: ImageMode) -> Vec<u8> {
+    let block = |block: u32| {
+        let mut pixels = vec![0; 8];
+        for band in 0..2 {
+            for row in 0..2 {
+                for column in 0..2 {
+                    pixels[position(mode, band, row, column)] = value(band, row, block * 2 + column);
+                }
+            }
+        }
+        pixels
+    };
+    let (first, second) = (block(0), block(1));
+    match mode {
+        ImageMode::Sequential => [&first[..4], &second[..4], &first[4..], &second[4..]].concat(),
+        _ => [first, second].concat()
+    }
+}
+
+
+/// Packs the values into `bits` bits each, most significant bit first.
+fn packed(values: &[u64], bits: u32) -> Vec<u8> {
+    let mut bytes = vec![0u8; (values.len() * bits as usize).div_ceil(8)];
+    for (index, value) in values.iter().enumerate() {
+        for bit in 0..bits {
+            if value >> (bits - 1 - bit) & 1 == 1 {
+                let position = index * bits as usize + bit as usize;
+                bytes[position / 8] |= 0x80 >> (position % 8);
+            }
+        }
+    }
+    bytes
+}
+
+
+#[test]
+fn each_image_mode_reads_the_same_pixels() {
+    let expected: Vec<i64> = [1, 0].iter()
+        .flat_map(|band| (0..2).flat_map(move |row| (0..4).map(move |column| i64::from(value(*band, row, column)))))
+        .collect();
+    for &mode in &[ImageMode::Block, ImageMode::Pixel, ImageMode::Row, ImageMode::Sequential] {
+        let mut subheader = image_subheader(2, 4, 2, 8);
+        subheader.mode = mode;
+        subheader.blocks_per_row = 2;
+        subheader.pixels_per_block_horizontal = 2;
+        let nitf = image_file(subheader, stored(mode));
+        let pixels = nitf.read_pixels(0, Rectangle::new(0, 0, 2, 4), &[1, 0]).unwrap();
+        assert_eq!(samples(&pixels), expected, "IMODE {}", mode.code());
+
+        let block = nitf.read_block(0, 0, 1, &[0]).unwrap().unwrap();
+        assert_eq!(samples(&block), vec![2, 3, 12, 13], "IMODE {}", mode.code());
+    }
+}
+
+
+#[test]
+fn significant_bits_are_taken_from_the_justified_side() {
+    // Ten significant bits in twelve, with the two bits outside them set to show they are
+    // dropped
+    let values: Vec<u64> = (0..6).map(|pixel| pixel * 150 + 7).collect();
+    for (justification, stored) in [
+        (PixelJustification::Left, values.iter().map(|value| value << 2 | 0b11).collect::<Vec<u64>>()),
+        (PixelJustification::Right, values.iter().map(|value| 0b11 << 10 | value).collect())
+    ] {
+        let mut subheader = image_subheader(2, 3, 1, 12);
+        subheader.actual_bits_per_pixel = 10;
+        subheader.pixel_justification = justification;
+        let nitf = image_file(subheader, packed(&stored, 12));
+        let pixels = nitf.read_pixels(0, Rectangle::new(0, 0, 2, 3), &[0]).unwrap();
+        assert_eq!(pixels.data.sample_type(), SampleType::U16);
+        assert_eq!(pixels.significant_bits, 10);
+        assert_eq!(samples(&pixels), values.iter().map(|value| *value as i64).collect::<Vec<i64>>(), "{:?}", justification);
+    }
+}
+
+
+#[test]
+fn signed_values_extend_from_their_significant_bits() {
+    let values: [i64; 6] = [-512, -1, 0, 1, 300, 511];
+    let stored: Vec<u64> = values.iter().map(|value| ((*value as u64) & 0x3FF) << 2).collect();
+    let mut subheader = image_subheader(2, 3, 1, 12);
+    subheader.pixel_value_type = PixelValueType::SignedInteger;
+    subheader.actual_bits_per_pixel = 10;
+    subheader.pixel_justification = PixelJustification::Left;
+    let nitf = image_file(subheader, packed(&stored, 12));
+    let pixels = nitf.read_pixels(0, Rectangle::new(0, 0, 2, 3), &[0]).unwrap();
+    assert_eq!(pixels.data.sample_type(), SampleType::I16);
+    assert_eq!(samples(&pixels), values.to_vec());
+}
@@ -0,0 +1,92 @@
+//! Image subheaders are read field by field through their conditional fields and band loop
+
+mod common;
+
+use std::io::Cursor;
+
+use ossim_oxide::model::nitf::*;
+
+use common::{extensions, nitf21, security, text, tre};
+
+const GEOLOCATION: &str = "+35.000-117.250+35.000-117.233+34.983-117.233+34.983-117.250";
+
+/// Returns the subheader of a two band image with coordinates, comments, a compression rate,
+/// the band count in XBANDS, look up tables for the first band and an extension in each area.
+fn image_subheader() -> Vec<u8> {
+    let mut bytes = b"IM".to_vec();
+    for (value, length) in &[("FULL", 10), ("20210304050607", 14), ("TARGET", 17), ("Every field", 80)] {
+        text(&mut bytes, value, *length);
+    }
+    security(&mut bytes);
+    for (value, length) in &[("0", 1), ("Sensor", 42), ("00000100", 8), ("00000200", 8), ("INT", 3), ("MULTI", 8), ("MS", 8),
+                             ("11", 2), ("L", 1), ("D", 1), (GEOLOCATION, 60), ("2", 1), ("First comment", 80),
+                             ("Second comment", 80), ("C3", 2), ("00.2", 4), ("0", 1), ("00002", 5)] {
+        text(&mut bytes, value, *length);
+    }
+    // The first band has two tables of three entries
+    for (value, length) in &[("LU", 2), ("", 6), ("N", 1), ("", 3), ("2", 1), ("00003", 5)] {
+        text(&mut bytes, value, *length);
+    }
+    bytes.extend_from_slice(&[0, 128, 255, 255, 128, 0]);
+    for (value, length) in &[("M", 2), ("BLUE", 6), ("N", 1), ("", 3), ("0", 1), ("0", 1), ("S", 1), ("0002", 4), ("0003", 4),
+                             ("0064", 4), ("0032", 4), ("16", 2), ("005", 3), ("002", 3), ("-0010", 5), ("00020", 5), ("0.50", 4)] {
+        text(&mut bytes, value, *length);
+    }
+    extensions(&mut bytes, &tre("TESTA", b"user defined"), 5);
+    extensions(&mut bytes, &tre("TESTB", &[0, 1, 2]), 5);
+    bytes
+}
+
+
+#[test]
+fn every_field_is_read_in_order() {
+    let subheader = image_subheader();
+    let bytes = nitf21(&[(subheader.clone(), Vec::new())], &[], b"", b"");
+    let nitf = NITF::from_reader(Cursor::new(bytes.clone())).unwrap();
+    let segment = &nitf.image_segments()[0];
+    assert_eq!(segment.subheader_length, subheader.len() as u64);
+
+    let offset = |tag: &[u8]| bytes.windows(tag.len()).position(|window| window == tag).unwrap() as u64;
+    assert_eq!(segment.subheader, ImageSubheader {
+        image_id: "FULL".to_string(),
+        date_time: DateTime { year: 2021, month: 3, day: 4, hour: 5, minute: 6, second: 7 },
+        target_id: "TARGET".to_string(),
+        image_title: "Every field".to_string(),
+        security: SecurityFields::default(),
+        encryption: 0,
+        source: "Sensor".to_string(),
+        rows: 100,
+        columns: 200,
+        pixel_value_type: PixelValueType::Integer,
+        representation: ImageRepresentation::Multiband,
+        category: "MS".to_string(),
+        actual_bits_per_pixel: 11,
+        pixel_justification: PixelJustification::Left,
+        coordinate_representation: Some(CoordinateRepresentation::DecimalDegrees),
+        geographic_location: GEOLOCATION.to_string(),
+        comments: vec!["First comment".to_string(), "Second comment".to_string()],
+        compression: ImageCompression::Jpeg,
+        compression_rate: "00.2".to_string(),
+        bands: vec![
+            Band { representation: "LU".to_string(), filter_condition: "N".to_string(), luts: vec![vec![0, 128, 255], vec![255, 128, 0]], ..Band::default() },
+            Band { representation: "M".to_string(), subcategory: "BLUE".to_string(), filter_condition: "N".to_string(), ..Band::default() }
+        ],
+        sync_code: 0,
+        mode: ImageMode::Sequential,
+        blocks_per_row: 2,
+        blocks_per_column: 3,
+        pixels_per_block_horizontal: 64,
+        pixels_per_block_vertical: 32,
+        bits_per_pixel: 16,
+        display_level: 5,
+        attachment_level: 2,
+        location: (-10, 20),
+        magnification: "0.50".to_string(),
+        user_defined_overflow: 0,
+        extended_overflow: 0,
+        tres: vec![
+            Tre { tag: "TESTA".to_string(), data: b"user defined".to_vec(), location: TreLocation::ImageUserDefined, offset: offset(b"TESTA") },
+            Tre { tag: "TESTB".to_string(), data: vec![0, 1, 2], location: TreLocation::ImageExtended, offset: offset(b"TESTB") }
+        ]
+    });
+}
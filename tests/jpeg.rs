@@ -0,0 +1,214 @@
+//! JPEG compressed images decode through read_pixels
+
+mod common;
+
+use ossim_oxide::model::nitf::*;
+
+use common::{image_file, image_subheader, samples};
+
+const ROWS: u32 = 16;
+const COLUMNS: u32 = 24;
+
+/// Baseline 8 bit stream of the gradient, quantized by ones.
+const BASELINE: &[u8] = include_bytes!("data/gradient_8.jpg");
+
+/// Extended 12 bit stream of the gradient scaled by 16, quantized by ones.
+const EXTENDED: &[u8] = include_bytes!("data/gradient_12.jpg");
+
+/// Baseline stream of the luminance and chrominance of the colors from `color`, quantized
+/// by ones.
+const LUMINANCE_CHROMINANCE: &[u8] = include_bytes!("data/gradient_ycbcr.jpg");
+
+/// Baseline stream of the gradient quantized by the default table 3 of COMRAT 00.3.
+const DEFAULT_TABLE: &[u8] = include_bytes!("data/gradient_q3.jpg");
+
+/// Returns the sample of the gradient the streams hold.
+fn gradient(row: u32, column: u32) -> i64 {
+    i64::from(row * 5 + column * 4 + (row * column) % 9)
+}
+
+
+/// Returns the sample of the twelve bit image, the gradient scaled by 16 with a ramp added.
+fn twelve_bit(row: u32, column: u32) -> i64 {
+    gradient(row, column) * 16 + i64::from(column)
+}
+
+
+/// Returns the red, green and blue samples of the color image.
+fn color(row: u32, column: u32) -> [i64; 3] {
+    [gradient(row, column), 255 - gradient(row, column), i64::from(row * 8 + column * 2)]
+}
+
+
+/// Returns a file holding the stream as a one band image of the given precision and COMRAT.
+fn jpeg_file(stream: &[u8], bits: u8, compression_rate: &str) -> NITF {
+    let mut subheader = image_subheader(ROWS, COLUMNS, 1, bits);
+    subheader.compression = ImageCompression::Jpeg;
+    subheader.compression_rate = compression_rate.to_string();
+    image_file(subheader, stream.to_vec())
+}
+
+
+/// Asserts that every sample is within `tolerance` of the expected sample.
+fn assert_close(samples: &[i64], expected: &[i64], tolerance: i64) {
+    assert_eq!(samples.len(), expected.len());
+    for (index, (sample, expected)) in samples.iter().zip(expected).enumerate() {
+        assert!((sample - expected).abs() <= tolerance, "sample {} is {} where {} was expected", index, sample, expected);
+    }
+}
+
+
+/// Returns the samples of an image within the rectangle, row by row.
+fn expected(rectangle: Rectangle, sample: fn(u32, u32) -> i64) -> Vec<i64> {
+    (rectangle.row..rectangle.row + rectangle.rows)
+        .flat_map(|row| (rectangle.column..rectangle.column + rectangle.columns).map(move |column| sample(row, column)))
+        .collect()
+}
+
+
+#[test]
+fn baseline_stream_decodes() {
+    let nitf = jpeg_file(BASELINE, 8, "00.0");
+    let everything = Rectangle::new(0, 0, ROWS, COLUMNS);
+    let pixels = nitf.read_pixels(0, everything, &[0]).unwrap();
+    assert_eq!(pixels.data.sample_type(), SampleType::U8);
+    assert_close(&samples(&pixels), &expected(everything, gradient), 1);
+}
+
+
+#[test]
+fn extended_twelve_bit_stream_decodes() {
+    let nitf = jpeg_file(EXTENDED, 12, "00.0");
+    for &rectangle in &[Rectangle::new(0, 0, ROWS, COLUMNS), Rectangle::new(3, 9, 2, 5)] {
+        let pixels = nitf.read_pixels(0, rectangle, &[0]).unwrap();
+        assert_eq!(pixels.data.sample_type(), SampleType::U16);
+        assert_close(&samples(&pixels), &expected(rectangle, twelve_bit), 1);
+    }
+}
+
+
+#[test]
+fn luminance_and_chrominance_read_as_rgb() {
+    let mut subheader = image_subheader(ROWS, COLUMNS, 3, 8);
+    subheader.representation = ImageRepresentation::YCbCr601;
+    for (band, representation) in subheader.bands.iter_mut().zip(&["Y", "Cb", "Cr"]) {
+        band.representation = representation.to_string();
+    }
+    subheader.mode = ImageMode::Pixel;
+    subheader.compression = ImageCompression::Jpeg;
+    subheader.compression_rate = "00.0".to_string();
+    let nitf = image_file(subheader, LUMINANCE_CHROMINANCE.to_vec());
+
+    let pixels = nitf.read_pixels(0, Rectangle::new(0, 0, ROWS, COLUMNS), &[2, 0]).unwrap();
+    let band = |index| (0..ROWS).flat_map(move |row| (0..COLUMNS).map(move |column| color(row, column)[index]));
+    let expected: Vec<i64> = band(2).chain(band(0)).collect();
+    assert_close(&samples(&pixels), &expected, 3);
+}
+
+
+#[test]
+fn missing_tables_come_from_comrat() {
+    // The stream without its DQT segment, which holds the table COMRAT 00.3 stands for
+    let start = DEFAULT_TABLE.windows(2).position(|marker| marker == [0xFF, 0xDB]).unwrap();
+    let length = usize::from(u16::from_be_bytes([DEFAULT_TABLE[start + 2], DEFAULT_TABLE[start + 3]]));
+    let without_table = [&DEFAULT_TABLE[..start], &DEFAULT_TABLE[start + 2 + length..]].concat();
+
+    let everything = Rectangle::new(0, 0, ROWS, COLUMNS);
+    let with_table = jpeg_file(DEFAULT_TABLE, 8, "00.3").read_pixels(0, everything, &[0]).unwrap();
+    let defaulted = jpeg_file(&without_table, 8, "00.3").read_pixels(0, everything, &[0]).unwrap();
+    assert_eq!(defaulted.data, with_table.data);
+    assert_close(&samples(&defaulted), &expected(everything, gradient), 8);
+
+    match jpeg_file(&without_table, 8, "00.0").read_pixels(0, everything, &[0]) {
+        Err(NitfError::Decode { message, .. }) => assert!(message.contains("quantization table"), "{}", message),
+        other => panic!("a stream without tables read {:?}", other.map(|pixels| pixels.rows))
+    }
+}
+
+
+#[test]
+fn blocks_are_found_stream_after_stream() {
+    // Two blocks side by side, each stream longer than the first read that measures it
+    let mut subheader = image_subheader(ROWS, COLUMNS * 2, 1, 12);
+    subheader.compression = ImageCompression::Jpeg;
+    subheader.compression_rate = "00.0".to_string();
+    subheader.blocks_per_row = 2;
+    subheader.pixels_per_block_horizontal = COLUMNS;
+    assert!(EXTENDED.len() > 1024);
+    let nitf = image_file(subheader.clone(), [EXTENDED, EXTENDED].concat());
+    let right = Rectangle::new(2, COLUMNS + 3, 5, 7);
+    let pixels = nitf.read_pixels(0, right, &[0]).unwrap();
+    assert_close(&samples(&pixels), &expected(right, |row, column| twelve_bit(row, column - COLUMNS)), 1);
+
+    // A second stream cut short has no end of image marker to measure it by
+    let nitf = image_file(subheader, [EXTENDED, &EXTENDED[..EXTENDED.len() - 2]].concat());
+    match nitf.read_pixels(0, right, &[0]) {
+        Err(NitfError::Decode { compression, .. }) => assert_eq!(compression, "C3"),
+        other => panic!("a cut short stream read {:?}", other.map(|pixels| pixels.rows))
+    }
+}
+
+
+#[test]
+fn downsampled_stream_is_the_whole_image() {
+    let mut subheader = image_subheader(ROWS, COLUMNS, 1, 8);
+    subheader.compression = ImageCompression::DownsampledJpeg;
+    subheader.compression_rate = "00.0".to_string();
+    let everything = Rectangle::new(0, 0, ROWS, COLUMNS);
+    let pixels = image_file(subheader.clone(), BASELINE.to_vec()).read_pixels(0, everything, &[0]).unwrap();
+    assert_close(&samples(&pixels), &expected(everything, gradient), 1);
+
+    // A stream smaller than the image, which C3 would take as a block with pad pixels
+    let mut larger = subheader.clone();
+    larger.columns = COLUMNS + 8;
+    larger.pixels_per_block_horizontal = COLUMNS + 8;
+    // Or an image of more than one block
+    let mut blocked = subheader;
+    blocked.blocks_per_row = 2;
+    blocked.pixels_per_block_horizontal = COLUMNS / 2;
+    for (subheader, data, message) in [(larger, BASELINE.to_vec(), "the stream is 16 by 24 pixels but the image is 16 by 32"),
+                                       (blocked, [BASELINE, BASELINE].concat(), "I1 images are one block but this one has 1 by 2")] {
+        match image_file(subheader, data).read_pixels(0, Rectangle::new(0, 0, 1, 1), &[0]) {
+            Err(NitfError::Decode { compression, message: read, .. }) => assert_eq!((compression.as_str(), read.as_str()), ("I1", message)),
+            other => panic!("an I1 image read {:?}", other.map(|pixels| pixels.rows))
+        }
+    }
+}
+
+
+/// Returns the stream with every value of its Huffman tables of the class (0 for DC, 1 for
+/// AC) replaced.
+fn with_huffman_values(stream: &[u8], class: u8, value: u8) -> Vec<u8> {
+    let mut stream = stream.to_vec();
+    let mut start = 0;
+    while let Some(found) = stream[start..].windows(2).position(|marker| marker == [0xFF, 0xC4]) {
+        let segment = start + found;
+        let length = usize::from(u16::from_be_bytes([stream[segment + 2], stream[segment + 3]]));
+        if stream[segment + 4] >> 4 == class {
+            let values = stream[segment + 5..segment + 21].iter().map(|count| usize::from(*count)).sum::<usize>();
+            stream[segment + 21..segment + 21 + values].fill(value);
+        }
+        start = segment + 2 + length;
+    }
+    stream
+}
+
+
+#[test]
+fn sizes_past_the_sample_precision_are_decode_errors() {
+    let cases = [
+        (0, 0xF0, "DC difference category 240 is past 11"),
+        (0, 12, "DC difference category 12 is past 11"),
+        (1, 0x0B, "AC coefficient size 11 is past 10")
+    ];
+    for (class, value, expected) in cases.iter() {
+        let stream = with_huffman_values(BASELINE, *class, *value);
+        match jpeg_file(&stream, 8, "00.0").read_pixels(0, Rectangle::new(0, 0, ROWS, COLUMNS), &[0]) {
+            Err(NitfError::Decode { compression, message, .. }) => {
+                assert_eq!(compression, "C3");
+                assert_eq!(message, *expected);
+            },
+            other => panic!("a table of {:#04X} values read {:?}", value, other.map(|pixels| pixels.rows))
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! JPEG 2000 compressed images decode at full and reduced resolution
+
+mod common;
+
+use ossim_oxide::model::nitf::*;
+
+use common::{image_file, image_subheader, samples};
+
+const ROWS: u32 = 20;
+const COLUMNS: u32 = 24;
+
+/// Codestream of the gradient in 12 by 10 tiles, with two decomposition levels and two
+/// quality layers, compressed losslessly with the 5/3 wavelet.
+const REVERSIBLE: &[u8] = include_bytes!("data/gradient_53.j2k");
+
+/// Codestream holding the same tiles as REVERSIBLE in two tile-parts each, located by a TLM
+/// marker segment.
+const TILE_PARTS: &[u8] = include_bytes!("data/gradient_53_tlm.j2k");
+
+/// Codestream of the gradient in one tile, compressed with the 9/7 wavelet.
+const IRREVERSIBLE: &[u8] = include_bytes!("data/gradient_97.j2k");
+
+/// The level 1 LL band of each tile of REVERSIBLE, which is the image at a reduction of 1.
+const REDUCED: [[i64; 12]; 10] = [
+    [0, 8, 17, 28, 35, 41, 48, 59, 68, 73, 80, 90],
+    [10, 22, 33, 37, 49, 52, 65, 70, 81, 83, 94, 105],
+    [21, 35, 40, 48, 55, 65, 72, 82, 88, 93, 107, 113],
+    [33, 41, 51, 57, 65, 75, 80, 91, 100, 103, 113, 123],
+    [43, 56, 61, 70, 73, 87, 93, 102, 107, 114, 128, 134],
+    [50, 61, 73, 79, 89, 91, 106, 110, 123, 123, 133, 146],
+    [60, 74, 81, 85, 97, 104, 111, 121, 127, 133, 146, 153],
+    [72, 82, 92, 98, 106, 114, 123, 130, 141, 143, 154, 165],
+    [83, 95, 100, 110, 113, 127, 131, 143, 148, 153, 167, 173],
+    [92, 101, 110, 117, 125, 134, 142, 149, 157, 164, 173, 183]
+];
+
+/// Returns the sample of the gradient every codestream holds.
+fn gradient(row: u32, column: u32) -> i64 {
+    i64::from(row * 5 + column * 4 + (row * column) % 9)
+}
+
+
+/// Returns the gradient within the rectangle, row by row.
+fn expected(rectangle: Rectangle) -> Vec<i64> {
+    (rectangle.row..rectangle.row + rectangle.rows)
+        .flat_map(|row| (rectangle.column..rectangle.column + rectangle.columns).map(move |column| gradient(row, column)))
+        .collect()
+}
+
+
+/// Returns a file holding the codestream as its one image, with J2KLRA recording two levels
+/// and two layers when `layers` is set.
+fn jpeg2000_file(codestream: &[u8], layers: bool) -> NITF {
+    let mut subheader = image_subheader(ROWS, COLUMNS, 1, 8);
+    subheader.compression = ImageCompression::Jpeg2000;
+    subheader.compression_rate = "N001".to_string();
+    if layers {
+        subheader.tres.push(Tre {
+            tag: "J2KLRA".to_string(),
+            data: b"0020000100200000.50000000102.000000".to_vec(),
+            location: TreLocation::ImageExtended,
+            offset: 0
+        });
+    }
+    image_file(subheader, codestream.to_vec())
+}
+
+
+#[test]
+fn reversible_codestream_decodes_exactly() {
+    let nitf = jpeg2000_file(REVERSIBLE, false);
+    let everything = Rectangle::new(0, 0, ROWS, COLUMNS);
+    let pixels = nitf.read_pixels(0, everything, &[0]).unwrap();
+    assert_eq!(pixels.data.sample_type(), SampleType::U8);
+    assert_eq!(samples(&pixels), expected(everything));
+
+    // A region within the lower right tile, and one across all four tiles
+    for &region in &[Rectangle::new(12, 14, 6, 9), Rectangle::new(7, 5, 9, 13)] {
+        let options = Jpeg2000Options { reduction: 0, layers: None };
+        let pixels = nitf.read_jpeg2000_pixels(0, region, &[0], &options).unwrap();
+        assert_eq!(samples(&pixels), expected(region));
+    }
+}
+
+
+#[test]
+fn irreversible_codestream_decodes_within_a_tolerance() {
+    let nitf = jpeg2000_file(IRREVERSIBLE, false);
+    let everything = Rectangle::new(0, 0, ROWS, COLUMNS);
+    let pixels = nitf.read_pixels(0, everything, &[0]).unwrap();
+    let errors: Vec<i64> = samples(&pixels).iter().zip(expected(everything)).map(|(sample, expected)| (sample - expected).abs()).collect();
+    assert!(errors.iter().all(|error| *error <= 1), "errors {:?}", errors);
+}
+
+
+#[test]
+fn reduced_resolution_is_the_low_pass_band() {
+    let nitf = jpeg2000_file(REVERSIBLE, true);
+    let options = Jpeg2000Options { reduction: 1, layers: None };
+    let pixels = nitf.read_jpeg2000_pixels(0, Rectangle::new(0, 0, 10, 12), &[0], &options).unwrap();
+    let reduced: Vec<i64> = REDUCED.iter().flatten().copied().collect();
+    assert_eq!(samples(&pixels), reduced);
+
+    let pixels = nitf.read_jpeg2000_pixels(0, Rectangle::new(4, 5, 3, 4), &[0], &options).unwrap();
+    assert_eq!(samples(&pixels), [&REDUCED[4][5..9], &REDUCED[5][5..9], &REDUCED[6][5..9]].concat());
+
+    let options = Jpeg2000Options { reduction: 2, layers: None };
+    let pixels = nitf.read_jpeg2000_pixels(0, Rectangle::new(0, 0, 5, 6), &[0], &options).unwrap();
+    assert_eq!((pixels.rows, pixels.columns), (5, 6));
+}
+
+
+#[test]
+fn layers_are_limited_by_j2klra() {
+    let nitf = jpeg2000_file(REVERSIBLE, true);
+    let everything = Rectangle::new(0, 0, ROWS, COLUMNS);
+    let read = |reduction, layers| nitf.read_jpeg2000_pixels(0, everything, &[0], &Jpeg2000Options { reduction, layers });
+
+    assert_eq!(samples(&read(0, Some(2)).unwrap()), expected(everything));
+    let first_layer = samples(&read(0, Some(1)).unwrap());
+    assert_ne!(first_layer, expected(everything));
+    let error: i64 = first_layer.iter().zip(expected(everything)).map(|(sample, expected)| (sample - expected).abs()).sum();
+    assert!(error < 8 * i64::from(ROWS * COLUMNS), "mean error {}", error / i64::from(ROWS * COLUMNS));
+
+    for &(reduction, layers) in &[(0, Some(3)), (0, Some(0)), (3, None)] {
+        match read(reduction, layers) {
+            Err(NitfError::InvalidRequest { message }) => assert!(message.contains("J2KLRA"), "{}", message),
+            other => panic!("a reduction of {} with {:?} layers read {:?}", reduction, layers, other.map(|pixels| pixels.rows))
+        }
+    }
+}
+
+
+#[test]
+fn reduction_is_checked_against_the_codestream() {
+    let nitf = jpeg2000_file(REVERSIBLE, false);
+    let options = Jpeg2000Options { reduction: 3, layers: None };
+    match nitf.read_jpeg2000_pixels(0, Rectangle::new(0, 0, 1, 1), &[0], &options) {
+        Err(NitfError::InvalidRequest { .. }) => {},
+        other => panic!("a reduction past the decomposition levels read {:?}", other.map(|pixels| pixels.rows))
+    }
+}
+
+
+#[test]
+fn tile_parts_are_located_by_tlm() {
+    let nitf = jpeg2000_file(TILE_PARTS, false);
+    let options = Jpeg2000Options { reduction: 0, layers: None };
+    for &region in &[Rectangle::new(0, 0, ROWS, COLUMNS), Rectangle::new(11, 2, 8, 7)] {
+        let pixels = nitf.read_jpeg2000_pixels(0, region, &[0], &options).unwrap();
+        assert_eq!(samples(&pixels), expected(region));
+    }
+}
+
+
+/// Returns REVERSIBLE with its SIZ image size (Xsiz, Ysiz) and tile size (XTsiz, YTsiz)
+/// replaced.
+fn resized(width: u32, height: u32, tile_width: u32, tile_height: u32) -> Vec<u8> {
+    let mut codestream = REVERSIBLE.to_vec();
+    assert_eq!(codestream[2..4], [0xFF, 0x51]);
+    codestream[8..12].copy_from_slice(&width.to_be_bytes());
+    codestream[12..16].copy_from_slice(&height.to_be_bytes());
+    codestream[24..28].copy_from_slice(&tile_width.to_be_bytes());
+    codestream[28..32].copy_from_slice(&tile_height.to_be_bytes());
+    codestream
+}
+
+
+#[test]
+fn codestream_of_another_size_is_rejected() {
+    // Billions of one pixel tiles, and an image of 276 rows from one changed Ysiz byte
+    let codestreams = [
+        (resized(0xFFFF_FFF0, 0xFFFF_FFF0, 1, 1), "too many tiles"),
+        (resized(COLUMNS, ROWS + 0x100, 12, 10), "the codestream is 276 by 24 pixels but the image is 20 by 24")
+    ];
+    for (codestream, expected) in codestreams.iter() {
+        let nitf = jpeg2000_file(codestream, false);
+        match nitf.read_pixels(0, Rectangle::new(0, 0, 1, 1), &[0]) {
+            Err(NitfError::Decode { compression, message, .. }) => {
+                assert_eq!(compression, "C8");
+                assert!(message.contains(expected), "{}", message);
+            },
+            other => panic!("a codestream of the wrong size read {:?}", other.map(|pixels| pixels.rows))
+        }
+    }
+}
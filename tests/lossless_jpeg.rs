@@ -0,0 +1,71 @@
+//! Lossless JPEG compressed images decode exactly with each predictor
+
+mod common;
+
+use ossim_oxide::model::nitf::*;
+
+use common::{image_file, image_subheader, samples};
+
+const ROWS: u32 = 16;
+const COLUMNS: u32 = 24;
+
+/// Returns the 12 bit sample every stream codes.
+fn sample(row: u32, column: u32) -> i64 {
+    i64::from((row * 5 + column * 4 + (row * column) % 9) * 16 + column)
+}
+
+
+/// Returns a file holding the stream as a 12 bit image.
+fn lossless_file(stream: &[u8]) -> NITF {
+    let mut subheader = image_subheader(ROWS, COLUMNS, 1, 16);
+    subheader.actual_bits_per_pixel = 12;
+    subheader.compression = ImageCompression::LosslessJpeg;
+    image_file(subheader, stream.to_vec())
+}
+
+
+/// Returns the samples within the rectangle, row by row, with the low `point_transform`
+/// bits cleared.
+fn expected(rectangle: Rectangle, point_transform: u32) -> Vec<i64> {
+    (rectangle.row..rectangle.row + rectangle.rows)
+        .flat_map(|row| (rectangle.column..rectangle.column + rectangle.columns).map(move |column| sample(row, column) >> point_transform << point_transform))
+        .collect()
+}
+
+
+#[test]
+fn each_predictor_decodes_exactly() {
+    let streams: [(u8, &[u8]); 3] = [
+        (1, include_bytes!("data/lossless_predictor_1.jpg")),
+        (4, include_bytes!("data/lossless_predictor_4.jpg")),
+        (7, include_bytes!("data/lossless_predictor_7.jpg"))
+    ];
+    for (predictor, stream) in streams.iter() {
+        let nitf = lossless_file(stream);
+        for &rectangle in &[Rectangle::new(0, 0, ROWS, COLUMNS), Rectangle::new(5, 7, 6, 9)] {
+            let pixels = nitf.read_pixels(0, rectangle, &[0]).unwrap();
+            assert_eq!(pixels.data.sample_type(), SampleType::U16);
+            assert_eq!(samples(&pixels), expected(rectangle, 0), "predictor {}", predictor);
+        }
+    }
+}
+
+
+#[test]
+fn point_transform_clears_low_bits() {
+    // Predictor 4 coding the samples shifted right by 3 bits
+    let nitf = lossless_file(include_bytes!("data/lossless_point_transform.jpg"));
+    let everything = Rectangle::new(0, 0, ROWS, COLUMNS);
+    let pixels = nitf.read_pixels(0, everything, &[0]).unwrap();
+    assert_eq!(samples(&pixels), expected(everything, 3));
+}
+
+
+#[test]
+fn restart_within_a_row_resets_the_prediction() {
+    // Predictor 6 with a restart interval of 7 samples, so intervals start mid-row
+    let nitf = lossless_file(include_bytes!("data/lossless_restart.jpg"));
+    let everything = Rectangle::new(0, 0, ROWS, COLUMNS);
+    let pixels = nitf.read_pixels(0, everything, &[0]).unwrap();
+    assert_eq!(samples(&pixels), expected(everything, 0));
+}
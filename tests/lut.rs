@@ -0,0 +1,108 @@
+//! Look up tables map stored indices of pixel interleaved and RGB/LUT images
+
+mod common;
+
+use std::io::Cursor;
+
+use ossim_oxide::model::nitf::*;
+
+use common::{image_subheader, samples};
+
+const ROWS: u32 = 3;
+const COLUMNS: u32 = 4;
+
+/// Returns the stored index of a pixel of the band, where 6 is past the end of the six
+/// entry tables of band 0.
+fn stored(band: usize, row: u32, column: u32) -> u8 {
+    match band {
+        0 => ((row * COLUMNS + column) % 7) as u8,
+        _ => (row * 50 + column * 3) as u8
+    }
+}
+
+
+/// Returns the red, green and blue tables of six entries.
+fn color_tables() -> Vec<Vec<u8>> {
+    vec![
+        (0..6).map(|entry| entry * 40).collect(),
+        (0..6).map(|entry| 255 - entry * 40).collect(),
+        (0..6).map(|entry| entry * 7).collect()
+    ]
+}
+
+
+/// Returns a file holding an image of the given representation and bands with their tables,
+/// stored pixel interleaved.
+fn lut_file(representation: ImageRepresentation, bands: Vec<Band>) -> NITF {
+    let mut subheader = image_subheader(ROWS, COLUMNS, bands.len(), 8);
+    subheader.representation = representation;
+    subheader.bands = bands;
+    subheader.mode = ImageMode::Pixel;
+    let band_count = subheader.bands.len();
+    let mut pixels = PixelBuffer::new(ROWS, COLUMNS, (0..band_count).collect(), SampleType::U8);
+    pixels.data = PixelData::U8((0..band_count)
+        .flat_map(|band| (0..ROWS).flat_map(move |row| (0..COLUMNS).map(move |column| stored(band, row, column))))
+        .collect());
+    let mut writer = NitfWriter::new(FileHeader::default());
+    writer.add_image(subheader, &pixels).unwrap();
+    let mut bytes = Vec::new();
+    writer.write(&mut bytes).unwrap();
+    NITF::from_reader(Cursor::new(bytes)).unwrap()
+}
+
+
+/// Returns the stored indices of the band mapped through the table, None past its end.
+fn mapped(band: usize, table: &[u8]) -> Vec<Option<i64>> {
+    (0..ROWS)
+        .flat_map(|row| (0..COLUMNS).map(move |column| stored(band, row, column)))
+        .map(|entry| table.get(usize::from(entry)).map(|value| i64::from(*value)))
+        .collect()
+}
+
+
+/// Returns the samples of the buffer, None where they are invalid.
+fn valid_samples(pixels: &PixelBuffer) -> Vec<Option<i64>> {
+    samples(pixels).into_iter().enumerate()
+        .map(|(index, sample)| if pixels.is_valid(index) { Some(sample) } else { None })
+        .collect()
+}
+
+
+#[test]
+fn pixel_interleaved_bands_map_through_their_tables() {
+    let inverse: Vec<u8> = (0..=255).rev().collect();
+    let nitf = lut_file(ImageRepresentation::Multiband, vec![
+        Band { representation: "LU".to_string(), luts: color_tables(), ..Band::default() },
+        Band { representation: "M".to_string(), luts: vec![inverse.clone()], ..Band::default() }
+    ]);
+    let everything = Rectangle::new(0, 0, ROWS, COLUMNS);
+
+    let indices = nitf.read_lut_pixels(0, everything, &[1, 0], LutMode::Indices).unwrap();
+    let identity: Vec<u8> = (0..=255).collect();
+    assert_eq!(valid_samples(&indices), [mapped(1, &identity), mapped(0, &identity)].concat());
+
+    // The three tables of band 0 expand it to red, green and blue after the one of band 1
+    let pixels = nitf.read_lut_pixels(0, everything, &[1, 0], LutMode::Mapped).unwrap();
+    assert_eq!(pixels.bands, vec![1, 0, 0, 0]);
+    assert_eq!(pixels.data.sample_type(), SampleType::U8);
+    let tables = color_tables();
+    let expected = [mapped(1, &inverse), mapped(0, &tables[0]), mapped(0, &tables[1]), mapped(0, &tables[2])].concat();
+    assert_eq!(valid_samples(&pixels), expected);
+    assert!(expected.contains(&None));
+}
+
+
+#[test]
+fn rgb_lut_image_expands_to_three_bands() {
+    let nitf = lut_file(ImageRepresentation::RgbLut, vec![
+        Band { representation: "LU".to_string(), luts: color_tables(), ..Band::default() }
+    ]);
+    let pixels = nitf.read_lut_pixels(0, Rectangle::new(0, 0, ROWS, COLUMNS), &[0], LutMode::Mapped).unwrap();
+    assert_eq!(pixels.bands, vec![0, 0, 0]);
+    let expected: Vec<Option<i64>> = color_tables().iter().flat_map(|table| mapped(0, table)).collect();
+    assert_eq!(valid_samples(&pixels), expected);
+
+    // The index past the end of the tables is at row 1, column 2
+    let pixel = nitf.read_lut_pixels(0, Rectangle::new(1, 2, 1, 1), &[0], LutMode::Mapped).unwrap();
+    assert_eq!(valid_samples(&pixel), vec![None, None, None]);
+}
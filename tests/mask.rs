@@ -0,0 +1,115 @@
+//! Masked images locate blocks through the image data mask table
+
+mod common;
+
+use ossim_oxide::model::nitf::*;
+
+use common::{image_file, image_subheader, samples};
+
+const ROWS: u32 = 6;
+const COLUMNS: u32 = 8;
+
+/// Pad Output Pixel Code (TPXCD) of the image
+const PAD: u16 = 9999;
+
+/// Returns the sample of a pixel, which is the pad code at the lower right corner of the
+/// last block.
+fn sample(row: u32, column: u32) -> u16 {
+    match (row, column) {
+        (5, 7) => PAD,
+        _ => (1000 + row * 100 + column) as u16
+    }
+}
+
+
+/// Returns the big endian samples of the 3 by 4 block at the block row and column.
+fn block(block_row: u32, block_column: u32) -> Vec<u8> {
+    (block_row * 3..block_row * 3 + 3)
+        .flat_map(|row| (block_column * 4..block_column * 4 + 4).map(move |column| sample(row, column)))
+        .flat_map(|sample| sample.to_be_bytes().to_vec())
+        .collect()
+}
+
+
+/// Returns the mask table and blocks of the image in 2 by 2 blocks, with the blocks stored
+/// in reverse order after a gap of two bytes, block 1 not recorded and block 3 flagged as
+/// holding pad pixels.
+fn masked_data() -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(&46u32.to_be_bytes());
+    data.extend_from_slice(&4u16.to_be_bytes());
+    data.extend_from_slice(&4u16.to_be_bytes());
+    data.extend_from_slice(&16u16.to_be_bytes());
+    data.extend_from_slice(&PAD.to_be_bytes());
+    for offset in &[48, 0xFFFF_FFFF, 24, 0] {
+        data.extend_from_slice(&u32::to_be_bytes(*offset));
+    }
+    for offset in &[0xFFFF_FFFF, 0xFFFF_FFFF, 0xFFFF_FFFF, 0] {
+        data.extend_from_slice(&u32::to_be_bytes(*offset));
+    }
+    assert_eq!(data.len(), 44);
+    data.extend_from_slice(&[0, 0]);
+    for &(block_row, block_column) in &[(1, 1), (1, 0), (0, 0)] {
+        data.extend(block(block_row, block_column));
+    }
+    data
+}
+
+
+/// Returns a file holding the masked image.
+fn masked_file() -> NITF {
+    let mut subheader = image_subheader(ROWS, COLUMNS, 1, 16);
+    subheader.compression = ImageCompression::NotCompressedMasked;
+    subheader.blocks_per_row = 2;
+    subheader.blocks_per_column = 2;
+    subheader.pixels_per_block_horizontal = 4;
+    subheader.pixels_per_block_vertical = 3;
+    image_file(subheader, masked_data())
+}
+
+
+#[test]
+fn mask_table_records_each_block() {
+    let nitf = masked_file();
+    let table = nitf.mask_table(0).unwrap().unwrap();
+    assert_eq!(table, MaskTable {
+        data_offset: 46,
+        block_mask_record_length: 4,
+        pad_mask_record_length: 4,
+        pad_code_length: 16,
+        pad_code: Some(u64::from(PAD)),
+        block_offsets: vec![Some(48), None, Some(24), Some(0)],
+        pad_offsets: vec![None, None, None, Some(0)]
+    });
+    assert_eq!(table.block_offset(2, 24), Some(24));
+    assert_eq!(table.block_offset(1, 24), None);
+    assert!(table.has_pad_pixels(3) && !table.has_pad_pixels(0));
+}
+
+
+#[test]
+fn unrecorded_blocks_and_pad_pixels_read_as_the_pad_code() {
+    let nitf = masked_file();
+    let pixels = nitf.read_pixels(0, Rectangle::new(0, 0, ROWS, COLUMNS), &[0]).unwrap();
+    let values = samples(&pixels);
+    for row in 0..ROWS {
+        for column in 0..COLUMNS {
+            let index = pixels.index(0, row, column);
+            let missing = row < 3 && column >= 4;
+            match missing || (row, column) == (5, 7) {
+                true => {
+                    assert!(!pixels.is_valid(index), "pixel ({}, {}) is valid", row, column);
+                    assert_eq!(values[index], i64::from(PAD));
+                },
+                false => {
+                    assert!(pixels.is_valid(index), "pixel ({}, {}) is invalid", row, column);
+                    assert_eq!(values[index], i64::from(sample(row, column)));
+                }
+            }
+        }
+    }
+
+    assert!(nitf.read_block(0, 0, 1, &[0]).unwrap().is_none());
+    let block = nitf.read_block(0, 1, 0, &[0]).unwrap().unwrap();
+    assert_eq!(samples(&block)[..4], [1300, 1301, 1302, 1303]);
+}
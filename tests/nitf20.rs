@@ -0,0 +1,184 @@
+//! NITF 2.0 files read with their downgrading events, symbols and labels
+
+use std::io::Cursor;
+
+use ossim_oxide::model::nitf::*;
+
+/// Appends a field holding text padded with spaces to its length.
+fn text(bytes: &mut Vec<u8>, value: &str, length: usize) {
+    assert!(value.len() <= length, "\"{}\" is longer than {} characters", value, length);
+    bytes.extend_from_slice(format!("{:<1$}", value, length).as_bytes());
+}
+
+
+/// Appends a field holding a number padded with zeros to its length.
+fn number(bytes: &mut Vec<u8>, value: usize, length: usize) {
+    bytes.extend_from_slice(format!("{:01$}", value, length).as_bytes());
+}
+
+
+/// Appends the NITF 2.0 security fields, which end with the downgrading event when the
+/// downgrade (xSDWNG) is 999998.
+fn security(bytes: &mut Vec<u8>, classification: &str, downgrading: &str, event: &str) {
+    text(bytes, classification, 1);
+    text(bytes, "CODE", 40);
+    text(bytes, "HANDLING", 40);
+    text(bytes, "RELEASE", 40);
+    text(bytes, "AUTHORITY", 20);
+    text(bytes, "CONTROL", 20);
+    text(bytes, downgrading, 6);
+    if downgrading == "999998" {
+        text(bytes, event, 40);
+    }
+}
+
+
+/// Returns the subheader of a 3 by 4 image of eight bit pixels without coordinates.
+fn image_subheader() -> Vec<u8> {
+    let mut bytes = b"IM".to_vec();
+    text(&mut bytes, "IMAGE20", 10);
+    text(&mut bytes, "04123045ZJAN98", 14);
+    text(&mut bytes, "TARGET", 17);
+    text(&mut bytes, "Image title", 80);
+    security(&mut bytes, "U", "", "");
+    text(&mut bytes, "0", 1);
+    text(&mut bytes, "Source", 42);
+    number(&mut bytes, 3, 8);
+    number(&mut bytes, 4, 8);
+    for (value, length) in &[("INT", 3), ("MONO", 8), ("VIS", 8), ("08", 2), ("R", 1), ("N", 1), ("0", 1), ("NC", 2), ("1", 1)] {
+        text(&mut bytes, value, *length);
+    }
+    for (value, length) in &[("M", 2), ("", 6), ("N", 1), ("", 3), ("0", 1)] {
+        text(&mut bytes, value, *length);
+    }
+    for (value, length) in &[("0", 1), ("B", 1), ("0001", 4), ("0001", 4), ("0004", 4), ("0003", 4), ("08", 2),
+                             ("001", 3), ("000", 3), ("0000000000", 10), ("1.0", 4), ("00000", 5), ("00000", 5)] {
+        text(&mut bytes, value, *length);
+    }
+    bytes
+}
+
+
+/// Returns the subheader of a confidential 4 by 8 bitmap symbol with a three color look up
+/// table, to be downgraded by an event.
+fn symbol_subheader() -> Vec<u8> {
+    let mut bytes = b"SY".to_vec();
+    text(&mut bytes, "SYMBOL1", 10);
+    text(&mut bytes, "Arrow", 20);
+    security(&mut bytes, "C", "999998", "End of exercise");
+    for (value, length) in &[("0", 1), ("B", 1), ("0004", 4), ("0008", 4), ("0001", 4), ("1", 1), ("002", 3), ("001", 3),
+                             ("0001000020", 10), ("0000000000", 10), ("", 1), ("", 6), ("090", 3), ("003", 3)] {
+        text(&mut bytes, value, *length);
+    }
+    bytes.extend_from_slice(&[255, 0, 0, 0, 255, 0, 0, 0, 255]);
+    text(&mut bytes, "00000", 5);
+    bytes
+}
+
+
+/// Returns the subheader of a label with red text on a blue background.
+fn label_subheader() -> Vec<u8> {
+    let mut bytes = b"LA".to_vec();
+    text(&mut bytes, "LABEL1", 10);
+    security(&mut bytes, "U", "980101", "");
+    for (value, length) in &[("0", 1), ("R", 1), ("07", 2), ("12", 2), ("003", 3), ("001", 3), ("-0010-0020", 10)] {
+        text(&mut bytes, value, *length);
+    }
+    bytes.extend_from_slice(&[255, 0, 0, 0, 0, 255]);
+    text(&mut bytes, "00000", 5);
+    bytes
+}
+
+
+const ORIGINATOR: &str = "Originator name of 27 chars";
+
+/// Returns a NITF 2.0 file with one image, one symbol and one label, whose header is to be
+/// downgraded by an event.
+fn nitf20() -> Vec<u8> {
+    let segments = [
+        (image_subheader(), (0..12).collect::<Vec<u8>>()),
+        (symbol_subheader(), vec![0x81, 0x42, 0x24, 0x18]),
+        (label_subheader(), b"HELLO LABEL".to_vec())
+    ];
+    let header = |file_length: usize, header_length: usize| {
+        let mut bytes = Vec::new();
+        for (value, length) in &[("NITF", 4), ("02.00", 5), ("03", 2), ("BF01", 4), ("STATION", 10), ("15093000ZMAR99", 14),
+                                 ("File title", 80)] {
+            text(&mut bytes, value, *length);
+        }
+        security(&mut bytes, "U", "999998", "Event text");
+        for (value, length) in &[("00001", 5), ("00002", 5), ("0", 1), (ORIGINATOR, 27), ("555 0100", 18)] {
+            text(&mut bytes, value, *length);
+        }
+        number(&mut bytes, file_length, 12);
+        number(&mut bytes, header_length, 6);
+        // One image, symbol and label, with the length fields of each segment type
+        for (index, (subheader_length, data_length)) in [(6, 10), (4, 6), (4, 3)].iter().enumerate() {
+            number(&mut bytes, 1, 3);
+            number(&mut bytes, segments[index].0.len(), *subheader_length);
+            number(&mut bytes, segments[index].1.len(), *data_length);
+        }
+        // No text, data extension or reserved extension segments and no extensions
+        for (value, length) in &[("000", 3), ("000", 3), ("000", 3), ("00000", 5), ("00000", 5)] {
+            text(&mut bytes, value, *length);
+        }
+        bytes
+    };
+    let body: Vec<u8> = segments.iter().flat_map(|(subheader, data)| [&subheader[..], &data[..]].concat()).collect();
+    let header_length = header(0, 0).len();
+    [header(header_length + body.len(), header_length), body].concat()
+}
+
+
+#[test]
+fn header_reads_nitf20_fields() {
+    let bytes = nitf20();
+    let nitf = NITF::from_reader(Cursor::new(bytes.clone())).unwrap();
+    let header = nitf.file_header();
+    assert_eq!(header.version, "02.00");
+    assert_eq!(header.date_time, DateTime { year: 1999, month: 3, day: 15, hour: 9, minute: 30, second: 0 });
+    assert_eq!(header.security.downgrading, "999998");
+    assert_eq!(header.security.downgrading_event, "Event text");
+    assert_eq!(header.originator_name, ORIGINATOR);
+    assert_eq!(header.originator_phone, "555 0100");
+    assert_eq!(header.file_length, bytes.len() as u64);
+
+    // NUML takes the place of NUMX, counting the labels whose lengths follow it
+    assert_eq!(header.reserved_for_future_use, 0);
+    assert_eq!(header.label_segments, vec![SegmentLengths { subheader_length: label_subheader().len() as u64, data_length: 11 }]);
+    assert_eq!(header.graphic_segments, vec![SegmentLengths { subheader_length: symbol_subheader().len() as u64, data_length: 4 }]);
+    assert!(nitf.graphic_segments().is_empty());
+}
+
+
+#[test]
+fn segments_read_after_the_nitf20_header() {
+    let nitf = NITF::from_reader(Cursor::new(nitf20())).unwrap();
+
+    let image = &nitf.image_segments()[0].subheader;
+    assert_eq!(image.image_id, "IMAGE20");
+    assert_eq!(image.date_time, DateTime { year: 1998, month: 1, day: 4, hour: 12, minute: 30, second: 45 });
+    assert_eq!(image.coordinate_representation, None);
+    let pixels = nitf.read_pixels(0, Rectangle::new(1, 1, 2, 3), &[0]).unwrap();
+    assert_eq!(pixels.data, PixelData::U8(vec![5, 6, 7, 9, 10, 11]));
+
+    let symbol = &nitf.symbol_segments()[0];
+    assert_eq!(symbol.subheader.symbol_id, "SYMBOL1");
+    assert_eq!(symbol.subheader.security.classification, Classification::Confidential);
+    assert_eq!(symbol.subheader.security.downgrading_event, "End of exercise");
+    assert_eq!(symbol.subheader.symbol_type, SymbolType::Bitmap);
+    assert_eq!((symbol.subheader.lines, symbol.subheader.pixels_per_line, symbol.subheader.bits_per_pixel), (4, 8, 1));
+    assert_eq!((symbol.subheader.location, symbol.subheader.rotation), ((10, 20), 90));
+    assert_eq!(symbol.subheader.lut[2], Color { red: 0, green: 0, blue: 255 });
+    assert_eq!(&*nitf.segment_data(symbol).unwrap(), &[0x81, 0x42, 0x24, 0x18]);
+
+    let label = &nitf.label_segments()[0];
+    assert_eq!(label.subheader.label_id, "LABEL1");
+    assert_eq!(label.subheader.security.downgrading, "980101");
+    assert_eq!((label.subheader.cell_width, label.subheader.cell_height), (7, 12));
+    assert_eq!(label.subheader.location, (-10, -20));
+    assert_eq!(label.subheader.text_color, Color { red: 255, green: 0, blue: 0 });
+    assert_eq!(label.subheader.background_color, Color { red: 0, green: 0, blue: 255 });
+    assert_eq!(&*nitf.segment_data(label).unwrap(), b"HELLO LABEL");
+    assert_eq!(nitf.highest_classification(), Classification::Confidential);
+}
@@ -0,0 +1,68 @@
+//! Command line options of ossim-info
+
+#[path = "../src/apps/info_options.rs"]
+mod info_options;
+
+use info_options::Options;
+
+/// Parses the arguments given after the program name.
+fn parse(args: &[&str]) -> Result<Options, String> {
+    Options::parse(args.iter().map(|arg| arg.to_string()))
+}
+
+
+#[test]
+fn no_options_print_the_metadata() {
+    let options = parse(&["image.ntf"]).unwrap();
+    assert!(options.metadata && !options.dump && !options.help);
+    assert!(!options.uses_image());
+    assert_eq!(options.entry, 0);
+    assert_eq!(options.filenames, ["image.ntf"]);
+
+    // Asking for anything else leaves -m off unless it is given too
+    assert!(!parse(&["-d", "image.ntf"]).unwrap().metadata);
+    assert!(parse(&["-dm", "image.ntf"]).unwrap().metadata);
+}
+
+
+#[test]
+fn single_letter_options_combine() {
+    let options = parse(&["-ipc", "first.ntf", "second.ntf"]).unwrap();
+    assert!(options.image && options.projection && options.center);
+    assert!(!options.levels && !options.dump && !options.metadata);
+    assert!(options.uses_image());
+    assert_eq!(options.filenames, ["first.ntf", "second.ntf"]);
+
+    // A lone dash is a file name
+    assert_eq!(parse(&["-r", "-"]).unwrap().filenames, ["-"]);
+}
+
+
+#[test]
+fn entry_is_given_apart_or_after_an_equals_sign() {
+    assert_eq!(parse(&["-i", "--entry", "3", "image.ntf"]).unwrap().entry, 3);
+    let options = parse(&["--entry=12", "-i", "image.ntf"]).unwrap();
+    assert_eq!(options.entry, 12);
+    assert_eq!(options.filenames, ["image.ntf"]);
+}
+
+
+#[test]
+fn missing_or_non_numeric_entry_is_an_error() {
+    assert_eq!(parse(&["-i", "--entry"]).unwrap_err(), "--entry needs an image entry number");
+    assert_eq!(parse(&["--entry", "first", "image.ntf"]).unwrap_err(),
+               "--entry needs an image entry number but was given \"first\"");
+    assert_eq!(parse(&["--entry=-1", "image.ntf"]).unwrap_err(),
+               "--entry needs an image entry number but was given \"-1\"");
+    assert_eq!(parse(&["--entry=", "image.ntf"]).unwrap_err(),
+               "--entry needs an image entry number but was given \"\"");
+}
+
+
+#[test]
+fn unknown_options_are_errors() {
+    assert_eq!(parse(&["-ix", "image.ntf"]).unwrap_err(), "unknown option -x");
+    assert_eq!(parse(&["--verbose", "image.ntf"]).unwrap_err(), "unknown option --verbose");
+    assert!(parse(&["--help"]).unwrap().help);
+    assert!(parse(&["-h"]).unwrap().help);
+}
@@ -0,0 +1,197 @@
+//! Accuracy of the RPC00A and RPC00B sensor models
+
+mod common;
+
+use ossim_oxide::model::nitf::{NitfError, RpcModel, Tre, TreLocation};
+
+use common::{image_file, image_subheader};
+
+/// Line numerator, line denominator, sample numerator and sample denominator coefficients in
+/// RPC00B order, in the form of a pushbroom scene a few kilometers across with terrain
+/// relief and mild off nadir distortion.
+const COEFFICIENTS: [[f64; 20]; 4] = [
+    [1.2e-3, 1.523e-2, -1.031245, 2.1e-2, -3.2e-4, 1.5e-5, -2.4e-4, 1.1e-4, -5.3e-4, 2.0e-6,
+     3.1e-6, -1.2e-6, 4.5e-6, 7.0e-7, -2.2e-6, 5.1e-6, 1.3e-7, -6.0e-7, 2.4e-7, 1.0e-8],
+    [1.0, 1.2e-4, -3.1e-4, 2.2e-5, 1.0e-6, -2.0e-6, 3.0e-6, -1.5e-6, 2.5e-6, 1.0e-7,
+     0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [-2.4e-3, 1.012345, 3.4e-2, -1.8e-2, 2.7e-4, -1.1e-5, 3.3e-5, -4.2e-4, 1.9e-4, -3.0e-6,
+     -2.6e-6, 1.4e-6, -3.3e-6, 5.0e-7, 2.9e-6, -1.7e-6, 2.2e-7, 4.1e-7, -3.5e-7, 2.0e-8],
+    [1.0, -2.1e-4, 1.7e-4, -1.3e-5, 2.0e-6, 1.0e-6, -1.0e-6, 2.0e-6, -3.0e-6, 0.0,
+     0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]
+];
+
+/// Formats a coefficient as the 12 character +d.ddddddE+d form used by the RPC TREs.
+fn coefficient(value: f64) -> String {
+    let sign = if value < 0.0 { '-' } else { '+' };
+    if value == 0.0 {
+        return "+0.000000E+0".to_string();
+    }
+    let mut exponent = value.abs().log10().floor() as i32;
+    let mut mantissa = value.abs() / 10f64.powi(exponent);
+    if (mantissa * 1e6).round() >= 1e7 {
+        exponent += 1;
+        mantissa /= 10.0;
+    }
+    format!("{}{:.6}E{}{}", sign, mantissa, if exponent < 0 { '-' } else { '+' }, exponent.abs())
+}
+
+/// Returns an RPC TRE with the given tag and coefficients written in the tag's term order.
+fn rpc_tre(tag: &str, success: bool, coefficients: &[[f64; 20]; 4]) -> Tre {
+    let mut data = format!("{}{:07.2}{:07.2}{:06}{:05}{:+08.4}{:+09.4}{:+05}{:06}{:05}{:+08.4}{:+09.4}{:+05}",
+                           if success { 1 } else { 0 }, 1.5, 0.75, 8000, 9000, 35.05, -117.25, 500,
+                           8000, 9000, 0.07, 0.085, 600);
+    for polynomial in coefficients {
+        for value in polynomial {
+            data.push_str(&coefficient(*value));
+        }
+    }
+    assert_eq!(data.len(), 1041);
+    Tre {
+        tag: tag.to_string(),
+        data: data.into_bytes(),
+        location: TreLocation::ImageExtended,
+        offset: 0
+    }
+}
+
+/// Reorders RPC00B coefficients into RPC00A order.
+fn b_to_a_order(b: [f64; 20]) -> [f64; 20] {
+    let mut a = b;
+    a[7] = b[10];
+    a[8] = b[7];
+    a[9] = b[8];
+    a[10] = b[9];
+    a
+}
+
+/// Ground points as latitude, longitude and height with the image line and sample each
+/// projects to under COEFFICIENTS, evaluated in exact rational arithmetic from the RPC00B term
+/// order of STDI-0002 Appendix E, independently of RpcModel.
+const CHECK_POINTS: [[f64; 5]; 4] = [
+    [35.0, -117.2, 100.0, 13858.728518, 14227.249240],
+    [35.1, -117.3, 900.0, 2153.750332, 3729.454656],
+    [34.99, -117.33, -50.0, 14805.562477, 290.028426],
+    [35.11, -117.17, 1050.0, 1200.248571, 17667.868286]
+];
+
+fn model() -> RpcModel {
+    RpcModel::from_tre(&rpc_tre("RPC00B", true, &COEFFICIENTS)).unwrap().unwrap()
+}
+
+#[test]
+fn decodes_rpc00b_fields() {
+    let model = model();
+    assert_eq!(model.error_bias, 1.5);
+    assert_eq!(model.error_random, 0.75);
+    assert_eq!(model.line_offset, 8000.0);
+    assert_eq!(model.sample_offset, 9000.0);
+    assert_eq!(model.latitude_offset, 35.05);
+    assert_eq!(model.longitude_offset, -117.25);
+    assert_eq!(model.height_offset, 500.0);
+    assert_eq!(model.height_scale, 600.0);
+    assert_eq!(model.line_numerator, COEFFICIENTS[0]);
+    assert_eq!(model.sample_denominator, COEFFICIENTS[3]);
+}
+
+#[test]
+fn offsets_project_to_image_offsets() {
+    let model = model();
+    let (line, sample) = model.ground_to_image(35.05, -117.25, 500.0);
+    assert!((line - (8000.0 + 1.2e-3 * 8000.0)).abs() < 1e-9);
+    assert!((sample - (9000.0 - 2.4e-3 * 9000.0)).abs() < 1e-9);
+}
+
+#[test]
+fn linear_model_projects_exactly() {
+    let mut coefficients = [[0.0; 20]; 4];
+    coefficients[0][2] = -1.0;
+    coefficients[1][0] = 1.0;
+    coefficients[2][1] = 1.0;
+    coefficients[3][0] = 1.0;
+    let model = RpcModel::from_tre(&rpc_tre("RPC00B", true, &coefficients)).unwrap().unwrap();
+
+    let (line, sample) = model.ground_to_image(35.05 + 0.035, -117.25 - 0.0425, 0.0);
+    assert!((line - 4000.0).abs() < 1e-6);
+    assert!((sample - 4500.0).abs() < 1e-6);
+
+    let (latitude, longitude) = model.image_to_ground(4000.0, 4500.0, 0.0).unwrap();
+    assert!((latitude - 35.085).abs() < 1e-12);
+    assert!((longitude - -117.2925).abs() < 1e-12);
+}
+
+#[test]
+fn image_to_ground_round_trips() {
+    let model = model();
+    for &height in &[-100.0, 500.0, 1100.0] {
+        for line in (0..=16000).step_by(2000) {
+            for sample in (0..=18000).step_by(2250) {
+                let (latitude, longitude) = model.image_to_ground(line as f64, sample as f64, height).unwrap();
+                let (line_back, sample_back) = model.ground_to_image(latitude, longitude, height);
+                assert!((line_back - line as f64).abs() < 1e-6, "line {} sample {} height {}", line, sample, height);
+                assert!((sample_back - sample as f64).abs() < 1e-6, "line {} sample {} height {}", line, sample, height);
+            }
+        }
+    }
+}
+
+#[test]
+fn ground_to_image_round_trips() {
+    let model = model();
+    for step in 0..=10 {
+        let latitude = 35.05 - 0.07 + 0.014 * step as f64;
+        let longitude = -117.25 + 0.085 - 0.017 * step as f64;
+        let (line, sample) = model.ground_to_image(latitude, longitude, 250.0);
+        let (latitude_back, longitude_back) = model.image_to_ground(line, sample, 250.0).unwrap();
+        assert!((latitude_back - latitude).abs() < 1e-9);
+        assert!((longitude_back - longitude).abs() < 1e-9);
+    }
+}
+
+#[test]
+fn rpc00a_matches_rpc00b_after_reordering() {
+    let a_coefficients = [b_to_a_order(COEFFICIENTS[0]), b_to_a_order(COEFFICIENTS[1]),
+                          b_to_a_order(COEFFICIENTS[2]), b_to_a_order(COEFFICIENTS[3])];
+    let a = RpcModel::from_tre(&rpc_tre("RPC00A", true, &a_coefficients)).unwrap().unwrap();
+    let b = model();
+    assert_eq!(a, b);
+    assert_eq!(a.ground_to_image(35.0, -117.2, 800.0), b.ground_to_image(35.0, -117.2, 800.0));
+}
+
+#[test]
+fn unsuccessful_fit_has_no_model() {
+    assert_eq!(RpcModel::from_tre(&rpc_tre("RPC00B", false, &COEFFICIENTS)).unwrap(), None);
+}
+
+#[test]
+fn other_tags_are_rejected() {
+    let mut tre = rpc_tre("RPC00B", true, &COEFFICIENTS);
+    tre.tag = "BLOCKA".to_string();
+    assert!(RpcModel::from_tre(&tre).is_err());
+}
+
+#[test]
+fn check_points_project_within_a_micropixel() {
+    let model = model();
+    for &[latitude, longitude, height, line, sample] in &CHECK_POINTS {
+        let (line_projected, sample_projected) = model.ground_to_image(latitude, longitude, height);
+        assert!((line_projected - line).abs() < 1e-5, "line error {} at {}, {}", line_projected - line, latitude, longitude);
+        assert!((sample_projected - sample).abs() < 1e-5, "sample error {} at {}, {}", sample_projected - sample, latitude, longitude);
+
+        // A micropixel is well under 1e-9 degrees on the ground
+        let (latitude_back, longitude_back) = model.image_to_ground(line, sample, height).unwrap();
+        assert!((latitude_back - latitude).abs() < 1e-9, "latitude error {}", latitude_back - latitude);
+        assert!((longitude_back - longitude).abs() < 1e-9, "longitude error {}", longitude_back - longitude);
+    }
+}
+
+#[test]
+fn image_segment_gives_its_model() {
+    let mut subheader = image_subheader(1, 1, 1, 8);
+    subheader.tres.push(rpc_tre("RPC00B", true, &COEFFICIENTS));
+    let nitf = image_file(subheader, vec![0]);
+    assert_eq!(nitf.rpc_model(0).unwrap(), Some(model()));
+    match nitf.rpc_model(1) {
+        Err(NitfError::InvalidRequest { message }) => assert_eq!(message, "there is no image segment 1"),
+        other => panic!("expected InvalidRequest, got {:?}", other)
+    }
+}
@@ -0,0 +1,45 @@
+//! Security markings of headers and the classification of a file
+
+use std::io::Cursor;
+
+use ossim_oxide::model::nitf::*;
+
+#[test]
+fn marking_banner_joins_categories() {
+    let fields = SecurityFields {
+        classification: Classification::Secret,
+        classification_system: "US".to_string(),
+        codewords: "SI TK".to_string(),
+        control_and_handling: "NF".to_string(),
+        releasing_instructions: "US UK".to_string(),
+        ..SecurityFields::default()
+    };
+    let marking = SecurityMarking::from_fields(&fields);
+    assert_eq!(marking.codewords, vec!["SI", "TK"]);
+    assert_eq!(marking.banner(), "SECRET//SI/TK//NF//REL TO US, UK");
+    assert_eq!(marking.portion_mark(), "(S//SI/TK//NF//REL TO US, UK)");
+
+    let foreign = SecurityFields { classification: Classification::Confidential, classification_system: "UK".to_string(), ..SecurityFields::default() };
+    assert_eq!(SecurityMarking::from_fields(&foreign).to_string(), "//UK CONFIDENTIAL");
+    assert_eq!(SecurityMarking::from_fields(&SecurityFields::default()).banner(), "UNCLASSIFIED");
+}
+
+
+#[test]
+fn segment_above_file_classification_is_flagged() {
+    let confidential = SecurityFields { classification: Classification::Confidential, ..SecurityFields::default() };
+    let mut writer = NitfWriter::new(FileHeader::default());
+    writer.add_text(TextSubheader { security: confidential.clone(), ..TextSubheader::default() }, b"Note".to_vec());
+    let mut bytes = Vec::new();
+    writer.write(&mut bytes).unwrap();
+    let nitf = NITF::from_reader(Cursor::new(bytes)).unwrap();
+    assert_eq!(nitf.highest_classification(), Classification::Confidential);
+    assert!(nitf.is_under_classified());
+
+    let mut writer = NitfWriter::new(FileHeader { security: confidential.clone(), ..FileHeader::default() });
+    writer.add_text(TextSubheader { security: confidential, ..TextSubheader::default() }, b"Note".to_vec());
+    let mut bytes = Vec::new();
+    writer.write(&mut bytes).unwrap();
+    let nitf = NITF::from_reader(Cursor::new(bytes)).unwrap();
+    assert!(!nitf.is_under_classified());
+}
@@ -0,0 +1,39 @@
+//! Segments keep their file order, index and offsets however their subheaders are parsed
+
+mod common;
+
+use std::io::Cursor;
+
+use ossim_oxide::model::nitf::*;
+
+use common::{image_fields, nitf21, text_fields};
+
+#[test]
+fn segments_are_in_file_order_with_their_offsets() {
+    // Enough images of differing lengths that their subheaders are parsed on several threads
+    let images: Vec<(Vec<u8>, Vec<u8>)> = (0..32).map(|index| {
+        (image_fields(&format!("IMAGE{:02}", index), 1, index + 1, b"", b""), vec![index as u8; index + 1])
+    }).collect();
+    let texts = vec![(text_fields("FIRST"), b"First".to_vec()), (text_fields("SECOND"), b"Second note".to_vec())];
+    let bytes = nitf21(&images, &texts, b"", b"");
+    let header_length: u64 = String::from_utf8_lossy(&bytes[354..360]).parse().unwrap();
+    let nitf = NITF::from_reader(Cursor::new(bytes)).unwrap();
+
+    let mut offset = header_length;
+    for (index, segment) in nitf.image_segments().iter().enumerate() {
+        assert_eq!(segment.subheader.image_id, format!("IMAGE{:02}", index));
+        assert_eq!((segment.index, segment.offset), (index, offset));
+        assert_eq!((segment.subheader_length, segment.data_length), (images[index].0.len() as u64, index as u64 + 1));
+        assert_eq!(segment.data_offset(), offset + segment.subheader_length);
+        assert_eq!(&*nitf.segment_data(segment).unwrap(), &images[index].1[..]);
+        offset = segment.data_offset() + segment.data_length;
+    }
+    for (index, segment) in nitf.text_segments().iter().enumerate() {
+        assert_eq!((segment.index, segment.offset), (index, offset));
+        assert_eq!(&*nitf.segment_data(segment).unwrap(), &texts[index].1[..]);
+        offset = segment.data_offset() + segment.data_length;
+    }
+    let ids: Vec<&str> = nitf.text_segments().iter().map(|segment| segment.subheader.text_id.as_str()).collect();
+    assert_eq!(ids, ["FIRST", "SECOND"]);
+    assert_eq!(offset, nitf.file_header().file_length);
+}
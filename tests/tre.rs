@@ -0,0 +1,142 @@
+//! Tagged record extensions are kept as read from each header and decode into named
+//! fields through the built in definitions
+
+mod common;
+
+use std::io::Cursor;
+
+use ossim_oxide::model::nitf::*;
+
+use common::{image_fields, nitf21};
+
+/// BLOCKA of the first block of a 1000 line image, giving its four corners.
+const BLOCKA: &str = concat!("01", "00000", "01000", "   ", "   ", "                ",
+                             "+42.319331-073.299861", "+42.308452-073.285611", "+42.319331-073.285611", "+42.308452-073.299861",
+                             "     ");
+
+/// Returns an extension read from the image subheader at offset 1000.
+fn tre(tag: &str, data: &[u8]) -> Tre {
+    Tre {
+        tag: tag.to_string(),
+        data: data.to_vec(),
+        location: TreLocation::ImageExtended,
+        offset: 1000
+    }
+}
+
+
+/// Returns the value of the field at the given loop indices.
+fn value<'a>(fields: &'a [TreField], name: &str, indices: &[usize]) -> &'a TreValue {
+    &fields.iter().find(|field| field.name == name && field.indices == indices)
+        .unwrap_or_else(|| panic!("no {} at {:?}", name, indices))
+        .value
+}
+
+
+/// Returns the names of the decoded fields in file order.
+fn names(fields: &[TreField]) -> Vec<&str> {
+    fields.iter().map(|field| field.name.as_str()).collect()
+}
+
+
+#[test]
+fn blocka_decodes_its_corners() {
+    let fields = TreRegistry::new().decode(&tre("BLOCKA", BLOCKA.as_bytes())).unwrap().unwrap();
+    assert_eq!(names(&fields), ["BLOCK_INSTANCE", "N_GRAY", "L_LINES", "LAYOVER_ANGLE", "SHADOW_ANGLE", "RESERVED1",
+                                "FRLC_LOC", "LRLC_LOC", "LRFC_LOC", "FRFC_LOC", "RESERVED2"]);
+    assert_eq!(value(&fields, "BLOCK_INSTANCE", &[]), &TreValue::Integer(1));
+    assert_eq!(value(&fields, "N_GRAY", &[]), &TreValue::Text("00000".to_string()));
+    assert_eq!(value(&fields, "L_LINES", &[]), &TreValue::Integer(1000));
+    assert_eq!(value(&fields, "LAYOVER_ANGLE", &[]), &TreValue::Text(String::new()));
+    assert_eq!(value(&fields, "FRLC_LOC", &[]), &TreValue::Text("+42.319331-073.299861".to_string()));
+    assert_eq!(value(&fields, "FRFC_LOC", &[]), &TreValue::Text("+42.308452-073.299861".to_string()));
+}
+
+
+#[test]
+fn rpc00b_decodes_its_coefficient_loops() {
+    let mut data = concat!("1", "0001.50", "0000.75", "008000", "09000", "+35.0500", "-117.2500", "+0500",
+                           "008000", "09000", "+00.0700", "+000.0850", "+0600").to_string();
+    for polynomial in 0..4 {
+        for term in 0..20 {
+            data.push_str(&format!("+{}.{:06}E-{}", term % 10, polynomial * 20 + term, polynomial));
+        }
+    }
+    let fields = TreRegistry::new().decode(&tre("RPC00B", data.as_bytes())).unwrap().unwrap();
+    assert_eq!(fields.len(), 13 + 80);
+    assert_eq!(value(&fields, "SUCCESS", &[]), &TreValue::Integer(1));
+    assert_eq!(value(&fields, "ERR_BIAS", &[]), &TreValue::Real(1.5));
+    assert_eq!(value(&fields, "LINE_OFF", &[]), &TreValue::Integer(8000));
+    assert_eq!(value(&fields, "LONG_OFF", &[]), &TreValue::Real(-117.25));
+    assert_eq!(value(&fields, "HEIGHT_SCALE", &[]), &TreValue::Integer(600));
+
+    // Each polynomial is a loop of twenty terms indexed from zero
+    assert_eq!(value(&fields, "LINE_NUM_COEFF", &[0]), &TreValue::Real(0.0));
+    assert_eq!(value(&fields, "LINE_NUM_COEFF", &[13]), &TreValue::Real(3.000013));
+    assert_eq!(value(&fields, "LINE_DEN_COEFF", &[19]), &TreValue::Real(9.000039e-1));
+    assert_eq!(value(&fields, "SAMP_NUM_COEFF", &[1]), &TreValue::Real(1.000041e-2));
+    assert_eq!(value(&fields, "SAMP_DEN_COEFF", &[19]), &TreValue::Real(9.000079e-3));
+}
+
+
+#[test]
+fn loop_counts_and_conditions_come_from_earlier_fields() {
+    let registry = TreRegistry::new();
+
+    // Two layers of an original codestream, which has no _I fields
+    let fields = registry.decode(&tre("J2KLRA", concat!("0", "05", "00003", "002", "000", "00.500000", "001", "02.000000").as_bytes())).unwrap().unwrap();
+    assert_eq!(names(&fields), ["ORIG", "NLEVELS_O", "NBANDS_O", "NLAYERS_O", "LAYER_ID", "BITRATE", "LAYER_ID", "BITRATE"]);
+    assert_eq!(value(&fields, "LAYER_ID", &[1]), &TreValue::Integer(1));
+    assert_eq!(value(&fields, "BITRATE", &[1]), &TreValue::Real(2.0));
+
+    // One layer of a parsed codestream, followed by the fields the ORIG=1 condition adds
+    let fields = registry.decode(&tre("J2KLRA", concat!("1", "05", "00003", "001", "000", "00.500000", "03", "00004", "102").as_bytes())).unwrap().unwrap();
+    assert_eq!(names(&fields), ["ORIG", "NLEVELS_O", "NBANDS_O", "NLAYERS_O", "LAYER_ID", "BITRATE",
+                                "NLEVELS_I", "NBANDS_I", "NLAYERS_I"]);
+    assert_eq!(value(&fields, "NLEVELS_I", &[]), &TreValue::Integer(3));
+    assert_eq!(value(&fields, "NBANDS_I", &[]), &TreValue::Integer(4));
+    assert_eq!(value(&fields, "NLAYERS_I", &[]), &TreValue::Integer(102));
+}
+
+
+#[test]
+fn cel_disagreeing_with_the_definition_is_a_length_mismatch() {
+    let registry = TreRegistry::new();
+
+    // A fixed length definition reports the CEL against the length it requires
+    match registry.decode(&tre("BLOCKA", &BLOCKA.as_bytes()[..122])) {
+        Err(NitfError::LengthMismatch { field, offset, expected, actual }) => assert_eq!((field.as_str(), offset, expected, actual), ("CEL", 1006, 122, 123)),
+        other => panic!("a short BLOCKA decoded as {:?}", other)
+    }
+
+    // A variable length one reports the CEL against the bytes its items consumed
+    match registry.decode(&tre("J2KLRA", concat!("0", "05", "00003", "001", "000", "00.500000", " ").as_bytes())) {
+        Err(NitfError::LengthMismatch { field, offset, expected, actual }) => assert_eq!((field.as_str(), offset, expected, actual), ("CEL", 1006, 24, 23)),
+        other => panic!("a long J2KLRA decoded as {:?}", other)
+    }
+}
+
+
+#[test]
+fn repeated_and_binary_extensions_are_kept_as_read() {
+    let binary = [0x00, 0xFF, 0x80, b'\n', 0xC3];
+    let user_defined = [common::tre("TESTA", b"first"), common::tre("BINARY", &binary)].concat();
+    let image = image_fields("IMAGE", 1, 1, &common::tre("TESTA", b"image"), &common::tre("TESTA", b"extended"));
+    let bytes = nitf21(&[(image, vec![0])], &[], &user_defined, &common::tre("TESTA", b"second"));
+    let offset = |data: &[u8]| (bytes.windows(data.len()).position(|window| window == data).unwrap() - 11) as u64;
+    let nitf = NITF::from_reader(Cursor::new(bytes.clone())).unwrap();
+
+    // Every occurrence in file order, whichever area it is in
+    let header = nitf.file_header();
+    assert_eq!(header.find_tres("TESTA"), [
+        &Tre { tag: "TESTA".to_string(), data: b"first".to_vec(), location: TreLocation::FileUserDefined, offset: offset(b"first") },
+        &Tre { tag: "TESTA".to_string(), data: b"second".to_vec(), location: TreLocation::FileExtended, offset: offset(b"second") }
+    ]);
+    assert_eq!(header.find_tres("BINARY")[0].data, binary);
+    assert!(header.find_tres("TESTB").is_empty());
+
+    let image = &nitf.image_segments()[0].subheader;
+    let found: Vec<(TreLocation, &[u8])> = image.find_tres("TESTA").iter().map(|tre| (tre.location, &tre.data[..])).collect();
+    assert_eq!(found, [(TreLocation::ImageUserDefined, &b"image"[..]), (TreLocation::ImageExtended, &b"extended"[..])]);
+    assert_eq!(image.find_tres("TESTA")[1].offset, offset(b"extended"));
+}
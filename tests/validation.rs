@@ -0,0 +1,91 @@
+//! Conformance findings for files that read but break the standard
+
+use std::io::Cursor;
+
+use ossim_oxide::model::nitf::*;
+
+/// Writes a file and returns it read back.
+fn written(writer: &NitfWriter) -> NITF {
+    let mut bytes = Vec::new();
+    writer.write(&mut bytes).unwrap();
+    NITF::from_reader(Cursor::new(bytes)).unwrap()
+}
+
+
+#[test]
+fn findings_locate_each_problem() {
+    let file_header = FileHeader {
+        complexity_level: 5,
+        originating_station_id: "OSS\tIM".to_string(),
+        tres: vec![Tre { tag: "BLOCKA".to_string(), data: b"short".to_vec(), location: TreLocation::FileUserDefined, offset: 0 }],
+        ..FileHeader::default()
+    };
+    let mut writer = NitfWriter::new(file_header);
+    writer.add_text(TextSubheader { encryption: 1, ..TextSubheader::default() }, b"Note".to_vec());
+    let nitf = written(&writer);
+    assert_eq!(required_complexity_level(&nitf), 3);
+
+    let findings = validate(&nitf);
+    let located: Vec<(Severity, &str)> = findings.iter().map(|finding| (finding.severity, finding.field.as_str())).collect();
+    assert_eq!(located, vec![
+        (Severity::Warning, "CLEVEL"),
+        (Severity::Error, "OSTAID"),
+        (Severity::Error, "BLOCKA"),
+        (Severity::Error, "ENCRYP")
+    ]);
+    assert_eq!(findings[0].offset, 9);
+    assert_eq!(findings[1].offset, 15);
+    assert_eq!(findings[0].to_string(), "warning: CLEVEL at offset 9 is 05, higher than the 03 the file requires");
+}
+
+
+#[test]
+fn length_mismatches_are_found_with_the_other_findings() {
+    let file_header = FileHeader {
+        originating_station_id: "OSS\tIM".to_string(),
+        tres: vec![Tre { tag: "BLOCKA".to_string(), data: b"short".to_vec(), location: TreLocation::FileUserDefined, offset: 0 }],
+        ..FileHeader::default()
+    };
+    let mut writer = NitfWriter::new(file_header);
+    writer.add_text(TextSubheader { encryption: 1, ..TextSubheader::default() }, b"Note".to_vec());
+    let mut bytes = Vec::new();
+    let length = writer.write(&mut bytes).unwrap();
+    let header_length: u64 = String::from_utf8_lossy(&bytes[354..360]).parse().unwrap();
+    bytes[342..354].copy_from_slice(format!("{:012}", length + 7).as_bytes());
+    bytes[354..360].copy_from_slice(format!("{:06}", header_length - 2).as_bytes());
+
+    match NITF::from_reader(Cursor::new(bytes.clone())) {
+        Err(NitfError::LengthMismatch { field, .. }) => assert_eq!(field, "FL"),
+        other => panic!("unexpected result {:?}", other.map(|_| ()))
+    }
+
+    let nitf = NITF::from_reader_lenient(Cursor::new(bytes.clone())).unwrap();
+    assert_eq!(nitf.length_mismatches().len(), 2);
+    assert_eq!(nitf.read_text(0).unwrap(), "Note");
+    let findings = validate(&nitf);
+    let located: Vec<(Severity, &str)> = findings.iter().map(|finding| (finding.severity, finding.field.as_str())).collect();
+    assert_eq!(located, vec![
+        (Severity::Error, "OSTAID"),
+        (Severity::Error, "FL"),
+        (Severity::Error, "HL"),
+        (Severity::Error, "BLOCKA"),
+        (Severity::Error, "ENCRYP")
+    ]);
+    assert_eq!((findings[1].offset, findings[2].offset), (342, 354));
+    assert_eq!(findings[1].message, format!("FL at offset 342 is {} but the actual length is {}", length + 7, length));
+    assert_eq!(findings[2].message, format!("HL at offset 354 is {} but the actual length is {}", header_length - 2, header_length));
+}
+
+
+#[test]
+fn undefined_complexity_level_is_an_error() {
+    let writer = NitfWriter::new(FileHeader { complexity_level: 4, ..FileHeader::default() });
+    let nitf = written(&writer);
+    let findings = validate(&nitf);
+    assert_eq!(findings.len(), 1);
+    assert_eq!((findings[0].severity, findings[0].field.as_str()), (Severity::Error, "CLEVEL"));
+
+    let writer = NitfWriter::new(FileHeader::default());
+    let nitf = written(&writer);
+    assert_eq!(validate(&nitf), Vec::new());
+}
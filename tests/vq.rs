@@ -0,0 +1,96 @@
+//! Vector quantized images decode kernel by kernel through the codebook
+
+mod common;
+
+use ossim_oxide::model::nitf::*;
+
+use common::{image_file, image_subheader, samples};
+
+/// Codes of the two blocks of the image, each two rows of three 4 by 4 kernels.
+const CODES: [[u16; 6]; 2] = [[0, 1, 2, 2, 0, 1], [1, 1, 0, 2, 2, 3]];
+
+/// Returns the value of the codebook at the row and column of the kernel of `code`, which
+/// tells the code, kernel row and kernel column apart.
+fn kernel_value(code: u16, row: u16, column: u16) -> u8 {
+    (code * 16 + row * 4 + column) as u8
+}
+
+
+/// Returns the VQ header of 4 by 4 kernels and four codes of 12 bits, in blocks of two rows
+/// of three codes. The codebook holds one lookup table per kernel row, each holding that row
+/// of the kernel of every code in turn.
+fn vq_header() -> Vec<u8> {
+    let mut header = Vec::new();
+    // Image display parameters: code rows and columns per block and code bits
+    header.extend_from_slice(&2u32.to_be_bytes());
+    header.extend_from_slice(&3u32.to_be_bytes());
+    header.push(12);
+    // Compression section: vector quantization with four lookup tables
+    header.extend_from_slice(&[0, 1, 0, 4, 0, 0]);
+    // Compression lookup subsection, with the records just after its offset record
+    header.extend_from_slice(&6u32.to_be_bytes());
+    header.extend_from_slice(&14u16.to_be_bytes());
+    let tables_offset = 6 + 4 * 14;
+    for table in 0..4u32 {
+        header.extend_from_slice(&(table as u16 + 1).to_be_bytes());
+        header.extend_from_slice(&4u32.to_be_bytes());
+        header.extend_from_slice(&4u16.to_be_bytes());
+        header.extend_from_slice(&8u16.to_be_bytes());
+        header.extend_from_slice(&(tables_offset + table * 16).to_be_bytes());
+    }
+    for row in 0..4 {
+        for code in 0..4 {
+            header.extend((0..4).map(|column| kernel_value(code, row, column)));
+        }
+    }
+    header
+}
+
+
+/// Returns the codes of a block packed 12 bits each, most significant bit first.
+fn packed(codes: &[u16; 6]) -> Vec<u8> {
+    codes.chunks(2)
+        .flat_map(|pair| vec![(pair[0] >> 4) as u8, ((pair[0] & 0xF) << 4 | pair[1] >> 8) as u8, pair[1] as u8])
+        .collect()
+}
+
+
+/// Returns a file holding an image of the two blocks of codes side by side.
+fn vq_file(codes: &[[u16; 6]; 2]) -> NITF {
+    let mut subheader = image_subheader(8, 24, 1, 8);
+    subheader.compression = ImageCompression::VectorQuantization;
+    subheader.compression_rate = "2.00".to_string();
+    subheader.blocks_per_row = 2;
+    subheader.pixels_per_block_horizontal = 12;
+    image_file(subheader, [vq_header(), packed(&codes[0]), packed(&codes[1])].concat())
+}
+
+
+#[test]
+fn kernels_fill_each_block_row_by_row() {
+    let nitf = vq_file(&CODES);
+    let pixels = nitf.read_pixels(0, Rectangle::new(0, 0, 8, 24), &[0]).unwrap();
+    let expected: Vec<i64> = (0..8u16)
+        .flat_map(|row| (0..24u16).map(move |column| {
+            let block = &CODES[usize::from(column / 12)];
+            let code = block[usize::from(row / 4 * 3 + column % 12 / 4)];
+            i64::from(kernel_value(code, row % 4, column % 4))
+        }))
+        .collect();
+    assert_eq!(samples(&pixels), expected);
+
+    // The first row of the second kernel row of the second block: codes 2, 2 and 3
+    let row = nitf.read_pixels(0, Rectangle::new(4, 12, 1, 12), &[0]).unwrap();
+    assert_eq!(samples(&row), vec![32, 33, 34, 35, 32, 33, 34, 35, 48, 49, 50, 51]);
+}
+
+
+#[test]
+fn codes_outside_the_codebook_fail() {
+    let nitf = vq_file(&[CODES[0], [0, 1, 2, 3, 4, 0]]);
+    assert!(nitf.read_pixels(0, Rectangle::new(0, 0, 4, 12), &[0]).is_ok());
+    match nitf.read_pixels(0, Rectangle::new(0, 12, 4, 12), &[0]) {
+        Err(NitfError::Decode { message, .. }) => assert!(message.contains("code 4"), "{}", message),
+        other => panic!("a code outside the codebook read {:?}", other.map(|pixels| pixels.rows))
+    }
+}
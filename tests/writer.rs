@@ -0,0 +1,254 @@
+//! Files written by NitfWriter read back with the same headers and pixels
+
+use std::io::Cursor;
+
+use ossim_oxide::model::nitf::*;
+
+/// Returns the subheader of a two band, 16 bit image of 5 by 7 pixels in blocks of 4 by 4,
+/// so the blocks in the last row and column hold pad pixels.
+fn image_subheader() -> ImageSubheader {
+    ImageSubheader {
+        image_id: "CHIP".to_string(),
+        date_time: DateTime { year: 2021, month: 3, day: 4, hour: 5, minute: 6, second: 7 },
+        target_id: String::new(),
+        image_title: "Derived chip".to_string(),
+        security: SecurityFields::default(),
+        encryption: 0,
+        source: "Test".to_string(),
+        rows: 5,
+        columns: 7,
+        pixel_value_type: PixelValueType::Integer,
+        representation: ImageRepresentation::Multiband,
+        category: "MS".to_string(),
+        actual_bits_per_pixel: 11,
+        pixel_justification: PixelJustification::Left,
+        coordinate_representation: Some(CoordinateRepresentation::Geographic),
+        geographic_location: "350000N1171500W350000N1171400W345900N1171400W345900N1171500W".to_string(),
+        comments: vec!["First comment".to_string()],
+        compression: ImageCompression::NotCompressed,
+        compression_rate: String::new(),
+        bands: vec![
+            Band { representation: "M".to_string(), filter_condition: "N".to_string(), ..Band::default() },
+            Band { representation: "M".to_string(), filter_condition: "N".to_string(), luts: vec![vec![3; 8]], ..Band::default() }
+        ],
+        sync_code: 0,
+        mode: ImageMode::Row,
+        blocks_per_row: 2,
+        blocks_per_column: 2,
+        pixels_per_block_horizontal: 4,
+        pixels_per_block_vertical: 4,
+        bits_per_pixel: 16,
+        display_level: 1,
+        attachment_level: 0,
+        location: (0, 0),
+        magnification: "1.0".to_string(),
+        user_defined_overflow: 0,
+        extended_overflow: 0,
+        tres: vec![Tre { tag: "TESTA".to_string(), data: b"image data".to_vec(), location: TreLocation::ImageExtended, offset: 0 }]
+    }
+}
+
+
+/// Clears the file offsets of tagged record extensions, which are only known once written.
+fn without_offsets(tres: &[Tre]) -> Vec<Tre> {
+    tres.iter().cloned().map(|tre| Tre { offset: 0, ..tre }).collect()
+}
+
+
+#[test]
+fn written_file_reads_back() {
+    let file_header = FileHeader {
+        originating_station_id: "OSSIM".to_string(),
+        title: "Written".to_string(),
+        tres: vec![Tre { tag: "TESTB".to_string(), data: b"file data".to_vec(), location: TreLocation::FileUserDefined, offset: 0 }],
+        ..FileHeader::default()
+    };
+
+    let subheader = image_subheader();
+    let mut pixels = PixelBuffer::new(5, 7, vec![0, 1], SampleType::U16);
+    for index in 0..pixels.data.len() {
+        if let PixelData::U16(data) = &mut pixels.data {
+            data[index] = (index as u16 * 37) % 2048;
+        }
+    }
+
+    let mut writer = NitfWriter::new(file_header);
+    writer.add_image(subheader.clone(), &pixels).unwrap();
+    let graphic = GraphicSubheader {
+        graphic_id: "ARROW".to_string(),
+        display_level: 2,
+        location: (1, 2),
+        second_bound: (4, 6),
+        tres: vec![Tre { tag: "TESTC".to_string(), data: b"graphic data".to_vec(), location: TreLocation::GraphicExtended, offset: 0 }],
+        ..GraphicSubheader::default()
+    };
+    writer.add_graphic(graphic.clone(), b"CGM".to_vec());
+    let text = TextSubheader {
+        text_id: "NOTE".to_string(),
+        date_time: DateTime { year: 2021, month: 3, day: 5, hour: 0, minute: 0, second: 0 },
+        title: "Mission note".to_string(),
+        format: TextFormat::ExtendedCharacters,
+        ..TextSubheader::default()
+    };
+    writer.add_text(text.clone(), b"Chip of sc\xE8ne 12\r\n".to_vec());
+    let data_extension = DesSubheader { des_id: "TEST_DES".to_string(), user_defined: b"fields".to_vec(), ..DesSubheader::default() };
+    writer.add_data_extension(data_extension.clone(), b"extension".to_vec());
+    let reserved_extension = ResSubheader { res_id: "TEST_RES".to_string(), user_defined: b"res fields".to_vec(), ..ResSubheader::default() };
+    writer.add_reserved_extension(reserved_extension.clone(), b"reserved".to_vec());
+
+    let mut bytes = Vec::new();
+    let length = writer.write(&mut bytes).unwrap();
+    assert_eq!(length, bytes.len() as u64);
+
+    let nitf = NITF::from_reader(Cursor::new(bytes)).unwrap();
+    let mut expected = writer.file_header().unwrap();
+    expected.tres = without_offsets(&expected.tres);
+    let mut file_header = nitf.file_header().clone();
+    file_header.tres = without_offsets(&file_header.tres);
+    assert_eq!(file_header, expected);
+
+    let segment = &nitf.image_segments()[0];
+    let mut read_subheader = segment.subheader.clone();
+    read_subheader.tres = without_offsets(&read_subheader.tres);
+    assert_eq!(read_subheader, subheader);
+    assert_eq!(segment.data_length, 2 * 2 * 4 * 4 * 2 * 2);
+    let read = nitf.read_pixels(0, Rectangle::new(0, 0, 5, 7), &[0, 1]).unwrap();
+    assert_eq!(read.data, pixels.data);
+
+    let mut read_graphic = nitf.graphic_segments()[0].subheader.clone();
+    read_graphic.tres = without_offsets(&read_graphic.tres);
+    assert_eq!(read_graphic, graphic);
+    assert_eq!(nitf.text_segments()[0].subheader, text);
+    assert_eq!(nitf.read_text(0).unwrap(), "Chip of sc\u{E8}ne 12\r\n");
+    assert_eq!(nitf.data_ext_segments()[0].subheader, data_extension);
+    assert_eq!(&*nitf.segment_data(&nitf.data_ext_segments()[0]).unwrap(), b"extension");
+    assert_eq!(nitf.res_segments()[0].subheader, reserved_extension);
+    assert_eq!(&*nitf.segment_data(&nitf.res_segments()[0]).unwrap(), b"reserved");
+}
+
+
+#[test]
+fn segments_must_account_for_the_file_length() {
+    let mut writer = NitfWriter::new(FileHeader::default());
+    writer.add_reserved_extension(ResSubheader { res_id: "TEST_RES".to_string(), ..ResSubheader::default() }, b"reserved".to_vec());
+    let mut bytes = Vec::new();
+    let length = writer.write(&mut bytes).unwrap();
+
+    // Trailing bytes that no segment holds, with FL matching the file
+    bytes.extend_from_slice(b"xyz");
+    bytes[342..354].copy_from_slice(format!("{:012}", length + 3).as_bytes());
+    match NITF::from_reader(Cursor::new(bytes)) {
+        Err(NitfError::LengthMismatch { field, offset, expected, actual }) => {
+            assert_eq!((field.as_str(), offset, expected, actual), ("FL", 342, length + 3, length));
+        },
+        other => panic!("unexpected result {:?}", other.map(|_| ()))
+    }
+}
+
+
+#[test]
+fn pixels_must_match_the_image() {
+    let mut writer = NitfWriter::new(FileHeader::default());
+    let pixels = PixelBuffer::new(5, 7, vec![0], SampleType::U16);
+    assert!(matches!(writer.add_image(image_subheader(), &pixels), Err(NitfError::InvalidRequest { .. })));
+    let pixels = PixelBuffer::new(5, 7, vec![0, 1], SampleType::U8);
+    assert!(matches!(writer.add_image(image_subheader(), &pixels), Err(NitfError::InvalidRequest { .. })));
+}
+
+
+#[test]
+fn overflow_extensions_join_their_segment() {
+    let mut writer = NitfWriter::new(FileHeader::default());
+    writer.add_text(TextSubheader { text_id: "NOTE".to_string(), ..TextSubheader::default() }, b"Note".to_vec());
+    let overflow = DesSubheader {
+        des_id: "TRE_OVERFLOW".to_string(),
+        overflow: Some((TreLocation::TextExtended, 1)),
+        ..DesSubheader::default()
+    };
+    writer.add_data_extension(overflow, b"TESTD 00004more".to_vec());
+    let mut bytes = Vec::new();
+    writer.write(&mut bytes).unwrap();
+
+    let nitf = NITF::from_reader(Cursor::new(bytes)).unwrap();
+    let tres = nitf.text_segments()[0].subheader.find_tres("TESTD");
+    assert_eq!(tres.len(), 1);
+    assert_eq!(tres[0].data, b"more");
+    assert_eq!(tres[0].location, TreLocation::Overflow);
+}
+
+
+/// Returns the user defined subheader fields of an XML_DATA_CONTENT segment, each padded
+/// with spaces to its length, up to DESSHTN for 283 bytes or DESSHABS for 773.
+fn xml_fields(crc: &str, length: usize) -> Vec<u8> {
+    let fields = [(crc, 5), ("XML", 8), ("2021-03-04T05:06:07Z", 20), ("Responsible party", 40), ("SPEC-ID", 60),
+                  ("1.0", 10), ("2020-01-01T00:00:00Z", 20), ("urn:example:spec", 120), ("POLYGON((0 0,1 0,1 1,0 0))", 125),
+                  ("POINT(0.5 0.5)", 25), ("AREA 51", 20), ("urn:example:locations", 120), ("A test document", 200)];
+    let bytes: Vec<u8> = fields.iter().flat_map(|(value, length)| format!("{:<1$}", value, length).into_bytes()).collect();
+    bytes[..length].to_vec()
+}
+
+
+/// Returns the subheader of an XML_DATA_CONTENT segment with the given user defined fields.
+fn xml_subheader(user_defined: Vec<u8>) -> DesSubheader {
+    DesSubheader { des_id: "XML_DATA_CONTENT".to_string(), user_defined, ..DesSubheader::default() }
+}
+
+
+#[test]
+fn xml_data_content_reads_back() {
+    let mut writer = NitfWriter::new(FileHeader::default());
+    writer.add_data_extension(xml_subheader(xml_fields("12345", 283)), b"<a>283</a>".to_vec());
+    writer.add_data_extension(xml_subheader(xml_fields("99999", 773)), "<a>\u{E8}</a>".as_bytes().to_vec());
+    writer.add_data_extension(xml_subheader(Vec::new()), b"<a>\xE8</a>".to_vec());
+    writer.add_data_extension(DesSubheader { des_id: "TEST_DES".to_string(), ..DesSubheader::default() }, b"<a/>".to_vec());
+    let mut bytes = Vec::new();
+    writer.write(&mut bytes).unwrap();
+    let nitf = NITF::from_reader(Cursor::new(bytes)).unwrap();
+    let segments = nitf.data_ext_segments();
+
+    // DESCRC through DESSHTN, with the location fields left blank
+    let fields = segments[0].subheader.xml_data_content.as_ref().unwrap();
+    assert_eq!(fields.crc, Some(12345));
+    assert_eq!((fields.file_type.as_str(), fields.date_time.as_str()), ("XML", "2021-03-04T05:06:07Z"));
+    assert_eq!(fields.target_namespace, "urn:example:spec");
+    assert_eq!(fields.location_polygon, "");
+    assert_eq!(nitf.read_xml_data_content(0).unwrap(), "<a>283</a>");
+
+    // Every field, with DESCRC 99999 for no CRC
+    let fields = segments[1].subheader.xml_data_content.as_ref().unwrap();
+    assert_eq!(fields.crc, None);
+    assert_eq!(fields.location_point, "POINT(0.5 0.5)");
+    assert_eq!(fields.location_id_namespace, "urn:example:locations");
+    assert_eq!(fields.description, "A test document");
+    assert_eq!(nitf.read_xml_data_content(1).unwrap(), "<a>\u{E8}</a>");
+
+    // No user defined fields, and a document that is not UTF-8
+    assert_eq!(segments[2].subheader.xml_data_content, Some(XmlDataContent::default()));
+    match nitf.read_xml_data_content(2) {
+        Err(NitfError::Decode { compression, offset, .. }) => assert_eq!((compression.as_str(), offset), ("XML", segments[2].data_offset() + 3)),
+        other => panic!("a Latin-1 document read as {:?}", other)
+    }
+    assert!(matches!(nitf.read_xml_data_content(3), Err(NitfError::InvalidRequest { .. })));
+    assert!(matches!(nitf.read_xml_data_content(4), Err(NitfError::InvalidRequest { .. })));
+}
+
+
+#[test]
+fn xml_data_content_needs_known_field_lengths_and_a_sixteen_bit_crc() {
+    // DESSHL follows DE, DESID, DESVER and the 167 bytes of security fields, and DESCRC
+    // follows DESSHL
+    for (user_defined, field, value, past_length) in [(xml_fields("12345", 10), "DESSHL", "10", 0), (xml_fields("70000", 5), "DESCRC", "70000", 4)] {
+        let mut writer = NitfWriter::new(FileHeader::default());
+        writer.add_data_extension(xml_subheader(user_defined), b"<a/>".to_vec());
+        let mut bytes = Vec::new();
+        writer.write(&mut bytes).unwrap();
+        let subheader = bytes.windows(18).position(|window| window == b"DEXML_DATA_CONTENT").unwrap();
+        let offset = (subheader + 2 + 25 + 2 + 167 + past_length) as u64;
+        match NITF::from_reader(Cursor::new(bytes)) {
+            Err(NitfError::InvalidValue { field: read_field, offset: read_offset, value: read_value }) => {
+                assert_eq!((read_field.as_str(), read_offset, read_value.as_str()), (field, offset, value));
+            },
+            other => panic!("unexpected result {:?}", other.map(|_| ()))
+        }
+    }
+}